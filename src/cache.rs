@@ -0,0 +1,106 @@
+use std::{
+	collections::{hash_map::DefaultHasher, HashSet},
+	env, fs,
+	hash::{Hash, Hasher},
+	io::Error as IoError,
+	path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	parser::{
+		iter::Element as IterElement,
+		tree::{Problems, Statuses, Tree},
+	},
+	state::StateStore,
+};
+
+const FILE_NAME: &str = "analysis.toml";
+
+/// Analysis results stored on disk, valid only for the key they were stored with.
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Cache {
+	pub key: String,
+	pub statuses: Statuses,
+	pub problems: Problems,
+}
+
+impl Cache {
+	/// Computes a key that changes whenever an analysis of the tree could have a different
+	/// result, that is, when the tree itself changes, when any directory that holds, or would
+	/// hold, its links is modified, or when a link or the file it's made from is, like when a
+	/// copy is edited in place.
+	pub fn key(tree: &Tree) -> String {
+		let mut hasher = DefaultHasher::new();
+
+		env::current_dir().unwrap_or_default().hash(&mut hasher);
+		tree.root.hash(&mut hasher);
+		tree.work_dir.hash(&mut hasher);
+		tree.link_opts.hash(&mut hasher);
+		tree.managed.hash(&mut hasher);
+		tree.renames.hash(&mut hasher);
+
+		let mut visited = HashSet::new();
+
+		for IterElement {
+			target_path, leaf, ..
+		} in &tree.root
+		{
+			let Some(leaf) = leaf else {
+				continue;
+			};
+			let source_path = tree.source_path(&target_path, &leaf);
+			let link_path = leaf.link_path;
+
+			for metadata in [fs::metadata(&source_path), fs::symlink_metadata(&link_path)] {
+				metadata
+					.ok()
+					.map(|metadata| (metadata.modified().ok(), metadata.len()))
+					.hash(&mut hasher);
+			}
+
+			for dir in link_path.ancestors().skip(1) {
+				let dir = if dir.as_os_str().is_empty() {
+					Path::new(".")
+				} else {
+					dir
+				};
+
+				if !visited.insert(dir.to_path_buf()) {
+					break; // the remaining ancestors are already hashed
+				}
+
+				dir.hash(&mut hasher);
+				fs::metadata(dir)
+					.and_then(|metadata| metadata.modified())
+					.ok()
+					.hash(&mut hasher);
+			}
+		}
+
+		format!("{:016x}", hasher.finish())
+	}
+
+	/// Reads the cache from the state directory, whatever its key is. A cache that can't be
+	/// read is as good as a missing one, since it's rebuilt by the next analysis anyway.
+	pub fn read(state: &StateStore) -> Option<Self> {
+		state.load(FILE_NAME).ok().flatten()
+	}
+
+	/// Reads the cache from the state directory, returning it only if it matches the given key.
+	pub fn load(state: &StateStore, key: &str) -> Option<Self> {
+		let cache = Self::read(state)?;
+
+		if cache.key != key {
+			return None;
+		}
+
+		Some(cache)
+	}
+
+	/// Writes the cache to the state directory, creating it if needed.
+	pub fn store(&self, state: &StateStore) -> Result<(), IoError> {
+		state.store(FILE_NAME, self)
+	}
+}
@@ -1,16 +1,20 @@
-use clap::{ArgAction, Parser};
+use std::path::PathBuf;
+
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
 
 /// park is a CLI tool for managing dotfiles based on a TOML file.
 ///
 /// See park(1) for more details about usage, and park(5) for how to use a
 /// configuration file with it.
-#[derive(Default, Parser)]
+#[derive(Clone, Default, Parser)]
 #[command(
 	about,
 	long_about,
 	version,
 	max_term_width = 80,
 	disable_help_flag = true,
+	disable_help_subcommand = true,
 	disable_version_flag = true
 )]
 pub struct Park {
@@ -35,6 +39,234 @@ pub struct Park {
 	#[arg(long, short)]
 	pub create_dirs: bool,
 
+	/// Retry links that fail due to missing permissions with sudo.
+	///
+	/// This allows managing targets whose links live outside the home directory, like
+	/// '/etc', by running only the failing steps of the linking step with superuser
+	/// privileges.
+	#[arg(long, short)]
+	pub escalate: bool,
+
+	/// Copy targets whose links can't be symlinks.
+	///
+	/// This allows bypassing the UNSUPPORTED status by copying targets to file systems that
+	/// don't support symlinks, like FAT or exFAT ones, instead of symlinking them.
+	#[arg(long)]
+	pub copy_fallback: bool,
+
+	/// Compare the content of copies and clones with their targets when analyzing them.
+	///
+	/// By default, copies are up to date when they're as large as their targets and newer
+	/// than them, and only the ones that are older get their content compared.
+	#[arg(long)]
+	pub deep_check: bool,
+
+	/// Send a desktop notification summarizing the linking step.
+	///
+	/// Useful when running the linking step where there's no terminal to read its output
+	/// from, like hooks of services.
+	#[arg(long)]
+	pub notify: bool,
+
+	/// Keep linking targets as files are added to the work directory.
+	///
+	/// After linking, sources of targets, the directories holding them and the configuration
+	/// file are polled for changes, and once they stay unchanged for a whole interval, targets
+	/// are linked again, so that the ones that became READY get linked. Nothing is ever replaced or moved while
+	/// watching, whatever targets do on conflicts, and errors are printed without stopping it.
+	#[arg(long, requires = "link", conflicts_with_all = ["replace", "pick", "prune_orphans"])]
+	pub watch: bool,
+
+	/// Milliseconds between polls while watching.
+	#[arg(long, value_name = "MS", default_value_t = 1000)]
+	pub watch_interval: u64,
+
+	/// Cache analysis results.
+	///
+	/// Previews reuse the last analysis as long as the configuration and all directories
+	/// holding links remain unchanged. The linking step always runs a fresh analysis.
+	#[arg(long)]
+	pub cache: bool,
+
+	/// Preview only targets whose status changed since the cached analysis.
+	///
+	/// The last analysis stored by --cache is compared with the current one, so that targets
+	/// that became CONFLICT or DONE, like after a system update moved files around, stand out.
+	/// Targets the cached analysis doesn't know about count as changed.
+	#[arg(long, requires = "cache", conflicts_with_all = ["link", "simulate"])]
+	pub changed: bool,
+
+	/// Track links created by park.
+	///
+	/// Created links are recorded in a manifest, so that links pointing elsewhere can be
+	/// told apart: the ones created by park are MISMATCH, while the others are FOREIGN and
+	/// never replaced.
+	#[arg(long)]
+	pub track: bool,
+
+	/// Remove links of targets that are no longer in the configuration.
+	///
+	/// Links recorded by --track whose targets were removed from the configuration are
+	/// orphaned, and they're reported during analysis. With this, linking removes them as
+	/// long as they still point to where they did when created.
+	#[arg(long, requires_all = ["link", "track"])]
+	pub prune_orphans: bool,
+
+	/// Record checksums of target files along with tracked links.
+	///
+	/// The checksums let the verify command report targets that changed since they were
+	/// linked, as well as copies that were modified in place.
+	#[arg(long, requires = "track")]
+	pub checksums: bool,
+
+	/// Print every action of the linking step to stderr.
+	///
+	/// Actions, like creating directories, removing mismatched symlinks and creating links,
+	/// are printed as they're done, along with links left untouched, which helps
+	/// troubleshooting logs of unattended runs.
+	#[arg(long, short)]
+	pub verbose: bool,
+
+	/// Preview targets as if the file system was the one described by a scenario file.
+	///
+	/// Scenarios force statuses onto link paths, or onto all links within directories, which
+	/// shows how park would behave on another machine without touching it.
+	#[arg(long, value_name = "FILE", conflicts_with = "link")]
+	pub simulate: Option<PathBuf>,
+
+	/// Read the configuration from a file instead of standard input.
+	///
+	/// Standard input is left alone, so that prompts, like the one from "--pick", can't mix
+	/// answers with the configuration. The file is held under a shared lock while park runs,
+	/// and linking is aborted if it changed since it was read.
+	#[arg(long, value_name = "FILE", conflicts_with = "config_ref")]
+	pub config: Option<PathBuf>,
+
+	/// Read the configuration from a Git revision instead of standard input.
+	///
+	/// The reference looks like "HEAD~3:park.toml", where the path is relative to the root of
+	/// the repository holding the current directory, unless it starts with "./". If the
+	/// configuration sets work_dir within another repository, it's read again from that one.
+	/// Previewing an older configuration this way helps bisecting which change broke it.
+	#[arg(long, value_name = "REV:PATH", conflicts_with = "link")]
+	pub config_ref: Option<String>,
+
+	/// Print how long parsing, analyzing and linking took to standard error.
+	///
+	/// Each phase is printed along with how many file system calls it made. When the output
+	/// is JSON, timings are printed as a JSON document instead.
+	#[arg(long)]
+	pub timings: bool,
+
+	/// Number of threads used to analyze targets.
+	///
+	/// Analyzing targets from multiple threads speeds it up when probing the file system is
+	/// slow, like with home directories on network mounts.
+	#[arg(long, short, value_name = "N", default_value_t = 1)]
+	pub jobs: usize,
+
+	/// Number of times links are retried when creating them fails with a transient error.
+	///
+	/// Errors that might go away on their own, like the ones of busy or full file systems and
+	/// stale handles of network mounts, are retried with a delay that doubles each time.
+	#[arg(long, value_name = "N", default_value_t = 0)]
+	pub retries: u32,
+
+	/// Milliseconds to wait before the first retry of a link.
+	#[arg(long, value_name = "MS", default_value_t = 100)]
+	pub retry_delay: u64,
+
+	/// Treat warnings as errors.
+	///
+	/// Warnings, like relative paths that make links depend on the current directory, abort
+	/// the program instead of just being reported.
+	#[arg(long, short)]
+	pub strict: bool,
+
+	/// Only evaluate targets whose names match a pattern.
+	///
+	/// Patterns work like target filters, except '*' matches any sequence of characters and
+	/// '?' matches a single one. The linking step lists targets left out by filters as
+	/// skipped.
+	#[arg(long, value_name = "PATTERN")]
+	pub only: Vec<String>,
+
+	/// Print the preview tree before linking, and a summary after it.
+	///
+	/// The tree shows what the linking step is about to do, which works as a preview that
+	/// can't get outdated, since both come from the same analysis.
+	#[arg(long, requires = "link")]
+	pub show_tree: bool,
+
+	/// Choose which pending targets to link from a list.
+	///
+	/// Targets that are not done are listed with numbers, and only the ones picked by typing
+	/// numbers, ranges like '2-4' or parts of their names are linked, while the others are
+	/// listed as skipped. The answer is read from the terminal, since standard input holds
+	/// the configuration.
+	#[arg(long, requires = "link")]
+	pub pick: bool,
+
+	/// Print a single line counting targets instead of the preview tree.
+	///
+	/// The line looks like "park: 42 done, 2 pending, 1 problem", which suits shell prompts
+	/// and login messages. When the output is JSON, the counts are printed as a JSON object.
+	#[arg(long, conflicts_with = "link")]
+	pub summary_only: bool,
+
+	/// Show target descriptions in the preview tree.
+	///
+	/// Link paths are shown in full, even if they don't fit in the terminal, and so are the
+	/// tags that let each target in.
+	#[arg(long, short = 'L')]
+	pub long: bool,
+
+	/// Show statuses as icons before target names.
+	///
+	/// Icons take less room than status labels, which keeps dense previews readable on
+	/// narrow terminals.
+	#[arg(long)]
+	pub icons: bool,
+
+	/// Never pipe the preview tree into a pager.
+	///
+	/// By default, previews taller than the terminal are piped into PAGER, or less if it's
+	/// not set.
+	#[arg(long)]
+	pub no_pager: bool,
+
+	/// Show paths within the home directory in full.
+	///
+	/// By default, the home directory at the beginning of link paths is shown as '~', both in
+	/// the preview tree and in the actions printed by --verbose.
+	#[arg(long)]
+	pub no_tilde: bool,
+
+	/// Run without anything meant for terminals.
+	///
+	/// Colors, paging and shortening link paths to the terminal width are all disabled, so
+	/// that the output is the same wherever park runs, like in services or provisioning
+	/// tools.
+	#[arg(long)]
+	pub non_interactive: bool,
+
+	/// Format of the output.
+	///
+	/// With 'json', the preview is printed as a JSON document, and so are errors, which are
+	/// printed to stderr. The preview can also be printed as tab-separated lines with
+	/// 'porcelain', as a YAML document with 'yaml', as a Graphviz graph with 'dot' or as an
+	/// HTML page with 'html'.
+	#[arg(long, short, value_enum, default_value_t = Output::Tree)]
+	pub output: Output,
+
+	/// When to use colors.
+	///
+	/// With 'auto', colors are used only when writing to a terminal, unless either
+	/// CLICOLOR_FORCE is set to something other than '0' (which forces colors), or NO_COLOR
+	/// is set or CLICOLOR is '0' (which disable them).
+	#[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+	pub color: ColorChoice,
+
 	/// Show help usage.
 	///
 	/// Use -h to show the short help, or --help to show the long one (or even better,
@@ -45,10 +277,376 @@ pub struct Park {
 	/// Show version.
 	///
 	/// The version format is 'park <version>'. Use it wisely.
-	#[arg(long, short, action = ArgAction::Version)]
+	#[arg(long, short = 'V', action = ArgAction::Version)]
 	pub version: Option<bool>,
 
+	#[command(flatten)]
+	pub filters: Filters,
+
+	/// Activate a tag, which is written without the plus sign.
+	///
+	/// Unlike tags among the arguments, these are never mistaken for target names, and they
+	/// can be repeated, as in '-t desktop -t wayland'.
+	#[arg(long = "tag", short, value_name = "TAG", global = true)]
+	pub tag: Vec<String>,
+
+	#[command(subcommand)]
+	pub command: Option<Command>,
+}
+
+/// Target filters, which are shared by the commands that take them.
+#[derive(Args, Clone, Debug, Default, PartialEq)]
+pub struct Filters {
 	/// List of tags (appended with a plus sign) or target names (for filtering purposes).
 	#[arg()]
 	pub filters: Vec<String>,
+
+	/// Target names after '--', which are never taken as tags, even if they begin with a plus
+	/// sign.
+	#[arg(last = true)]
+	pub literal: Vec<String>,
+}
+
+#[derive(Clone, Subcommand)]
+pub enum Command {
+	/// Check whether all targets are done.
+	///
+	/// Exits successfully only when every target is already linked accordingly.
+	Check(Check),
+
+	/// Explain how a target is resolved.
+	///
+	/// Prints, step by step, which tags and overrides apply to the target, how its link path
+	/// is computed and why the analysis results in its status.
+	Explain(Explain),
+
+	/// Print the status of a single target.
+	///
+	/// Only the target is analyzed, so this is fast regardless of how many targets there
+	/// are. Its link path and where an existing link points to are printed as well.
+	Status(Status),
+
+	/// Print the status, link and source of targets matching filters.
+	///
+	/// Nothing but the matched targets is analyzed and no tree is printed, which suits scripts
+	/// that need to know which targets are linked, like before backing them up. Target names
+	/// may have wildcards, like 'nvim/*'.
+	Query(Query),
+
+	/// Print targets that are not done.
+	///
+	/// Each target is printed along with its status, or alone with --names-only, which suits
+	/// command substitution, like `park --link $(park pending --names-only | fzf)`. Target
+	/// names may have wildcards, like 'nvim/*'.
+	Pending(Pending),
+
+	/// Print which targets are active for each profile.
+	///
+	/// Profiles are the tag sets under "profiles" in the configuration, and only their tags
+	/// count, regardless of the current machine. Nothing is analyzed, so this helps auditing
+	/// whether every profile still gets the targets it should.
+	Matrix(Matrix),
+
+	/// Print the preview of targets under a target path.
+	///
+	/// Targets elsewhere are left out before the analysis, so this is faster than the full
+	/// preview when working on the targets of a single application.
+	Tree(Subtree),
+
+	/// List targets along with their links and descriptions.
+	///
+	/// Only targets enabled by tags and target filters are listed.
+	Targets(Targets),
+
+	/// Remove links created for targets.
+	///
+	/// Only symlinks that point to their targets are removed, while anything else in link
+	/// paths is left untouched. With tags, only targets gated on any of them are unlinked.
+	Unlink(Unlink),
+
+	/// Print a configuration of the targets that are done.
+	///
+	/// Each target gets its link path spelled out, so the configuration describes what is
+	/// applied in this machine regardless of tags, which is useful for auditing it or for
+	/// bootstrapping a minimal configuration elsewhere.
+	Export(Export),
+
+	/// Report targets that changed since they were linked.
+	///
+	/// Targets are compared with the checksums recorded by --checksums, and so are copies,
+	/// which tells local edits that were never committed back apart.
+	Verify(Verify),
+
+	/// Print the completion script for a shell.
+	///
+	/// No configuration is read from standard input, so the script can be generated anywhere,
+	/// like when packaging park.
+	Completions(Completions),
+
+	/// Print a JSON Schema of the configuration.
+	///
+	/// Editors can validate and complete configurations with it, like through taplo. No
+	/// configuration is read from standard input.
+	Schema(Schema),
+}
+
+#[derive(Args, Clone, Default)]
+pub struct Check {
+	/// Stop at the first target that is not done.
+	#[arg(long, short)]
+	pub quick: bool,
+
+	/// Print targets that are not done.
+	#[arg(long, short)]
+	pub verbose: bool,
+
+	/// Also report tags no profile activates and targets no profile enables.
+	#[arg(long)]
+	pub lint: bool,
+
+	/// Show help usage.
+	#[arg(long, short, action = ArgAction::Help)]
+	pub help: Option<bool>,
+
+	#[command(flatten)]
+	pub filters: Filters,
+}
+
+#[derive(Args, Clone, Default)]
+pub struct Explain {
+	/// Show help usage.
+	#[arg(long, short, action = ArgAction::Help)]
+	pub help: Option<bool>,
+
+	/// Name of the target to be explained.
+	#[arg()]
+	pub target: String,
+
+	/// List of tags (appended with a plus sign).
+	#[arg()]
+	pub tags: Vec<String>,
+}
+
+#[derive(Args, Clone, Default)]
+pub struct Status {
+	/// Show help usage.
+	#[arg(long, short, action = ArgAction::Help)]
+	pub help: Option<bool>,
+
+	/// Name of the target to be queried.
+	#[arg()]
+	pub target: String,
+
+	/// List of tags (appended with a plus sign).
+	#[arg()]
+	pub tags: Vec<String>,
+}
+
+#[derive(Args, Clone, Default)]
+pub struct Query {
+	/// Show help usage.
+	#[arg(long, short, action = ArgAction::Help)]
+	pub help: Option<bool>,
+
+	/// Format of the queried targets.
+	#[arg(long, value_enum, default_value_t)]
+	pub format: QueryFormat,
+
+	#[command(flatten)]
+	pub filters: Filters,
+}
+
+#[derive(Args, Clone, Default)]
+pub struct Pending {
+	/// Show help usage.
+	#[arg(long, short, action = ArgAction::Help)]
+	pub help: Option<bool>,
+
+	/// Print only target paths, one per line.
+	#[arg(long)]
+	pub names_only: bool,
+
+	#[command(flatten)]
+	pub filters: Filters,
+}
+
+#[derive(Args, Clone, Default)]
+pub struct Matrix {
+	/// Show help usage.
+	#[arg(long, short, action = ArgAction::Help)]
+	pub help: Option<bool>,
+
+	/// Names of the profiles to be shown, which are all of them by default.
+	#[arg()]
+	pub profiles: Vec<String>,
+}
+
+#[derive(Args, Clone, Default)]
+pub struct Subtree {
+	/// Show help usage.
+	#[arg(long, short, action = ArgAction::Help)]
+	pub help: Option<bool>,
+
+	/// Target path whose targets are previewed, like "nvim" for "nvim/init.lua".
+	#[arg()]
+	pub prefix: PathBuf,
+
+	/// List of tags (appended with a plus sign).
+	#[arg()]
+	pub tags: Vec<String>,
+}
+
+#[derive(Args, Clone, Default)]
+pub struct Targets {
+	/// Show help usage.
+	#[arg(long, short, action = ArgAction::Help)]
+	pub help: Option<bool>,
+
+	#[command(flatten)]
+	pub filters: Filters,
+}
+
+#[derive(Args, Clone, Default)]
+pub struct Unlink {
+	/// Show help usage.
+	#[arg(long, short, action = ArgAction::Help)]
+	pub help: Option<bool>,
+
+	#[command(flatten)]
+	pub filters: Filters,
+}
+
+#[derive(Args, Clone, Default)]
+pub struct Export {
+	/// Show help usage.
+	#[arg(long, short, action = ArgAction::Help)]
+	pub help: Option<bool>,
+
+	#[command(flatten)]
+	pub filters: Filters,
+}
+
+#[derive(Args, Clone, Default)]
+pub struct Verify {
+	/// Show help usage.
+	#[arg(long, short, action = ArgAction::Help)]
+	pub help: Option<bool>,
+
+	#[command(flatten)]
+	pub filters: Filters,
+}
+
+#[derive(Args, Clone)]
+pub struct Completions {
+	/// Show help usage.
+	#[arg(long, short, action = ArgAction::Help)]
+	pub help: Option<bool>,
+
+	/// Shell to print the completion script for.
+	#[arg(value_enum)]
+	pub shell: Shell,
+}
+
+#[derive(Args, Clone, Default)]
+pub struct Schema {
+	/// Show help usage.
+	#[arg(long, short, action = ArgAction::Help)]
+	pub help: Option<bool>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum ColorChoice {
+	#[default]
+	Auto,
+	Always,
+	Never,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum Output {
+	#[default]
+	Tree,
+	Json,
+	Porcelain,
+	Yaml,
+	Dot,
+	Html,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum QueryFormat {
+	#[default]
+	Text,
+	Json,
+}
+
+#[cfg(test)]
+mod tests {
+	use clap::CommandFactory;
+	use pretty_assertions::assert_eq;
+
+	use super::*;
+
+	#[test]
+	fn parse_filters() {
+		struct Test<'a> {
+			description: &'a str,
+			input: &'a [&'a str],
+			output: (Filters, Vec<String>),
+		}
+
+		let filters = |filters: &[&str], literal: &[&str]| Filters {
+			filters: filters.iter().map(|filter| filter.to_string()).collect(),
+			literal: literal.iter().map(|name| name.to_string()).collect(),
+		};
+
+		let test_cases = Vec::from([
+			Test {
+				description: "tags and target names",
+				input: &["park", "+desktop", "nvim"],
+				output: (filters(&["+desktop", "nvim"], &[]), Vec::new()),
+			},
+			Test {
+				description: "target names after '--'",
+				input: &["park", "+desktop", "--", "+notes", "nvim"],
+				output: (filters(&["+desktop"], &["+notes", "nvim"]), Vec::new()),
+			},
+			Test {
+				description: "repeated tags",
+				input: &["park", "--tag", "desktop", "-t", "wayland", "nvim"],
+				output: (
+					filters(&["nvim"], &[]),
+					Vec::from(["desktop".into(), "wayland".into()]),
+				),
+			},
+			Test {
+				description: "subcommand filters",
+				input: &["park", "check", "-t", "desktop", "+work", "--", "+notes"],
+				output: (
+					filters(&["+work"], &["+notes"]),
+					Vec::from(["desktop".into()]),
+				),
+			},
+		]);
+
+		for case in test_cases {
+			let park = Park::try_parse_from(case.input).unwrap();
+			let filters = match park.command {
+				Some(Command::Check(Check { filters, .. })) => filters,
+				_ => park.filters,
+			};
+
+			assert_eq!(
+				(filters, park.tag),
+				case.output,
+				"bad result for {:?}",
+				case.description
+			);
+		}
+	}
+
+	#[test]
+	fn verify_command() {
+		Park::command().debug_assert();
+	}
 }
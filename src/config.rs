@@ -1,12 +1,36 @@
 use std::{
-	collections::{BTreeMap, HashSet},
-	path::PathBuf,
+	collections::{BTreeMap, HashMap, HashSet},
+	fmt::{Formatter, Result as FmtResult},
+	path::{Path, PathBuf},
 };
 
-use serde::Deserialize;
+use serde::{
+	de::{
+		value::MapAccessDeserializer, DeserializeSeed, Error as DeError, IgnoredAny,
+		IntoDeserializer, MapAccess, SeqAccess, Visitor,
+	},
+	Deserialize, Deserializer,
+};
+use toml::{Spanned, Value};
+
+use crate::{
+	parser::node::{OnConflict, Status},
+	vars::Vars,
+};
 
 pub type TargetMap = BTreeMap<PathBuf, Target>;
 pub type TagSet = HashSet<String>;
+/// Prefixes of link paths mapped to the ones replacing them.
+pub type Remap = BTreeMap<PathBuf, PathBuf>;
+
+/// Entry of the remap table, which is either a prefix replaced regardless of tags or the
+/// prefixes replaced while a tag is active.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum RemapEntry {
+	Prefix(PathBuf),
+	Tagged(Remap),
+}
 
 #[derive(Debug, Default, Deserialize, PartialEq)]
 /// The main configuration for Park.
@@ -14,19 +38,125 @@ pub struct Config {
 	pub tags: Option<TagSet>,
 	pub base_dir: Option<PathBuf>,
 	pub work_dir: Option<PathBuf>,
+	/// Whether link names may be paths rather than plain file names.
+	pub allow_path_names: Option<bool>,
+	/// Options for how previews are printed.
+	pub printer: Option<PrinterConfig>,
+	/// Options for which statuses make Park exit with failure.
+	pub policy: Option<Policy>,
+	/// Variables for placeholders in links, which machine-local ones take precedence over.
+	pub vars: Option<Vars>,
+	/// Whether the base directory defaults to the XDG config directory instead of the home
+	/// directory.
+	pub xdg: Option<bool>,
+	/// Previous paths of renamed targets, mapped to their current ones.
+	pub renames: Option<BTreeMap<PathBuf, PathBuf>>,
+	/// Owner of links, as "user:group", for targets that don't set one.
+	pub owner: Option<String>,
+	/// Tags of each machine the configuration is meant for, keyed by the machine's name.
+	pub profiles: Option<BTreeMap<String, TagSet>>,
+	/// Prefixes of link paths replaced by others, along with the ones replaced only while a tag
+	/// is active, keyed by the tag.
+	pub remap: Option<BTreeMap<String, RemapEntry>>,
+	/// Whether links of directories that other links are within get unfolded into links of
+	/// their entries, like Stow does, instead of being errors.
+	pub unfold: Option<bool>,
+	/// Character tags are prepended with among the arguments, instead of a plus sign.
+	pub tag_prefix: Option<char>,
+	/// Targets keyed by their paths, which may also be written as nested tables.
+	#[serde(default, deserialize_with = "deserialize_targets")]
 	pub targets: Option<TargetMap>,
 }
 
 #[derive(Debug, Default, Deserialize, PartialEq)]
+/// Configuration for the preview tree.
+pub struct PrinterConfig {
+	/// Whether statuses are shown as icons before target names.
+	pub icons: Option<bool>,
+	/// Whether icons are Nerd Font glyphs rather than plain Unicode symbols.
+	pub nerd_font: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+/// Configuration for exit codes.
+pub struct Policy {
+	/// Statuses that are considered failures, both when previewing and checking targets.
+	pub failures: Option<Vec<Status>>,
+	/// Directories which --create-dirs may create missing directories under. A leading '~'
+	/// stands for the home directory.
+	pub create_dirs_allowed_under: Option<Vec<PathBuf>>,
+	/// Whether regular files with the same content as their targets are replaced by links,
+	/// rather than being conflicts.
+	pub replace_identical: Option<bool>,
+	/// Size, in bytes, of the largest files whose content is compared with their targets, for
+	/// targets that don't set one. Larger files are assumed to differ.
+	pub max_compare_size: Option<u64>,
+	/// Bytes of content that may be compared in a single analysis, after which files are
+	/// assumed to differ.
+	pub compare_budget: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
 /// Represents configuration for a dotfile.
 pub struct Target {
 	/// Link options of a dotfile.
 	pub link: Option<Link>,
 	/// Tags under which a dotfile should be managed.
 	pub tags: Option<Tags>,
+	/// Overrides used when the "linux" tag is active.
+	pub linux: Option<TargetOverride>,
+	/// Overrides used when the "macos" tag is active.
+	pub macos: Option<TargetOverride>,
+	/// Whether a dotfile is copied only when nothing exists in its link path yet.
+	pub seed: Option<bool>,
+	/// Path of the dotfile, in case it doesn't live in the work directory under the target's name.
+	pub source: Option<PathBuf>,
+	/// Work directory of the dotfile, in case it's not the top-level one.
+	pub work_dir: Option<PathBuf>,
+	/// Human-readable description of a dotfile.
+	pub description: Option<String>,
+	/// Whether problems with a dotfile are only reported, instead of blocking the others.
+	pub optional: Option<bool>,
+	/// Conditions under which a dotfile is managed, besides its tags.
+	pub when: Option<When>,
+	/// How files in the way of the dotfile's link are dealt with when linking.
+	pub on_conflict: Option<OnConflict>,
+	/// Note shown along with problems of a dotfile, like how to fix them on this machine.
+	pub problem_hint: Option<String>,
+	/// Owner of the dotfile's link, as "user:group", for when Park runs as another user.
+	pub owner: Option<String>,
+	/// Name of the section a dotfile is listed under in previews, regardless of its path.
+	pub group: Option<String>,
+	/// Where the content of a dotfile comes from when it's not a file in the work directory.
+	pub secret: Option<Secret>,
+	/// Size, in bytes, of the largest files whose content is compared with a dotfile.
+	pub max_compare_size: Option<u64>,
 }
 
-#[derive(Debug, Default, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+/// Conditions that can't be expressed by tags.
+pub struct When {
+	/// Executable, relative to the work directory, whose success makes a dotfile managed.
+	pub script: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+/// Content of a dotfile that is kept out of the work directory, like credentials.
+pub struct Secret {
+	/// Shell command, like "pass show dotfiles/netrc", whose output is written to the link path.
+	pub command: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+/// Options of a dotfile that can be overridden for a specific operating system.
+pub struct TargetOverride {
+	/// Link options that take precedence over the target's ones.
+	pub link: Option<Link>,
+	/// Tags that take precedence over the target's ones.
+	pub tags: Option<Tags>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
 /// Configuration for constraints that toggle certain dotfiles on and off.
 pub struct Tags {
 	/// These tags are evaluated conjunctively.
@@ -35,13 +165,406 @@ pub struct Tags {
 	pub any_of: Option<TagSet>,
 }
 
-#[derive(Debug, Default, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
 /// Configuration for the symlink of dotfiles.
 pub struct Link {
 	/// The place where the symlink gets created in.
 	pub base_dir: Option<PathBuf>,
 	/// Filename for the symlink.
 	pub name: Option<PathBuf>,
+	/// Permissions of the symlink itself, on platforms where symlinks have their own, written
+	/// the way `copy_mode` is.
+	#[serde(default, deserialize_with = "deserialize_mode")]
+	pub mode: Option<u32>,
+	/// Whether the target is cloned to the link path instead of symlinked, sharing its data
+	/// until either of them changes.
+	pub reflink: Option<bool>,
+	/// Permissions of copies and clones of the target, as octal digits like "644" or an integer
+	/// like 0o644, instead of the target's own.
+	#[serde(default, deserialize_with = "deserialize_mode")]
+	pub copy_mode: Option<u32>,
+}
+
+/// Names of the fields of a target, which tell targets apart from tables of nested targets.
+pub const TARGET_FIELDS: [&str; 16] = [
+	"link",
+	"tags",
+	"linux",
+	"macos",
+	"seed",
+	"source",
+	"work_dir",
+	"description",
+	"optional",
+	"when",
+	"on_conflict",
+	"problem_hint",
+	"owner",
+	"group",
+	"secret",
+	"max_compare_size",
+];
+
+/// Deserializes targets, flattening nested tables into target paths. This is done while
+/// reading the input, so that errors keep pointing at where they happen in it.
+fn deserialize_targets<'de, D>(deserializer: D) -> Result<Option<TargetMap>, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	struct TargetsVisitor;
+
+	impl<'de> Visitor<'de> for TargetsVisitor {
+		type Value = TargetMap;
+
+		fn expecting(&self, f: &mut Formatter) -> FmtResult {
+			f.write_str("a map")
+		}
+
+		fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+		where
+			A: MapAccess<'de>,
+		{
+			let mut targets = TargetMap::new();
+
+			while let Some(key) = map.next_key::<PathBuf>()? {
+				match map.next_value()? {
+					Entry::Target(target) => {
+						targets.insert(key, *target);
+					}
+					Entry::Nested(nested) => targets.extend(
+						nested
+							.into_iter()
+							.map(|(target_path, target)| (key.join(target_path), target)),
+					),
+					Entry::Ignored => {
+						return Err(A::Error::custom(format!("target {:?} is not a table", key)))
+					}
+				}
+			}
+
+			Ok(targets)
+		}
+	}
+
+	deserializer.deserialize_map(TargetsVisitor).map(Some)
+}
+
+/// Deserializes permissions written either as octal digits, like "644" or "0755", or as an
+/// integer, like 0o644.
+fn deserialize_mode<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	struct ModeVisitor;
+
+	impl<'de> Visitor<'de> for ModeVisitor {
+		type Value = u32;
+
+		fn expecting(&self, f: &mut Formatter) -> FmtResult {
+			write!(f, "octal permissions like \"644\" or 0o644")
+		}
+
+		fn visit_str<E>(self, mode: &str) -> Result<Self::Value, E>
+		where
+			E: DeError,
+		{
+			match u32::from_str_radix(mode, 8) {
+				Ok(bits) if bits <= 0o7777 => Ok(bits),
+				_ => Err(E::custom(format!(
+					"invalid mode {:?}, expected octal permissions like \"644\" or 0o644",
+					mode
+				))),
+			}
+		}
+
+		fn visit_i64<E>(self, mode: i64) -> Result<Self::Value, E>
+		where
+			E: DeError,
+		{
+			match u32::try_from(mode) {
+				Ok(bits) if bits <= 0o7777 => Ok(bits),
+				_ => Err(E::custom(format!(
+					"invalid mode {:#o}, expected permissions up to 0o7777",
+					mode
+				))),
+			}
+		}
+
+		fn visit_u64<E>(self, mode: u64) -> Result<Self::Value, E>
+		where
+			E: DeError,
+		{
+			self.visit_i64(i64::try_from(mode).unwrap_or(i64::MAX))
+		}
+	}
+
+	deserializer.deserialize_any(ModeVisitor).map(Some)
+}
+
+/// Table found under targets, which is either a target or a table of nested targets. The
+/// latter is the case when none of its keys are fields of a target.
+enum Entry {
+	Target(Box<Target>),
+	Nested(TargetMap),
+	/// Anything that is not a table, like a misspelled field of a target.
+	Ignored,
+}
+
+impl<'de> Deserialize<'de> for Entry {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		deserializer.deserialize_any(EntryVisitor)
+	}
+}
+
+struct EntryVisitor;
+
+impl<'de> Visitor<'de> for EntryVisitor {
+	type Value = Entry;
+
+	fn expecting(&self, f: &mut Formatter) -> FmtResult {
+		f.write_str("a target or a table of targets")
+	}
+
+	fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+	where
+		A: MapAccess<'de>,
+	{
+		let mut nested = TargetMap::new();
+
+		while let Some(key) = map.next_key::<String>()? {
+			if TARGET_FIELDS.contains(&key.as_str()) {
+				if !nested.is_empty() {
+					return Err(A::Error::custom(format!(
+						"field {:?} is mixed with nested targets",
+						key
+					)));
+				}
+
+				let map = Replay {
+					key: Some(key),
+					map,
+				};
+
+				return Target::deserialize(MapAccessDeserializer::new(map))
+					.map(|target| Entry::Target(Box::new(target)));
+			}
+
+			match map.next_value()? {
+				Entry::Target(target) => {
+					nested.insert(key.into(), *target);
+				}
+				Entry::Nested(targets) => nested.extend(
+					targets
+						.into_iter()
+						.map(|(target_path, target)| (Path::new(&key).join(target_path), target)),
+				),
+				Entry::Ignored => {}
+			}
+		}
+
+		if nested.is_empty() {
+			return Ok(Entry::Target(Box::default()));
+		}
+
+		Ok(Entry::Nested(nested))
+	}
+
+	fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+	where
+		A: SeqAccess<'de>,
+	{
+		while seq.next_element::<IgnoredAny>()?.is_some() {}
+
+		Ok(Entry::Ignored)
+	}
+
+	fn visit_bool<E>(self, _: bool) -> Result<Self::Value, E> {
+		Ok(Entry::Ignored)
+	}
+
+	fn visit_i64<E>(self, _: i64) -> Result<Self::Value, E> {
+		Ok(Entry::Ignored)
+	}
+
+	fn visit_u64<E>(self, _: u64) -> Result<Self::Value, E> {
+		Ok(Entry::Ignored)
+	}
+
+	fn visit_f64<E>(self, _: f64) -> Result<Self::Value, E> {
+		Ok(Entry::Ignored)
+	}
+
+	fn visit_str<E>(self, _: &str) -> Result<Self::Value, E> {
+		Ok(Entry::Ignored)
+	}
+}
+
+/// Map whose first key was already read, so that it's handed back before the remaining ones.
+struct Replay<M> {
+	key: Option<String>,
+	map: M,
+}
+
+impl<'de, M> MapAccess<'de> for Replay<M>
+where
+	M: MapAccess<'de>,
+{
+	type Error = M::Error;
+
+	fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+	where
+		K: DeserializeSeed<'de>,
+	{
+		match self.key.take() {
+			Some(key) => seed.deserialize(key.into_deserializer()).map(Some),
+			None => self.map.next_key_seed(seed),
+		}
+	}
+
+	fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+	where
+		V: DeserializeSeed<'de>,
+	{
+		self.map.next_value_seed(seed)
+	}
+}
+
+/// Merges an overlay into a configuration document. Tables are merged key by key, while any
+/// other value in the overlay replaces the one in the document.
+pub fn merge(document: &mut Value, overlay: Value) {
+	match (document, overlay) {
+		(Value::Table(document), Value::Table(overlay)) => {
+			for (key, value) in overlay {
+				match document.get_mut(&key) {
+					Some(existing) => merge(existing, value),
+					None => {
+						document.insert(key, value);
+					}
+				}
+			}
+		}
+		(document, overlay) => *document = overlay,
+	}
+}
+
+/// Finds the lines, starting from 1, where targets are declared, like `[targets.foo]`,
+/// `config."init.lua" = {}` under `[targets]` or `foo.seed = true`, so that errors can point at
+/// them. The line of a target is the one of the key naming it, as spanned by the TOML parser.
+pub fn target_lines(input: &str) -> HashMap<PathBuf, usize> {
+	#[derive(Deserialize)]
+	struct Document {
+		targets: Option<Keys>,
+	}
+
+	let mut lines = HashMap::new();
+
+	if let Ok(Document {
+		targets: Some(Keys::Table(targets)),
+	}) = toml::from_str(input)
+	{
+		let line_starts: Vec<usize> = input.match_indices('\n').map(|(i, _)| i + 1).collect();
+		find_lines(&line_starts, Path::new(""), &targets, &mut lines);
+	}
+
+	lines
+}
+
+/// Finds the lines of targets within a table of targets, telling targets from nested tables of
+/// targets the same way they're deserialized. Lines are told from where each one but the first
+/// starts, so that the input isn't read again for every target.
+fn find_lines(
+	line_starts: &[usize],
+	prefix: &Path,
+	table: &BTreeMap<Spanned<String>, Keys>,
+	lines: &mut HashMap<PathBuf, usize>,
+) {
+	for (key, keys) in table {
+		let Keys::Table(table) = keys else {
+			continue;
+		};
+		let target_path = prefix.join(key.get_ref());
+		let is_target = table.is_empty()
+			|| table
+				.keys()
+				.any(|key| TARGET_FIELDS.contains(&key.get_ref().as_str()));
+
+		if is_target {
+			let line = line_starts.partition_point(|&start| start <= key.start()) + 1;
+			lines.insert(target_path, line);
+		} else {
+			find_lines(line_starts, &target_path, table, lines);
+		}
+	}
+}
+
+/// Keys of a TOML document along with where they are, with anything but tables left out.
+enum Keys {
+	Table(BTreeMap<Spanned<String>, Keys>),
+	Other,
+}
+
+impl<'de> Deserialize<'de> for Keys {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		struct KeysVisitor;
+
+		impl<'de> Visitor<'de> for KeysVisitor {
+			type Value = Keys;
+
+			fn expecting(&self, f: &mut Formatter) -> FmtResult {
+				f.write_str("any value")
+			}
+
+			fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+			where
+				A: MapAccess<'de>,
+			{
+				let mut table = BTreeMap::new();
+
+				while let Some(key) = map.next_key()? {
+					table.insert(key, map.next_value()?);
+				}
+
+				Ok(Keys::Table(table))
+			}
+
+			fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+			where
+				A: SeqAccess<'de>,
+			{
+				while seq.next_element::<IgnoredAny>()?.is_some() {}
+
+				Ok(Keys::Other)
+			}
+
+			fn visit_bool<E>(self, _: bool) -> Result<Self::Value, E> {
+				Ok(Keys::Other)
+			}
+
+			fn visit_i64<E>(self, _: i64) -> Result<Self::Value, E> {
+				Ok(Keys::Other)
+			}
+
+			fn visit_u64<E>(self, _: u64) -> Result<Self::Value, E> {
+				Ok(Keys::Other)
+			}
+
+			fn visit_f64<E>(self, _: f64) -> Result<Self::Value, E> {
+				Ok(Keys::Other)
+			}
+
+			fn visit_str<E>(self, _: &str) -> Result<Self::Value, E> {
+				Ok(Keys::Other)
+			}
+		}
+
+		deserializer.deserialize_any(KeysVisitor)
+	}
 }
 
 #[cfg(test)]
@@ -72,6 +595,17 @@ mod tests {
 				tags: Some(TagSet::from(["foo".into(), "bar".into(),])),
 				base_dir: Some("test".into()),
 				work_dir: None,
+				allow_path_names: None,
+				printer: None,
+				policy: None,
+				vars: None,
+				xdg: None,
+				renames: None,
+				owner: None,
+				profiles: None,
+				remap: None,
+				unfold: None,
+				tag_prefix: None,
 				targets: None,
 			}
 		);
@@ -93,6 +627,17 @@ mod tests {
 				tags: Some(TagSet::from(["foo".into(), "bar".into()])),
 				base_dir: Some("test".into()),
 				work_dir: Some("somewhere".into()),
+				allow_path_names: None,
+				printer: None,
+				policy: None,
+				vars: None,
+				xdg: None,
+				renames: None,
+				owner: None,
+				profiles: None,
+				remap: None,
+				unfold: None,
+				tag_prefix: None,
 				targets: Some(TargetMap::new()),
 			}
 		);
@@ -116,12 +661,24 @@ mod tests {
 				tags: Some(TagSet::from(["foo".into(), "bar".into(),])),
 				base_dir: Some("test".into()),
 				work_dir: None,
+				allow_path_names: None,
+				printer: None,
+				policy: None,
+				vars: None,
+				xdg: None,
+				renames: None,
+				owner: None,
+				profiles: None,
+				remap: None,
+				unfold: None,
+				tag_prefix: None,
 				targets: Some(TargetMap::from([
 					(
 						"baz".into(),
 						Target {
 							link: None,
 							tags: None,
+							..Target::default()
 						},
 					),
 					(
@@ -129,6 +686,7 @@ mod tests {
 						Target {
 							link: None,
 							tags: None,
+							..Target::default()
 						},
 					),
 				])),
@@ -148,7 +706,15 @@ mod tests {
 
 			[targets.qux]
 			link.base_dir = "elsewhere"
+			link.mode = "700"
+			link.copy_mode = 0o755
 			tags.any_of = ["qux"]
+			seed = true
+			optional = true
+			when.script = "is_work.sh"
+			on_conflict = "backup"
+			problem_hint = "run :PackerSync after linking"
+			owner = "park:staff"
 		"#})
 		.unwrap();
 
@@ -158,6 +724,17 @@ mod tests {
 				tags: Some(TagSet::from(["foo".into(), "bar".into()])),
 				base_dir: Some("test".into()),
 				work_dir: None,
+				allow_path_names: None,
+				printer: None,
+				policy: None,
+				vars: None,
+				xdg: None,
+				renames: None,
+				owner: None,
+				profiles: None,
+				remap: None,
+				unfold: None,
+				tag_prefix: None,
 				targets: Some(TargetMap::from([
 					(
 						"baz".into(),
@@ -165,11 +742,15 @@ mod tests {
 							link: Some(Link {
 								name: Some("BAZ".into()),
 								base_dir: None,
+								mode: None,
+								reflink: None,
+								copy_mode: None,
 							}),
 							tags: Some(Tags {
 								all_of: Some(TagSet::from(["baz".into()])),
 								any_of: None,
 							}),
+							..Target::default()
 						},
 					),
 					(
@@ -178,15 +759,330 @@ mod tests {
 							link: Some(Link {
 								name: None,
 								base_dir: Some("elsewhere".into()),
+								mode: Some(0o700),
+								reflink: None,
+								copy_mode: Some(0o755),
 							}),
 							tags: Some(Tags {
 								all_of: None,
 								any_of: Some(TagSet::from(["qux".into()])),
 							}),
+							seed: Some(true),
+							optional: Some(true),
+							when: Some(When {
+								script: Some("is_work.sh".into()),
+							}),
+							on_conflict: Some(OnConflict::Backup),
+							problem_hint: Some("run :PackerSync after linking".into()),
+							owner: Some("park:staff".into()),
+							..Target::default()
+						},
+					),
+				])),
+			}
+		);
+
+		let got = toml::from_str::<Config>(indoc! {r#"
+			[targets.qux]
+			link.copy_mode = "rw-r--r--"
+		"#});
+
+		assert!(got.is_err(), "modes that aren't octal should fail");
+
+		let got = toml::from_str::<Config>(indoc! {r#"
+			[targets.qux]
+			link.mode = 0o17777
+		"#});
+
+		assert!(got.is_err(), "modes beyond permissions should fail");
+	}
+
+	#[test]
+	fn deserialize_config_with_os_overrides() {
+		let got: Config = toml::from_str(indoc! {r#"
+			[targets.foo]
+			link.name = "foo"
+
+			[targets.foo.linux]
+			link.base_dir = ".config"
+
+			[targets.foo.macos]
+			link.base_dir = "Library/Application Support"
+			tags.any_of = ["bar"]
+		"#})
+		.unwrap();
+
+		assert_eq!(
+			got,
+			Config {
+				targets: Some(TargetMap::from([(
+					"foo".into(),
+					Target {
+						link: Some(Link {
+							name: Some("foo".into()),
+							base_dir: None,
+							mode: None,
+							reflink: None,
+							copy_mode: None,
+						}),
+						linux: Some(TargetOverride {
+							link: Some(Link {
+								name: None,
+								base_dir: Some(".config".into()),
+								mode: None,
+								reflink: None,
+								copy_mode: None,
+							}),
+							tags: None,
+						}),
+						macos: Some(TargetOverride {
+							link: Some(Link {
+								name: None,
+								base_dir: Some("Library/Application Support".into()),
+								mode: None,
+								reflink: None,
+								copy_mode: None,
+							}),
+							tags: Some(Tags {
+								all_of: None,
+								any_of: Some(TagSet::from(["bar".into()])),
+							}),
+						}),
+						..Target::default()
+					},
+				)])),
+				..Config::default()
+			}
+		);
+	}
+
+	#[test]
+	fn deserialize_config_with_printer_options() {
+		let got: Config = toml::from_str(indoc! {r#"
+			[printer]
+			icons = true
+			nerd_font = true
+		"#})
+		.unwrap();
+
+		assert_eq!(
+			got,
+			Config {
+				printer: Some(PrinterConfig {
+					icons: Some(true),
+					nerd_font: Some(true),
+				}),
+				..Config::default()
+			}
+		);
+	}
+
+	#[test]
+	fn deserialize_config_with_policy() {
+		let got: Config = toml::from_str(indoc! {r#"
+			[policy]
+			failures = ["Conflict", "Obstructed"]
+			create_dirs_allowed_under = ["~"]
+			replace_identical = true
+			max_compare_size = 1048576
+			compare_budget = 67108864
+		"#})
+		.unwrap();
+
+		assert_eq!(
+			got,
+			Config {
+				policy: Some(Policy {
+					failures: Some(Vec::from([Status::Conflict, Status::Obstructed])),
+					create_dirs_allowed_under: Some(Vec::from(["~".into()])),
+					replace_identical: Some(true),
+					max_compare_size: Some(1048576),
+					compare_budget: Some(67108864),
+				}),
+				..Config::default()
+			}
+		);
+	}
+
+	#[test]
+	fn deserialize_config_with_renames() {
+		let got: Config = toml::from_str(indoc! {r#"
+			[renames]
+			nvim = "config/nvim"
+			"zsh/zshrc" = "zshrc"
+		"#})
+		.unwrap();
+
+		assert_eq!(
+			got,
+			Config {
+				renames: Some(BTreeMap::from([
+					("nvim".into(), "config/nvim".into()),
+					("zsh/zshrc".into(), "zshrc".into()),
+				])),
+				..Config::default()
+			}
+		);
+	}
+
+	#[test]
+	fn deserialize_config_with_remap() {
+		let got: Config = toml::from_str(indoc! {r#"
+			[remap]
+			"~/.config" = "~/.local/config"
+
+			[remap.macos]
+			"~/.config" = "~/Library/Application Support"
+		"#})
+		.unwrap();
+
+		assert_eq!(
+			got,
+			Config {
+				remap: Some(BTreeMap::from([
+					(
+						"~/.config".into(),
+						RemapEntry::Prefix("~/.local/config".into()),
+					),
+					(
+						"macos".into(),
+						RemapEntry::Tagged(Remap::from([(
+							"~/.config".into(),
+							"~/Library/Application Support".into(),
+						)])),
+					),
+				])),
+				..Config::default()
+			}
+		);
+	}
+
+	#[test]
+	fn deserialize_config_with_nested_targets() {
+		let got: Config = toml::from_str(indoc! {r#"
+			[targets.zsh]
+
+			[targets.config.nvim."init.lua"]
+			link.name = "init.lua"
+
+			[targets.config.git.config]
+			tags.all_of = ["git"]
+
+			[targets.config.git.ignore]
+		"#})
+		.unwrap();
+
+		assert_eq!(
+			got,
+			Config {
+				targets: Some(TargetMap::from([
+					("zsh".into(), Target::default()),
+					(
+						"config/nvim/init.lua".into(),
+						Target {
+							link: Some(Link {
+								base_dir: None,
+								name: Some("init.lua".into()),
+								mode: None,
+								reflink: None,
+								copy_mode: None,
+							}),
+							..Target::default()
+						},
+					),
+					(
+						"config/git/config".into(),
+						Target {
+							tags: Some(Tags {
+								all_of: Some(TagSet::from(["git".into()])),
+								any_of: None,
+							}),
+							..Target::default()
 						},
 					),
+					("config/git/ignore".into(), Target::default()),
 				])),
+				..Config::default()
 			}
 		);
+
+		let got = toml::from_str::<Config>(indoc! {r#"
+			[targets.config]
+			nvim.link.name = "init.lua"
+			description = "Mixed"
+		"#});
+
+		assert!(got.is_err(), "mixed fields and nested targets should fail");
+	}
+
+	#[test]
+	fn find_target_lines() {
+		let got = target_lines(indoc! {r#"
+			base_dir = "test"
+
+			[targets.foo]
+			link.name = "FOO"
+
+			[ targets . "bar/baz" ] # comment
+			[targets.config.nvim.'init.lua']
+			[targets.config.zsh]
+			"zshrc.local" = { seed = true }
+			zshenv.seed = true
+			[targets.qux]
+			[[targets.qux.tags.any_of]]
+			[tags]
+		"#});
+
+		assert_eq!(
+			got,
+			HashMap::from([
+				("foo".into(), 3),
+				("bar/baz".into(), 6),
+				("config/nvim/init.lua".into(), 7),
+				("config/zsh/zshrc.local".into(), 9),
+				("config/zsh/zshenv".into(), 10),
+				("qux".into(), 11),
+			])
+		);
+	}
+
+	#[test]
+	fn merge_overlay() {
+		let mut document: Value = toml::from_str(indoc! {r#"
+			tags = ["foo"]
+			base_dir = "test"
+
+			[targets.foo]
+			link.name = "FOO"
+			tags.all_of = ["foo"]
+		"#})
+		.unwrap();
+		let overlay: Value = toml::from_str(indoc! {r#"
+			tags = ["bar"]
+
+			[targets.foo]
+			link.base_dir = "elsewhere"
+
+			[targets.bar]
+		"#})
+		.unwrap();
+
+		merge(&mut document, overlay);
+
+		assert_eq!(
+			document,
+			toml::from_str(indoc! {r#"
+				tags = ["bar"]
+				base_dir = "test"
+
+				[targets.foo]
+				link.name = "FOO"
+				link.base_dir = "elsewhere"
+				tags.all_of = ["foo"]
+
+				[targets.bar]
+			"#})
+			.unwrap(),
+		);
 	}
 }
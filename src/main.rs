@@ -1,42 +1,85 @@
 use std::{
-	env,
-	io::{self, Read},
+	env, fs,
+	io::{self, IsTerminal, Read},
+	process::ExitCode,
 };
 
-use cli::Park;
+use cli::{Command, Park};
 
 use anyhow::Result;
 use clap::Parser;
 use run::Env;
+use terminal_size::{terminal_size, Height, Width};
 
+mod cache;
 mod cli;
 mod config;
+mod manifest;
 mod parser;
 mod printer;
 mod run;
+mod scenario;
+mod schema;
+mod state;
+mod vars;
 
 // TODO: Test CLI interactions.
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
 	let args = Park::parse();
 
 	let mut input = String::new();
 
-	let stdin = io::stdin();
-	let mut handle = stdin.lock();
-	handle.read_to_string(&mut input)?;
+	// Completion scripts and the schema don't depend on any configuration, and configurations
+	// read from files or Git don't come from standard input.
+	if !matches!(
+		args.command,
+		Some(Command::Completions(_) | Command::Schema(_))
+	) && args.config.is_none()
+		&& args.config_ref.is_none()
+	{
+		let stdin = io::stdin();
+		let mut handle = stdin.lock();
+		handle.read_to_string(&mut input)?;
+	}
 
 	let stdout = io::stdout();
 	let handle = stdout.lock();
 
-	run::run(
+	let stderr = io::stderr();
+	let err_handle = stderr.lock();
+
+	let size = terminal_size();
+
+	let exit_code = run::run(
 		Env {
-			colored: env::var_os("NO_COLOR").is_none(),
+			colored: run::use_colors(args.color, stdout.is_terminal(), |key| env::var_os(key)),
 			home: env::var_os("HOME"),
+			auto_tags: {
+				let mut tags = run::environment_tags(
+					|key| env::var_os(key),
+					|path| fs::read_to_string(path).ok(),
+					|path| path.symlink_metadata().is_ok(),
+				);
+				tags.insert(env::consts::OS.into());
+
+				tags
+			},
+			state_dir: state::default_dir(|key| env::var_os(key)),
+			vars_path: vars::default_path(|key| env::var_os(key)),
+			builtin_vars: vars::builtin(|key| env::var_os(key)),
+			umask: run::current_umask(),
+			width: size.map(|(Width(width), _)| width.into()),
+			height: size.map(|(_, Height(height))| height.into()),
+			pager: run::find_pager(stdout.is_terminal(), |key| env::var_os(key)),
+			hostname: run::current_hostname(),
+			terminal: run::find_terminal(),
+			tag_prefix: run::tag_prefix(|key| env::var_os(key)),
 		},
 		&input,
 		handle,
+		err_handle,
 		args,
 	)?;
 
-	Ok(())
+	Ok(exit_code)
 }
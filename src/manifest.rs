@@ -0,0 +1,61 @@
+use std::{
+	collections::BTreeMap,
+	fs::File,
+	io::{Error as IoError, Read},
+	path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::state::StateStore;
+
+/// Name of the manifest file within the state directory.
+const FILE_NAME: &str = "manifest.toml";
+
+/// Record of links created by Park, mapping each link path to the file it points to.
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Manifest {
+	pub links: BTreeMap<PathBuf, PathBuf>,
+	/// Checksums of target files as of when their links were created, keyed by link path.
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub checksums: BTreeMap<PathBuf, String>,
+	/// Checksums of copies as of when they were written, keyed by link path, since copies like
+	/// seeds and secrets needn't have the content of their targets.
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub copies: BTreeMap<PathBuf, String>,
+}
+
+impl Manifest {
+	/// Reads the manifest from the state directory. A missing file results in an empty manifest,
+	/// while one that doesn't parse is an error.
+	pub fn load(state: &StateStore) -> Result<Self, IoError> {
+		Ok(state.load(FILE_NAME)?.unwrap_or_default())
+	}
+
+	/// Writes the manifest to the state directory, creating it if needed.
+	pub fn store(&self, state: &StateStore) -> Result<(), IoError> {
+		state.store(FILE_NAME, self)
+	}
+}
+
+/// Computes the 64-bit FNV-1a hash of a file's content, which tells whether it changed, even
+/// though it doesn't guard against collisions made on purpose.
+pub fn checksum(path: &Path) -> Result<String, IoError> {
+	let mut file = File::open(path)?;
+	let mut buffer = [0u8; 8192];
+	let mut hash: u64 = 0xcbf29ce484222325;
+
+	loop {
+		let read = file.read(&mut buffer)?;
+		if read == 0 {
+			break;
+		}
+
+		for byte in &buffer[..read] {
+			hash ^= u64::from(*byte);
+			hash = hash.wrapping_mul(0x100000001b3);
+		}
+	}
+
+	Ok(format!("{:016x}", hash))
+}
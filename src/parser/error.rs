@@ -1,17 +1,21 @@
 use std::{
+	collections::BTreeSet,
 	error::Error as StdError,
 	fmt::{Display, Formatter},
 	io::{Error as IoError, ErrorKind as IoErrorKind},
 	path::PathBuf,
 };
 
-use super::tree::Problems;
+use super::tree::{Descriptions, Hints, Origins, Problems, Remediation};
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, PartialEq)]
 pub enum Error {
 	InternalError(PathBuf),
 	IoError(IoErrorKind),
-	BadFiles(Problems),
+	BadFiles(Box<Problems>, Box<Descriptions>, Box<Origins>, Box<Hints>),
+	PermissionDenied(Vec<PathBuf>),
+	EscalationFailed(PathBuf),
 }
 
 impl Display for Error {
@@ -21,21 +25,62 @@ impl Display for Error {
 				write!(f, "there's an error associated with {:?}", link_path)
 			}
 			Self::IoError(io_err) => IoError::new(*io_err, "unexpected IO error").fmt(f),
-			Self::BadFiles(problems) => {
+			Self::BadFiles(problems, descriptions, origins, hints) => {
 				let len = problems.len();
 
 				writeln!(f, "found {} problematic target(s):", len)?;
 
 				for (idx, (path, status)) in problems.iter().enumerate() {
-					write!(f, "\t- {:?} at {:?}", status, path)?;
+					write!(f, "\t- ")?;
+
+					if let Some(origin) = origins.get(path) {
+						write!(f, "{}: ", origin)?;
+					}
+
+					write!(f, "{:?} at {:?}", status, path)?;
+
+					if let Some(description) = descriptions.get(path) {
+						write!(f, " ({})", description)?;
+					}
+
+					if let Some(hint) = hints.get(path) {
+						write!(f, "\n\t  hint: {}", hint)?;
+					}
 
 					if idx != len - 1 {
 						writeln!(f)?;
 					}
 				}
 
+				let remediations: BTreeSet<Remediation> =
+					problems.values().filter_map(Remediation::of).collect();
+
+				for remediation in remediations {
+					write!(f, "\nhint: {}", remediation)?;
+				}
+
 				Ok(())
 			}
+			Self::PermissionDenied(link_paths) => {
+				writeln!(
+					f,
+					"permission denied for {} link(s), use --escalate to retry with sudo:",
+					link_paths.len()
+				)?;
+
+				for (idx, link_path) in link_paths.iter().enumerate() {
+					write!(f, "\t- {:?}", link_path)?;
+
+					if idx != link_paths.len() - 1 {
+						writeln!(f)?;
+					}
+				}
+
+				Ok(())
+			}
+			Self::EscalationFailed(link_path) => {
+				write!(f, "could not link {:?} with sudo", link_path)
+			}
 		}
 	}
 }
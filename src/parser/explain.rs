@@ -0,0 +1,229 @@
+use std::{
+	fmt::{Display, Error as FmtError, Formatter, Result as FmtResult},
+	io::Write,
+	path::Path,
+	str,
+};
+
+use tabwriter::TabWriter;
+
+use crate::config::Target;
+
+use super::{
+	node::{Error as NodeError, Leaf},
+	tree::{self, Resolver},
+};
+
+/// A single decision taken while resolving a target.
+#[derive(Debug, PartialEq)]
+pub struct Step {
+	pub name: &'static str,
+	pub detail: String,
+}
+
+impl Step {
+	pub fn new<S>(name: &'static str, detail: S) -> Self
+	where
+		S: Into<String>,
+	{
+		Self {
+			name,
+			detail: detail.into(),
+		}
+	}
+}
+
+/// Step by step description of how a target gets resolved from a configuration.
+#[derive(Debug, Default, PartialEq)]
+pub struct Explanation {
+	pub steps: Vec<Step>,
+	/// The resulting leaf, unless the target is left out by its tags.
+	pub leaf: Option<Leaf>,
+}
+
+impl Explanation {
+	/// Explains how a target is resolved, the same way `Tree::parse` does it.
+	pub fn new(
+		mut resolver: Resolver,
+		target_path: &Path,
+		target: Target,
+	) -> Result<Self, NodeError> {
+		let mut explanation = Explanation::default();
+		explanation.steps.push(Step::new(
+			"active tags",
+			tree::format_tags(&resolver.runtime_tags),
+		));
+		explanation.leaf = resolver.resolve(target_path, target, &mut explanation.steps)?;
+
+		Ok(explanation)
+	}
+}
+
+impl Display for Explanation {
+	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+		let mut tab_writer = TabWriter::new(Vec::new()).padding(1);
+
+		for Step { name, detail } in &self.steps {
+			if writeln!(tab_writer, "{}:\t{}", name, detail).is_err() {
+				return Err(FmtError);
+			}
+		}
+
+		match tab_writer.into_inner() {
+			Err(_) => Err(FmtError),
+			Ok(w) => write!(f, "{}", str::from_utf8(&w).unwrap()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use indoc::indoc;
+	use pretty_assertions::assert_eq;
+
+	use crate::config::{Config, Link, TagSet, Tags, TargetMap, TargetOverride};
+
+	use super::*;
+
+	#[test]
+	fn explain() {
+		struct Test<'a> {
+			description: &'a str,
+			input: (&'a str, TagSet),
+			output: Result<&'a str, NodeError>,
+		}
+
+		let config = Config {
+			base_dir: Some("/home/park".into()),
+			tags: Some(TagSet::from(["desktop".into()])),
+			targets: Some(TargetMap::from([
+				(
+					"nvim".into(),
+					Target {
+						tags: Some(Tags {
+							all_of: Some(TagSet::from(["desktop".into(), "editor".into()])),
+							any_of: None,
+						}),
+						..Target::default()
+					},
+				),
+				(
+					"sway/config".into(),
+					Target {
+						link: Some(Link {
+							base_dir: Some("/home/park/.config/sway".into()),
+							name: None,
+							mode: None,
+							reflink: None,
+							copy_mode: None,
+						}),
+						tags: Some(Tags {
+							all_of: None,
+							any_of: Some(TagSet::from(["wayland".into()])),
+						}),
+						linux: Some(TargetOverride {
+							link: Some(Link {
+								base_dir: None,
+								name: Some("sway.conf".into()),
+								mode: None,
+								reflink: None,
+								copy_mode: None,
+							}),
+							tags: None,
+						}),
+						..Target::default()
+					},
+				),
+				(
+					"zsh/zshrc".into(),
+					Target {
+						link: Some(Link {
+							name: Some("../.zshrc".into()),
+							..Link::default()
+						}),
+						..Target::default()
+					},
+				),
+				(
+					"zsh/zshenv".into(),
+					Target {
+						link: Some(Link {
+							base_dir: Some("{zdotdir}".into()),
+							..Link::default()
+						}),
+						..Target::default()
+					},
+				),
+			])),
+			..Config::default()
+		};
+
+		let test_cases = Vec::from([
+			Test {
+				description: "target left out by its tags",
+				input: ("nvim", TagSet::new()),
+				output: Ok(indoc! {r#"
+					active tags: ["desktop"]
+					all_of:      ["desktop", "editor"] failed, missing ["editor"]
+				"#}),
+			},
+			Test {
+				description: "target with an override",
+				input: (
+					"sway/config",
+					TagSet::from(["linux".into(), "wayland".into()]),
+				),
+				output: Ok(indoc! {r#"
+					active tags: ["desktop", "linux", "wayland"]
+					override:    linux applied
+					any_of:      ["wayland"] matched
+					base_dir:    "/home/park/.config/sway", set by the target
+					link name:   "sway.conf", set by the target
+					link path:   "/home/park/.config/sway/sway.conf"
+				"#}),
+			},
+			Test {
+				description: "target without an override",
+				input: ("sway/config", TagSet::from(["wayland".into()])),
+				output: Ok(indoc! {r#"
+					active tags: ["desktop", "wayland"]
+					override:    linux ignored, tag is not active
+					any_of:      ["wayland"] matched
+					base_dir:    "/home/park/.config/sway", set by the target
+					link name:   "config", taken from the target
+					link path:   "/home/park/.config/sway/config"
+				"#}),
+			},
+			Test {
+				description: "target with an unsafe link name",
+				input: ("zsh/zshrc", TagSet::new()),
+				output: Err(NodeError::UnsafeLinkName(
+					"zsh/zshrc".into(),
+					"../.zshrc".into(),
+				)),
+			},
+			Test {
+				description: "target with an unknown variable",
+				input: ("zsh/zshenv", TagSet::new()),
+				output: Err(NodeError::UnknownVar("zsh/zshenv".into(), "zdotdir".into())),
+			},
+		]);
+
+		for case in test_cases {
+			let (target_path, runtime_tags) = case.input;
+			let target = config.targets.as_ref().unwrap()[Path::new(target_path)].clone();
+			let got = Explanation::new(
+				Resolver::new(&config, runtime_tags),
+				Path::new(target_path),
+				target,
+			);
+
+			assert_eq!(
+				got.map(|explanation| explanation.to_string()),
+				case.output.map(String::from),
+				"bad explanation for {:?}",
+				case.description
+			);
+		}
+	}
+}
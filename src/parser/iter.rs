@@ -1,6 +1,6 @@
 use std::path::{Path, PathBuf};
 
-use super::node::Node;
+use super::node::{Leaf, Node};
 
 /// Some metadata for a node inside a tree.
 #[derive(Debug, PartialEq)]
@@ -16,7 +16,7 @@ pub struct NodeMetadata {
 pub struct Element {
 	pub metadata: NodeMetadata,
 	pub target_path: PathBuf,
-	pub link_path: Option<PathBuf>,
+	pub leaf: Option<Leaf>,
 }
 
 /// Iterator that visits nodes using preorder traversal.
@@ -75,7 +75,7 @@ impl<'a> Iterator for Iter<'a> {
 		Some(Element {
 			metadata: info,
 			target_path: self.path_stack.iter().collect(),
-			link_path: node.get_link_path().map(PathBuf::from),
+			leaf: node.get_leaf().cloned(),
 		})
 	}
 }
@@ -100,13 +100,19 @@ mod tests {
 		let root = Node::Branch(Edges::from([
 			(
 				"baz".into(),
-				Node::Branch(Edges::from([("qux".into(), Node::Leaf("test/qux".into()))])),
+				Node::Branch(Edges::from([(
+					"qux".into(),
+					Node::Leaf(Box::new("test/qux".into())),
+				)])),
 			),
 			(
 				"foo".into(),
-				Node::Branch(Edges::from([("bar".into(), Node::Leaf("test/bar".into()))])),
+				Node::Branch(Edges::from([(
+					"bar".into(),
+					Node::Leaf(Box::new("test/bar".into())),
+				)])),
 			),
-			("test".into(), Node::Leaf("something/else".into())),
+			("test".into(), Node::Leaf(Box::new("something/else".into()))),
 		]));
 		let mut iter = Iter {
 			stack: Vec::from([State {
@@ -128,7 +134,7 @@ mod tests {
 					last_sibling: false
 				},
 				target_path: "".into(),
-				link_path: None,
+				leaf: None,
 			}),
 		);
 		assert_eq!(
@@ -139,7 +145,7 @@ mod tests {
 					last_sibling: false
 				},
 				target_path: "baz".into(),
-				link_path: None,
+				leaf: None,
 			}),
 		);
 		assert_eq!(
@@ -150,7 +156,7 @@ mod tests {
 					last_sibling: true
 				},
 				target_path: "baz/qux".into(),
-				link_path: Some("test/qux".into()),
+				leaf: Some("test/qux".into()),
 			}),
 		);
 		assert_eq!(
@@ -161,7 +167,7 @@ mod tests {
 					last_sibling: false
 				},
 				target_path: "foo".into(),
-				link_path: None,
+				leaf: None,
 			}),
 		);
 		assert_eq!(
@@ -172,7 +178,7 @@ mod tests {
 					last_sibling: true
 				},
 				target_path: "foo/bar".into(),
-				link_path: Some("test/bar".into()),
+				leaf: Some("test/bar".into()),
 			}),
 		);
 		assert_eq!(
@@ -183,7 +189,7 @@ mod tests {
 					last_sibling: true
 				},
 				target_path: "test".into(),
-				link_path: Some("something/else".into()),
+				leaf: Some("something/else".into()),
 			}),
 		);
 		assert_eq!(iter.next(), None);
@@ -1,4 +1,6 @@
-mod error;
+pub mod error;
+pub mod explain;
 pub mod iter;
 pub mod node;
 pub mod tree;
+pub mod warning;
@@ -1,13 +1,94 @@
 use std::{
-	ffi::OsStr,
+	ffi::{CString, OsStr},
+	fmt::{Display, Formatter, Result as FmtResult},
 	path::{Path, PathBuf},
+	str::FromStr,
 };
 
+use serde::{de::Error as DeError, Deserialize, Deserializer, Serialize, Serializer};
 use thiserror::Error;
 
 use super::iter::{Element, Iter};
 
+/// How a target deals with files in the way of its link, overriding the link options.
+#[derive(Clone, Copy, Debug, Deserialize, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+	/// The file in the way is renamed to a backup next to it before linking.
+	Backup,
+	/// The file in the way is removed, even without --replace.
+	Replace,
+	/// The target is left alone without blocking the others, even with --replace.
+	Skip,
+	/// Linking is aborted, even with --replace.
+	Fail,
+}
+
+impl OnConflict {
+	/// Whether a status is one of a file in the way of a link, which this applies to.
+	pub fn applies_to(status: &Status) -> bool {
+		matches!(status, Status::Mismatch | Status::Conflict)
+	}
+}
+
+/// User and group that links, and the directories created for them, are handed over to.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq)]
+pub struct Owner {
+	pub uid: Option<u32>,
+	pub gid: Option<u32>,
+}
+
+impl Owner {
+	/// Resolves an owner written as "user:group", where either part may be left out, like in
+	/// "user" or ":group", and be a numeric ID. The name that doesn't exist is returned as an
+	/// error.
+	pub fn resolve(owner: &str) -> Result<Self, String> {
+		let (user, group) = owner.split_once(':').unwrap_or((owner, ""));
+		let resolve = |name: &str, lookup: fn(&CString) -> Option<u32>| match name {
+			"" => Ok(None),
+			name => name
+				.parse()
+				.ok()
+				.or_else(|| lookup(&CString::new(name).ok()?))
+				.map(Some)
+				.ok_or_else(|| name.to_string()),
+		};
+
+		Ok(Self {
+			uid: resolve(user, |name| {
+				// SAFETY: the name is NUL-terminated, and the entry is read right away.
+				let entry = unsafe { libc::getpwnam(name.as_ptr()) };
+
+				// SAFETY: the entry was just checked not to be null.
+				(!entry.is_null()).then(|| unsafe { (*entry).pw_uid })
+			})?,
+			gid: resolve(group, |name| {
+				// SAFETY: the name is NUL-terminated, and the entry is read right away.
+				let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+
+				// SAFETY: the entry was just checked not to be null.
+				(!entry.is_null()).then(|| unsafe { (*entry).gr_gid })
+			})?,
+		})
+	}
+}
+
+impl Display for Owner {
+	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+		// A trailing colon would make chown pick the user's login group.
+		match (self.uid, self.gid) {
+			(Some(uid), Some(gid)) => write!(f, "{}:{}", uid, gid),
+			(Some(uid), None) => write!(f, "{}", uid),
+			(None, Some(gid)) => write!(f, ":{}", gid),
+			(None, None) => Ok(()),
+		}
+	}
+}
+
 /// Possible states a link node can be in.
+///
+/// Statuses are serialized as their names in kebab case, like "read-only", which also are
+/// what parsing them expects. Neither depend on how variants are named.
 #[derive(Clone, Debug, PartialEq)]
 pub enum Status {
 	/// Unknown state, probably because the node wasn't analyzed.
@@ -18,12 +99,118 @@ pub enum Status {
 	Done,
 	/// Link exists, but points to a different target.
 	Mismatch,
+	/// Link exists and points to a different target, but it wasn't created by Park.
+	Foreign,
 	/// Target can be created but the parent directory will need to be created as well.
 	Unparented,
 	/// Another file already exists in the link path.
 	Conflict,
 	/// The file supposed to serve as the link directory is not a directory.
 	Obstructed,
+	/// The file system the link would be created in doesn't support symlinks.
+	Unsupported,
+	/// Nothing can be created in the link's directory, since it's read-only or immutable.
+	ReadOnly,
+	/// An ancestor of the link path is a symlink that loops, so it can't be resolved.
+	Looping,
+	/// Link exists and points to where the target was before being renamed.
+	Renamed,
+	/// A regular file with the same content as the target exists in the link path, which can
+	/// be replaced without losing anything.
+	Identical,
+	/// Link was created by Park for a target that is no longer in the configuration.
+	Orphaned,
+}
+
+impl Status {
+	/// Every status, in the order they're declared.
+	pub const ALL: [Self; 14] = [
+		Self::Unknown,
+		Self::Ready,
+		Self::Done,
+		Self::Mismatch,
+		Self::Foreign,
+		Self::Unparented,
+		Self::Conflict,
+		Self::Obstructed,
+		Self::Unsupported,
+		Self::ReadOnly,
+		Self::Looping,
+		Self::Renamed,
+		Self::Identical,
+		Self::Orphaned,
+	];
+
+	/// Returns the stable name of the status.
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Unknown => "unknown",
+			Self::Ready => "ready",
+			Self::Done => "done",
+			Self::Mismatch => "mismatch",
+			Self::Foreign => "foreign",
+			Self::Unparented => "unparented",
+			Self::Conflict => "conflict",
+			Self::Obstructed => "obstructed",
+			Self::Unsupported => "unsupported",
+			Self::ReadOnly => "read-only",
+			Self::Looping => "looping",
+			Self::Renamed => "renamed",
+			Self::Identical => "identical",
+			Self::Orphaned => "orphaned",
+		}
+	}
+}
+
+/// Name that doesn't belong to any status.
+#[derive(Debug, Error, PartialEq)]
+#[error("unknown status {0:?}, expected one of {}", status_names())]
+pub struct UnknownStatus(pub String);
+
+fn status_names() -> String {
+	Status::ALL.map(|status| status.name()).join(", ")
+}
+
+impl FromStr for Status {
+	type Err = UnknownStatus;
+
+	/// Parses a status from its name. Case and separators are ignored, so that names as
+	/// printed in previews, like "READ-ONLY", and the ones from before names were stable, like
+	/// "ReadOnly", are accepted too.
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let normalize = |name: &str| {
+			name.chars()
+				.filter(|c| !matches!(c, '-' | '_'))
+				.collect::<String>()
+				.to_lowercase()
+		};
+		let name = normalize(s);
+
+		Self::ALL
+			.into_iter()
+			.find(|status| normalize(status.name()) == name)
+			.ok_or_else(|| UnknownStatus(s.into()))
+	}
+}
+
+impl Serialize for Status {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		serializer.serialize_str(self.name())
+	}
+}
+
+impl<'de> Deserialize<'de> for Status {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		String::deserialize(deserializer)?
+			.parse()
+			.map_err(D::Error::custom)
+	}
 }
 
 #[derive(Debug, Error, PartialEq)]
@@ -32,8 +219,35 @@ pub enum Error {
 	NotABranch(PathBuf, PathBuf),
 	#[error("node for link {1:?} at segment {0:?} already exists as a leaf")]
 	LeafExists(PathBuf, PathBuf),
+	#[error("target {1:?} cannot be added because it overlaps with target {0:?}")]
+	OverlappingTargets(PathBuf, PathBuf),
+	#[error("link of target {1:?} is within {2:?}, which is the link of target {0:?}")]
+	OverlappingLinks(PathBuf, PathBuf, PathBuf),
+	#[error("link of target {0:?} uses the unknown variable {1:?}")]
+	UnknownVar(PathBuf, String),
+	#[error("script {1:?} of target {0:?} could not be run: {2}")]
+	FailedScript(PathBuf, PathBuf, String),
 	#[error("cannot add empty link path")]
 	EmptySegment,
+	#[error("link name {1:?} of target {0:?} is not a plain file name, so it escapes its base directory")]
+	UnsafeLinkName(PathBuf, PathBuf),
+	#[error("owner of target {0:?} has the unknown user or group {1:?}")]
+	UnknownOwner(PathBuf, String),
+}
+
+impl Error {
+	/// Returns the name of the target the error is about, if it's known.
+	pub fn target_path(&self) -> Option<&Path> {
+		match self {
+			Self::UnsafeLinkName(target_path, _)
+			| Self::UnknownVar(target_path, _)
+			| Self::UnknownOwner(target_path, _)
+			| Self::FailedScript(target_path, ..)
+			| Self::OverlappingTargets(_, target_path)
+			| Self::OverlappingLinks(_, target_path, _) => Some(target_path),
+			Self::NotABranch(..) | Self::LeafExists(..) | Self::EmptySegment => None,
+		}
+	}
 }
 
 /// A vector of edges.
@@ -43,10 +257,89 @@ pub type Edges = Vec<Edge>;
 pub type Edge = (PathBuf, Node);
 
 /// Node for a recursive tree that holds symlink paths. It is either a branch or a leaf.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Hash, PartialEq)]
 pub enum Node {
 	Branch(Edges),
-	Leaf(PathBuf),
+	Leaf(Box<Leaf>),
+}
+
+/// Everything needed in order to link a single target.
+#[derive(Clone, Debug, Default, Hash, PartialEq)]
+pub struct Leaf {
+	/// Path of the resulting link.
+	pub link_path: PathBuf,
+	/// Whether the target is copied once instead of symlinked.
+	pub seed: bool,
+	/// Path of the target when it's not the one implied by its position in the tree.
+	pub source: Option<PathBuf>,
+	/// Directory the target lives in when it's not the tree's work directory.
+	pub work_dir: Option<PathBuf>,
+	/// Human-readable description of the target.
+	pub description: Option<String>,
+	/// Whether the target is skipped, rather than aborting the linking step, upon problems.
+	pub optional: bool,
+	/// Permissions set on the symlink itself after creating it.
+	pub link_mode: Option<u32>,
+	/// Whether the target is cloned instead of symlinked.
+	pub reflink: bool,
+	/// Permissions set on copies and clones of the target instead of the target's.
+	pub copy_mode: Option<u32>,
+	/// How files in the way of the link are dealt with, unless the link options decide.
+	pub on_conflict: Option<OnConflict>,
+	/// Note shown along with problems of the target.
+	pub problem_hint: Option<String>,
+	/// Who the link is handed over to after creating it, instead of whoever runs Park.
+	pub owner: Option<Owner>,
+	/// Owner as configured, like "user:group", which doesn't depend on the IDs of a machine.
+	pub owner_name: Option<String>,
+	/// Section the target is listed under in previews.
+	pub group: Option<String>,
+	/// Command whose output is copied to the link path instead of the target.
+	pub secret: Option<String>,
+	/// Size of the largest files compared with the target, unless the link options decide.
+	pub max_compare_size: Option<u64>,
+	/// Tags that let the target in.
+	pub gate: Gate,
+}
+
+/// Tags a target was let in by, which tell why it's managed in a machine.
+#[derive(Clone, Debug, Default, Hash, PartialEq)]
+pub struct Gate {
+	/// Tags that all had to be active.
+	pub all_of: Vec<String>,
+	/// Tags that matched among the ones of which any had to be active.
+	pub any_of: Vec<String>,
+}
+
+impl Display for Gate {
+	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+		let constraints: Vec<String> = [("all_of", &self.all_of), ("any_of", &self.any_of)]
+			.into_iter()
+			.filter(|(_, tags)| !tags.is_empty())
+			.map(|(name, tags)| {
+				let tags: Vec<String> = tags.iter().map(|tag| format!("+{}", tag)).collect();
+
+				format!("{} {}", name, tags.join(" "))
+			})
+			.collect();
+
+		write!(f, "{}", constraints.join(", "))
+	}
+}
+
+impl From<PathBuf> for Leaf {
+	fn from(link_path: PathBuf) -> Self {
+		Self {
+			link_path,
+			..Self::default()
+		}
+	}
+}
+
+impl From<&str> for Leaf {
+	fn from(link_path: &str) -> Self {
+		PathBuf::from(link_path).into()
+	}
 }
 
 impl Default for Node {
@@ -57,7 +350,7 @@ impl Default for Node {
 
 impl Node {
 	/// Adds new paths to the node. Each segment becomes a new node.
-	pub fn add(&mut self, segments: Vec<&OsStr>, link_path: PathBuf) -> Result<(), Error> {
+	pub fn add(&mut self, segments: Vec<&OsStr>, leaf: Leaf) -> Result<(), Error> {
 		let segments = segments.split_first();
 		if segments.is_none() {
 			return Err(Error::EmptySegment);
@@ -74,22 +367,22 @@ impl Node {
 
 				if is_leaf {
 					if current_slot.is_some() {
-						return Err(Error::LeafExists(key, link_path));
+						return Err(Error::LeafExists(key, leaf.link_path));
 					}
 
-					edges.push((key, Self::Leaf(link_path)));
+					edges.push((key, Self::Leaf(Box::new(leaf))));
 				} else if let Some(edge) = current_slot {
 					let (_, ref mut branch_node) = edge;
 
-					branch_node.add(segments.into(), link_path)?;
+					branch_node.add(segments.into(), leaf)?;
 				} else {
 					let mut branch_node = Self::Branch(Edges::new());
-					branch_node.add(segments.into(), link_path)?;
+					branch_node.add(segments.into(), leaf)?;
 					edges.push((key, branch_node));
 				}
 				Ok(())
 			}
-			Self::Leaf { .. } => Err(Error::NotABranch(key, link_path)),
+			Self::Leaf { .. } => Err(Error::NotABranch(key, leaf.link_path)),
 		}
 	}
 
@@ -101,11 +394,43 @@ impl Node {
 		}
 	}
 
-	/// Returns the node's link path if it's a leaf, otherwise returns None.
-	pub fn get_link_path(&self) -> Option<&Path> {
+	/// Removes leaves whose target paths, which are joined from `prefix`, are not kept by `keep`,
+	/// along with branches left empty. Removed leaves are returned with their target paths.
+	pub fn retain<F>(&mut self, prefix: &Path, keep: &F) -> Vec<(PathBuf, Leaf)>
+	where
+		F: Fn(&Path) -> bool,
+	{
+		let Self::Branch(edges) = self else {
+			return Vec::new();
+		};
+
+		let mut removed = Vec::new();
+		edges.retain_mut(|(key, node)| {
+			let target_path = prefix.join(key);
+
+			match node {
+				Self::Leaf(leaf) if !keep(&target_path) => {
+					removed.push((target_path, Leaf::clone(leaf)));
+
+					false
+				}
+				Self::Leaf(_) => true,
+				Self::Branch(_) => {
+					removed.extend(node.retain(&target_path, keep));
+
+					node.get_children().is_some_and(|edges| !edges.is_empty())
+				}
+			}
+		});
+
+		removed
+	}
+
+	/// Returns the node's leaf data if it's a leaf, otherwise returns None.
+	pub fn get_leaf(&self) -> Option<&Leaf> {
 		match self {
 			Self::Branch(_) => None,
-			Self::Leaf(path) => Some(path),
+			Self::Leaf(leaf) => Some(leaf),
 		}
 	}
 }
@@ -120,7 +445,6 @@ impl<'a> IntoIterator for &'a Node {
 }
 
 #[cfg(test)]
-
 mod tests {
 	use std::ffi::OsString;
 
@@ -132,7 +456,7 @@ mod tests {
 	fn test_add_nodes() {
 		struct Test<'a> {
 			description: &'a str,
-			input: (Node, Vec<&'a OsStr>, PathBuf),
+			input: (Node, Vec<&'a OsStr>, Leaf),
 			output: (Node, Result<(), Error>),
 		}
 
@@ -150,21 +474,27 @@ mod tests {
 					"test/foo".into(),
 				),
 				output: (
-					Node::Branch(Edges::from([("foo".into(), Node::Leaf("test/foo".into()))])),
+					Node::Branch(Edges::from([(
+						"foo".into(),
+						Node::Leaf(Box::new("test/foo".into())),
+					)])),
 					Ok(()),
 				),
 			},
 			Test {
 				description: "add sibling node to existing one",
 				input: (
-					Node::Branch(Edges::from([("foo".into(), Node::Leaf("test/foo".into()))])),
+					Node::Branch(Edges::from([(
+						"foo".into(),
+						Node::Leaf(Box::new("test/foo".into())),
+					)])),
 					Vec::from([&bar[..]]),
 					"yay/bar".into(),
 				),
 				output: (
 					Node::Branch(Edges::from([
-						("foo".into(), Node::Leaf("test/foo".into())),
-						("bar".into(), Node::Leaf("yay/bar".into())),
+						("foo".into(), Node::Leaf(Box::new("test/foo".into()))),
+						("bar".into(), Node::Leaf(Box::new("yay/bar".into()))),
 					])),
 					Ok(()),
 				),
@@ -179,7 +509,10 @@ mod tests {
 				output: (
 					Node::Branch(Edges::from([(
 						"foo".into(),
-						Node::Branch(Edges::from([("bar".into(), Node::Leaf("test/bar".into()))])),
+						Node::Branch(Edges::from([(
+							"bar".into(),
+							Node::Leaf(Box::new("test/bar".into())),
+						)])),
 					)])),
 					Ok(()),
 				),
@@ -189,7 +522,10 @@ mod tests {
 				input: (
 					Node::Branch(Edges::from([(
 						"foo".into(),
-						Node::Branch(Edges::from([("bar".into(), Node::Leaf("test/bar".into()))])),
+						Node::Branch(Edges::from([(
+							"bar".into(),
+							Node::Leaf(Box::new("test/bar".into())),
+						)])),
 					)])),
 					Vec::from([&foo[..], &baz[..]]),
 					"yay/baz".into(),
@@ -198,8 +534,8 @@ mod tests {
 					Node::Branch(Edges::from([(
 						"foo".into(),
 						Node::Branch(Edges::from([
-							("bar".into(), Node::Leaf("test/bar".into())),
-							("baz".into(), Node::Leaf("yay/baz".into())),
+							("bar".into(), Node::Leaf(Box::new("test/bar".into()))),
+							("baz".into(), Node::Leaf(Box::new("yay/baz".into()))),
 						])),
 					)])),
 					Ok(()),
@@ -210,7 +546,10 @@ mod tests {
 				input: (
 					Node::Branch(Edges::from([(
 						"foo".into(),
-						Node::Branch(Edges::from([("bar".into(), Node::Leaf("test/bar".into()))])),
+						Node::Branch(Edges::from([(
+							"bar".into(),
+							Node::Leaf(Box::new("test/bar".into())),
+						)])),
 					)])),
 					Vec::from([&foo[..], &bar[..]]),
 					"please/let_me_in".into(),
@@ -218,7 +557,10 @@ mod tests {
 				output: (
 					Node::Branch(Edges::from([(
 						"foo".into(),
-						Node::Branch(Edges::from([("bar".into(), Node::Leaf("test/bar".into()))])),
+						Node::Branch(Edges::from([(
+							"bar".into(),
+							Node::Leaf(Box::new("test/bar".into())),
+						)])),
 					)])),
 					Err(Error::LeafExists("bar".into(), "please/let_me_in".into())),
 				),
@@ -226,12 +568,18 @@ mod tests {
 			Test {
 				description: "add node to a leaf node",
 				input: (
-					Node::Branch(Edges::from([("foo".into(), Node::Leaf("test/foo".into()))])),
+					Node::Branch(Edges::from([(
+						"foo".into(),
+						Node::Leaf(Box::new("test/foo".into())),
+					)])),
 					Vec::from([&foo[..], &bar[..]]),
 					"please/let_me_in".into(),
 				),
 				output: (
-					Node::Branch(Edges::from([("foo".into(), Node::Leaf("test/foo".into()))])),
+					Node::Branch(Edges::from([(
+						"foo".into(),
+						Node::Leaf(Box::new("test/foo".into())),
+					)])),
 					Err(Error::NotABranch("bar".into(), "please/let_me_in".into())),
 				),
 			},
@@ -248,21 +596,21 @@ mod tests {
 				description: "nodes don't get sorted anymore",
 				input: (
 					Node::Branch(Edges::from([
-						("C".into(), Node::Leaf("1".into())),
-						("Z".into(), Node::Leaf("2".into())),
-						("B".into(), Node::Leaf("3".into())),
-						("A".into(), Node::Leaf("4".into())),
+						("C".into(), Node::Leaf(Box::new("1".into()))),
+						("Z".into(), Node::Leaf(Box::new("2".into()))),
+						("B".into(), Node::Leaf(Box::new("3".into()))),
+						("A".into(), Node::Leaf(Box::new("4".into()))),
 					])),
 					Vec::from([&capital_e[..]]),
 					"5".into(),
 				),
 				output: (
 					Node::Branch(Edges::from([
-						("C".into(), Node::Leaf("1".into())),
-						("Z".into(), Node::Leaf("2".into())),
-						("B".into(), Node::Leaf("3".into())),
-						("A".into(), Node::Leaf("4".into())),
-						("E".into(), Node::Leaf("5".into())),
+						("C".into(), Node::Leaf(Box::new("1".into()))),
+						("Z".into(), Node::Leaf(Box::new("2".into()))),
+						("B".into(), Node::Leaf(Box::new("3".into()))),
+						("A".into(), Node::Leaf(Box::new("4".into()))),
+						("E".into(), Node::Leaf(Box::new("5".into()))),
 					])),
 					Ok(()),
 				),
@@ -283,4 +631,104 @@ mod tests {
 			assert_eq!(result, want_result);
 		}
 	}
+
+	#[test]
+	fn resolve_owners() {
+		struct Test<'a> {
+			description: &'a str,
+			input: &'a str,
+			output: Result<Owner, String>,
+		}
+
+		let test_cases = Vec::from([
+			Test {
+				description: "user and group",
+				input: "root:0",
+				output: Ok(Owner {
+					uid: Some(0),
+					gid: Some(0),
+				}),
+			},
+			Test {
+				description: "only user",
+				input: "1000",
+				output: Ok(Owner {
+					uid: Some(1000),
+					gid: None,
+				}),
+			},
+			Test {
+				description: "only group",
+				input: ":1000",
+				output: Ok(Owner {
+					uid: None,
+					gid: Some(1000),
+				}),
+			},
+			Test {
+				description: "unknown user",
+				input: "no-such-park-user:0",
+				output: Err("no-such-park-user".into()),
+			},
+		]);
+
+		for case in test_cases {
+			let got = Owner::resolve(case.input);
+
+			assert_eq!(got, case.output, "bad owner for {:?}", case.description);
+
+			if let Ok(owner) = got {
+				assert_eq!(owner.to_string(), case.input.replace("root", "0"));
+			}
+		}
+	}
+
+	#[test]
+	fn parse_statuses() {
+		struct Test<'a> {
+			description: &'a str,
+			input: &'a str,
+			output: Result<Status, UnknownStatus>,
+		}
+
+		let test_cases = Vec::from([
+			Test {
+				description: "stable name",
+				input: "read-only",
+				output: Ok(Status::ReadOnly),
+			},
+			Test {
+				description: "name as printed in previews",
+				input: "READ-ONLY",
+				output: Ok(Status::ReadOnly),
+			},
+			Test {
+				description: "name from before names were stable",
+				input: "ReadOnly",
+				output: Ok(Status::ReadOnly),
+			},
+			Test {
+				description: "unknown name",
+				input: "broken",
+				output: Err(UnknownStatus("broken".into())),
+			},
+		]);
+
+		for case in test_cases {
+			assert_eq!(
+				case.input.parse::<Status>(),
+				case.output,
+				"bad status for {:?}",
+				case.description
+			);
+		}
+
+		for status in Status::ALL {
+			assert_eq!(status.name().parse(), Ok(status.clone()));
+			assert_eq!(
+				serde_json::to_value(&status).unwrap(),
+				serde_json::Value::from(status.name())
+			);
+		}
+	}
 }
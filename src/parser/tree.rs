@@ -1,26 +1,329 @@
 use std::{
-	collections::{BTreeMap, HashMap, HashSet},
-	env, fs,
-	io::Error as IoError,
-	os::unix::fs as unix_fs,
-	path::PathBuf,
+	cmp::Reverse,
+	collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+	env,
+	ffi::OsStr,
+	fmt::{Display, Formatter, Result as FmtResult},
+	fs::{self, File, FileType, Permissions},
+	io::{BufRead, BufReader, Error as IoError, ErrorKind as IoErrorKind, Write},
+	os::unix::fs::{self as unix_fs, OpenOptionsExt, PermissionsExt},
+	path::{Component, Path, PathBuf},
+	process::{self, Command, Stdio},
+	sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+	thread,
+	time::Duration,
 };
 
-use crate::config::{Config, TagSet, Tags, Target};
+use serde::Serialize;
+
+use crate::{
+	config::{Config, Link, RemapEntry, TagSet, Tags, Target, TargetOverride},
+	vars::{self, Vars},
+};
 
 use super::{
 	error::Error,
+	explain::Step,
 	iter::Element as IterElement,
-	node::{Error as NodeError, Node, Status},
+	node::{Error as NodeError, Gate, Leaf, Node, OnConflict, Owner, Status},
+	warning::Warning,
 };
 
 pub type Statuses = HashMap<PathBuf, Status>;
 pub type Problems = BTreeMap<PathBuf, Status>;
+pub type Descriptions = HashMap<PathBuf, String>;
+pub type Origins = HashMap<PathBuf, Origin>;
+pub type Hints = HashMap<PathBuf, String>;
+pub type Lines = HashMap<PathBuf, usize>;
+pub type Files = HashMap<PathBuf, PathBuf>;
+
+/// Entry of the configuration a link comes from.
+#[derive(Debug, PartialEq)]
+pub struct Origin {
+	pub target_path: PathBuf,
+	/// File the target is declared in, unless it's the configuration itself.
+	pub file: Option<PathBuf>,
+	/// Line of the file where the target is declared, if known.
+	pub line: Option<usize>,
+}
+
+impl Origin {
+	/// Finds where a target is declared, according to the lines and files known for targets.
+	pub fn of(target_path: &Path, lines: &Lines, files: &Files) -> Self {
+		Self {
+			target_path: target_path.to_path_buf(),
+			file: files.get(target_path).cloned(),
+			line: lines.get(target_path).copied(),
+		}
+	}
+
+	/// Describes where the target is declared, like "line 4" or "park.home.toml, line 2",
+	/// unless nothing is known about it.
+	pub fn location(&self) -> Option<String> {
+		match (&self.file, self.line) {
+			(Some(file), Some(line)) => Some(format!("{}, line {}", file.display(), line)),
+			(Some(file), None) => Some(file.display().to_string()),
+			(None, Some(line)) => Some(format!("line {}", line)),
+			(None, None) => None,
+		}
+	}
+}
+
+impl Display for Origin {
+	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+		write!(f, "target `{}`", self.target_path.display())?;
+
+		match self.location() {
+			Some(location) => write!(f, " ({})", location),
+			None => Ok(()),
+		}
+	}
+}
+
+/// What is known about an ancestor of a link path, so that it's checked only once per
+/// analysis, no matter how many links share it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AncestorState {
+	Missing,
+	Directory {
+		symlinks: bool,
+		read_only: bool,
+	},
+	NotADirectory,
+	/// The path can't be resolved because a symlink in it loops.
+	Looping,
+}
+
+type Ancestors = HashMap<PathBuf, AncestorState>;
+
+/// Kind of file found in a link path, without following symlinks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileKind {
+	File,
+	Dir,
+	Symlink,
+	Other,
+}
+
+impl FileKind {
+	fn of(file_type: &FileType) -> Self {
+		if file_type.is_symlink() {
+			Self::Symlink
+		} else if file_type.is_dir() {
+			Self::Dir
+		} else if file_type.is_file() {
+			Self::File
+		} else {
+			Self::Other
+		}
+	}
+}
+
+/// What the file system looks like around a link path, as far as the link's status depends
+/// on it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+	/// The closest ancestor of the link path that exists, or the farthest one that loops, if any.
+	pub ancestor: Option<(PathBuf, AncestorState)>,
+	/// Whether the directory the link would be created in exists.
+	pub parent_exists: bool,
+	/// What is in the link path, if anything.
+	pub file: Option<FileKind>,
+	/// Where the symlink in the link path points to, if that's what is there.
+	pub destination: Option<PathBuf>,
+	/// Whether the symlink points to the target, even if through symlinked directories.
+	pub points_to_source: bool,
+	/// Whether the copy or clone in the link path is up to date with the target.
+	pub up_to_date: bool,
+	/// Whether the file in the link path has the same content as the target.
+	pub identical: bool,
+	/// Whether the file in the link path was left uncompared with the target, since comparing
+	/// it would go past the limits.
+	pub too_large: bool,
+	/// The unfolded directory the link path is within, if it's still a link to the directory it
+	/// was unfolded from.
+	pub folded: Option<PathBuf>,
+}
+
+impl Snapshot {
+	/// Returns whether the closest existing directory supports symlinks and is writable.
+	/// Directories that can't be found are assumed to do both.
+	fn directory(&self) -> (bool, bool) {
+		match self.ancestor {
+			Some((
+				_,
+				AncestorState::Directory {
+					symlinks,
+					read_only,
+				},
+			)) => (symlinks, !read_only),
+			_ => (true, true),
+		}
+	}
+}
 
+/// Outcome of a linking step that was not aborted.
 #[derive(Debug, Default, PartialEq)]
+pub struct Linked {
+	/// Links created, in the order they were.
+	pub created: Vec<PathBuf>,
+	/// Optional links that could not be created.
+	pub warnings: Vec<Warning>,
+	/// Orphaned links that were removed.
+	pub pruned: Vec<PathBuf>,
+}
+
+/// Operation done in the file system during the linking step, reported as soon as it's done.
+#[derive(Debug, PartialEq)]
+pub enum Action {
+	/// Parent directories of a link were created.
+	CreateDir(PathBuf),
+	/// A mismatched link was removed so that it can be replaced, or an orphaned one was pruned.
+	Remove(PathBuf),
+	/// A file in the way of a link was moved aside so that it can be replaced.
+	Backup(PathBuf, PathBuf),
+	/// A link was created, pointing to its target.
+	Symlink(PathBuf, PathBuf),
+	/// A link pointing to where its target was before being renamed was replaced at once.
+	Repoint(PathBuf, PathBuf),
+	/// A target was copied to its link path.
+	Copy(PathBuf, PathBuf),
+	/// A target was cloned to its link path.
+	Clone(PathBuf, PathBuf),
+	/// The output of a secret's command was written to its link path.
+	Secret(String, PathBuf),
+	/// A link was left untouched due to its status.
+	Skip(PathBuf, Status),
+	/// A link, or a directory created for it, was handed over to its owner.
+	Chown(PathBuf, Owner),
+	/// Any of the above, run with superuser privileges.
+	Escalated(Box<Action>),
+}
+
+impl Action {
+	/// Rewrites every path in the action, like to abbreviate them before printing.
+	pub fn map_paths<F>(self, f: &F) -> Self
+	where
+		F: Fn(&Path) -> PathBuf,
+	{
+		match self {
+			Self::CreateDir(dir) => Self::CreateDir(f(&dir)),
+			Self::Remove(link_path) => Self::Remove(f(&link_path)),
+			Self::Backup(link_path, backup_path) => Self::Backup(f(&link_path), f(&backup_path)),
+			Self::Symlink(target_path, link_path) => Self::Symlink(f(&target_path), f(&link_path)),
+			Self::Repoint(target_path, link_path) => Self::Repoint(f(&target_path), f(&link_path)),
+			Self::Copy(target_path, link_path) => Self::Copy(f(&target_path), f(&link_path)),
+			Self::Clone(target_path, link_path) => Self::Clone(f(&target_path), f(&link_path)),
+			Self::Secret(command, link_path) => Self::Secret(command, f(&link_path)),
+			Self::Skip(link_path, status) => Self::Skip(f(&link_path), status),
+			Self::Chown(path, owner) => Self::Chown(f(&path), owner),
+			Self::Escalated(action) => Self::Escalated(Box::new(action.map_paths(f))),
+		}
+	}
+}
+
+impl Display for Action {
+	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+		match self {
+			Self::CreateDir(dir) => write!(f, "mkdir {}", dir.display()),
+			Self::Remove(link_path) => write!(f, "rm {}", link_path.display()),
+			Self::Backup(link_path, backup_path) => {
+				write!(f, "mv {} {}", link_path.display(), backup_path.display())
+			}
+			Self::Symlink(target_path, link_path) => {
+				write!(f, "ln -s {} {}", target_path.display(), link_path.display())
+			}
+			Self::Repoint(target_path, link_path) => {
+				write!(
+					f,
+					"ln -sfn {} {}",
+					target_path.display(),
+					link_path.display()
+				)
+			}
+			Self::Copy(target_path, link_path) => {
+				write!(f, "cp {} {}", target_path.display(), link_path.display())
+			}
+			Self::Clone(target_path, link_path) => write!(
+				f,
+				"cp {} {} {}",
+				CLONE_FLAG,
+				target_path.display(),
+				link_path.display()
+			),
+			Self::Secret(command, link_path) => write!(f, "{} > {}", command, link_path.display()),
+			Self::Skip(link_path, status) => {
+				write!(f, "skip ({}) {}", status.name(), link_path.display())
+			}
+			Self::Chown(path, owner) => write!(f, "chown -h {} {}", owner, path.display()),
+			Self::Escalated(action) => write!(f, "sudo {}", action),
+		}
+	}
+}
+
+#[derive(Debug, Default, Hash, PartialEq)]
 pub struct LinkOpts {
 	pub replace: bool,
 	pub create_dirs: bool,
+	/// Directories missing ones may be created under, unless any directory is fine.
+	pub create_dirs_roots: Option<Vec<PathBuf>>,
+	/// Retry links that fail due to missing permissions by using sudo.
+	pub escalate: bool,
+	/// Copy targets whose links would be in file systems that don't support symlinks.
+	pub copy_fallback: bool,
+	/// Compare the content of copies with their targets, even when they look up to date.
+	pub deep_check: bool,
+	/// Replace regular files with the same content as their targets without --replace.
+	pub replace_identical: bool,
+	/// Size of the largest files compared with targets that don't set one.
+	pub max_compare_size: Option<u64>,
+	/// Remove links of targets that are no longer in the configuration.
+	pub prune_orphans: bool,
+	/// Count anything that would replace or move a file as a problem, whatever targets do
+	/// with files in their way, since nobody is around to confirm it.
+	pub watch: bool,
+}
+
+/// Bytes of content that may still be compared during an analysis, shared by the threads
+/// doing it. Unlimited unless set.
+#[derive(Debug, Default)]
+pub struct CompareBudget(Option<AtomicU64>);
+
+impl CompareBudget {
+	pub fn new(bytes: Option<u64>) -> Self {
+		Self(bytes.map(AtomicU64::new))
+	}
+
+	/// Whether there's a budget at all, rather than an unlimited one.
+	fn is_limited(&self) -> bool {
+		self.0.is_some()
+	}
+
+	/// Takes bytes from the budget, or nothing at all if there aren't that many left.
+	fn take(&self, bytes: u64) -> bool {
+		let Some(remaining) = &self.0 else {
+			return true;
+		};
+
+		remaining
+			.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |remaining| {
+				remaining.checked_sub(bytes)
+			})
+			.is_ok()
+	}
+}
+
+impl PartialEq for CompareBudget {
+	fn eq(&self, other: &Self) -> bool {
+		let remaining = |budget: &Self| {
+			budget
+				.0
+				.as_ref()
+				.map(|remaining| remaining.load(Ordering::Relaxed))
+		};
+
+		remaining(self) == remaining(other)
+	}
 }
 
 /// Structure representing all dotfiles after reading a configuration for Park.
@@ -31,6 +334,415 @@ pub struct Tree {
 	pub statuses: Statuses,
 	pub problems: Problems,
 	pub link_opts: LinkOpts,
+	/// Targets left out by target filters.
+	pub skipped: Vec<PathBuf>,
+	/// Links known to have been created by Park, if they're being tracked.
+	pub managed: Option<BTreeSet<PathBuf>>,
+	/// Links created by Park for targets that are no longer in the configuration, mapped to the
+	/// files they point to.
+	pub orphans: BTreeMap<PathBuf, PathBuf>,
+	/// How many threads analyze leaves at once.
+	pub jobs: usize,
+	/// Bytes of content left to compare, past which files are assumed to differ from targets.
+	pub compare_budget: CompareBudget,
+	/// File mode creation mask used when creating directories.
+	pub umask: u32,
+	/// Lines of the configuration where targets are declared.
+	pub lines: Lines,
+	/// Files other than the configuration that targets are declared in, like host overlays.
+	pub files: Files,
+	/// Previous paths of renamed targets, mapped to their current ones.
+	pub renames: BTreeMap<PathBuf, PathBuf>,
+	/// Links of directories that were unfolded into links of their entries, mapped to the
+	/// directories they were links of.
+	pub unfolded: BTreeMap<PathBuf, PathBuf>,
+	/// Results of the scripts targets are gated by, which explaining a target reuses.
+	pub scripts: HashMap<PathBuf, bool>,
+	/// How operations of the linking step are retried when they fail with transient errors.
+	pub retry: Retry,
+	/// Terminal that commands of secrets read from, if any, since standard input may hold the
+	/// configuration.
+	pub terminal: Option<PathBuf>,
+}
+
+/// How file system operations are retried when they fail with errors that might go away on
+/// their own, like the ones of flaky network mounts.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Retry {
+	/// How many times an operation is retried before its error is reported.
+	pub attempts: u32,
+	/// How long to wait before the first retry, which doubles for each of the next ones.
+	pub delay: Duration,
+}
+
+impl Retry {
+	/// Runs an operation until it succeeds, fails with an error that isn't transient or runs
+	/// out of attempts.
+	fn run<T, F>(&self, mut operation: F) -> Result<T, IoError>
+	where
+		F: FnMut() -> Result<T, IoError>,
+	{
+		let mut delay = self.delay;
+
+		for _ in 0..self.attempts {
+			match operation() {
+				Err(err) if is_transient(&err) => {
+					thread::sleep(delay);
+					delay = delay.saturating_mul(2);
+				}
+				result => return result,
+			}
+		}
+
+		operation()
+	}
+}
+
+/// What resolving a target relies on besides the target itself, so that parsing a tree and
+/// explaining a single target resolve targets the same way.
+pub struct Resolver {
+	/// Tags activated at runtime, along with the ones of the configuration.
+	pub runtime_tags: TagSet,
+	/// Directory scripts are run in.
+	pub work_dir: PathBuf,
+	/// Results of the scripts run so far, since scripts shared by several targets are run once.
+	pub scripts: HashMap<PathBuf, bool>,
+	vars: Vars,
+	base_dir: PathBuf,
+	allow_path_names: bool,
+	owner: Option<String>,
+	remaps: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Resolver {
+	pub fn new(config: &Config, mut runtime_tags: TagSet) -> Self {
+		runtime_tags.extend(config.tags.iter().flatten().cloned());
+
+		Self {
+			work_dir: config
+				.work_dir
+				.clone()
+				.unwrap_or_else(|| env::current_dir().unwrap_or_default()),
+			scripts: HashMap::new(),
+			vars: config.vars.clone().unwrap_or_default(),
+			base_dir: config.base_dir.clone().unwrap_or_default(),
+			allow_path_names: config.allow_path_names.unwrap_or_default(),
+			owner: config.owner.clone(),
+			remaps: active_remaps(config.remap.as_ref(), &runtime_tags),
+			runtime_tags,
+		}
+	}
+
+	/// Resolves a target into a leaf, recording each decision taken along the way. Nothing is
+	/// returned if the target is left out by its tags or its script.
+	pub fn resolve(
+		&mut self,
+		target_path: &Path,
+		target: Target,
+		steps: &mut Vec<Step>,
+	) -> Result<Option<Leaf>, NodeError> {
+		let Target {
+			mut link,
+			tags: mut target_tags,
+			linux,
+			macos,
+			seed,
+			source,
+			work_dir,
+			description,
+			optional,
+			when,
+			on_conflict,
+			problem_hint,
+			owner,
+			group,
+			secret,
+			max_compare_size,
+		} = target;
+
+		for (os_tag, overrides) in [("linux", linux), ("macos", macos)] {
+			if let Some(overrides) = overrides {
+				if self.runtime_tags.contains(os_tag) {
+					apply_override(&mut link, &mut target_tags, overrides);
+					steps.push(Step::new("override", format!("{} applied", os_tag)));
+				} else {
+					steps.push(Step::new(
+						"override",
+						format!("{} ignored, tag is not active", os_tag),
+					));
+				}
+			}
+		}
+
+		let Tags { all_of, any_of } = target_tags.unwrap_or_default();
+		let mut gate = Gate::default();
+
+		if let Some(all_of) = all_of.filter(|tags| !tags.is_empty()) {
+			let missing: TagSet = all_of.difference(&self.runtime_tags).cloned().collect();
+
+			steps.push(Step::new(
+				"all_of",
+				if missing.is_empty() {
+					format!("{} matched", format_tags(&all_of))
+				} else {
+					format!(
+						"{} failed, missing {}",
+						format_tags(&all_of),
+						format_tags(&missing)
+					)
+				},
+			));
+
+			if !missing.is_empty() {
+				return Ok(None);
+			}
+
+			gate.all_of = all_of.into_iter().collect();
+			gate.all_of.sort();
+		}
+
+		if let Some(any_of) = any_of.filter(|tags| !tags.is_empty()) {
+			gate.any_of = any_of
+				.iter()
+				.filter(|tag| self.runtime_tags.contains(*tag))
+				.cloned()
+				.collect();
+			gate.any_of.sort();
+
+			steps.push(Step::new(
+				"any_of",
+				if gate.any_of.is_empty() {
+					format!("{} failed, none is active", format_tags(&any_of))
+				} else {
+					format!("{} matched", format_tags(&any_of))
+				},
+			));
+
+			if gate.any_of.is_empty() {
+				return Ok(None);
+			}
+		}
+
+		if let Some(script) = when.and_then(|when| when.script) {
+			let passed = match self.scripts.get(&script) {
+				Some(passed) => *passed,
+				None => {
+					let passed = passes_script(&self.work_dir, &script).map_err(|err| {
+						NodeError::FailedScript(
+							target_path.to_path_buf(),
+							script.clone(),
+							err.to_string(),
+						)
+					})?;
+					self.scripts.insert(script.clone(), passed);
+
+					passed
+				}
+			};
+
+			steps.push(Step::new(
+				"when.script",
+				if passed {
+					format!("{:?} succeeded", script)
+				} else {
+					format!("{:?} failed", script)
+				},
+			));
+
+			if !passed {
+				return Ok(None);
+			}
+		}
+
+		let link = link.unwrap_or_default();
+		let expand = |path: &Path| {
+			vars::expand(&path.to_string_lossy(), &self.vars)
+				.map(PathBuf::from)
+				.map_err(|var| NodeError::UnknownVar(target_path.to_path_buf(), var))
+		};
+
+		let base_dir = match &link.base_dir {
+			Some(base_dir) => {
+				let base_dir = expand(base_dir)?;
+				steps.push(Step::new(
+					"base_dir",
+					format!("{:?}, set by the target", base_dir),
+				));
+
+				base_dir
+			}
+			None => {
+				let base_dir = expand(&self.base_dir)?;
+				steps.push(Step::new(
+					"base_dir",
+					format!("{:?}, set by the configuration", base_dir),
+				));
+
+				base_dir
+			}
+		};
+
+		let name = match &link.name {
+			Some(name) => {
+				let name = expand(name)?;
+
+				if !self.allow_path_names && !is_plain_name(&name) {
+					return Err(NodeError::UnsafeLinkName(target_path.to_path_buf(), name));
+				}
+
+				steps.push(Step::new(
+					"link name",
+					format!("{:?}, set by the target", name),
+				));
+
+				name
+			}
+			None => {
+				let name = PathBuf::from(target_path.file_name().unwrap());
+				steps.push(Step::new(
+					"link name",
+					format!("{:?}, taken from the target", name),
+				));
+
+				name
+			}
+		};
+
+		let link_path = base_dir.join(name);
+		let link_path = match remap_link(&link_path, &self.remaps) {
+			Some(remapped) => {
+				steps.push(Step::new(
+					"remap",
+					format!("{:?} to {:?}", link_path, remapped),
+				));
+
+				remapped
+			}
+			None => link_path,
+		};
+		steps.push(Step::new("link path", format!("{:?}", link_path)));
+
+		let owner_name = owner.or_else(|| self.owner.clone());
+		let owner = owner_name
+			.as_deref()
+			.map(Owner::resolve)
+			.transpose()
+			.map_err(|name| NodeError::UnknownOwner(target_path.to_path_buf(), name))?;
+		let secret = secret.and_then(|secret| secret.command);
+
+		if let Some(command) = &secret {
+			steps.push(Step::new(
+				"secret",
+				format!("{:?} writes the link once", command),
+			));
+		}
+
+		Ok(Some(Leaf {
+			link_path,
+			// Secrets are never rewritten, since running their commands could prompt.
+			seed: seed.unwrap_or_default() || secret.is_some(),
+			source,
+			work_dir,
+			description,
+			optional: optional.unwrap_or_default(),
+			link_mode: link.mode,
+			reflink: link.reflink.unwrap_or_default(),
+			copy_mode: link.copy_mode,
+			on_conflict,
+			problem_hint,
+			owner,
+			owner_name,
+			group,
+			secret,
+			max_compare_size,
+			gate,
+		}))
+	}
+}
+
+/// Formats tags sorted, so that steps read the same every time.
+pub(super) fn format_tags(tags: &TagSet) -> String {
+	let mut tags: Vec<&String> = tags.iter().collect();
+	tags.sort();
+
+	format!("{:?}", tags)
+}
+
+/// Checks whether an error might not happen again if the operation is retried.
+fn is_transient(err: &IoError) -> bool {
+	matches!(
+		err.kind(),
+		IoErrorKind::WouldBlock
+			| IoErrorKind::Interrupted
+			| IoErrorKind::TimedOut
+			| IoErrorKind::ResourceBusy
+			| IoErrorKind::StorageFull
+			| IoErrorKind::StaleNetworkFileHandle
+	)
+}
+
+/// What it takes for a problem to stop blocking the linking step.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Remediation {
+	/// Mismatched links are replaced with --replace.
+	NeedsReplaceFlag,
+	/// Missing parent directories are created with --create-dirs.
+	NeedsCreateDirs,
+	/// Targets are copied where symlinks are unsupported with --copy-fallback.
+	NeedsCopyFallback,
+	/// No option helps, so the link path has to be fixed by hand.
+	ManualIntervention,
+}
+
+impl Remediation {
+	/// Returns how a status can be remedied, unless it's not a problem to begin with.
+	pub fn of(status: &Status) -> Option<Self> {
+		match status {
+			Status::Mismatch => Some(Self::NeedsReplaceFlag),
+			Status::Unparented => Some(Self::NeedsCreateDirs),
+			Status::Unsupported => Some(Self::NeedsCopyFallback),
+			Status::Conflict
+			| Status::Obstructed
+			| Status::Foreign
+			| Status::ReadOnly
+			| Status::Looping => Some(Self::ManualIntervention),
+			Status::Unknown
+			| Status::Ready
+			| Status::Done
+			| Status::Renamed
+			| Status::Identical
+			| Status::Orphaned => None,
+		}
+	}
+}
+
+impl Display for Remediation {
+	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+		f.write_str(match self {
+			Self::NeedsReplaceFlag => "use --replace to replace mismatched symlinks",
+			Self::NeedsCreateDirs => "use --create-dirs to create missing parent directories",
+			Self::NeedsCopyFallback => "use --copy-fallback to copy targets instead of symlinking",
+			Self::ManualIntervention => "move, remove or make writable what's in the way manually",
+		})
+	}
+}
+
+/// Problem found by the analysis, along with how to remedy it.
+#[derive(Debug, PartialEq)]
+pub struct Problem<'a> {
+	pub link_path: &'a Path,
+	pub status: &'a Status,
+	pub remediation: Remediation,
+}
+
+/// Permissions of what linking a leaf creates besides the link itself.
+#[derive(Debug, Default, PartialEq)]
+pub struct Modes {
+	/// Mode of parent directories that get created.
+	pub dirs: Option<u32>,
+	/// Mode of the copy, for leaves that get copied.
+	pub file: Option<u32>,
 }
 
 impl Tree {
@@ -40,890 +752,5626 @@ impl Tree {
 		filters: (TagSet, HashSet<PathBuf>),
 		link_opts: LinkOpts,
 	) -> Result<Self, NodeError> {
-		let (mut runtime_tags, target_filters) = filters;
-		let targets = config.targets.unwrap_or_default();
-
-		let cwd = env::current_dir().unwrap_or_default();
-		let work_dir = config.work_dir.unwrap_or(cwd);
+		let (runtime_tags, target_filters) = filters;
+		let mut resolver = Resolver::new(&config, runtime_tags);
 
 		let mut tree = Tree {
-			work_dir,
+			work_dir: resolver.work_dir.clone(),
 			link_opts,
+			renames: config.renames.unwrap_or_default(),
 			..Tree::default()
 		};
+		// Steps are only of use when explaining a single target.
+		let mut steps = Vec::new();
 
-		let Config {
-			base_dir: default_base_dir,
-			tags: default_tags,
-			..
-		} = config;
-
-		let default_base_dir = default_base_dir.unwrap_or_default();
-
-		if let Some(default_tags) = default_tags {
-			runtime_tags.extend(default_tags);
-		}
-
-		for (target_path, target) in targets {
-			if !target_filters.is_empty() && !target_filters.contains(&target_path) {
+		for (target_path, target) in config.targets.unwrap_or_default() {
+			if !target_filters.is_empty() && !matches_any(&target_filters, &target_path) {
+				tree.skipped.push(target_path);
 				continue;
 			}
 
-			let Target {
-				link,
-				tags: target_tags,
-			} = target;
-
-			let target_tags = target_tags.unwrap_or_default();
+			steps.clear();
+			let Some(leaf) = resolver.resolve(&target_path, target, &mut steps)? else {
+				continue;
+			};
+			let result = tree.root.add(target_path.iter().collect(), leaf);
 
-			let Tags { all_of, any_of } = target_tags;
-			let (all_of, any_of) = (all_of.unwrap_or_default(), any_of.unwrap_or_default());
+			if let Err(err) = result {
+				let overlapping = tree.root.into_iter().find(|element| {
+					element.leaf.is_some()
+						&& (target_path.starts_with(&element.target_path)
+							|| element.target_path.starts_with(&target_path))
+				});
 
-			if !all_of.is_empty() && !all_of.iter().all(|tag| runtime_tags.contains(tag)) {
-				continue;
+				return Err(match overlapping {
+					Some(IterElement {
+						target_path: overlapping_path,
+						..
+					}) => NodeError::OverlappingTargets(overlapping_path, target_path),
+					None => err,
+				});
 			}
+		}
 
-			if !any_of.is_empty() && !any_of.iter().any(|tag| runtime_tags.contains(tag)) {
-				continue;
-			}
+		tree.scripts = resolver.scripts;
 
-			let link = link.unwrap_or_default();
-			let base_dir = link.base_dir.as_ref().unwrap_or(&default_base_dir);
-			let link_path = link.name.map_or_else(
-				|| {
-					target_path
-						.file_name()
-						.map(|file_name| base_dir.join(file_name))
-						.unwrap()
-				},
-				|name| base_dir.join(name),
-			);
-			tree.root.add(target_path.iter().collect(), link_path)?;
+		if config.unfold.unwrap_or_default() {
+			tree.unfold_overlapping_links()?;
 		}
 
+		tree.check_overlapping_links()?;
+
 		Ok(tree)
 	}
 
-	/// Analyze the tree's nodes in order to check viability for symlinks to be done.
-	/// This means it will iterate the tree and update each node's status.
-	pub fn analyze(&mut self) -> Result<(), IoError> {
-		let Tree {
-			ref mut statuses,
-			ref mut problems,
-			ref root,
-			..
-		} = self;
+	/// Unfolds links of directories that other links are within into links of the directories'
+	/// entries, outermost first, until no link is within another one. Entries that other targets
+	/// link to are left to them, while links of anything but directories are left for
+	/// `check_overlapping_links` to report.
+	fn unfold_overlapping_links(&mut self) -> Result<(), NodeError> {
+		// Targets whose sources can't be unfolded, which are no longer candidates.
+		let mut rejected: HashSet<PathBuf> = HashSet::new();
 
-		'check: for IterElement {
-			link_path,
-			target_path,
-			..
-		} in root
-		{
-			if let Some(link_path) = link_path {
-				if let Some(parent) = link_path.parent() {
-					for parent in parent.ancestors() {
-						if parent.exists() && !parent.is_dir() {
-							problems.insert(link_path, Status::Obstructed);
-
-							continue 'check;
-						}
-					}
-				}
+		loop {
+			let leaves: Vec<(PathBuf, Leaf)> = self
+				.root
+				.into_iter()
+				.filter_map(
+					|IterElement {
+					     target_path, leaf, ..
+					 }| { leaf.map(|leaf| (target_path, leaf)) },
+				)
+				.collect();
+			let link_paths: HashSet<&Path> = leaves
+				.iter()
+				.map(|(_, leaf)| leaf.link_path.as_path())
+				.collect();
 
-				if let Ok(existing_target_path) = link_path.read_link() {
-					let target_path = self.work_dir.join(target_path);
+			let outer = leaves
+				.iter()
+				.filter(|(target_path, leaf)| {
+					!rejected.contains(target_path)
+						&& link_paths.iter().any(|link_path| {
+							*link_path != leaf.link_path && link_path.starts_with(&leaf.link_path)
+						})
+				})
+				.min_by_key(|(_, leaf)| leaf.link_path.components().count());
 
-					if existing_target_path == target_path {
-						statuses.insert(link_path, Status::Done);
-					} else if self.link_opts.replace {
-						statuses.insert(link_path, Status::Mismatch);
-					} else {
-						problems.insert(link_path, Status::Mismatch);
-					}
+			let Some((target_path, leaf)) = outer else {
+				return Ok(());
+			};
+
+			let source_dir = self.source_path(target_path, leaf);
+			let Ok(entries) = fs::read_dir(&source_dir) else {
+				rejected.insert(target_path.clone());
+				continue;
+			};
+
+			let mut names: Vec<_> = entries
+				.filter_map(|entry| Some(entry.ok()?.file_name()))
+				.collect();
+			names.sort();
+
+			self.root
+				.retain(Path::new(""), &|path: &Path| path != target_path);
+			self.unfolded.insert(leaf.link_path.clone(), source_dir);
 
+			for name in names {
+				let link_path = leaf.link_path.join(&name);
+
+				if link_paths.contains(link_path.as_path()) {
 					continue;
 				}
 
-				let link_exists = link_path.exists();
-				let link_parent_exists = link_path.parent().map_or(true, |parent| {
-					parent.as_os_str().is_empty() || parent.exists()
-				});
+				let entry = Leaf {
+					link_path,
+					source: leaf.source.as_ref().map(|source| source.join(&name)),
+					..leaf.clone()
+				};
 
-				if link_exists {
-					problems.insert(link_path, Status::Conflict);
-				} else if link_parent_exists {
-					statuses.insert(link_path, Status::Ready);
-				} else if self.link_opts.create_dirs {
-					statuses.insert(link_path, Status::Unparented);
-				} else {
-					problems.insert(link_path, Status::Unparented);
-				}
+				self.root
+					.add(target_path.join(&name).iter().collect(), entry)?;
 			}
 		}
-
-		Ok(())
 	}
 
-	pub fn link(self) -> Result<(), Error> {
-		if !self.problems.is_empty() {
-			return Err(Error::BadFiles(self.problems));
-		}
-
-		let links: Result<Vec<(PathBuf, PathBuf)>, Error> = self
+	/// Fails if a link would be created inside another link, which means either an obstructed
+	/// link or, when the outer link is a directory, a link written into the working directory.
+	fn check_overlapping_links(&self) -> Result<(), NodeError> {
+		let targets: HashMap<PathBuf, PathBuf> = self
 			.root
 			.into_iter()
-			.filter(|IterElement { link_path, .. }| link_path.is_some()) // filters branches
-			.filter(|IterElement { link_path, .. }| {
-				match self.statuses.get(link_path.as_ref().unwrap()) {
-					Some(Status::Done) => false,
-					_ => true,
-				}
-			})
-			.map(
+			.filter_map(
 				|IterElement {
-				     target_path,
-				     link_path,
-				     ..
-				 }| {
-					let link_path = link_path.unwrap();
-
-					if let Some(status) = self.statuses.get(&link_path) {
-						match status {
-							Status::Unknown | Status::Conflict | Status::Obstructed => {
-								return Err(Error::InternalError(link_path))
-							}
-							Status::Mismatch => {
-								if let Err(err) = fs::remove_file(&link_path) {
-									return Err(Error::IoError(err.kind()));
-								}
-							}
-							Status::Unparented => {
-								if let Some(link_parent_dir) = link_path.parent() {
-									if let Err(err) = fs::create_dir_all(link_parent_dir) {
-										return Err(Error::IoError(err.kind()));
-									}
-								}
-							}
-							_ => {}
-						}
-
-						return Ok((self.work_dir.join(target_path), link_path.clone()));
-					}
-
-					Err(Error::InternalError(link_path))
-				},
+				     target_path, leaf, ..
+				 }| { leaf.map(|leaf| (leaf.link_path, target_path)) },
 			)
 			.collect();
 
-		let mut created_links = Vec::new();
-		for (target_path, link_path) in links? {
-			if let Err(err) = unix_fs::symlink(target_path, &link_path) {
-				return Err(Error::IoError(err.kind()));
-			};
+		let mut link_paths: Vec<&PathBuf> = targets.keys().collect();
+		link_paths.sort();
 
-			created_links.push(link_path);
+		for link_path in link_paths {
+			let outer_link = link_path
+				.ancestors()
+				.skip(1)
+				.find_map(|dir| targets.get_key_value(dir));
+
+			if let Some((outer_link_path, outer_target)) = outer_link {
+				return Err(NodeError::OverlappingLinks(
+					outer_target.clone(),
+					targets[link_path].clone(),
+					outer_link_path.clone(),
+				));
+			}
 		}
 
 		Ok(())
 	}
-}
 
-#[cfg(test)]
-mod tests {
-	use std::{fs, path::PathBuf};
+	/// Checks a configuration against its profiles, reporting tags that targets use but no
+	/// profile activates, along with targets no profile enables. Targets whose tags contradict
+	/// each other are told apart, since no profile could ever enable them. Only tags are taken
+	/// into account, since scripts depend on the machine running them.
+	pub fn lint(config: &Config) -> Vec<Warning> {
+		let profiles: Vec<TagSet> = config
+			.profiles
+			.iter()
+			.flatten()
+			.map(|(_, tags)| {
+				tags.iter()
+					.chain(config.tags.iter().flatten())
+					.cloned()
+					.collect()
+			})
+			.collect();
+		let active_tags: TagSet = profiles.iter().flatten().cloned().collect();
 
-	use pretty_assertions::assert_eq;
+		let mut used_tags = BTreeSet::new();
+		let mut unreachable = Vec::new();
 
-	use crate::{
-		config::{Link, TagSet, Tags, TargetMap},
-		parser::node::Edges,
-	};
+		for (target_path, target) in config.targets.iter().flatten() {
+			let overrides = [("linux", &target.linux), ("macos", &target.macos)];
 
-	use super::*;
+			for (os_tag, overrides) in &overrides {
+				if overrides.is_some() {
+					used_tags.insert(os_tag.to_string());
+				}
+			}
 
-	#[test]
-	fn parse() -> Result<(), IoError> {
-		struct Test<'a> {
-			description: &'a str,
-			input: (Config, (TagSet, HashSet<PathBuf>), LinkOpts),
-			output: Result<Tree, NodeError>,
+			let mut target_tags = TagSet::new();
+			for Tags { all_of, any_of } in overrides
+				.iter()
+				.filter_map(|(_, overrides)| overrides.as_ref()?.tags.as_ref())
+				.chain(&target.tags)
+			{
+				target_tags.extend(all_of.iter().chain(any_of).flatten().cloned());
+			}
+
+			let enabled = profiles
+				.iter()
+				.any(|profile_tags| Self::enables(target, profile_tags));
+
+			if !enabled && is_contradictory(target, &target_tags) {
+				unreachable.push(Warning::ContradictoryTarget(target_path.clone()));
+			} else if !enabled {
+				unreachable.push(Warning::UnreachableTarget(target_path.clone()));
+			}
+
+			used_tags.extend(target_tags);
 		}
 
-		let current_dir = &env::current_dir()?;
+		used_tags
+			.into_iter()
+			.filter(|tag| !active_tags.contains(tag))
+			.map(Warning::UnusedTag)
+			.chain(unreachable)
+			.collect()
+	}
 
-		let test_cases = Vec::from([
-			Test {
-				description: "simple config with a single target",
-				input: (
-					Config {
-						targets: Some(TargetMap::from([("foo".into(), Target::default())])),
-						..Config::default()
-					},
-					(TagSet::default(), HashSet::default()),
-					LinkOpts {
-						replace: true,
-						create_dirs: true,
+	/// Checks whether a target's tags let it in, given the active ones, along with the tags of
+	/// the OS-specific overrides those activate. Scripts are left out, since they depend on the
+	/// machine running them.
+	pub fn enables(target: &Target, active_tags: &TagSet) -> bool {
+		let mut tags = target.tags.clone();
+
+		for (os_tag, overrides) in [("linux", &target.linux), ("macos", &target.macos)] {
+			if let Some(overrides) = overrides.as_ref().filter(|_| active_tags.contains(os_tag)) {
+				apply_override(&mut None, &mut tags, overrides.clone());
+			}
+		}
+
+		passes_tags(&tags.unwrap_or_default(), active_tags)
+	}
+
+	/// Checks whether target filters and runtime tags match anything in a configuration,
+	/// suggesting the closest name for the ones that don't.
+	pub fn check_filters(config: &Config, filters: (&TagSet, &HashSet<PathBuf>)) -> Vec<Warning> {
+		let (runtime_tags, target_filters) = filters;
+		let targets = config.targets.as_ref();
+
+		let target_names: Vec<String> = targets
+			.into_iter()
+			.flat_map(|targets| targets.keys())
+			.map(|target_path| target_path.to_string_lossy().into())
+			.collect();
+
+		let mut tag_names: TagSet = config.tags.clone().unwrap_or_default();
+		for target in targets.into_iter().flat_map(|targets| targets.values()) {
+			let overrides = [&target.linux, &target.macos];
+			let all_tags = overrides
+				.into_iter()
+				.flatten()
+				.filter_map(|overrides| overrides.tags.as_ref())
+				.chain(target.tags.as_ref());
+
+			for Tags { all_of, any_of } in all_tags {
+				tag_names.extend(all_of.iter().chain(any_of).flatten().cloned());
+			}
+		}
+
+		let mut warnings = Vec::new();
+
+		let mut target_filters: Vec<&PathBuf> = target_filters.iter().collect();
+		target_filters.sort();
+
+		for target_path in target_filters {
+			let name = target_path.to_string_lossy();
+
+			if !target_names
+				.iter()
+				.any(|target_name| matches_glob(&name, target_name))
+			{
+				warnings.push(Warning::UnknownTarget(
+					target_path.clone(),
+					closest_match(&name, target_names.iter()),
+				));
+			}
+		}
+
+		let mut runtime_tags: Vec<&String> = runtime_tags.iter().collect();
+		runtime_tags.sort();
+
+		for tag in runtime_tags {
+			if !tag_names.contains(tag) {
+				warnings.push(Warning::UnknownTag(
+					tag.clone(),
+					closest_match(tag, tag_names.iter()),
+				));
+			}
+		}
+
+		warnings
+	}
+
+	/// Analyze the tree's nodes in order to check viability for symlinks to be done.
+	/// This means it will iterate the tree and update each node's status.
+	///
+	/// Leaves are checked from up to `jobs` threads at once, which pays off when latency rather
+	/// than CPU dominates, like with home directories on network mounts.
+	pub fn analyze(&mut self) -> Result<(), IoError> {
+		let leaves: Vec<(PathBuf, Leaf)> = self
+			.root
+			.into_iter()
+			.filter_map(
+				|IterElement {
+				     target_path, leaf, ..
+				 }| { leaf.map(|leaf| (target_path, leaf)) },
+			)
+			.collect();
+
+		let analyze_chunk = |chunk: &[(PathBuf, Leaf)]| {
+			let mut ancestors = Ancestors::new();
+
+			chunk
+				.iter()
+				.map(|(target_path, leaf)| self.status_of(target_path, leaf, &mut ancestors))
+				.collect::<Vec<_>>()
+		};
+
+		// Leaves take from the budget in the order of the tree, so that the same ones are
+		// compared every time.
+		let jobs = if self.compare_budget.is_limited() {
+			1
+		} else {
+			self.jobs
+		};
+		let chunk_size = leaves.len().div_ceil(jobs.max(1)).max(1);
+		let results = if leaves.len() <= chunk_size {
+			analyze_chunk(&leaves)
+		} else {
+			thread::scope(|scope| {
+				let handles: Vec<_> = leaves
+					.chunks(chunk_size)
+					.map(|chunk| scope.spawn(move || analyze_chunk(chunk)))
+					.collect();
+
+				handles
+					.into_iter()
+					.flat_map(|handle| handle.join().expect("analysis thread panicked"))
+					.collect()
+			})
+		};
+
+		for ((_, leaf), status) in leaves.into_iter().zip(results) {
+			if self.is_problem(&leaf, &status) {
+				self.problems.insert(leaf.link_path, status);
+			} else {
+				self.statuses.insert(leaf.link_path, status);
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Analyzes leaves only until one of them is pending according to a predicate, which is then
+	/// returned along with its status. Nothing is returned if no leaves are pending.
+	pub fn find_pending<P>(&self, is_pending: P) -> Option<(PathBuf, Status)>
+	where
+		P: Fn(&Status) -> bool,
+	{
+		let mut ancestors = Ancestors::new();
+
+		self.root
+			.into_iter()
+			.filter_map(
+				|IterElement {
+				     target_path, leaf, ..
+				 }| {
+					leaf.map(|leaf| {
+						let status = self.status_of(&target_path, &leaf, &mut ancestors);
+
+						(status, leaf.link_path)
+					})
+				},
+			)
+			.find(|(status, _)| is_pending(status))
+			.map(|(status, link_path)| (link_path, status))
+	}
+
+	/// Removes the links of leaves accepted by a predicate, as long as they're symlinks pointing
+	/// to their targets. Everything else, like seeds, clones or foreign files, is left alone. The
+	/// removed link paths are returned.
+	pub fn unlink<P>(&self, predicate: P) -> Result<Vec<PathBuf>, IoError>
+	where
+		P: Fn(&Leaf) -> bool,
+	{
+		let mut unlinked = Vec::new();
+
+		for IterElement {
+			target_path, leaf, ..
+		} in &self.root
+		{
+			let Some(leaf) = leaf.filter(|leaf| predicate(leaf)) else {
+				continue;
+			};
+
+			let source_path = self.source_path(&target_path, &leaf);
+			let destination = counted(leaf.link_path.read_link());
+			if destination.is_ok_and(|path| points_to(&leaf.link_path, &path, &source_path)) {
+				counted(fs::remove_file(&leaf.link_path))?;
+				unlinked.push(leaf.link_path);
+			}
+		}
+
+		Ok(unlinked)
+	}
+
+	/// Iterates over problems found by the analysis, ordered by their link paths.
+	pub fn problems(&self) -> impl Iterator<Item = Problem<'_>> {
+		let pinned: HashSet<PathBuf> = self
+			.root
+			.into_iter()
+			.filter_map(|IterElement { leaf, .. }| leaf)
+			.filter(|leaf| matches!(leaf.on_conflict, Some(OnConflict::Skip | OnConflict::Fail)))
+			.map(|leaf| leaf.link_path)
+			.collect();
+
+		self.problems.iter().filter_map(move |(link_path, status)| {
+			let remediation = match Remediation::of(status)? {
+				// Directories outside of the allowed ones are never created.
+				Remediation::NeedsCreateDirs if self.link_opts.create_dirs => {
+					Remediation::ManualIntervention
+				}
+				// Targets that keep files in their way don't heed --replace.
+				Remediation::NeedsReplaceFlag if pinned.contains(link_path) => {
+					Remediation::ManualIntervention
+				}
+				remediation => remediation,
+			};
+
+			Some(Problem {
+				link_path,
+				status,
+				remediation,
+			})
+		})
+	}
+
+	/// Returns the analyzed status of a link, whether it's a problem or not.
+	pub fn get_status(&self, link_path: &Path) -> Option<&Status> {
+		self.problems
+			.get(link_path)
+			.or_else(|| self.statuses.get(link_path))
+	}
+
+	/// Returns the directories linking creates for unparented links, which leaves out those
+	/// created along with others.
+	pub fn dirs_to_create(&self) -> BTreeSet<PathBuf> {
+		parent_dirs(
+			self.statuses
+				.iter()
+				.filter(|(_, status)| **status == Status::Unparented)
+				.map(|(link_path, _)| link_path.as_path()),
+		)
+	}
+
+	/// Returns whether the status of a link prevents the tree from being linked, given the link
+	/// options and how its target deals with files in its way.
+	pub fn is_problem(&self, leaf: &Leaf, status: &Status) -> bool {
+		let link_path = &leaf.link_path;
+
+		if self.link_opts.watch
+			&& matches!(
+				status,
+				Status::Mismatch | Status::Conflict | Status::Renamed | Status::Identical
+			) {
+			return true;
+		}
+
+		match (status, leaf.on_conflict) {
+			// Directories are never removed, so they're still in the way of replacing.
+			(Status::Conflict, Some(OnConflict::Replace))
+				if counted(link_path.symlink_metadata())
+					.is_ok_and(|metadata| metadata.is_dir()) =>
+			{
+				return true;
+			}
+			(
+				Status::Mismatch | Status::Conflict,
+				Some(OnConflict::Backup | OnConflict::Replace),
+			) => {
+				return false;
+			}
+			(Status::Mismatch, Some(OnConflict::Skip | OnConflict::Fail)) => return true,
+			_ => {}
+		}
+
+		match status {
+			Status::Mismatch => !self.link_opts.replace,
+			Status::Unparented => !self.link_opts.create_dirs || !self.may_create_dirs(link_path),
+			Status::Unsupported => !self.link_opts.copy_fallback,
+			Status::Conflict
+			| Status::Obstructed
+			| Status::Foreign
+			| Status::ReadOnly
+			| Status::Looping => true,
+			Status::Unknown
+			| Status::Ready
+			| Status::Done
+			| Status::Renamed
+			| Status::Identical
+			| Status::Orphaned => false,
+		}
+	}
+
+	/// Returns whether missing parent directories of a link may be created. Both the link path
+	/// and the roots are relative to the current directory when they're relative, and roots have
+	/// their '~' expanded already.
+	fn may_create_dirs(&self, link_path: &Path) -> bool {
+		let Some(roots) = &self.link_opts.create_dirs_roots else {
+			return true;
+		};
+		let current_dir = env::current_dir().unwrap_or_default();
+		let parent = current_dir.join(link_path.parent().unwrap_or(link_path));
+
+		roots
+			.iter()
+			.any(|root| parent.starts_with(current_dir.join(root)))
+	}
+
+	/// Checks the file system in order to find out the status of a single leaf.
+	fn status_of(&self, target_path: &Path, leaf: &Leaf, ancestors: &mut Ancestors) -> Status {
+		self.diagnose_with(target_path, leaf, ancestors).0
+	}
+
+	/// Analyzes a single leaf, returning its status along with the reason for it.
+	pub fn diagnose(&self, target_path: &Path, leaf: &Leaf) -> (Status, String) {
+		let snapshot = self.snapshot(target_path, leaf);
+
+		self.classify(target_path, leaf, &snapshot)
+	}
+
+	/// Same as `diagnose`, but reuses what is already known about the link's ancestors.
+	fn diagnose_with(
+		&self,
+		target_path: &Path,
+		leaf: &Leaf,
+		ancestors: &mut Ancestors,
+	) -> (Status, String) {
+		let snapshot = self.snapshot_with(target_path, leaf, ancestors);
+
+		self.classify(target_path, leaf, &snapshot)
+	}
+
+	/// Takes a snapshot of what a leaf's status depends on, which is the only part of its
+	/// analysis that reads the file system.
+	pub fn snapshot(&self, target_path: &Path, leaf: &Leaf) -> Snapshot {
+		self.snapshot_with(target_path, leaf, &mut Ancestors::new())
+	}
+
+	/// Same as `snapshot`, but reuses what is already known about the link's ancestors. Only
+	/// what the classification gets to look at is read, like contents of files, which are only
+	/// compared when nothing else decides the status.
+	fn snapshot_with(
+		&self,
+		target_path: &Path,
+		leaf: &Leaf,
+		ancestors: &mut Ancestors,
+	) -> Snapshot {
+		let Leaf {
+			link_path,
+			seed,
+			reflink,
+			..
+		} = leaf;
+
+		// Whatever is in a folded directory belongs to the target it's a link of.
+		if let Some(folded) = self.folded_dir(link_path) {
+			return Snapshot {
+				folded: Some(folded.to_path_buf()),
+				..Snapshot::default()
+			};
+		}
+
+		let mut snapshot = Snapshot::default();
+
+		if let Some(parent) = link_path.parent() {
+			// The farthest ancestor that can't be resolved is the one looping.
+			let mut looping = None;
+
+			for parent in parent.ancestors() {
+				match ancestor_state(ancestors, parent) {
+					AncestorState::Looping => {
+						looping = Some(parent);
+						continue;
+					}
+					_ if looping.is_some() => break,
+					AncestorState::Missing => {}
+					// A directory can only be reached if all of its own ancestors are directories.
+					state => {
+						snapshot.ancestor = Some((parent.to_path_buf(), state));
+						break;
+					}
+				}
+			}
+
+			if let Some(looping) = looping {
+				snapshot.ancestor = Some((looping.to_path_buf(), AncestorState::Looping));
+			}
+
+			if !matches!(
+				snapshot.ancestor,
+				None | Some((_, AncestorState::Directory { .. }))
+			) {
+				return snapshot;
+			}
+
+			snapshot.parent_exists = ancestor_state(ancestors, parent) != AncestorState::Missing;
+		} else {
+			snapshot.parent_exists = true;
+		}
+
+		snapshot.file = counted(link_path.symlink_metadata())
+			.ok()
+			.map(|metadata| FileKind::of(&metadata.file_type()));
+
+		// Seeds are meant to be edited once copied, so existing ones are never compared, even
+		// when going deep.
+		if *seed && snapshot.file.is_some() {
+			return snapshot;
+		}
+
+		let source_path = || self.source_path(target_path, leaf);
+
+		if snapshot.file == Some(FileKind::Symlink) {
+			snapshot.destination = counted(link_path.read_link()).ok();
+			snapshot.points_to_source = snapshot
+				.destination
+				.as_ref()
+				.is_some_and(|destination| points_to(link_path, destination, &source_path()));
+		}
+
+		let (supports_symlinks, writable) = snapshot.directory();
+		let may_compare = |size| self.may_compare(leaf, size);
+
+		// Copies made in place of symlinks are told apart from other files only by their content.
+		let copied = self.link_opts.copy_fallback && !supports_symlinks && !seed;
+		if (*reflink || copied) && snapshot.file == Some(FileKind::File) {
+			let up_to_date = is_up_to_date(
+				&source_path(),
+				link_path,
+				self.link_opts.deep_check,
+				may_compare,
+			);
+			snapshot.up_to_date = up_to_date.unwrap_or_default();
+			snapshot.too_large = up_to_date.is_none();
+		}
+
+		// Only files that can be removed are worth comparing, and differing clones are
+		// mismatches regardless of their content.
+		let compared = snapshot.file == Some(FileKind::File)
+			&& !snapshot.up_to_date
+			&& !snapshot.too_large
+			&& !reflink
+			&& self.link_opts.replace_identical
+			&& writable;
+		if compared {
+			let identical = same_content(&source_path(), link_path, may_compare);
+			snapshot.identical = identical.unwrap_or_default();
+			snapshot.too_large = identical.is_none();
+		}
+
+		snapshot
+	}
+
+	/// Checks whether files of a size may have their content compared with a leaf's target,
+	/// taking their size from the budget if so.
+	fn may_compare(&self, leaf: &Leaf, size: u64) -> bool {
+		let max_size = leaf.max_compare_size.or(self.link_opts.max_compare_size);
+
+		max_size.is_none_or(|max_size| size <= max_size) && self.compare_budget.take(size)
+	}
+
+	/// Classifies a leaf from a snapshot of the file system around its link, returning its
+	/// status along with the reason for it. Nothing is read from the file system, so snapshots
+	/// may as well be made up.
+	pub fn classify(
+		&self,
+		target_path: &Path,
+		leaf: &Leaf,
+		snapshot: &Snapshot,
+	) -> (Status, String) {
+		let Leaf { seed, reflink, .. } = leaf;
+
+		if let Some(dir) = &snapshot.folded {
+			return (
+				Status::Ready,
+				format!("{:?} is still folded, and linking unfolds it", dir),
+			);
+		}
+
+		match &snapshot.ancestor {
+			Some((path, AncestorState::NotADirectory)) => {
+				return (
+					Status::Obstructed,
+					format!("{:?} exists but is not a directory", path),
+				);
+			}
+			Some((path, AncestorState::Looping)) => {
+				return (
+					Status::Looping,
+					format!("{:?} is a symlink that loops", path),
+				);
+			}
+			_ => {}
+		}
+
+		if *seed && snapshot.file.is_some() {
+			return (Status::Done, "seed already exists in the link path".into());
+		}
+
+		let (supports_symlinks, writable) = snapshot.directory();
+		let copied = self.link_opts.copy_fallback && !supports_symlinks && !seed;
+
+		if *reflink || copied {
+			let source_path = self.source_path(target_path, leaf);
+
+			if snapshot.file == Some(FileKind::File) && snapshot.up_to_date {
+				return (
+					Status::Done,
+					format!(
+						"{} is up to date with {:?}",
+						if *reflink { "clone" } else { "copy" },
+						source_path
+					),
+				);
+			}
+
+			// Copies that differ are analyzed like any other file in the link path.
+			if *reflink && snapshot.file.is_some_and(|file| file != FileKind::Dir) {
+				if !writable {
+					return (
+						Status::ReadOnly,
+						"file differs from the target, but its directory is read-only".into(),
+					);
+				}
+
+				// Such a file may not be a clone at all, so it's never replaced without a word.
+				if snapshot.too_large {
+					return (
+						Status::Conflict,
+						format!(
+							"file in the link path is too large to be compared with {:?}",
+							source_path
+						),
+					);
+				}
+
+				return (
+					Status::Mismatch,
+					format!("file in the link path differs from {:?}", source_path),
+				);
+			}
+		}
+
+		if let Some(existing_target_path) = &snapshot.destination {
+			let source_path = self.source_path(target_path, leaf);
+
+			if *existing_target_path == source_path || snapshot.points_to_source {
+				return (
+					Status::Done,
+					format!("link already points to {:?}", source_path),
+				);
+			}
+
+			let renamed = self
+				.renames
+				.iter()
+				.filter(|(_, new_path)| *new_path == target_path)
+				.any(|(old_path, _)| self.source_path(old_path, leaf) == *existing_target_path);
+
+			// Links to where a target was before being renamed were surely created by Park.
+			if renamed {
+				if !writable {
+					return (
+						Status::ReadOnly,
+						"link points to the renamed target, but its directory is read-only".into(),
+					);
+				}
+
+				return (
+					Status::Renamed,
+					format!(
+						"link points to {:?}, where the target was before being renamed",
+						existing_target_path
+					),
+				);
+			}
+
+			let is_foreign = self
+				.managed
+				.as_ref()
+				.is_some_and(|managed| !managed.contains(&leaf.link_path));
+
+			if is_foreign {
+				return (
+					Status::Foreign,
+					format!(
+						"link points to {:?} and was not created by park",
+						existing_target_path
+					),
+				);
+			}
+
+			if !writable {
+				return (
+					Status::ReadOnly,
+					"link points elsewhere, but its directory is read-only".into(),
+				);
+			}
+
+			return (
+				Status::Mismatch,
+				format!(
+					"link points to {:?} instead of {:?}",
+					existing_target_path, source_path
+				),
+			);
+		}
+
+		if snapshot.identical {
+			(
+				Status::Identical,
+				format!(
+					"file in the link path has the same content as {:?}",
+					self.source_path(target_path, leaf)
+				),
+			)
+		} else if snapshot.too_large {
+			(
+				Status::Conflict,
+				"a file that is too large to be compared with the target already exists in the link path".into(),
+			)
+		} else if snapshot.file.is_some() {
+			(
+				Status::Conflict,
+				"a file that is not a link already exists in the link path".into(),
+			)
+		} else if !writable {
+			(
+				Status::ReadOnly,
+				"the closest existing directory of the link path is read-only".into(),
+			)
+		} else if snapshot.parent_exists && !supports_symlinks && !seed && !reflink {
+			(
+				Status::Unsupported,
+				"file system of the link path does not support symlinks".into(),
+			)
+		} else if snapshot.parent_exists {
+			(
+				Status::Ready,
+				"nothing exists in the link path and its parent directory exists".into(),
+			)
+		} else {
+			(
+				Status::Unparented,
+				"parent directory of the link path does not exist".into(),
+			)
+		}
+	}
+
+	/// Returns the unfolded directory a link path is within that is still folded, that is, still a
+	/// link to the directory it was unfolded from.
+	fn folded_dir<'a>(&self, link_path: &'a Path) -> Option<&'a Path> {
+		link_path.ancestors().skip(1).find(|dir| {
+			self.unfolded.get(*dir).is_some_and(|source_dir| {
+				counted(dir.read_link())
+					.is_ok_and(|destination| points_to(dir, &destination, source_dir))
+			})
+		})
+	}
+
+	/// Resolves the path of the file a leaf links to, which is relative to the work directory
+	/// unless the leaf has an absolute source. A leaf's own work directory is relative to the
+	/// tree's one.
+	pub fn source_path(&self, target_path: &Path, leaf: &Leaf) -> PathBuf {
+		source_path(
+			&self.work_dir,
+			target_path,
+			leaf.work_dir.as_deref(),
+			leaf.source.as_deref(),
+		)
+	}
+
+	/// Finds links recorded in the manifest whose targets are no longer in the configuration,
+	/// given the files every configured target links to. Links that were changed since they
+	/// were created are left alone, since they're no longer Park's.
+	pub fn find_orphans(&mut self, links: &BTreeMap<PathBuf, PathBuf>, sources: &HashSet<PathBuf>) {
+		let link_paths: HashSet<PathBuf> = self
+			.root
+			.into_iter()
+			.filter_map(|IterElement { leaf, .. }| leaf.map(|leaf| leaf.link_path))
+			.collect();
+
+		self.orphans = links
+			.iter()
+			.filter(|(link_path, source_path)| {
+				!link_paths.contains(*link_path)
+					&& !sources.contains(*source_path)
+					&& link_path
+						.read_link()
+						.is_ok_and(|destination| points_to(link_path, &destination, source_path))
+			})
+			.map(|(link_path, source_path)| (link_path.clone(), source_path.clone()))
+			.collect();
+	}
+
+	/// Keeps only the targets `keep` returns true for, leaving the others out along with their
+	/// statuses, as if target filters had left them out.
+	pub fn retain<F>(&mut self, keep: F)
+	where
+		F: Fn(&Path) -> bool,
+	{
+		for (target_path, leaf) in self.root.retain(Path::new(""), &keep) {
+			self.statuses.remove(&leaf.link_path);
+			self.problems.remove(&leaf.link_path);
+			self.skipped.push(target_path);
+		}
+	}
+
+	/// Predicts the permissions of the directories and copies created when linking a leaf.
+	/// Directories are subject to the umask, while copies keep the target's permissions
+	/// unless they have their own.
+	pub fn modes_of(&self, target_path: &Path, leaf: &Leaf) -> Modes {
+		let status = self.get_status(&leaf.link_path);
+
+		let dirs = Some(0o777 & !self.umask)
+			.filter(|_| self.link_opts.create_dirs && status == Some(&Status::Unparented));
+
+		let copied = leaf.seed
+			|| leaf.reflink
+			|| (self.link_opts.copy_fallback && status == Some(&Status::Unsupported));
+		let file = leaf
+			.copy_mode
+			.or(leaf.secret.as_ref().map(|_| SECRET_MODE))
+			.or_else(|| {
+				fs::metadata(self.source_path(target_path, leaf))
+					.ok()
+					.map(|metadata| metadata.permissions().mode() & 0o7777)
+			})
+			.filter(|_| copied && !matches!(status, Some(Status::Done) | None));
+
+		Modes { dirs, file }
+	}
+
+	/// Checks the tree for paths whose resolution depends on the directory Park is run from.
+	pub fn validate(&self) -> Vec<Warning> {
+		let cwd = env::current_dir().unwrap_or_default();
+		let mut warnings = Vec::new();
+
+		if self.work_dir.is_relative() {
+			warnings.push(Warning::RelativeWorkDir(
+				self.work_dir.clone(),
+				cwd.join(&self.work_dir),
+			));
+		}
+
+		for IterElement {
+			leaf, target_path, ..
+		} in &self.root
+		{
+			let leaf = match leaf {
+				Some(leaf) => leaf,
+				None => continue,
+			};
+
+			if is_sensitive(&leaf.link_path) {
+				let Modes { dirs, file } = self.modes_of(&target_path, &leaf);
+
+				if let Some(mode) = dirs.into_iter().chain(file).find(|mode| mode & 0o044 != 0) {
+					warnings.push(Warning::ExposedLink(leaf.link_path.clone(), mode));
+				}
+			}
+
+			if leaf.link_mode.is_some() && !SYMLINK_MODES {
+				warnings.push(Warning::IgnoredLinkMode(leaf.link_path.clone()));
+			}
+
+			match self.problems.get(&leaf.link_path) {
+				Some(status) if leaf.optional => warnings.push(Warning::SkippedOptional(
+					leaf.link_path.clone(),
+					status.clone(),
+				)),
+				Some(status) if is_skippable(&leaf, status) => warnings.push(
+					Warning::SkippedConflict(leaf.link_path.clone(), status.clone()),
+				),
+				_ => {}
+			}
+
+			if leaf.link_path.is_relative() {
+				let resolved_path = cwd.join(&leaf.link_path);
+
+				warnings.push(Warning::RelativeBaseDir(leaf.link_path, resolved_path));
+			}
+		}
+
+		if !self.link_opts.prune_orphans {
+			warnings.extend(self.orphans.keys().cloned().map(Warning::Orphaned));
+		}
+
+		warnings
+	}
+
+	/// Links all leaves that are not done yet, recording what was done in `linked` as it's done,
+	/// so that links created before linking failed are still known. Every action is passed to
+	/// `log` right after it's done.
+	pub fn link_with<F>(self, linked: &mut Linked, mut log: F) -> Result<(), Error>
+	where
+		F: FnMut(Action),
+	{
+		let leaves: Vec<(PathBuf, Leaf)> = self
+			.root
+			.into_iter()
+			.filter_map(
+				|IterElement {
+				     target_path, leaf, ..
+				 }| { leaf.map(|leaf| (target_path, leaf)) },
+			)
+			.collect();
+		let blocking: Problems = self
+			.problems
+			.iter()
+			.filter(|(link_path, status)| {
+				!leaves
+					.iter()
+					.any(|(_, leaf)| leaf.link_path == **link_path && is_skippable(leaf, status))
+			})
+			.map(|(link_path, status)| (link_path.clone(), status.clone()))
+			.collect();
+
+		if !blocking.is_empty() {
+			let leaves = leaves
+				.into_iter()
+				.filter(|(_, leaf)| blocking.contains_key(&leaf.link_path));
+			let mut descriptions = Descriptions::new();
+			let mut origins = Origins::new();
+			let mut hints = Hints::new();
+
+			for (target_path, leaf) in leaves {
+				if let Some(description) = leaf.description {
+					descriptions.insert(leaf.link_path.clone(), description);
+				}
+
+				if let Some(hint) = leaf.problem_hint {
+					hints.insert(leaf.link_path.clone(), hint);
+				}
+
+				let origin = Origin::of(&target_path, &self.lines, &self.files);
+				origins.insert(leaf.link_path, origin);
+			}
+
+			return Err(Error::BadFiles(
+				Box::new(blocking),
+				Box::new(descriptions),
+				Box::new(origins),
+				Box::new(hints),
+			));
+		}
+
+		let links: Result<Vec<(PathBuf, Leaf, Status)>, Error> = self
+			.root
+			.into_iter()
+			.filter_map(
+				|IterElement {
+				     target_path, leaf, ..
+				 }| {
+					leaf.map(|leaf| (target_path, leaf)) // filters branches
+				},
+			)
+			.filter(|(_, leaf)| {
+				// Only skippable leaves can have problems at this point.
+				let status = match self.problems.get(&leaf.link_path) {
+					Some(status) => status,
+					None => match self.statuses.get(&leaf.link_path) {
+						Some(Status::Done) => &Status::Done,
+						_ => return true,
+					},
+				};
+
+				log(Action::Skip(leaf.link_path.clone(), status.clone()));
+
+				false
+			})
+			.map(
+				|(target_path, leaf)| match self.statuses.get(&leaf.link_path) {
+					Some(Status::Conflict) if leaf.on_conflict.is_none() => {
+						Err(Error::InternalError(leaf.link_path))
+					}
+					Some(
+						Status::Unknown
+						| Status::Obstructed
+						| Status::Foreign
+						| Status::ReadOnly
+						| Status::Looping,
+					)
+					| None => Err(Error::InternalError(leaf.link_path)),
+					Some(status) => {
+						Ok((self.source_path(&target_path, &leaf), leaf, status.clone()))
+					}
+				},
+			)
+			.collect();
+
+		let links = links?;
+
+		for link_path in self.orphans.keys().filter(|_| self.link_opts.prune_orphans) {
+			match self.retry.run(|| counted(fs::remove_file(link_path))) {
+				Ok(()) => {
+					log(Action::Remove(link_path.clone()));
+					linked.pruned.push(link_path.clone());
+				}
+				Err(err) => linked
+					.warnings
+					.push(Warning::FailedPrune(link_path.clone(), err.kind())),
+			}
+		}
+
+		unfold_dirs(&self.unfolded, &self.retry, &mut log)
+			.map_err(|err| Error::IoError(err.kind()))?;
+
+		// Missing directories are looked up before any gets created, so that each owned link
+		// knows which ones are its own.
+		let missing: Vec<Vec<PathBuf>> = links
+			.iter()
+			.map(|(_, leaf, _)| missing_dirs(leaf))
+			.collect();
+		let created_parents = create_parent_dirs(&links, &self.retry, &mut log);
+
+		let mut denied_links = Vec::new();
+		for ((target_path, leaf, status), created_dirs) in links.into_iter().zip(missing) {
+			let status = match status {
+				Status::Unparented
+					if leaf
+						.link_path
+						.parent()
+						.is_some_and(|dir| created_parents.contains(dir)) =>
+				{
+					Status::Ready
+				}
+				status => status,
+			};
+			let result = create_link(
+				&target_path,
+				&leaf,
+				&status,
+				self.terminal.as_deref(),
+				&self.retry,
+				&mut log,
+			);
+			// Links in place only need to be handed over to their owners once escalated.
+			let in_place = result.is_ok();
+			let result =
+				result.and_then(|()| set_owner(&leaf, &created_dirs, &self.retry, &mut log));
+
+			match result {
+				Err(err) if err.kind() == IoErrorKind::PermissionDenied => {
+					denied_links.push((target_path, leaf, status, created_dirs, in_place));
+				}
+				Err(err) if leaf.optional => linked
+					.warnings
+					.push(Warning::FailedOptional(leaf.link_path, err.kind())),
+				Err(err) => return Err(Error::IoError(err.kind())),
+				Ok(()) => linked.created.push(leaf.link_path),
+			}
+		}
+
+		if denied_links.is_empty() {
+			return Ok(());
+		}
+
+		if !self.link_opts.escalate {
+			let (in_place, denied_links): (Vec<_>, Vec<_>) = denied_links
+				.into_iter()
+				.partition(|(.., in_place)| *in_place);
+
+			for (_, leaf, ..) in in_place {
+				linked.created.push(leaf.link_path.clone());
+				linked.warnings.push(Warning::UnownedLink(leaf.link_path));
+			}
+
+			let (optional, required): (Vec<_>, Vec<_>) = denied_links
+				.into_iter()
+				.map(|(_, leaf, ..)| leaf)
+				.partition(|leaf| leaf.optional);
+
+			if !required.is_empty() {
+				return Err(Error::PermissionDenied(
+					required.into_iter().map(|leaf| leaf.link_path).collect(),
+				));
+			}
+
+			linked.warnings.extend(optional.into_iter().map(|leaf| {
+				Warning::FailedOptional(leaf.link_path, IoErrorKind::PermissionDenied)
+			}));
+
+			return Ok(());
+		}
+
+		// Directories are the ones missing before the first attempt, which may have created some.
+		for (target_path, leaf, status, created_dirs, in_place) in denied_links {
+			let result = if in_place {
+				Ok(())
+			} else {
+				create_escalated_link(&target_path, &leaf, &status, &mut log)
+			};
+			let result = result.and_then(|()| set_escalated_owner(&leaf, &created_dirs, &mut log));
+
+			match result {
+				Err(err) if leaf.optional => linked
+					.warnings
+					.push(Warning::FailedOptional(leaf.link_path, err.kind())),
+				Err(_) => return Err(Error::EscalationFailed(leaf.link_path)),
+				Ok(()) => linked.created.push(leaf.link_path),
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// Creates a symlink (or a copy, for seeds, or a clone, for reflinks), first preparing its path
+/// according to its status.
+fn create_link<F>(
+	target_path: &Path,
+	leaf: &Leaf,
+	status: &Status,
+	terminal: Option<&Path>,
+	retry: &Retry,
+	log: &mut F,
+) -> Result<(), IoError>
+where
+	F: FnMut(Action),
+{
+	let link_path = &leaf.link_path;
+
+	match status {
+		Status::Mismatch | Status::Conflict if leaf.on_conflict == Some(OnConflict::Backup) => {
+			let backup_path = backup_path(link_path);
+
+			// Former backups are never overwritten.
+			if counted(backup_path.symlink_metadata()).is_ok() {
+				return Err(IoErrorKind::AlreadyExists.into());
+			}
+
+			retry.run(|| counted(fs::rename(link_path, &backup_path)))?;
+			log(Action::Backup(link_path.clone(), backup_path));
+		}
+		Status::Mismatch | Status::Conflict | Status::Identical
+			if leaf.reflink && !leaf.seed && leaf.secret.is_none() =>
+		{
+			return replace_with_clone(target_path, leaf, retry, log);
+		}
+		Status::Mismatch | Status::Conflict | Status::Identical => {
+			retry.run(|| counted(fs::remove_file(link_path)))?;
+			log(Action::Remove(link_path.clone()));
+		}
+		Status::Renamed if !leaf.seed && !leaf.reflink => {
+			return repoint_link(target_path, link_path, retry, log);
+		}
+		Status::Renamed => {
+			retry.run(|| counted(fs::remove_file(link_path)))?;
+			log(Action::Remove(link_path.clone()));
+		}
+		Status::Unparented => {
+			if let Some(link_parent_dir) = link_path.parent() {
+				retry.run(|| counted(fs::create_dir_all(link_parent_dir)))?;
+				log(Action::CreateDir(link_parent_dir.to_path_buf()));
+			}
+		}
+		_ => {}
+	}
+
+	if let Some(command) = &leaf.secret {
+		write_secret(command, link_path, leaf.copy_mode, terminal, retry)?;
+		log(Action::Secret(command.clone(), link_path.clone()));
+
+		return Ok(());
+	}
+
+	if leaf.seed || *status == Status::Unsupported {
+		retry.run(|| counted(fs::copy(target_path, link_path)))?;
+		log(Action::Copy(target_path.to_path_buf(), link_path.clone()));
+
+		return set_copy_mode(link_path, leaf.copy_mode, retry);
+	}
+
+	if leaf.reflink {
+		retry.run(|| counted(clone_file(target_path, link_path)))?;
+		log(Action::Clone(target_path.to_path_buf(), link_path.clone()));
+
+		return set_copy_mode(link_path, leaf.copy_mode, retry);
+	}
+
+	retry.run(|| counted(unix_fs::symlink(target_path, link_path)))?;
+	log(Action::Symlink(
+		target_path.to_path_buf(),
+		link_path.clone(),
+	));
+
+	match leaf.link_mode {
+		Some(mode) if SYMLINK_MODES => counted(set_symlink_mode(link_path, mode)),
+		_ => Ok(()),
+	}
+}
+
+/// Replaces the file in a leaf's link path by a clone of its target. The file is only moved
+/// aside until the clone is made, and put back if cloning fails, like on file systems without
+/// copy-on-write, so that it's never lost for nothing.
+fn replace_with_clone<F>(
+	target_path: &Path,
+	leaf: &Leaf,
+	retry: &Retry,
+	log: &mut F,
+) -> Result<(), IoError>
+where
+	F: FnMut(Action),
+{
+	let link_path = &leaf.link_path;
+	let file_name = link_path.file_name().unwrap_or_default().to_string_lossy();
+	let aside_path = link_path.with_file_name(format!(".{}.park-{}", file_name, process::id()));
+
+	retry.run(|| counted(fs::rename(link_path, &aside_path)))?;
+
+	if let Err(err) = retry.run(|| counted(clone_file(target_path, link_path))) {
+		counted(fs::rename(&aside_path, link_path))?;
+
+		return Err(err);
+	}
+
+	retry.run(|| counted(fs::remove_file(&aside_path)))?;
+	log(Action::Remove(link_path.clone()));
+	log(Action::Clone(target_path.to_path_buf(), link_path.clone()));
+
+	set_copy_mode(link_path, leaf.copy_mode, retry)
+}
+
+/// Replaces unfolded directories that are still folded by real ones, outermost first, along with
+/// the unfolded directories within them, so that links of their entries can be created.
+fn unfold_dirs<F>(
+	unfolded: &BTreeMap<PathBuf, PathBuf>,
+	retry: &Retry,
+	log: &mut F,
+) -> Result<(), IoError>
+where
+	F: FnMut(Action),
+{
+	let mut created = HashSet::new();
+
+	for (dir, source_dir) in unfolded {
+		let folded = counted(dir.read_link())
+			.is_ok_and(|destination| points_to(dir, &destination, source_dir));
+		let within_created = dir.ancestors().skip(1).any(|dir| created.contains(dir));
+
+		if folded {
+			retry.run(|| counted(fs::remove_file(dir)))?;
+			log(Action::Remove(dir.clone()));
+		} else if !within_created {
+			continue;
+		}
+
+		retry.run(|| counted(fs::create_dir(dir)))?;
+		log(Action::CreateDir(dir.clone()));
+		created.insert(dir.as_path());
+	}
+
+	Ok(())
+}
+
+/// Creates the parent directories of unparented links at once. Returns every directory that
+/// exists afterwards, while links whose directories failed are left to create their own.
+fn create_parent_dirs<F>(
+	links: &[(PathBuf, Leaf, Status)],
+	retry: &Retry,
+	log: &mut F,
+) -> HashSet<PathBuf>
+where
+	F: FnMut(Action),
+{
+	let dirs = parent_dirs(
+		links
+			.iter()
+			.filter(|(_, _, status)| *status == Status::Unparented)
+			.map(|(_, leaf, _)| leaf.link_path.as_path()),
+	);
+
+	let mut created = HashSet::new();
+	for dir in dirs {
+		if retry.run(|| counted(fs::create_dir_all(&dir))).is_err() {
+			continue;
+		}
+
+		created.extend(dir.ancestors().map(PathBuf::from));
+		log(Action::CreateDir(dir));
+	}
+
+	created
+}
+
+/// Returns the parent directories of links, skipping those which are ancestors of others, since
+/// they get created along with them.
+fn parent_dirs<'a>(link_paths: impl Iterator<Item = &'a Path>) -> BTreeSet<PathBuf> {
+	let dirs: BTreeSet<&Path> = link_paths
+		.filter_map(Path::parent)
+		.filter(|dir| !dir.as_os_str().is_empty())
+		.collect();
+
+	dirs.iter()
+		.filter(|dir| {
+			!dirs
+				.iter()
+				.any(|other| other != *dir && other.starts_with(dir))
+		})
+		.map(|dir| dir.to_path_buf())
+		.collect()
+}
+
+/// Returns the directories missing for a link with an owner, which are created along with it
+/// and handed over to the owner as well.
+fn missing_dirs(leaf: &Leaf) -> Vec<PathBuf> {
+	let Some(parent_dir) = leaf.link_path.parent().filter(|_| leaf.owner.is_some()) else {
+		return Vec::new();
+	};
+
+	parent_dir
+		.ancestors()
+		.take_while(|dir| !dir.as_os_str().is_empty() && counted(dir.symlink_metadata()).is_err())
+		.map(PathBuf::from)
+		.collect()
+}
+
+/// Hands a link, along with the directories created for it, over to its owner, if it has one.
+fn set_owner<F>(
+	leaf: &Leaf,
+	created_dirs: &[PathBuf],
+	retry: &Retry,
+	log: &mut F,
+) -> Result<(), IoError>
+where
+	F: FnMut(Action),
+{
+	let Some(owner) = leaf.owner else {
+		return Ok(());
+	};
+
+	// Outer directories come first, so that nothing is left behind if any of them fails.
+	for path in created_dirs.iter().rev().chain([&leaf.link_path]) {
+		retry.run(|| counted(unix_fs::lchown(path, owner.uid, owner.gid)))?;
+		log(Action::Chown(path.clone(), owner));
+	}
+
+	Ok(())
+}
+
+/// Returns where a file in the way of a link is moved to when its target backs it up.
+fn backup_path(link_path: &Path) -> PathBuf {
+	let file_name = link_path.file_name().unwrap_or_default().to_string_lossy();
+
+	link_path.with_file_name(format!("{}.park-backup", file_name))
+}
+
+/// Checks whether a leaf with a problem is skipped when linking, rather than aborting it.
+fn is_skippable(leaf: &Leaf, status: &Status) -> bool {
+	leaf.optional || (leaf.on_conflict == Some(OnConflict::Skip) && OnConflict::applies_to(status))
+}
+
+/// Writes the output of a secret's command to a new file, which is created with its mode, so that
+/// the secret is never readable by others, not even for a moment. The file is written next to the
+/// link first and then renamed into place, so that a failure never leaves part of a secret behind.
+/// The command may prompt, like for a passphrase, so it's run only once and reads from the
+/// terminal, since standard input may hold the configuration.
+fn write_secret(
+	command: &str,
+	link_path: &Path,
+	mode: Option<u32>,
+	terminal: Option<&Path>,
+	retry: &Retry,
+) -> Result<(), IoError> {
+	let stdin = match terminal {
+		Some(terminal) => Stdio::from(File::open(terminal)?),
+		None => Stdio::null(),
+	};
+	let output = Command::new("sh")
+		.arg("-c")
+		.arg(command)
+		.stdin(stdin)
+		.stderr(Stdio::inherit())
+		.output()?;
+
+	if !output.status.success() {
+		return Err(IoError::other(format!(
+			"secret command {:?} failed with {}",
+			command, output.status
+		)));
+	}
+
+	let file_name = link_path.file_name().unwrap_or_default().to_string_lossy();
+	let temp_path = link_path.with_file_name(format!(".{}.park-{}", file_name, process::id()));
+	let mut file = retry.run(|| {
+		counted(
+			File::options()
+				.write(true)
+				.create_new(true)
+				.mode(mode.unwrap_or(SECRET_MODE))
+				.open(&temp_path),
+		)
+	})?;
+	let result = counted(file.write_all(&output.stdout))
+		// The umask may have taken permissions away from an explicit mode.
+		.and_then(|()| set_copy_mode(&temp_path, mode, retry))
+		.and_then(|()| retry.run(|| counted(fs::rename(&temp_path, link_path))));
+
+	if result.is_err() {
+		let _ = fs::remove_file(&temp_path);
+	}
+
+	result
+}
+
+/// Sets the permissions of a copy or a clone, unless they're the ones of its target.
+fn set_copy_mode(link_path: &Path, mode: Option<u32>, retry: &Retry) -> Result<(), IoError> {
+	match mode {
+		Some(mode) => {
+			retry.run(|| counted(fs::set_permissions(link_path, Permissions::from_mode(mode))))
+		}
+		None => Ok(()),
+	}
+}
+
+/// Replaces a link by renaming a new one over it, so that the link path never goes missing.
+fn repoint_link<F>(
+	target_path: &Path,
+	link_path: &Path,
+	retry: &Retry,
+	log: &mut F,
+) -> Result<(), IoError>
+where
+	F: FnMut(Action),
+{
+	let file_name = link_path.file_name().unwrap_or_default().to_string_lossy();
+	let temp_path = link_path.with_file_name(format!(".{}.park-{}", file_name, process::id()));
+
+	retry.run(|| counted(unix_fs::symlink(target_path, &temp_path)))?;
+	if let Err(err) = retry.run(|| counted(fs::rename(&temp_path, link_path))) {
+		let _ = fs::remove_file(&temp_path);
+
+		return Err(err);
+	}
+	log(Action::Repoint(
+		target_path.to_path_buf(),
+		link_path.to_path_buf(),
+	));
+
+	Ok(())
+}
+
+/// Whether symlinks have permissions of their own, which can be changed after creating them.
+const SYMLINK_MODES: bool = cfg!(target_os = "macos");
+
+/// Permissions of secrets that have no mode of their own, which only their owner can read.
+const SECRET_MODE: u32 = 0o600;
+
+#[cfg(target_os = "macos")]
+fn set_symlink_mode(link_path: &Path, mode: u32) -> Result<(), IoError> {
+	use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+	let path = CString::new(link_path.as_os_str().as_bytes()).map_err(IoError::other)?;
+
+	// SAFETY: the path is NUL-terminated.
+	if unsafe { libc::lchmod(path.as_ptr(), mode as libc::mode_t) } != 0 {
+		return Err(IoError::last_os_error());
+	}
+
+	Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+fn set_symlink_mode(_link_path: &Path, _mode: u32) -> Result<(), IoError> {
+	Ok(())
+}
+
+/// Same as `create_link`, but runs every step with superuser privileges by using sudo.
+fn create_escalated_link<F>(
+	target_path: &Path,
+	leaf: &Leaf,
+	status: &Status,
+	log: &mut F,
+) -> Result<(), IoError>
+where
+	F: FnMut(Action),
+{
+	let link_path = &leaf.link_path;
+	let mut log = |action| log(Action::Escalated(Box::new(action)));
+
+	// Secrets would have to pass through sudo's command line.
+	if leaf.secret.is_some() {
+		return Err(IoError::new(
+			IoErrorKind::Unsupported,
+			"secrets are never written with sudo",
+		));
+	}
+
+	match status {
+		Status::Mismatch | Status::Conflict if leaf.on_conflict == Some(OnConflict::Backup) => {
+			let backup_path = backup_path(link_path);
+
+			// Former backups are never overwritten.
+			sudo([
+				OsStr::new("mv"),
+				"-n".as_ref(),
+				"--".as_ref(),
+				link_path.as_ref(),
+				backup_path.as_ref(),
+			])?;
+			log(Action::Backup(link_path.clone(), backup_path));
+		}
+		Status::Mismatch | Status::Conflict | Status::Identical if leaf.reflink && !leaf.seed => {
+			let file_name = link_path.file_name().unwrap_or_default().to_string_lossy();
+			let aside_path =
+				link_path.with_file_name(format!(".{}.park-{}", file_name, process::id()));
+			let clone = [
+				OsStr::new("cp"),
+				"-p".as_ref(),
+				CLONE_FLAG.as_ref(),
+				"--".as_ref(),
+				target_path.as_ref(),
+				link_path.as_ref(),
+			];
+
+			// Like when not escalated, the file is only moved aside until the clone is made.
+			sudo([
+				OsStr::new("mv"),
+				"--".as_ref(),
+				link_path.as_ref(),
+				aside_path.as_ref(),
+			])?;
+			if let Err(err) = sudo(clone) {
+				sudo([
+					OsStr::new("mv"),
+					"--".as_ref(),
+					aside_path.as_ref(),
+					link_path.as_ref(),
+				])?;
+
+				return Err(err);
+			}
+			sudo([
+				OsStr::new("rm"),
+				"-f".as_ref(),
+				"--".as_ref(),
+				aside_path.as_ref(),
+			])?;
+			log(Action::Remove(link_path.clone()));
+			log(Action::Clone(target_path.to_path_buf(), link_path.clone()));
+
+			return set_escalated_copy_mode(link_path, leaf.copy_mode);
+		}
+		Status::Mismatch | Status::Conflict | Status::Renamed | Status::Identical => {
+			sudo([
+				OsStr::new("rm"),
+				"-f".as_ref(),
+				"--".as_ref(),
+				link_path.as_ref(),
+			])?;
+			log(Action::Remove(link_path.clone()));
+		}
+		Status::Unparented => {
+			if let Some(link_parent_dir) = link_path.parent() {
+				sudo([
+					OsStr::new("mkdir"),
+					"-p".as_ref(),
+					"--".as_ref(),
+					link_parent_dir.as_ref(),
+				])?;
+				log(Action::CreateDir(link_parent_dir.to_path_buf()));
+			}
+		}
+		_ => {}
+	}
+
+	let copy = leaf.seed || *status == Status::Unsupported;
+
+	if leaf.reflink && !copy {
+		sudo([
+			OsStr::new("cp"),
+			"-p".as_ref(),
+			CLONE_FLAG.as_ref(),
+			"--".as_ref(),
+			target_path.as_ref(),
+			link_path.as_ref(),
+		])?;
+		log(Action::Clone(target_path.to_path_buf(), link_path.clone()));
+
+		return set_escalated_copy_mode(link_path, leaf.copy_mode);
+	}
+
+	sudo([
+		OsStr::new(if copy { "cp" } else { "ln" }),
+		if copy { "-p" } else { "-s" }.as_ref(),
+		"--".as_ref(),
+		target_path.as_ref(),
+		link_path.as_ref(),
+	])?;
+	log(if copy {
+		Action::Copy(target_path.to_path_buf(), link_path.clone())
+	} else {
+		Action::Symlink(target_path.to_path_buf(), link_path.clone())
+	});
+
+	match leaf.link_mode {
+		Some(mode) if SYMLINK_MODES && !copy => sudo([
+			OsStr::new("chmod"),
+			"-h".as_ref(),
+			format!("{:o}", mode).as_ref(),
+			"--".as_ref(),
+			link_path.as_ref(),
+		]),
+		_ if copy => set_escalated_copy_mode(link_path, leaf.copy_mode),
+		_ => Ok(()),
+	}
+}
+
+/// Sets the permissions of a copy or a clone by using sudo, unless they're the ones of its
+/// target.
+fn set_escalated_copy_mode(link_path: &Path, mode: Option<u32>) -> Result<(), IoError> {
+	match mode {
+		Some(mode) => sudo([
+			OsStr::new("chmod"),
+			format!("{:o}", mode).as_ref(),
+			"--".as_ref(),
+			link_path.as_ref(),
+		]),
+		None => Ok(()),
+	}
+}
+
+/// Same as `set_owner`, but with superuser privileges.
+fn set_escalated_owner<F>(leaf: &Leaf, created_dirs: &[PathBuf], log: &mut F) -> Result<(), IoError>
+where
+	F: FnMut(Action),
+{
+	let Some(owner) = leaf.owner else {
+		return Ok(());
+	};
+
+	for path in created_dirs.iter().rev().chain([&leaf.link_path]) {
+		sudo([
+			OsStr::new("chown"),
+			"-h".as_ref(),
+			owner.to_string().as_ref(),
+			"--".as_ref(),
+			path.as_ref(),
+		])?;
+		log(Action::Escalated(Box::new(Action::Chown(
+			path.clone(),
+			owner,
+		))));
+	}
+
+	Ok(())
+}
+
+/// Flag that makes cp clone files instead of copying them, failing where it's not possible.
+#[cfg(target_os = "macos")]
+const CLONE_FLAG: &str = "-c";
+
+#[cfg(not(target_os = "macos"))]
+const CLONE_FLAG: &str = "--reflink=always";
+
+/// Clones a file with copy-on-write, so that no data is duplicated until either file changes.
+/// File systems that can't clone files, or clones across file systems, result in an error.
+#[cfg(target_os = "linux")]
+fn clone_file(target_path: &Path, link_path: &Path) -> Result<(), IoError> {
+	use std::{fs::OpenOptions, os::fd::AsRawFd};
+
+	// Same as FICLONE from linux/fs.h.
+	const FICLONE: libc::c_ulong = 0x4004_9409;
+
+	let source = fs::File::open(target_path)?;
+	let permissions = source.metadata()?.permissions();
+	let clone = OpenOptions::new()
+		.write(true)
+		.create_new(true)
+		.open(link_path)?;
+
+	// SAFETY: both file descriptors stay open during the call.
+	if unsafe { libc::ioctl(clone.as_raw_fd(), FICLONE as _, source.as_raw_fd()) } != 0 {
+		let err = IoError::last_os_error();
+		fs::remove_file(link_path).ok();
+
+		return Err(err);
+	}
+
+	clone.set_permissions(permissions)
+}
+
+#[cfg(target_os = "macos")]
+fn clone_file(target_path: &Path, link_path: &Path) -> Result<(), IoError> {
+	use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+	let source = CString::new(target_path.as_os_str().as_bytes()).map_err(IoError::other)?;
+	let clone = CString::new(link_path.as_os_str().as_bytes()).map_err(IoError::other)?;
+
+	// SAFETY: both paths are NUL-terminated.
+	if unsafe { libc::clonefile(source.as_ptr(), clone.as_ptr(), 0) } != 0 {
+		return Err(IoError::last_os_error());
+	}
+
+	Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn clone_file(_target_path: &Path, _link_path: &Path) -> Result<(), IoError> {
+	Err(IoErrorKind::Unsupported.into())
+}
+
+/// Checks whether a copy is as large as the file it was copied from and newer than it, which
+/// only means it's up to date as long as neither was modified since. Older copies, or all of
+/// them when going deep, have their content compared instead, unless that's refused by
+/// `may_compare`, in which case nothing is returned.
+fn is_up_to_date<F>(path: &Path, copy_path: &Path, deep: bool, may_compare: F) -> Option<bool>
+where
+	F: Fn(u64) -> bool,
+{
+	let (Ok(metadata), Ok(copy_metadata)) = (
+		counted(fs::metadata(path)),
+		counted(fs::metadata(copy_path)),
+	) else {
+		return Some(false);
+	};
+
+	if metadata.len() != copy_metadata.len() {
+		return Some(false);
+	}
+
+	let newer = match (metadata.modified(), copy_metadata.modified()) {
+		(Ok(modified), Ok(copy_modified)) => copy_modified >= modified,
+		_ => false,
+	};
+
+	if newer && !deep {
+		return Some(true);
+	}
+
+	same_content(path, copy_path, may_compare)
+}
+
+/// Checks whether two files have the same content, reading both only as far as they match.
+/// Unlike the manifest's FNV checksums, this needs no digest stored beforehand, stops at the
+/// first differing byte instead of hashing both files whole, and can't be fooled by a
+/// collision. Files of the same size are only read if `may_compare` allows it for their size,
+/// otherwise nothing is returned.
+fn same_content<F>(path: &Path, other_path: &Path, may_compare: F) -> Option<bool>
+where
+	F: Fn(u64) -> bool,
+{
+	let (Ok(file), Ok(other_file)) = (
+		counted(fs::File::open(path)),
+		counted(fs::File::open(other_path)),
+	) else {
+		return Some(false);
+	};
+
+	match (file.metadata(), other_file.metadata()) {
+		(Ok(metadata), Ok(other_metadata)) if metadata.len() == other_metadata.len() => {
+			if !may_compare(metadata.len()) {
+				return None;
+			}
+		}
+		_ => return Some(false),
+	}
+
+	let (mut reader, mut other_reader) = (BufReader::new(file), BufReader::new(other_file));
+
+	loop {
+		let (Ok(buf), Ok(other_buf)) = (reader.fill_buf(), other_reader.fill_buf()) else {
+			return Some(false);
+		};
+
+		let len = buf.len().min(other_buf.len());
+		if len == 0 {
+			return Some(buf.is_empty() && other_buf.is_empty());
+		}
+
+		if buf[..len] != other_buf[..len] {
+			return Some(false);
+		}
+
+		reader.consume(len);
+		other_reader.consume(len);
+	}
+}
+
+/// Runs a command through sudo, failing if it doesn't exit successfully.
+fn sudo<const N: usize>(args: [&OsStr; N]) -> Result<(), IoError> {
+	let status = counted(Command::new("sudo").args(args).status())?;
+
+	if !status.success() {
+		return Err(IoError::other(format!("sudo exited with {}", status)));
+	}
+
+	Ok(())
+}
+
+/// Returns the files every target of a configuration links to, including targets left out by
+/// tags and filters. Links pointing elsewhere belong to targets that were removed from the
+/// configuration. Sources are resolved the way leaves resolve them, which neither overrides nor
+/// vars take part in, so that targets need no resolving.
+pub fn configured_sources(config: &Config) -> HashSet<PathBuf> {
+	let work_dir = Resolver::new(config, TagSet::new()).work_dir;
+
+	config
+		.targets
+		.iter()
+		.flatten()
+		.map(|(target_path, target)| {
+			source_path(
+				&work_dir,
+				target_path,
+				target.work_dir.as_deref(),
+				target.source.as_deref(),
+			)
+		})
+		.collect()
+}
+
+/// Joins the source of a target to the directory it lives in, which is relative to the work
+/// directory, defaulting to the target path.
+fn source_path(
+	work_dir: &Path,
+	target_path: &Path,
+	target_work_dir: Option<&Path>,
+	source: Option<&Path>,
+) -> PathBuf {
+	let work_dir = match target_work_dir {
+		Some(target_work_dir) => work_dir.join(target_work_dir),
+		None => work_dir.to_path_buf(),
+	};
+
+	work_dir.join(source.unwrap_or(target_path))
+}
+
+/// Runs the script of a target from the work directory, returning whether it succeeded. Its
+/// standard output is discarded, so that it doesn't mix with what Park prints.
+pub fn passes_script(work_dir: &Path, script: &Path) -> Result<bool, IoError> {
+	let status = Command::new(work_dir.join(script))
+		.current_dir(work_dir)
+		.stdin(Stdio::null())
+		.stdout(Stdio::null())
+		.status()?;
+
+	Ok(status.success())
+}
+
+/// Number of file system calls made so far, including the ones made through sudo.
+static FS_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns how many file system calls were made so far.
+pub fn fs_calls() -> usize {
+	FS_CALLS.load(Ordering::Relaxed)
+}
+
+/// Counts a file system call, passing its result through.
+fn counted<T>(result: T) -> T {
+	FS_CALLS.fetch_add(1, Ordering::Relaxed);
+
+	result
+}
+
+/// Returns whether a symlink with the given destination points to a path. Symlinked directories
+/// in either path are resolved first, so that reaching the same file through a directory like a
+/// symlinked ~/.config still counts, while the file names are compared as they are, since a
+/// symlink to another symlink doesn't point to where the latter does.
+fn points_to(link_path: &Path, destination: &Path, path: &Path) -> bool {
+	if destination == path {
+		return true;
+	}
+
+	// Relative destinations are relative to the directory the symlink is in.
+	let destination = link_path
+		.parent()
+		.map_or_else(|| destination.to_path_buf(), |dir| dir.join(destination));
+
+	resolve_parent(&destination)
+		.is_some_and(|destination| Some(destination) == resolve_parent(path))
+}
+
+/// Resolves every symlink in the directory a path is in, leaving the path's file name alone.
+fn resolve_parent(path: &Path) -> Option<PathBuf> {
+	let name = path.file_name()?;
+	let parent = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+
+	counted(parent.unwrap_or(Path::new(".")).canonicalize())
+		.ok()
+		.map(|dir| dir.join(name))
+}
+
+/// Returns the state of an ancestor of a link path, checking the file system only if it hasn't
+/// been checked before. An empty path stands for the current directory.
+fn ancestor_state(ancestors: &mut Ancestors, path: &Path) -> AncestorState {
+	if path.as_os_str().is_empty() {
+		return ancestor_state(ancestors, Path::new("."));
+	}
+
+	if let Some(state) = ancestors.get(path) {
+		return *state;
+	}
+
+	let state = match counted(fs::metadata(path)) {
+		Ok(metadata) if metadata.is_dir() => AncestorState::Directory {
+			symlinks: supports_symlinks(path),
+			read_only: is_read_only(path),
+		},
+		Ok(_) => AncestorState::NotADirectory,
+		Err(err) if err.raw_os_error() == Some(libc::ELOOP) => AncestorState::Looping,
+		// Dangling symlinks are in the way of creating directories just like files are.
+		Err(_) if counted(path.symlink_metadata()).is_ok() => AncestorState::NotADirectory,
+		Err(_) => AncestorState::Missing,
+	};
+	ancestors.insert(path.to_path_buf(), state);
+
+	state
+}
+
+/// Checks whether the file system a directory is in supports symlinks. File systems that can't
+/// be identified are assumed to support them.
+#[cfg(target_os = "linux")]
+fn supports_symlinks(dir: &Path) -> bool {
+	const MSDOS_SUPER_MAGIC: libc::c_long = 0x4d44;
+	const EXFAT_SUPER_MAGIC: libc::c_long = 0x2011_bab0;
+
+	match statfs(dir) {
+		Some(stats) => !matches!(
+			stats.f_type as libc::c_long,
+			MSDOS_SUPER_MAGIC | EXFAT_SUPER_MAGIC
+		),
+		None => true,
+	}
+}
+
+#[cfg(target_os = "macos")]
+fn supports_symlinks(dir: &Path) -> bool {
+	use std::ffi::CStr;
+
+	match statfs(dir) {
+		Some(stats) => {
+			// SAFETY: the kernel always writes a NUL-terminated name.
+			let name = unsafe { CStr::from_ptr(stats.f_fstypename.as_ptr()) };
+
+			!matches!(name.to_bytes(), b"msdos" | b"exfat")
+		}
+		None => true,
+	}
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn supports_symlinks(_dir: &Path) -> bool {
+	true
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn statfs(path: &Path) -> Option<libc::statfs> {
+	use std::{ffi::CString, mem::MaybeUninit, os::unix::ffi::OsStrExt};
+
+	let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+	let mut stats = MaybeUninit::<libc::statfs>::uninit();
+
+	// SAFETY: the path is NUL-terminated and the buffer is only read if the call succeeds.
+	unsafe {
+		if counted(libc::statfs(path.as_ptr(), stats.as_mut_ptr())) != 0 {
+			return None;
+		}
+
+		Some(stats.assume_init())
+	}
+}
+
+/// Checks whether nothing can be created in a directory, either because its file system is
+/// mounted as read-only or because the directory itself is immutable.
+fn is_read_only(dir: &Path) -> bool {
+	use std::{ffi::CString, mem::MaybeUninit, os::unix::ffi::OsStrExt};
+
+	let Ok(path) = CString::new(dir.as_os_str().as_bytes()) else {
+		return false;
+	};
+	let mut stats = MaybeUninit::<libc::statvfs>::uninit();
+
+	// SAFETY: the path is NUL-terminated and the buffer is only read if the call succeeds.
+	let read_only_mount = unsafe {
+		counted(libc::statvfs(path.as_ptr(), stats.as_mut_ptr())) == 0
+			&& stats.assume_init().f_flag & libc::ST_RDONLY != 0
+	};
+
+	read_only_mount || is_immutable(dir)
+}
+
+#[cfg(target_os = "linux")]
+fn is_immutable(dir: &Path) -> bool {
+	use std::os::fd::AsRawFd;
+
+	const FS_IMMUTABLE_FL: libc::c_int = 0x10;
+
+	let Ok(dir) = counted(fs::File::open(dir)) else {
+		return false;
+	};
+	let mut flags: libc::c_int = 0;
+
+	// SAFETY: the descriptor stays open during the call, which writes an int into the flags.
+	unsafe {
+		counted(libc::ioctl(
+			dir.as_raw_fd(),
+			libc::FS_IOC_GETFLAGS,
+			&mut flags,
+		)) == 0 && flags & FS_IMMUTABLE_FL != 0
+	}
+}
+
+#[cfg(target_os = "macos")]
+fn is_immutable(dir: &Path) -> bool {
+	use std::os::macos::fs::MetadataExt;
+
+	counted(fs::metadata(dir))
+		.is_ok_and(|metadata| metadata.st_flags() & (libc::UF_IMMUTABLE | libc::SF_IMMUTABLE) != 0)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn is_immutable(_dir: &Path) -> bool {
+	false
+}
+
+/// Checks whether a link lives in a directory meant to be private, like the ones holding keys.
+fn is_sensitive(link_path: &Path) -> bool {
+	link_path
+		.components()
+		.any(|component| matches!(component.as_os_str().to_str(), Some(".ssh" | ".gnupg")))
+}
+
+/// Checks whether a target path matches any of the filters.
+fn matches_any(filters: &HashSet<PathBuf>, target_path: &Path) -> bool {
+	filters.contains(target_path)
+		|| filters
+			.iter()
+			.any(|filter| matches_glob(&filter.to_string_lossy(), &target_path.to_string_lossy()))
+}
+
+/// Matches a name against a pattern where '*' matches any sequence of characters and '?'
+/// matches a single one.
+fn matches_glob(pattern: &str, name: &str) -> bool {
+	let (pattern, name): (Vec<char>, Vec<char>) =
+		(pattern.chars().collect(), name.chars().collect());
+	let (mut p, mut n) = (0, 0);
+	let mut backtrack = None;
+
+	while n < name.len() {
+		match pattern.get(p) {
+			Some('*') => {
+				backtrack = Some((p, n));
+				p += 1;
+			}
+			Some('?') => (p, n) = (p + 1, n + 1),
+			Some(c) if *c == name[n] => (p, n) = (p + 1, n + 1),
+			_ => match backtrack {
+				Some((star, matched)) => {
+					backtrack = Some((star, matched + 1));
+					(p, n) = (star + 1, matched + 1);
+				}
+				None => return false,
+			},
+		}
+	}
+
+	pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Checks whether a link name consists of a single normal component, i.e. it's neither absolute
+/// nor does it have parent directories or separators in it.
+fn is_plain_name(name: &Path) -> bool {
+	let mut components = name.components();
+
+	matches!(
+		(components.next(), components.next()),
+		(Some(Component::Normal(_)), None)
+	)
+}
+
+/// Returns the candidate closest to a name, as long as it's similar enough to be a typo.
+fn closest_match<'a, I>(name: &str, candidates: I) -> Option<String>
+where
+	I: Iterator<Item = &'a String>,
+{
+	let max_distance = name.chars().count() / 3 + 1;
+
+	candidates
+		.map(|candidate| (strsim::levenshtein(name, candidate), candidate))
+		.filter(|(distance, _)| *distance <= max_distance)
+		.min()
+		.map(|(_, candidate)| candidate.clone())
+}
+
+/// Collects the remapped prefixes of the active tags along with the ones of no tag, the longest
+/// ones first, so that the most specific prefix of a link path is the one replaced. Among
+/// prefixes of the same length, the ones of tags come first, in the order tags are sorted in,
+/// and the ones of no tag come last.
+pub(super) fn active_remaps(
+	remap: Option<&BTreeMap<String, RemapEntry>>,
+	runtime_tags: &TagSet,
+) -> Vec<(PathBuf, PathBuf)> {
+	let entries = remap.into_iter().flatten();
+	let tagged = entries.clone().filter_map(|(tag, entry)| match entry {
+		RemapEntry::Tagged(prefixes) if runtime_tags.contains(tag) => Some(prefixes.clone()),
+		_ => None,
+	});
+	let untagged = entries.filter_map(|(from, entry)| match entry {
+		RemapEntry::Prefix(to) => Some((PathBuf::from(from), to.clone())),
+		RemapEntry::Tagged(_) => None,
+	});
+
+	let mut remaps: Vec<(PathBuf, PathBuf)> = tagged.flatten().chain(untagged).collect();
+	// Sorting is stable, which keeps the order above among prefixes of the same length.
+	remaps.sort_by_key(|(from, _)| Reverse(from.components().count()));
+
+	remaps
+}
+
+/// Replaces the first remapped prefix a link path starts with, if any. Prefixes match whole
+/// components, so "~/.config" doesn't remap "~/.configs".
+pub(super) fn remap_link(link_path: &Path, remaps: &[(PathBuf, PathBuf)]) -> Option<PathBuf> {
+	remaps.iter().find_map(|(from, to)| {
+		let rest = link_path.strip_prefix(from).ok()?;
+
+		Some(if rest.as_os_str().is_empty() {
+			to.clone()
+		} else {
+			to.join(rest)
+		})
+	})
+}
+
+/// Checks whether no set of active tags enables a target, given the ones it uses. Only OS tags
+/// exclude each other, so activating every other tag along with at most one of them is as
+/// lenient as a machine gets.
+fn is_contradictory(target: &Target, target_tags: &TagSet) -> bool {
+	let other_tags: TagSet = target_tags
+		.iter()
+		.filter(|tag| !EXCLUSIVE_TAGS.contains(&tag.as_str()))
+		.cloned()
+		.collect();
+
+	[None, Some("linux"), Some("macos")]
+		.into_iter()
+		.all(|os_tag| {
+			let mut active_tags = other_tags.clone();
+			active_tags.extend(os_tag.map(String::from));
+
+			!Tree::enables(target, &active_tags)
+		})
+}
+
+/// Tags that are never active together, since they're the ones of operating systems.
+const EXCLUSIVE_TAGS: [&str; 2] = ["linux", "macos"];
+
+/// Checks whether a target's tags let it in, given the active ones.
+fn passes_tags(tags: &Tags, active_tags: &TagSet) -> bool {
+	let Tags { all_of, any_of } = tags;
+	let mut any_of = any_of.iter().flatten().peekable();
+
+	all_of.iter().flatten().all(|tag| active_tags.contains(tag))
+		&& (any_of.peek().is_none() || any_of.any(|tag| active_tags.contains(tag)))
+}
+
+/// Merges OS-specific overrides into a target's link and tags options, field by field.
+pub(super) fn apply_override(
+	link: &mut Option<Link>,
+	tags: &mut Option<Tags>,
+	overrides: TargetOverride,
+) {
+	let TargetOverride {
+		link: link_override,
+		tags: tags_override,
+	} = overrides;
+
+	if let Some(link_override) = link_override {
+		let link = link.get_or_insert_with(Link::default);
+
+		link.base_dir = link_override.base_dir.or(link.base_dir.take());
+		link.name = link_override.name.or(link.name.take());
+		link.mode = link_override.mode.or(link.mode.take());
+		link.copy_mode = link_override.copy_mode.or(link.copy_mode.take());
+	}
+
+	if let Some(tags_override) = tags_override {
+		let tags = tags.get_or_insert_with(Tags::default);
+
+		tags.all_of = tags_override.all_of.or(tags.all_of.take());
+		tags.any_of = tags_override.any_of.or(tags.any_of.take());
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		fs::{self, File},
+		iter,
+		path::PathBuf,
+		time::{Instant, SystemTime},
+	};
+
+	use indoc::indoc;
+	use pretty_assertions::assert_eq;
+
+	use crate::{
+		config::{Link, Remap, TagSet, Tags, TargetMap, When},
+		parser::node::Edges,
+	};
+
+	use super::*;
+
+	impl Tree {
+		/// Same as `link_with`, but only returns what was done if linking succeeded.
+		fn link<F>(self, log: F) -> Result<Linked, Error>
+		where
+			F: FnMut(Action),
+		{
+			let mut linked = Linked::default();
+			self.link_with(&mut linked, log)?;
+
+			Ok(linked)
+		}
+	}
+
+	#[test]
+	fn parse() -> Result<(), IoError> {
+		struct Test<'a> {
+			description: &'a str,
+			input: (Config, (TagSet, HashSet<PathBuf>), LinkOpts),
+			output: Result<Tree, NodeError>,
+		}
+
+		let current_dir = &env::current_dir()?;
+
+		let test_cases = Vec::from([
+			Test {
+				description: "simple config with a single target",
+				input: (
+					Config {
+						targets: Some(TargetMap::from([("foo".into(), Target::default())])),
+						..Config::default()
+					},
+					(TagSet::default(), HashSet::default()),
+					LinkOpts {
+						replace: true,
+						create_dirs: true,
+						..LinkOpts::default()
+					},
+				),
+				output: Ok(Tree {
+					root: Node::Branch(Edges::from([(
+						"foo".into(),
+						Node::Leaf(Box::new("foo".into())),
+					)])),
+					work_dir: current_dir.into(),
+					link_opts: LinkOpts {
+						replace: true,
+						create_dirs: true,
+						..LinkOpts::default()
+					},
+					..Tree::default()
+				}),
+			},
+			Test {
+				description: "remapped link paths",
+				input: (
+					Config {
+						base_dir: Some("/home/park/.config".into()),
+						remap: Some(BTreeMap::from([
+							(
+								"macos".into(),
+								RemapEntry::Tagged(Remap::from([
+									(
+										"/home/park/.config".into(),
+										"/home/park/Library/Application Support".into(),
+									),
+									("/home/park/.config/git".into(), "/home/park/.git".into()),
+								])),
+							),
+							(
+								"linux".into(),
+								RemapEntry::Tagged(Remap::from([(
+									"/home/park".into(),
+									"/home/linux".into(),
+								)])),
+							),
+							(
+								"/home/park/.config".into(),
+								RemapEntry::Prefix("/home/park/.local/config".into()),
+							),
+							(
+								"/home/park/.configs".into(),
+								RemapEntry::Prefix("/home/park/.local/configs".into()),
+							),
+						])),
+						targets: Some(TargetMap::from([
+							("alacritty".into(), Target::default()),
+							("git".into(), Target::default()),
+							(
+								"zshrc".into(),
+								Target {
+									link: Some(Link {
+										base_dir: Some("/home/park/.configs".into()),
+										..Link::default()
+									}),
+									..Target::default()
+								},
+							),
+						])),
+						..Config::default()
+					},
+					(TagSet::from(["macos".into()]), HashSet::default()),
+					LinkOpts::default(),
+				),
+				output: Ok(Tree {
+					root: Node::Branch(Edges::from([
+						(
+							"alacritty".into(),
+							Node::Leaf(Box::new(
+								"/home/park/Library/Application Support/alacritty".into(),
+							)),
+						),
+						("git".into(), Node::Leaf(Box::new("/home/park/.git".into()))),
+						(
+							"zshrc".into(),
+							Node::Leaf(Box::new("/home/park/.local/configs/zshrc".into())),
+						),
+					])),
+					work_dir: current_dir.into(),
+					..Tree::default()
+				}),
+			},
+			Test {
+				description: "target with a link mode",
+				input: (
+					Config {
+						targets: Some(TargetMap::from([(
+							"foo".into(),
+							Target {
+								link: Some(Link {
+									mode: Some(0o700),
+									..Link::default()
+								}),
+								..Target::default()
+							},
+						)])),
+						..Config::default()
+					},
+					(TagSet::default(), HashSet::default()),
+					LinkOpts::default(),
+				),
+				output: Ok(Tree {
+					root: Node::Branch(Edges::from([(
+						"foo".into(),
+						Node::Leaf(Box::new(Leaf {
+							link_path: "foo".into(),
+							link_mode: Some(0o700),
+							..Leaf::default()
+						})),
+					)])),
+					work_dir: current_dir.into(),
+					..Tree::default()
+				}),
+			},
+			Test {
+				description: "simple config with a nested target",
+				input: (
+					Config {
+						targets: Some(TargetMap::from([("foo/bar".into(), Target::default())])),
+						..Config::default()
+					},
+					(TagSet::from([]), HashSet::from([])),
+					LinkOpts::default(),
+				),
+				output: Ok(Tree {
+					root: Node::Branch(Edges::from([(
+						"foo".into(),
+						Node::Branch(Edges::from([(
+							"bar".into(),
+							Node::Leaf(Box::new("bar".into())),
+						)])),
+					)])),
+					work_dir: current_dir.into(),
+					..Tree::default()
+				}),
+			},
+			Test {
+				description: "target with custom options",
+				input: (
+					Config {
+						targets: Some(TargetMap::from([(
+							"foo".into(),
+							Target {
+								link: Some(Link {
+									name: Some("new_name".into()),
+									..Link::default()
+								}),
+								..Target::default()
+							},
+						)])),
+						..Config::default()
+					},
+					(TagSet::from([]), HashSet::from([])),
+					LinkOpts::default(),
+				),
+				output: Ok(Tree {
+					root: Node::Branch(Edges::from([(
+						"foo".into(),
+						Node::Leaf(Box::new("new_name".into())),
+					)])),
+					work_dir: current_dir.into(),
+					..Tree::default()
+				}),
+			},
+			Test {
+				description: "target disabled due to conjunctive tags",
+				input: (
+					Config {
+						targets: Some(TargetMap::from([(
+							"foo".into(),
+							Target {
+								tags: Some(Tags {
+									all_of: Some(TagSet::from(["test".into()])),
+									any_of: Some(TagSet::from(["foo/bar".into()])),
+								}),
+								..Target::default()
+							},
+						)])),
+						..Config::default()
+					},
+					(
+						TagSet::from(["foo".into(), "bar".into()]),
+						HashSet::from([]),
+					),
+					LinkOpts::default(),
+				),
+				output: Ok(Tree {
+					root: Node::Branch(Edges::from([])),
+					work_dir: current_dir.into(),
+					..Tree::default()
+				}),
+			},
+			Test {
+				description: "target enabled with tags #1",
+				input: (
+					Config {
+						targets: Some(TargetMap::from([(
+							"foo".into(),
+							Target {
+								tags: Some(Tags {
+									all_of: Some(TagSet::from(["test".into()])),
+									..Tags::default()
+								}),
+								..Target::default()
+							},
+						)])),
+						..Config::default()
+					},
+					(TagSet::from(["test".into()]), HashSet::from([])),
+					LinkOpts::default(),
+				),
+				output: Ok(Tree {
+					root: Node::Branch(Edges::from([(
+						"foo".into(),
+						Node::Leaf(Box::new(Leaf {
+							link_path: "foo".into(),
+							gate: Gate {
+								all_of: Vec::from(["test".into()]),
+								any_of: Vec::new(),
+							},
+							..Leaf::default()
+						})),
+					)])),
+					work_dir: current_dir.into(),
+					..Tree::default()
+				}),
+			},
+			Test {
+				description: "target enabled with tags #2",
+				input: (
+					Config {
+						targets: Some(TargetMap::from([(
+							"foo".into(),
+							Target {
+								tags: Some(Tags {
+									all_of: Some(TagSet::from(["test".into()])),
+									any_of: Some(TagSet::from(["foo".into(), "bar".into()])),
+								}),
+								..Target::default()
+							},
+						)])),
+						..Config::default()
+					},
+					(
+						TagSet::from(["test".into(), "bar".into()]),
+						HashSet::from([]),
+					),
+					LinkOpts::default(),
+				),
+				output: Ok(Tree {
+					root: Node::Branch(Edges::from([(
+						"foo".into(),
+						Node::Leaf(Box::new(Leaf {
+							link_path: "foo".into(),
+							gate: Gate {
+								all_of: Vec::from(["test".into()]),
+								any_of: Vec::from(["bar".into()]),
+							},
+							..Leaf::default()
+						})),
+					)])),
+					work_dir: current_dir.into(),
+					..Tree::default()
+				}),
+			},
+			Test {
+				description: "target disabled due to disjunctive tags",
+				input: (
+					Config {
+						targets: Some(TargetMap::from([(
+							"foo".into(),
+							Target {
+								tags: Some(Tags {
+									all_of: Some(TagSet::from(["test".into()])),
+									any_of: Some(TagSet::from(["foo".into(), "bar".into()])),
+								}),
+								..Target::default()
+							},
+						)])),
+						..Config::default()
+					},
+					(TagSet::from(["test".into()]), HashSet::from([])),
+					LinkOpts::default(),
+				),
+				output: Ok(Tree {
+					work_dir: current_dir.into(),
+					..Tree::default()
+				}),
+			},
+			Test {
+				description: "target enabled with tags #3",
+				input: (
+					Config {
+						targets: Some(TargetMap::from([(
+							"foo".into(),
+							Target {
+								tags: Some(Tags {
+									any_of: Some(TagSet::from(["test".into()])),
+									..Tags::default()
+								}),
+								..Target::default()
+							},
+						)])),
+						..Config::default()
+					},
+					(TagSet::from(["test".into()]), HashSet::from([])),
+					LinkOpts::default(),
+				),
+				output: Ok(Tree {
+					root: Node::Branch(Edges::from([(
+						"foo".into(),
+						Node::Leaf(Box::new(Leaf {
+							link_path: "foo".into(),
+							gate: Gate {
+								all_of: Vec::new(),
+								any_of: Vec::from(["test".into()]),
+							},
+							..Leaf::default()
+						})),
+					)])),
+					work_dir: current_dir.into(),
+					..Tree::default()
+				}),
+			},
+			Test {
+				description: "target using its file name as link name",
+				input: (
+					Config {
+						targets: Some(TargetMap::from([("foo/bar/baz".into(), Target::default())])),
+						..Config::default()
+					},
+					(TagSet::from([]), HashSet::from([])),
+					LinkOpts::default(),
+				),
+				output: Ok(Tree {
+					root: Node::Branch(Edges::from([(
+						"foo".into(),
+						Node::Branch(Edges::from([(
+							"bar".into(),
+							Node::Branch(Edges::from([(
+								"baz".into(),
+								Node::Leaf(Box::new("baz".into())),
+							)])),
+						)])),
+					)])),
+					work_dir: current_dir.into(),
+					..Tree::default()
+				}),
+			},
+			Test {
+				description: "target enabled with target name filtering",
+				input: (
+					Config {
+						targets: Some(TargetMap::from([
+							("foo/bar".into(), Target::default()),
+							("baz/qux".into(), Target::default()),
+						])),
+						..Config::default()
+					},
+					(TagSet::from([]), HashSet::from(["foo/bar".into()])),
+					LinkOpts::default(),
+				),
+				output: Ok(Tree {
+					root: Node::Branch(Edges::from([(
+						"foo".into(),
+						Node::Branch(Edges::from([(
+							"bar".into(),
+							Node::Leaf(Box::new("bar".into())),
+						)])),
+					)])),
+					work_dir: current_dir.into(),
+					skipped: Vec::from(["baz/qux".into()]),
+					..Tree::default()
+				}),
+			},
+			Test {
+				description: "target enabled with a target pattern",
+				input: (
+					Config {
+						targets: Some(TargetMap::from([
+							("foo/bar".into(), Target::default()),
+							("foo/baz".into(), Target::default()),
+							("qux/bar".into(), Target::default()),
+						])),
+						..Config::default()
+					},
+					(
+						TagSet::from([]),
+						HashSet::from(["foo/*".into(), "qux/?a".into()]),
+					),
+					LinkOpts::default(),
+				),
+				output: Ok(Tree {
+					root: Node::Branch(Edges::from([(
+						"foo".into(),
+						Node::Branch(Edges::from([
+							("bar".into(), Node::Leaf(Box::new("bar".into()))),
+							("baz".into(), Node::Leaf(Box::new("baz".into()))),
+						])),
+					)])),
+					work_dir: current_dir.into(),
+					skipped: Vec::from(["qux/bar".into()]),
+					..Tree::default()
+				}),
+			},
+			Test {
+				description: "seed target",
+				input: (
+					Config {
+						targets: Some(TargetMap::from([(
+							"foo".into(),
+							Target {
+								seed: Some(true),
+								..Target::default()
+							},
+						)])),
+						..Config::default()
+					},
+					(TagSet::from([]), HashSet::from([])),
+					LinkOpts::default(),
+				),
+				output: Ok(Tree {
+					root: Node::Branch(Edges::from([(
+						"foo".into(),
+						Node::Leaf(Box::new(Leaf {
+							link_path: "foo".into(),
+							seed: true,
+							..Leaf::default()
+						})),
+					)])),
+					work_dir: current_dir.into(),
+					..Tree::default()
+				}),
+			},
+			Test {
+				description: "target with OS-specific overrides",
+				input: (
+					Config {
+						targets: Some(TargetMap::from([
+							(
+								"foo".into(),
+								Target {
+									link: Some(Link {
+										name: Some("foo_name".into()),
+										..Link::default()
+									}),
+									linux: Some(TargetOverride {
+										link: Some(Link {
+											base_dir: Some("linux_dir".into()),
+											..Link::default()
+										}),
+										..TargetOverride::default()
+									}),
+									macos: Some(TargetOverride {
+										link: Some(Link {
+											base_dir: Some("macos_dir".into()),
+											..Link::default()
+										}),
+										..TargetOverride::default()
+									}),
+									..Target::default()
+								},
+							),
+							(
+								"bar".into(),
+								Target {
+									linux: Some(TargetOverride {
+										tags: Some(Tags {
+											all_of: Some(TagSet::from(["baz".into()])),
+											..Tags::default()
+										}),
+										..TargetOverride::default()
+									}),
+									..Target::default()
+								},
+							),
+						])),
+						..Config::default()
+					},
+					(TagSet::from(["linux".into()]), HashSet::from([])),
+					LinkOpts::default(),
+				),
+				output: Ok(Tree {
+					root: Node::Branch(Edges::from([(
+						"foo".into(),
+						Node::Leaf(Box::new("linux_dir/foo_name".into())),
+					)])),
+					work_dir: current_dir.into(),
+					..Tree::default()
+				}),
+			},
+			Test {
+				description: "target with a source outside the work directory",
+				input: (
+					Config {
+						targets: Some(TargetMap::from([(
+							"foo".into(),
+							Target {
+								source: Some("/tmp/generated/foo".into()),
+								..Target::default()
+							},
+						)])),
+						..Config::default()
+					},
+					(TagSet::from([]), HashSet::from([])),
+					LinkOpts::default(),
+				),
+				output: Ok(Tree {
+					root: Node::Branch(Edges::from([(
+						"foo".into(),
+						Node::Leaf(Box::new(Leaf {
+							link_path: "foo".into(),
+							source: Some("/tmp/generated/foo".into()),
+							..Leaf::default()
+						})),
+					)])),
+					work_dir: current_dir.into(),
+					..Tree::default()
+				}),
+			},
+			Test {
+				description: "link name escaping its base directory",
+				input: (
+					Config {
+						targets: Some(TargetMap::from([(
+							"foo".into(),
+							Target {
+								link: Some(Link {
+									name: Some("../foo".into()),
+									..Link::default()
+								}),
+								..Target::default()
+							},
+						)])),
+						..Config::default()
+					},
+					(TagSet::from([]), HashSet::from([])),
+					LinkOpts::default(),
+				),
+				output: Err(NodeError::UnsafeLinkName("foo".into(), "../foo".into())),
+			},
+			Test {
+				description: "absolute link name",
+				input: (
+					Config {
+						targets: Some(TargetMap::from([(
+							"foo".into(),
+							Target {
+								link: Some(Link {
+									name: Some("/tmp/foo".into()),
+									..Link::default()
+								}),
+								..Target::default()
+							},
+						)])),
+						..Config::default()
+					},
+					(TagSet::from([]), HashSet::from([])),
+					LinkOpts::default(),
+				),
+				output: Err(NodeError::UnsafeLinkName("foo".into(), "/tmp/foo".into())),
+			},
+			Test {
+				description: "targets gated by scripts",
+				input: (
+					Config {
+						targets: Some(TargetMap::from([
+							(
+								"foo".into(),
+								Target {
+									when: Some(When {
+										script: Some("tests/when/pass.sh".into()),
+									}),
+									..Target::default()
+								},
+							),
+							(
+								"bar".into(),
+								Target {
+									when: Some(When {
+										script: Some("tests/when/fail.sh".into()),
+									}),
+									..Target::default()
+								},
+							),
+						])),
+						..Config::default()
+					},
+					(TagSet::from([]), HashSet::from([])),
+					LinkOpts::default(),
+				),
+				output: Ok(Tree {
+					root: Node::Branch(Edges::from([(
+						"foo".into(),
+						Node::Leaf(Box::new("foo".into())),
+					)])),
+					work_dir: current_dir.into(),
+					scripts: HashMap::from([
+						("tests/when/pass.sh".into(), true),
+						("tests/when/fail.sh".into(), false),
+					]),
+					..Tree::default()
+				}),
+			},
+			Test {
+				description: "target gated by a missing script",
+				input: (
+					Config {
+						targets: Some(TargetMap::from([(
+							"foo".into(),
+							Target {
+								when: Some(When {
+									script: Some("tests/when/missing.sh".into()),
+								}),
+								..Target::default()
+							},
+						)])),
+						..Config::default()
+					},
+					(TagSet::from([]), HashSet::from([])),
+					LinkOpts::default(),
+				),
+				output: Err(NodeError::FailedScript(
+					"foo".into(),
+					"tests/when/missing.sh".into(),
+					"No such file or directory (os error 2)".into(),
+				)),
+			},
+			Test {
+				description: "target within another target",
+				input: (
+					Config {
+						targets: Some(TargetMap::from([
+							("nvim".into(), Target::default()),
+							("nvim/init.lua".into(), Target::default()),
+						])),
+						..Config::default()
+					},
+					(TagSet::from([]), HashSet::from([])),
+					LinkOpts::default(),
+				),
+				output: Err(NodeError::OverlappingTargets(
+					"nvim".into(),
+					"nvim/init.lua".into(),
+				)),
+			},
+			Test {
+				description: "link within the link of another target",
+				input: (
+					Config {
+						base_dir: Some("/home/park/.config".into()),
+						targets: Some(TargetMap::from([
+							("nvim".into(), Target::default()),
+							(
+								"init.lua".into(),
+								Target {
+									link: Some(Link {
+										base_dir: Some("/home/park/.config/nvim".into()),
+										..Link::default()
+									}),
+									..Target::default()
+								},
+							),
+						])),
+						..Config::default()
+					},
+					(TagSet::from([]), HashSet::from([])),
+					LinkOpts::default(),
+				),
+				output: Err(NodeError::OverlappingLinks(
+					"nvim".into(),
+					"init.lua".into(),
+					"/home/park/.config/nvim".into(),
+				)),
+			},
+			Test {
+				description: "link name as a path when explicitly allowed",
+				input: (
+					Config {
+						allow_path_names: Some(true),
+						targets: Some(TargetMap::from([(
+							"foo".into(),
+							Target {
+								link: Some(Link {
+									name: Some("config/foo".into()),
+									..Link::default()
+								}),
+								..Target::default()
+							},
+						)])),
+						..Config::default()
+					},
+					(TagSet::from([]), HashSet::from([])),
+					LinkOpts::default(),
+				),
+				output: Ok(Tree {
+					root: Node::Branch(Edges::from([(
+						"foo".into(),
+						Node::Leaf(Box::new("config/foo".into())),
+					)])),
+					work_dir: current_dir.into(),
+					..Tree::default()
+				}),
+			},
+		]);
+
+		for case in test_cases {
+			let got = Tree::parse(case.input.0, case.input.1, case.input.2);
+
+			assert_eq!(got, case.output, "bad result for {:?}", case.description);
+		}
+
+		Ok(())
+	}
+
+	#[test]
+	fn parse_in_stable_order() {
+		let config: Config = toml::from_str(indoc! {r#"
+			[targets."zsh/zshrc"]
+			[targets.Xresources]
+			[targets."nvim/lua/init.lua"]
+			[targets."nvim/init.lua"]
+			[targets.alacritty]
+		"#})
+		.unwrap();
+
+		let tree = Tree::parse(config, Default::default(), LinkOpts::default()).unwrap();
+		let got: Vec<PathBuf> = tree
+			.root
+			.into_iter()
+			.map(|IterElement { target_path, .. }| target_path)
+			.collect();
+
+		assert_eq!(
+			got,
+			Vec::<PathBuf>::from([
+				"".into(),
+				"Xresources".into(),
+				"alacritty".into(),
+				"nvim".into(),
+				"nvim/init.lua".into(),
+				"nvim/lua".into(),
+				"nvim/lua/init.lua".into(),
+				"zsh".into(),
+				"zsh/zshrc".into(),
+			]),
+		);
+	}
+
+	#[test]
+	fn analyze_tree() -> Result<(), IoError> {
+		struct Test<'a> {
+			description: &'a str,
+			input: Tree,
+			output: Tree,
+		}
+
+		let current_dir = &env::current_dir()?;
+
+		let test_cases = Vec::from([
+			Test {
+				description: "single target should be ready",
+				input: Tree {
+					root: Node::Branch(Edges::from([(
+						"tests/foo".into(),
+						Node::Leaf(Box::new("tests/foo".into())),
+					)])),
+					work_dir: current_dir.into(),
+					..Tree::default()
+				},
+				output: Tree {
+					root: Node::Branch(Edges::from([(
+						"tests/foo".into(),
+						Node::Leaf(Box::new("tests/foo".into())),
+					)])),
+					work_dir: current_dir.into(),
+					statuses: Statuses::from([("tests/foo".into(), Status::Ready)]),
+					..Tree::default()
+				},
+			},
+			Test {
+				description:
+					"single target whose parent is nonexistent should be unparented (problem)",
+				input: Tree {
+					root: Node::Branch(Edges::from([(
+						"xxx/foo".into(),
+						Node::Leaf(Box::new("xxx/foo".into())),
+					)])),
+					work_dir: current_dir.into(),
+					..Tree::default()
+				},
+				output: Tree {
+					root: Node::Branch(Edges::from([(
+						"xxx/foo".into(),
+						Node::Leaf(Box::new("xxx/foo".into())),
+					)])),
+					work_dir: current_dir.into(),
+					problems: Problems::from([("xxx/foo".into(), Status::Unparented)]),
+					..Tree::default()
+				},
+			},
+			Test {
+				description: "single target whose parent is nonexistent should be unparented (ok)",
+				input: Tree {
+					root: Node::Branch(Edges::from([(
+						"xxx/foo".into(),
+						Node::Leaf(Box::new("xxx/foo".into())),
+					)])),
+					work_dir: current_dir.into(),
+					link_opts: LinkOpts {
+						create_dirs: true,
+						..LinkOpts::default()
+					},
+					..Tree::default()
+				},
+				output: Tree {
+					root: Node::Branch(Edges::from([(
+						"xxx/foo".into(),
+						Node::Leaf(Box::new("xxx/foo".into())),
+					)])),
+					work_dir: current_dir.into(),
+					link_opts: LinkOpts {
+						create_dirs: true,
+						..LinkOpts::default()
 					},
-				),
-				output: Ok(Tree {
-					root: Node::Branch(Edges::from([("foo".into(), Node::Leaf("foo".into()))])),
+					statuses: Statuses::from([("xxx/foo".into(), Status::Unparented)]),
+					..Tree::default()
+				},
+			},
+			Test {
+				description: "single target whose base directory is empty",
+				input: Tree {
+					root: Node::Branch(Edges::from([(
+						"foo".into(),
+						Node::Leaf(Box::new("foo".into())),
+					)])),
+					work_dir: current_dir.into(),
+					..Tree::default()
+				},
+				output: Tree {
+					root: Node::Branch(Edges::from([(
+						"foo".into(),
+						Node::Leaf(Box::new("foo".into())),
+					)])),
+					work_dir: current_dir.into(),
+					statuses: Statuses::from([("foo".into(), Status::Ready)]),
+					..Tree::default()
+				},
+			},
+			Test {
+				description: "single target has conflict",
+				input: Tree {
+					root: Node::Branch(Edges::from([(
+						"LICENSE".into(),
+						Node::Leaf(Box::new("LICENSE".into())),
+					)])),
+					work_dir: current_dir.into(),
+					..Tree::default()
+				},
+				output: Tree {
+					root: Node::Branch(Edges::from([(
+						"LICENSE".into(),
+						Node::Leaf(Box::new("LICENSE".into())),
+					)])),
+					work_dir: current_dir.into(),
+					problems: Problems::from([("LICENSE".into(), Status::Conflict)]),
+					..Tree::default()
+				},
+			},
+			Test {
+				description: "single target with wrong existing link (ok)",
+				input: Tree {
+					root: Node::Branch(Edges::from([(
+						"something".into(),
+						Node::Leaf(Box::new("tests/data/something".into())),
+					)])),
 					work_dir: current_dir.into(),
 					link_opts: LinkOpts {
 						replace: true,
-						create_dirs: true,
+						..LinkOpts::default()
 					},
 					..Tree::default()
-				}),
+				},
+				output: Tree {
+					root: Node::Branch(Edges::from([(
+						"something".into(),
+						Node::Leaf(Box::new("tests/data/something".into())),
+					)])),
+					work_dir: current_dir.into(),
+					link_opts: LinkOpts {
+						replace: true,
+						..LinkOpts::default()
+					},
+					statuses: Statuses::from([("tests/data/something".into(), Status::Mismatch)]),
+					..Tree::default()
+				},
+			},
+			Test {
+				description: "single target with existing link not created by park",
+				input: Tree {
+					root: Node::Branch(Edges::from([(
+						"something".into(),
+						Node::Leaf(Box::new("tests/data/something".into())),
+					)])),
+					work_dir: current_dir.into(),
+					link_opts: LinkOpts {
+						replace: true,
+						..LinkOpts::default()
+					},
+					managed: Some(BTreeSet::from(["tests/data/other".into()])),
+					..Tree::default()
+				},
+				output: Tree {
+					root: Node::Branch(Edges::from([(
+						"something".into(),
+						Node::Leaf(Box::new("tests/data/something".into())),
+					)])),
+					work_dir: current_dir.into(),
+					link_opts: LinkOpts {
+						replace: true,
+						..LinkOpts::default()
+					},
+					managed: Some(BTreeSet::from(["tests/data/other".into()])),
+					problems: Problems::from([("tests/data/something".into(), Status::Foreign)]),
+					..Tree::default()
+				},
+			},
+			Test {
+				description: "single target with existing link created by park",
+				input: Tree {
+					root: Node::Branch(Edges::from([(
+						"something".into(),
+						Node::Leaf(Box::new("tests/data/something".into())),
+					)])),
+					work_dir: current_dir.into(),
+					managed: Some(BTreeSet::from(["tests/data/something".into()])),
+					..Tree::default()
+				},
+				output: Tree {
+					root: Node::Branch(Edges::from([(
+						"something".into(),
+						Node::Leaf(Box::new("tests/data/something".into())),
+					)])),
+					work_dir: current_dir.into(),
+					managed: Some(BTreeSet::from(["tests/data/something".into()])),
+					problems: Problems::from([("tests/data/something".into(), Status::Mismatch)]),
+					..Tree::default()
+				},
+			},
+			Test {
+				description: "single target with correct existing link",
+				input: Tree {
+					root: Node::Branch(Edges::from([(
+						"something".into(),
+						Node::Leaf(Box::new("tests/data/something".into())),
+					)])),
+					work_dir: "test".into(),
+					..Tree::default()
+				},
+				output: Tree {
+					root: Node::Branch(Edges::from([(
+						"something".into(),
+						Node::Leaf(Box::new("tests/data/something".into())),
+					)])),
+					work_dir: "test".into(),
+					statuses: Statuses::from([("tests/data/something".into(), Status::Done)]),
+					..Tree::default()
+				},
+			},
+			Test {
+				description: "link with invalid parent directory",
+				input: Tree {
+					root: Node::Branch(Edges::from([(
+						"something".into(),
+						Node::Leaf(Box::new("LICENSE/something".into())),
+					)])),
+					work_dir: "test".into(),
+					..Tree::default()
+				},
+				output: Tree {
+					root: Node::Branch(Edges::from([(
+						"something".into(),
+						Node::Leaf(Box::new("LICENSE/something".into())),
+					)])),
+					work_dir: "test".into(),
+					problems: Problems::from([("LICENSE/something".into(), Status::Obstructed)]),
+					..Tree::default()
+				},
+			},
+			Test {
+				description: "link with invalid parent path",
+				input: Tree {
+					root: Node::Branch(Edges::from([(
+						"something".into(),
+						Node::Leaf(Box::new("LICENSE/foo/bar/something".into())),
+					)])),
+					work_dir: "test".into(),
+					..Tree::default()
+				},
+				output: Tree {
+					root: Node::Branch(Edges::from([(
+						"something".into(),
+						Node::Leaf(Box::new("LICENSE/foo/bar/something".into())),
+					)])),
+					work_dir: "test".into(),
+					problems: Problems::from([(
+						"LICENSE/foo/bar/something".into(),
+						Status::Obstructed,
+					)]),
+					..Tree::default()
+				},
+			},
+			Test {
+				description: "seed whose link path is taken should be done",
+				input: Tree {
+					root: Node::Branch(Edges::from([(
+						"LICENSE".into(),
+						Node::Leaf(Box::new(Leaf {
+							link_path: "LICENSE".into(),
+							seed: true,
+							..Leaf::default()
+						})),
+					)])),
+					work_dir: current_dir.into(),
+					..Tree::default()
+				},
+				output: Tree {
+					root: Node::Branch(Edges::from([(
+						"LICENSE".into(),
+						Node::Leaf(Box::new(Leaf {
+							link_path: "LICENSE".into(),
+							seed: true,
+							..Leaf::default()
+						})),
+					)])),
+					work_dir: current_dir.into(),
+					statuses: Statuses::from([("LICENSE".into(), Status::Done)]),
+					..Tree::default()
+				},
+			},
+			Test {
+				description: "clone with the same content should be done",
+				input: Tree {
+					root: Node::Branch(Edges::from([(
+						"LICENSE".into(),
+						Node::Leaf(Box::new(Leaf {
+							link_path: "LICENSE".into(),
+							reflink: true,
+							..Leaf::default()
+						})),
+					)])),
+					work_dir: current_dir.into(),
+					..Tree::default()
+				},
+				output: Tree {
+					root: Node::Branch(Edges::from([(
+						"LICENSE".into(),
+						Node::Leaf(Box::new(Leaf {
+							link_path: "LICENSE".into(),
+							reflink: true,
+							..Leaf::default()
+						})),
+					)])),
+					work_dir: current_dir.into(),
+					statuses: Statuses::from([("LICENSE".into(), Status::Done)]),
+					..Tree::default()
+				},
+			},
+			Test {
+				description: "clone with a different content should mismatch",
+				input: Tree {
+					root: Node::Branch(Edges::from([(
+						"LICENSE".into(),
+						Node::Leaf(Box::new(Leaf {
+							link_path: "README.md".into(),
+							reflink: true,
+							..Leaf::default()
+						})),
+					)])),
+					work_dir: current_dir.into(),
+					..Tree::default()
+				},
+				output: Tree {
+					root: Node::Branch(Edges::from([(
+						"LICENSE".into(),
+						Node::Leaf(Box::new(Leaf {
+							link_path: "README.md".into(),
+							reflink: true,
+							..Leaf::default()
+						})),
+					)])),
+					work_dir: current_dir.into(),
+					problems: Problems::from([("README.md".into(), Status::Mismatch)]),
+					..Tree::default()
+				},
+			},
+			Test {
+				description: "seed whose link path is free should be ready",
+				input: Tree {
+					root: Node::Branch(Edges::from([(
+						"seed".into(),
+						Node::Leaf(Box::new(Leaf {
+							link_path: "tests/seed".into(),
+							seed: true,
+							..Leaf::default()
+						})),
+					)])),
+					work_dir: current_dir.into(),
+					..Tree::default()
+				},
+				output: Tree {
+					root: Node::Branch(Edges::from([(
+						"seed".into(),
+						Node::Leaf(Box::new(Leaf {
+							link_path: "tests/seed".into(),
+							seed: true,
+							..Leaf::default()
+						})),
+					)])),
+					work_dir: current_dir.into(),
+					statuses: Statuses::from([("tests/seed".into(), Status::Ready)]),
+					..Tree::default()
+				},
+			},
+		]);
+
+		for mut case in test_cases {
+			case.input.analyze()?;
+
+			assert_eq!(
+				case.input, case.output,
+				"bad result for {:?}",
+				case.description
+			);
+		}
+
+		Ok(())
+	}
+
+	#[test]
+	fn analyze_with_multiple_jobs() {
+		let tree = |jobs| Tree {
+			root: Node::Branch(Edges::from([
+				("foo".into(), Node::Leaf(Box::new("tests/xxx/foo".into()))),
+				("bar".into(), Node::Leaf(Box::new("tests/bar".into()))),
+				("LICENSE".into(), Node::Leaf(Box::new("LICENSE".into()))),
+				("baz".into(), Node::Leaf(Box::new("LICENSE/baz".into()))),
+				("qux".into(), Node::Leaf(Box::new("tests/qux".into()))),
+			])),
+			work_dir: "fake_path".into(),
+			jobs,
+			..Tree::default()
+		};
+
+		for jobs in [2, 3, 10] {
+			let (mut sequential, mut concurrent) = (tree(1), tree(jobs));
+			sequential.analyze().unwrap();
+			concurrent.analyze().unwrap();
+
+			assert_eq!(
+				(concurrent.statuses, concurrent.problems),
+				(sequential.statuses, sequential.problems),
+				"bad analysis with {} jobs",
+				jobs
+			);
+		}
+	}
+
+	#[test]
+	fn analyze_filtered_subtree() -> Result<(), NodeError> {
+		let config = Config {
+			base_dir: Some("tests".into()),
+			work_dir: Some("fake_path".into()),
+			targets: Some(TargetMap::from([
+				("foo".into(), Target::default()),
+				("bar/baz".into(), Target::default()),
+				("bar/qux".into(), Target::default()),
+			])),
+			..Config::default()
+		};
+
+		let mut tree = Tree::parse(
+			config,
+			(TagSet::new(), HashSet::from(["bar/*".into()])),
+			LinkOpts::default(),
+		)?;
+		tree.analyze().unwrap();
+
+		assert_eq!(tree.skipped, Vec::from([PathBuf::from("foo")]));
+		assert_eq!(
+			tree.statuses,
+			Statuses::from([
+				("tests/baz".into(), Status::Ready),
+				("tests/qux".into(), Status::Ready),
+			]),
+		);
+		assert_eq!(
+			tree.root
+				.into_iter()
+				.map(|element| element.target_path)
+				.collect::<Vec<_>>(),
+			Vec::from([
+				PathBuf::new(),
+				"bar".into(),
+				"bar/baz".into(),
+				"bar/qux".into(),
+			]),
+		);
+
+		Ok(())
+	}
+
+	/// Compares parsing and analyzing a large configuration whole against doing it for a
+	/// hundredth of its targets. Run with `cargo test --release bench_ -- --ignored --nocapture`.
+	#[test]
+	#[ignore = "benchmark"]
+	fn bench_filtered_analysis() -> Result<(), NodeError> {
+		const TARGETS: usize = 20_000;
+		const ROUNDS: u32 = 5;
+
+		let config = || Config {
+			base_dir: Some("tests".into()),
+			work_dir: Some("fake_path".into()),
+			targets: Some(
+				(0..TARGETS)
+					.map(|i| {
+						(
+							format!("group{}/file{}", i % 100, i).into(),
+							Target::default(),
+						)
+					})
+					.collect(),
+			),
+			..Config::default()
+		};
+
+		let measure = |target_filters: HashSet<PathBuf>| -> Result<_, NodeError> {
+			let configs: Vec<Config> = (0..ROUNDS).map(|_| config()).collect();
+			let start = Instant::now();
+			let mut statuses = 0;
+			for config in configs {
+				let mut tree = Tree::parse(
+					config,
+					(TagSet::new(), target_filters.clone()),
+					LinkOpts::default(),
+				)?;
+				tree.analyze().unwrap();
+				statuses = tree.statuses.len();
+			}
+
+			Ok((start.elapsed() / ROUNDS, statuses))
+		};
+
+		let (whole, whole_statuses) = measure(HashSet::new())?;
+		let (filtered, filtered_statuses) = measure(HashSet::from(["group0/*".into()]))?;
+
+		eprintln!("whole:    {} targets in {:?}", whole_statuses, whole);
+		eprintln!("filtered: {} targets in {:?}", filtered_statuses, filtered);
+
+		assert_eq!(whole_statuses, TARGETS);
+		assert_eq!(filtered_statuses, TARGETS / 100);
+
+		Ok(())
+	}
+
+	#[test]
+	fn analyze_without_symlink_support() {
+		let tree = Tree {
+			work_dir: "fake_path".into(),
+			..Tree::default()
+		};
+		let mut ancestors = Ancestors::from([(
+			"tests".into(),
+			AncestorState::Directory {
+				symlinks: false,
+				read_only: false,
 			},
-			Test {
-				description: "simple config with a nested target",
-				input: (
-					Config {
-						targets: Some(TargetMap::from([("foo/bar".into(), Target::default())])),
-						..Config::default()
+		)]);
+
+		let got = [
+			Leaf::from("tests/foo"),
+			Leaf {
+				link_path: "tests/foo".into(),
+				seed: true,
+				..Leaf::default()
+			},
+			Leaf::from("tests/xxx/foo"),
+		]
+		.map(|leaf| tree.status_of(Path::new("foo"), &leaf, &mut ancestors));
+
+		assert_eq!(
+			got,
+			[Status::Unsupported, Status::Ready, Status::Unparented]
+		);
+	}
+
+	#[test]
+	fn analyze_read_only_destination() {
+		let tree = Tree {
+			work_dir: env::current_dir().unwrap(),
+			..Tree::default()
+		};
+		let mut ancestors = Ancestors::from([(
+			"tests".into(),
+			AncestorState::Directory {
+				symlinks: true,
+				read_only: true,
+			},
+		)]);
+
+		let got = [
+			("foo", "tests/foo"),
+			("bar", "tests/xxx/bar"),
+			("something", "tests/data/something"),
+		]
+		.map(|(target_path, link_path)| {
+			tree.status_of(Path::new(target_path), &link_path.into(), &mut ancestors)
+		});
+
+		assert_eq!(got, [Status::ReadOnly, Status::ReadOnly, Status::Mismatch]);
+
+		ancestors.insert(
+			"tests/data".into(),
+			AncestorState::Directory {
+				symlinks: true,
+				read_only: true,
+			},
+		);
+
+		assert_eq!(
+			tree.status_of(
+				Path::new("something"),
+				&"tests/data/something".into(),
+				&mut ancestors
+			),
+			Status::ReadOnly,
+		);
+	}
+
+	#[test]
+	fn analyze_with_create_dirs_roots() -> Result<(), IoError> {
+		let current_dir = env::current_dir()?;
+
+		for (roots, problem) in [
+			(None, false),
+			(Some(Vec::from([current_dir.join("tests")])), false),
+			(Some(Vec::from([current_dir.join("src")])), true),
+			(Some(Vec::from(["tests".into()])), false),
+			(Some(Vec::from(["./src".into()])), true),
+		] {
+			let mut tree = Tree {
+				root: Node::Branch(Edges::from([(
+					"foo".into(),
+					Node::Leaf(Box::new("tests/xxx/foo".into())),
+				)])),
+				work_dir: "fake_path".into(),
+				link_opts: LinkOpts {
+					create_dirs: true,
+					create_dirs_roots: roots.clone(),
+					..LinkOpts::default()
+				},
+				..Tree::default()
+			};
+			tree.analyze()?;
+
+			let remediations: Vec<Remediation> = tree
+				.problems()
+				.map(|Problem { remediation, .. }| remediation)
+				.collect();
+
+			assert_eq!(
+				(
+					tree.problems.contains_key(Path::new("tests/xxx/foo")),
+					remediations
+				),
+				if problem {
+					(true, Vec::from([Remediation::ManualIntervention]))
+				} else {
+					(false, Vec::new())
+				},
+				"bad analysis for roots {:?}",
+				roots
+			);
+		}
+
+		Ok(())
+	}
+
+	#[test]
+	fn analyze_looping_ancestor() -> Result<(), IoError> {
+		let tree = Tree {
+			work_dir: "fake_path".into(),
+			..Tree::default()
+		};
+
+		unix_fs::symlink("looping", "tests/looping")?;
+		let got = [
+			("foo", "tests/looping/foo"),
+			("bar", "tests/looping/xxx/bar"),
+		]
+		.map(|(target_path, link_path)| tree.diagnose(Path::new(target_path), &link_path.into()));
+		fs::remove_file("tests/looping")?;
+
+		let looping = (
+			Status::Looping,
+			r#""tests/looping" is a symlink that loops"#.into(),
+		);
+		assert_eq!(got, [looping.clone(), looping]);
+
+		Ok(())
+	}
+
+	#[test]
+	fn analyze_symlinked_dirs() -> Result<(), IoError> {
+		let tree = Tree {
+			work_dir: "tests/symlinked/dotfiles".into(),
+			..Tree::default()
+		};
+		let diagnose = |link_path: &str| tree.diagnose(Path::new("foo"), &link_path.into()).0;
+
+		let got = (|| {
+			fs::create_dir_all("tests/symlinked/real/dotfiles")?;
+			unix_fs::symlink("real/dotfiles", "tests/symlinked/dotfiles")?;
+			unix_fs::symlink("real", "tests/symlinked/config")?;
+			unix_fs::symlink("missing", "tests/symlinked/dangling")?;
+
+			// Links made through either directory point to the same target.
+			let destination = env::current_dir()?.join("tests/symlinked/real/dotfiles/foo");
+			unix_fs::symlink(&destination, "tests/symlinked/config/foo")?;
+			unix_fs::symlink("dotfiles/foo", "tests/symlinked/real/bar")?;
+
+			Ok::<_, IoError>([
+				diagnose("tests/symlinked/config/foo"),
+				diagnose("tests/symlinked/real/foo"),
+				diagnose("tests/symlinked/config/bar"),
+				diagnose("tests/symlinked/dangling/foo"),
+			])
+		})();
+		fs::remove_dir_all("tests/symlinked")?;
+
+		assert_eq!(
+			got?,
+			[Status::Done, Status::Done, Status::Done, Status::Obstructed,]
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn retry_transient_errors() {
+		let retry = Retry {
+			attempts: 2,
+			delay: Duration::ZERO,
+		};
+		let run = |kinds: &[IoErrorKind]| {
+			let mut kinds = kinds.iter();
+			let mut calls = 0;
+			let result = retry.run(|| {
+				calls += 1;
+				kinds
+					.next()
+					.map_or(Ok(()), |kind| Err(IoError::from(*kind)))
+			});
+
+			(result.map_err(|err| err.kind()), calls)
+		};
+
+		assert_eq!(run(&[IoErrorKind::WouldBlock]), (Ok(()), 2));
+		assert_eq!(
+			run(&[IoErrorKind::StorageFull; 3]),
+			(Err(IoErrorKind::StorageFull), 3)
+		);
+		assert_eq!(
+			run(&[IoErrorKind::PermissionDenied]),
+			(Err(IoErrorKind::PermissionDenied), 1)
+		);
+	}
+
+	#[test]
+	fn analyze_clone_with_deep_check() -> Result<(), IoError> {
+		let leaf = Leaf {
+			link_path: "tests/LICENSE.clone".into(),
+			reflink: true,
+			..Leaf::default()
+		};
+		let seed = Leaf {
+			link_path: "tests/LICENSE.clone".into(),
+			seed: true,
+			..Leaf::default()
+		};
+		let diagnose = |leaf: &Leaf, deep_check| {
+			let tree = Tree {
+				work_dir: env::current_dir().unwrap(),
+				link_opts: LinkOpts {
+					deep_check,
+					..LinkOpts::default()
+				},
+				..Tree::default()
+			};
+
+			tree.diagnose(Path::new("LICENSE"), leaf).0
+		};
+
+		// A newer clone of the same size is taken to be up to date, unless its content is checked.
+		let mut content = fs::read("LICENSE")?;
+		content.reverse();
+		fs::write("tests/LICENSE.clone", &content)?;
+		let edited = [diagnose(&leaf, false), diagnose(&leaf, true)];
+
+		// An edited seed is never compared, since it's meant to diverge from its target.
+		let seeded = diagnose(&seed, true);
+
+		// An older clone has its content checked regardless.
+		fs::copy("LICENSE", "tests/LICENSE.clone")?;
+		File::options()
+			.write(true)
+			.open("tests/LICENSE.clone")?
+			.set_modified(SystemTime::UNIX_EPOCH)?;
+		let older = diagnose(&leaf, false);
+		fs::remove_file("tests/LICENSE.clone")?;
+
+		assert_eq!(edited, [Status::Done, Status::Mismatch]);
+		assert_eq!(older, Status::Done);
+		assert_eq!(seeded, Status::Done);
+
+		Ok(())
+	}
+
+	#[test]
+	fn analyze_shared_ancestors_once() {
+		let tree = Tree {
+			work_dir: "fake_path".into(),
+			..Tree::default()
+		};
+		let mut ancestors = Ancestors::new();
+
+		let got: Vec<Status> = ["foo", "bar", "baz"]
+			.into_iter()
+			.zip(["tests/xxx/foo", "tests/xxx/bar", "tests/baz"])
+			.map(|(target_path, link_path)| {
+				tree.status_of(Path::new(target_path), &link_path.into(), &mut ancestors)
+			})
+			.collect();
+
+		assert_eq!(
+			got,
+			Vec::from([Status::Unparented, Status::Unparented, Status::Ready])
+		);
+		assert_eq!(
+			ancestors,
+			Ancestors::from([
+				("tests/xxx".into(), AncestorState::Missing),
+				(
+					"tests".into(),
+					AncestorState::Directory {
+						symlinks: true,
+						read_only: false,
 					},
-					(TagSet::from([]), HashSet::from([])),
-					LinkOpts::default(),
 				),
-				output: Ok(Tree {
-					root: Node::Branch(Edges::from([(
-						"foo".into(),
-						Node::Branch(Edges::from([("bar".into(), Node::Leaf("bar".into()))])),
-					)])),
-					work_dir: current_dir.into(),
-					..Tree::default()
-				}),
-			},
+			]),
+		);
+	}
+
+	#[test]
+	fn classify_snapshots() {
+		struct Test<'a> {
+			description: &'a str,
+			input: (LinkOpts, Leaf, Snapshot),
+			output: Status,
+		}
+
+		let directory = |read_only| {
+			Some((
+				PathBuf::from("/home/park"),
+				AncestorState::Directory {
+					symlinks: true,
+					read_only,
+				},
+			))
+		};
+
+		let test_cases = Vec::from([
 			Test {
-				description: "target with custom options",
+				description: "nothing in the link path",
 				input: (
-					Config {
-						targets: Some(TargetMap::from([(
-							"foo".into(),
-							Target {
-								link: Some(Link {
-									name: Some("new_name".into()),
-									..Link::default()
-								}),
-								..Target::default()
-							},
-						)])),
-						..Config::default()
-					},
-					(TagSet::from([]), HashSet::from([])),
 					LinkOpts::default(),
+					"/home/park/foo".into(),
+					Snapshot {
+						ancestor: directory(false),
+						parent_exists: true,
+						..Snapshot::default()
+					},
 				),
-				output: Ok(Tree {
-					root: Node::Branch(Edges::from([(
-						"foo".into(),
-						Node::Leaf("new_name".into()),
-					)])),
-					work_dir: current_dir.into(),
-					..Tree::default()
-				}),
+				output: Status::Ready,
 			},
 			Test {
-				description: "target disabled due to conjunctive tags",
+				description: "missing parent directory",
 				input: (
-					Config {
-						targets: Some(TargetMap::from([(
-							"foo".into(),
-							Target {
-								tags: Some(Tags {
-									all_of: Some(TagSet::from(["test".into()])),
-									any_of: Some(TagSet::from(["foo/bar".into()])),
-								}),
-								..Target::default()
-							},
-						)])),
-						..Config::default()
-					},
-					(
-						TagSet::from(["foo".into(), "bar".into()]),
-						HashSet::from([]),
-					),
 					LinkOpts::default(),
+					"/home/park/.config/foo".into(),
+					Snapshot {
+						ancestor: directory(false),
+						..Snapshot::default()
+					},
 				),
-				output: Ok(Tree {
-					root: Node::Branch(Edges::from([])),
-					work_dir: current_dir.into(),
-					..Tree::default()
-				}),
+				output: Status::Unparented,
 			},
 			Test {
-				description: "target enabled with tags #1",
+				description: "ancestor that is a file",
 				input: (
-					Config {
-						targets: Some(TargetMap::from([(
-							"foo".into(),
-							Target {
-								tags: Some(Tags {
-									all_of: Some(TagSet::from(["test".into()])),
-									..Tags::default()
-								}),
-								..Target::default()
-							},
-						)])),
-						..Config::default()
-					},
-					(TagSet::from(["test".into()]), HashSet::from([])),
 					LinkOpts::default(),
+					"/home/park/foo/bar".into(),
+					Snapshot {
+						ancestor: Some(("/home/park/foo".into(), AncestorState::NotADirectory)),
+						..Snapshot::default()
+					},
 				),
-				output: Ok(Tree {
-					root: Node::Branch(Edges::from([("foo".into(), Node::Leaf("foo".into()))])),
-					work_dir: current_dir.into(),
-					..Tree::default()
-				}),
+				output: Status::Obstructed,
 			},
 			Test {
-				description: "target enabled with tags #2",
+				description: "link to the target",
 				input: (
-					Config {
-						targets: Some(TargetMap::from([(
-							"foo".into(),
-							Target {
-								tags: Some(Tags {
-									all_of: Some(TagSet::from(["test".into()])),
-									any_of: Some(TagSet::from(["foo".into(), "bar".into()])),
-								}),
-								..Target::default()
-							},
-						)])),
-						..Config::default()
-					},
-					(
-						TagSet::from(["test".into(), "bar".into()]),
-						HashSet::from([]),
-					),
 					LinkOpts::default(),
+					"/home/park/foo".into(),
+					Snapshot {
+						ancestor: directory(false),
+						parent_exists: true,
+						file: Some(FileKind::Symlink),
+						destination: Some("/dotfiles/foo".into()),
+						..Snapshot::default()
+					},
 				),
-				output: Ok(Tree {
-					root: Node::Branch(Edges::from([("foo".into(), Node::Leaf("foo".into()))])),
-					work_dir: current_dir.into(),
-					..Tree::default()
-				}),
+				output: Status::Done,
 			},
 			Test {
-				description: "target disabled due to disjunctive tags",
+				description: "link pointing elsewhere",
 				input: (
-					Config {
-						targets: Some(TargetMap::from([(
-							"foo".into(),
-							Target {
-								tags: Some(Tags {
-									all_of: Some(TagSet::from(["test".into()])),
-									any_of: Some(TagSet::from(["foo".into(), "bar".into()])),
-								}),
-								..Target::default()
-							},
-						)])),
-						..Config::default()
-					},
-					(TagSet::from(["test".into()]), HashSet::from([])),
 					LinkOpts::default(),
+					"/home/park/foo".into(),
+					Snapshot {
+						ancestor: directory(false),
+						parent_exists: true,
+						file: Some(FileKind::Symlink),
+						destination: Some("/elsewhere/foo".into()),
+						..Snapshot::default()
+					},
 				),
-				output: Ok(Tree {
-					work_dir: current_dir.into(),
-					..Tree::default()
-				}),
+				output: Status::Mismatch,
 			},
 			Test {
-				description: "target enabled with tags #3",
+				description: "link pointing elsewhere in a read-only directory",
 				input: (
-					Config {
-						targets: Some(TargetMap::from([(
-							"foo".into(),
-							Target {
-								tags: Some(Tags {
-									any_of: Some(TagSet::from(["test".into()])),
-									..Tags::default()
-								}),
-								..Target::default()
-							},
-						)])),
-						..Config::default()
+					LinkOpts::default(),
+					"/home/park/foo".into(),
+					Snapshot {
+						ancestor: directory(true),
+						parent_exists: true,
+						file: Some(FileKind::Symlink),
+						destination: Some("/elsewhere/foo".into()),
+						..Snapshot::default()
 					},
-					(TagSet::from(["test".into()]), HashSet::from([])),
+				),
+				output: Status::ReadOnly,
+			},
+			Test {
+				description: "file in the link path",
+				input: (
 					LinkOpts::default(),
+					"/home/park/foo".into(),
+					Snapshot {
+						ancestor: directory(false),
+						parent_exists: true,
+						file: Some(FileKind::File),
+						..Snapshot::default()
+					},
 				),
-				output: Ok(Tree {
-					root: Node::Branch(Edges::from([("foo".into(), Node::Leaf("foo".into()))])),
-					work_dir: current_dir.into(),
-					..Tree::default()
-				}),
+				output: Status::Conflict,
 			},
 			Test {
-				description: "target using its file name as link name",
+				description: "file with the same content in the link path",
 				input: (
-					Config {
-						targets: Some(TargetMap::from([("foo/bar/baz".into(), Target::default())])),
-						..Config::default()
+					LinkOpts {
+						replace_identical: true,
+						..LinkOpts::default()
 					},
-					(TagSet::from([]), HashSet::from([])),
+					"/home/park/foo".into(),
+					Snapshot {
+						ancestor: directory(false),
+						parent_exists: true,
+						file: Some(FileKind::File),
+						identical: true,
+						..Snapshot::default()
+					},
+				),
+				output: Status::Identical,
+			},
+			Test {
+				description: "existing seed",
+				input: (
 					LinkOpts::default(),
+					Leaf {
+						link_path: "/home/park/foo".into(),
+						seed: true,
+						..Leaf::default()
+					},
+					Snapshot {
+						ancestor: directory(false),
+						parent_exists: true,
+						file: Some(FileKind::File),
+						..Snapshot::default()
+					},
 				),
-				output: Ok(Tree {
-					root: Node::Branch(Edges::from([(
-						"foo".into(),
-						Node::Branch(Edges::from([(
-							"bar".into(),
-							Node::Branch(Edges::from([("baz".into(), Node::Leaf("baz".into()))])),
-						)])),
-					)])),
-					work_dir: current_dir.into(),
-					..Tree::default()
-				}),
+				output: Status::Done,
 			},
 			Test {
-				description: "target enabled with target name filtering",
+				description: "outdated clone",
 				input: (
-					Config {
-						targets: Some(TargetMap::from([
-							("foo/bar".into(), Target::default()),
-							("baz/qux".into(), Target::default()),
-						])),
-						..Config::default()
+					LinkOpts::default(),
+					Leaf {
+						link_path: "/home/park/foo".into(),
+						reflink: true,
+						..Leaf::default()
 					},
-					(TagSet::from([]), HashSet::from(["foo/bar".into()])),
+					Snapshot {
+						ancestor: directory(false),
+						parent_exists: true,
+						file: Some(FileKind::File),
+						..Snapshot::default()
+					},
+				),
+				output: Status::Mismatch,
+			},
+			Test {
+				description: "clone too large to be compared",
+				input: (
 					LinkOpts::default(),
+					Leaf {
+						link_path: "/home/park/foo".into(),
+						reflink: true,
+						..Leaf::default()
+					},
+					Snapshot {
+						ancestor: directory(false),
+						parent_exists: true,
+						file: Some(FileKind::File),
+						too_large: true,
+						..Snapshot::default()
+					},
 				),
-				output: Ok(Tree {
-					root: Node::Branch(Edges::from([(
-						"foo".into(),
-						Node::Branch(Edges::from([("bar".into(), Node::Leaf("bar".into()))])),
-					)])),
-					work_dir: current_dir.into(),
-					..Tree::default()
-				}),
+				output: Status::Conflict,
 			},
 		]);
 
 		for case in test_cases {
-			let got = Tree::parse(case.input.0, case.input.1, case.input.2);
+			let (link_opts, leaf, snapshot) = case.input;
+			let tree = Tree {
+				work_dir: "/dotfiles".into(),
+				link_opts,
+				..Tree::default()
+			};
+			let (status, _) = tree.classify(Path::new("foo"), &leaf, &snapshot);
 
-			assert_eq!(got, case.output, "bad result for {:?}", case.description);
+			assert_eq!(status, case.output, "bad status for {:?}", case.description);
 		}
+	}
+
+	#[test]
+	fn validate() -> Result<(), IoError> {
+		let current_dir = &env::current_dir()?;
+
+		let tree = Tree {
+			root: Node::Branch(Edges::from([
+				("foo".into(), Node::Leaf(Box::new("/tmp/foo".into()))),
+				("bar".into(), Node::Leaf(Box::new("tests/bar".into()))),
+			])),
+			work_dir: "test".into(),
+			..Tree::default()
+		};
+
+		assert_eq!(
+			tree.validate(),
+			Vec::from([
+				Warning::RelativeWorkDir("test".into(), current_dir.join("test")),
+				Warning::RelativeBaseDir("tests/bar".into(), current_dir.join("tests/bar")),
+			]),
+		);
+
+		let tree = Tree {
+			root: Node::Branch(Edges::from([(
+				"foo".into(),
+				Node::Leaf(Box::new("/tmp/foo".into())),
+			)])),
+			work_dir: "/tmp".into(),
+			..Tree::default()
+		};
+
+		assert_eq!(tree.validate(), Vec::new());
+
+		let tree = Tree {
+			root: Node::Branch(Edges::from([(
+				"ssh_config".into(),
+				Node::Leaf(Box::new("/tmp/.ssh/config".into())),
+			)])),
+			work_dir: "/tmp".into(),
+			statuses: Statuses::from([("/tmp/.ssh/config".into(), Status::Unparented)]),
+			link_opts: LinkOpts {
+				create_dirs: true,
+				..LinkOpts::default()
+			},
+			umask: 0o022,
+			..Tree::default()
+		};
+
+		assert_eq!(
+			tree.validate(),
+			Vec::from([Warning::ExposedLink("/tmp/.ssh/config".into(), 0o755)]),
+		);
+
+		let tree = Tree {
+			umask: 0o077,
+			..tree
+		};
+
+		assert_eq!(tree.validate(), Vec::new());
+
+		let tree = Tree {
+			root: Node::Branch(Edges::from([(
+				"foo".into(),
+				Node::Leaf(Box::new(Leaf {
+					link_path: "/tmp/foo".into(),
+					optional: true,
+					..Leaf::default()
+				})),
+			)])),
+			work_dir: "/tmp".into(),
+			problems: Problems::from([("/tmp/foo".into(), Status::Conflict)]),
+			..Tree::default()
+		};
+
+		assert_eq!(
+			tree.validate(),
+			Vec::from([Warning::SkippedOptional(
+				"/tmp/foo".into(),
+				Status::Conflict
+			)]),
+		);
+
+		let tree = Tree {
+			root: Node::Branch(Edges::from([(
+				"foo".into(),
+				Node::Leaf(Box::new(Leaf {
+					link_path: "/tmp/foo".into(),
+					link_mode: Some(0o700),
+					..Leaf::default()
+				})),
+			)])),
+			work_dir: "/tmp".into(),
+			..Tree::default()
+		};
+
+		assert_eq!(
+			tree.validate(),
+			if SYMLINK_MODES {
+				Vec::new()
+			} else {
+				Vec::from([Warning::IgnoredLinkMode("/tmp/foo".into())])
+			},
+		);
 
 		Ok(())
 	}
 
 	#[test]
-	fn analyze_tree() -> Result<(), IoError> {
+	fn source_path() {
+		let tree = Tree {
+			work_dir: "/dotfiles".into(),
+			..Tree::default()
+		};
+
+		let got = [
+			Leaf::default(),
+			Leaf {
+				work_dir: Some("vendor/prompts".into()),
+				..Leaf::default()
+			},
+			Leaf {
+				work_dir: Some("/elsewhere".into()),
+				source: Some("bar".into()),
+				..Leaf::default()
+			},
+		]
+		.map(|leaf| tree.source_path(Path::new("foo"), &leaf));
+
+		assert_eq!(
+			got,
+			[
+				PathBuf::from("/dotfiles/foo"),
+				PathBuf::from("/dotfiles/vendor/prompts/foo"),
+				PathBuf::from("/elsewhere/bar"),
+			]
+		);
+	}
+
+	#[test]
+	fn modes_of() -> Result<(), IoError> {
+		let seed = Leaf {
+			link_path: "tests/data/LICENSE".into(),
+			seed: true,
+			..Leaf::default()
+		};
+		let tree = Tree {
+			work_dir: env::current_dir()?,
+			statuses: Statuses::from([("tests/data/LICENSE".into(), Status::Unparented)]),
+			link_opts: LinkOpts {
+				create_dirs: true,
+				..LinkOpts::default()
+			},
+			umask: 0o027,
+			..Tree::default()
+		};
+
+		assert_eq!(
+			tree.modes_of(Path::new("LICENSE"), &seed),
+			Modes {
+				dirs: Some(0o750),
+				file: Some(fs::metadata("LICENSE")?.permissions().mode() & 0o7777),
+			},
+		);
+
+		let tree = Tree {
+			statuses: Statuses::from([("tests/data/LICENSE".into(), Status::Done)]),
+			..tree
+		};
+
+		assert_eq!(tree.modes_of(Path::new("LICENSE"), &seed), Modes::default());
+
+		Ok(())
+	}
+
+	#[test]
+	fn check_filters() {
+		let config = Config {
+			tags: Some(TagSet::from(["desktop".into()])),
+			targets: Some(TargetMap::from([
+				("nvim".into(), Target::default()),
+				(
+					"alacritty".into(),
+					Target {
+						tags: Some(Tags {
+							all_of: Some(TagSet::from(["wayland".into()])),
+							any_of: None,
+						}),
+						macos: Some(TargetOverride {
+							tags: Some(Tags {
+								all_of: None,
+								any_of: Some(TagSet::from(["quartz".into()])),
+							}),
+							..TargetOverride::default()
+						}),
+						..Target::default()
+					},
+				),
+			])),
+			..Config::default()
+		};
+
+		assert_eq!(
+			Tree::check_filters(
+				&config,
+				(
+					&TagSet::from([
+						"desktop".into(),
+						"quartz".into(),
+						"waylnd".into(),
+						"xorg".into(),
+					]),
+					&HashSet::from(["nvim".into(), "alacrity".into(), "emacs".into()]),
+				),
+			),
+			Vec::from([
+				Warning::UnknownTarget("alacrity".into(), Some("alacritty".into())),
+				Warning::UnknownTarget("emacs".into(), None),
+				Warning::UnknownTag("waylnd".into(), Some("wayland".into())),
+				Warning::UnknownTag("xorg".into(), None),
+			]),
+		);
+	}
+
+	#[test]
+	fn iterate_problems() {
+		let tree = Tree {
+			problems: Problems::from([
+				("foo".into(), Status::Unparented),
+				("bar".into(), Status::Conflict),
+				("baz".into(), Status::Mismatch),
+			]),
+			..Tree::default()
+		};
+
+		assert_eq!(
+			tree.problems().collect::<Vec<_>>(),
+			Vec::from([
+				Problem {
+					link_path: Path::new("bar"),
+					status: &Status::Conflict,
+					remediation: Remediation::ManualIntervention,
+				},
+				Problem {
+					link_path: Path::new("baz"),
+					status: &Status::Mismatch,
+					remediation: Remediation::NeedsReplaceFlag,
+				},
+				Problem {
+					link_path: Path::new("foo"),
+					status: &Status::Unparented,
+					remediation: Remediation::NeedsCreateDirs,
+				},
+			]),
+		);
+	}
+
+	#[test]
+	fn link() -> Result<(), IoError> {
 		struct Test<'a> {
 			description: &'a str,
 			input: Tree,
-			output: Tree,
+			output: Result<Linked, Error>,
+			files_created: Vec<PathBuf>,
+			dirs_created: Vec<PathBuf>,
 		}
 
-		let current_dir = &env::current_dir()?;
-
 		let test_cases = Vec::from([
 			Test {
-				description: "single target should be ready",
+				description: "nothing to be done",
 				input: Tree {
 					root: Node::Branch(Edges::from([(
-						"tests/foo".into(),
-						Node::Leaf("tests/foo".into()),
-					)])),
-					work_dir: current_dir.into(),
-					..Tree::default()
-				},
-				output: Tree {
-					root: Node::Branch(Edges::from([(
-						"tests/foo".into(),
-						Node::Leaf("tests/foo".into()),
+						"foo".into(),
+						Node::Leaf(Box::new("tests/data/foo".into())),
 					)])),
-					work_dir: current_dir.into(),
-					statuses: Statuses::from([("tests/foo".into(), Status::Ready)]),
+					work_dir: "fake_path".into(),
+					statuses: Statuses::from([("tests/data/foo".into(), Status::Done)]),
 					..Tree::default()
 				},
+				output: Ok(Linked {
+					created: Vec::new(),
+					..Linked::default()
+				}),
+				files_created: Vec::from([]),
+				dirs_created: Vec::from([]),
 			},
 			Test {
-				description:
-					"single target whose parent is nonexistent should be unparented (problem)",
+				description: "simple link",
 				input: Tree {
 					root: Node::Branch(Edges::from([(
-						"xxx/foo".into(),
-						Node::Leaf("xxx/foo".into()),
+						"foo".into(),
+						Node::Leaf(Box::new("tests/data/foo".into())),
 					)])),
-					work_dir: current_dir.into(),
+					work_dir: "fake_path".into(),
+					statuses: Statuses::from([("tests/data/foo".into(), Status::Ready)]),
 					..Tree::default()
 				},
-				output: Tree {
+				output: Ok(Linked {
+					created: Vec::from(["tests/data/foo".into()]),
+					..Linked::default()
+				}),
+				files_created: Vec::from(["tests/data/foo".into()]),
+				dirs_created: Vec::from([]),
+			},
+			Test {
+				description: "simple unparented link",
+				input: Tree {
 					root: Node::Branch(Edges::from([(
-						"xxx/foo".into(),
-						Node::Leaf("xxx/foo".into()),
+						"foo".into(),
+						Node::Leaf(Box::new("tests/xxx/foo".into())),
 					)])),
-					work_dir: current_dir.into(),
-					problems: Problems::from([("xxx/foo".into(), Status::Unparented)]),
+					work_dir: "fake_path".into(),
+					statuses: Statuses::from([("tests/xxx/foo".into(), Status::Unparented)]),
 					..Tree::default()
 				},
+				output: Ok(Linked {
+					created: Vec::from(["tests/xxx/foo".into()]),
+					..Linked::default()
+				}),
+				files_created: Vec::from(["tests/xxx/foo".into()]),
+				dirs_created: Vec::from(["tests/xxx".into()]),
 			},
 			Test {
-				description: "single target whose parent is nonexistent should be unparented (ok)",
+				description: "bad unparented link",
 				input: Tree {
-					root: Node::Branch(Edges::from([(
-						"xxx/foo".into(),
-						Node::Leaf("xxx/foo".into()),
-					)])),
-					work_dir: current_dir.into(),
-					link_opts: LinkOpts {
-						create_dirs: true,
-						..LinkOpts::default()
-					},
+					root: Node::Branch(Edges::from([
+						(
+							"foo".into(),
+							Node::Leaf(Box::new(Leaf {
+								link_path: "tests/xxx/foo".into(),
+								description: Some("The foo".into()),
+								problem_hint: Some("create tests/xxx first".into()),
+								..Leaf::default()
+							})),
+						),
+						(
+							"bar".into(),
+							Node::Leaf(Box::new(Leaf {
+								link_path: "tests/data/bar".into(),
+								description: Some("The bar".into()),
+								..Leaf::default()
+							})),
+						),
+					])),
+					work_dir: "fake_path".into(),
+					statuses: Statuses::from([("tests/data/bar".into(), Status::Ready)]),
+					problems: Problems::from([("tests/xxx/foo".into(), Status::Unparented)]),
+					lines: Lines::from([("foo".into(), 4), ("bar".into(), 7)]),
 					..Tree::default()
 				},
-				output: Tree {
-					root: Node::Branch(Edges::from([(
-						"xxx/foo".into(),
-						Node::Leaf("xxx/foo".into()),
+				output: Err(Error::BadFiles(
+					Box::new(Problems::from([(
+						"tests/xxx/foo".into(),
+						Status::Unparented,
 					)])),
-					work_dir: current_dir.into(),
-					link_opts: LinkOpts {
-						create_dirs: true,
-						..LinkOpts::default()
-					},
-					statuses: Statuses::from([("xxx/foo".into(), Status::Unparented)]),
-					..Tree::default()
-				},
+					Box::new(Descriptions::from([(
+						"tests/xxx/foo".into(),
+						"The foo".into(),
+					)])),
+					Box::new(Origins::from([(
+						"tests/xxx/foo".into(),
+						Origin {
+							target_path: "foo".into(),
+							file: None,
+							line: Some(4),
+						},
+					)])),
+					Box::new(Hints::from([(
+						"tests/xxx/foo".into(),
+						"create tests/xxx first".into(),
+					)])),
+				)),
+				files_created: Vec::from([]),
+				dirs_created: Vec::from([]),
 			},
 			Test {
-				description: "single target whose base directory is empty",
+				description: "optional unparented link",
 				input: Tree {
-					root: Node::Branch(Edges::from([("foo".into(), Node::Leaf("foo".into()))])),
-					work_dir: current_dir.into(),
-					..Tree::default()
-				},
-				output: Tree {
-					root: Node::Branch(Edges::from([("foo".into(), Node::Leaf("foo".into()))])),
-					work_dir: current_dir.into(),
-					statuses: Statuses::from([("foo".into(), Status::Ready)]),
+					root: Node::Branch(Edges::from([
+						(
+							"foo".into(),
+							Node::Leaf(Box::new(Leaf {
+								link_path: "tests/xxx/foo".into(),
+								optional: true,
+								..Leaf::default()
+							})),
+						),
+						("bar".into(), Node::Leaf(Box::new("tests/data/bar".into()))),
+					])),
+					work_dir: "fake_path".into(),
+					statuses: Statuses::from([("tests/data/bar".into(), Status::Ready)]),
+					problems: Problems::from([("tests/xxx/foo".into(), Status::Unparented)]),
 					..Tree::default()
 				},
+				output: Ok(Linked {
+					created: Vec::from(["tests/data/bar".into()]),
+					..Linked::default()
+				}),
+				files_created: Vec::from(["tests/data/bar".into()]),
+				dirs_created: Vec::from([]),
 			},
 			Test {
-				description: "single target has conflict",
+				description: "optional link that fails",
 				input: Tree {
-					root: Node::Branch(Edges::from([(
-						"LICENSE".into(),
-						Node::Leaf("LICENSE".into()),
-					)])),
-					work_dir: current_dir.into(),
+					root: Node::Branch(Edges::from([
+						(
+							"foo".into(),
+							Node::Leaf(Box::new(Leaf {
+								link_path: "tests/none/foo".into(),
+								optional: true,
+								..Leaf::default()
+							})),
+						),
+						("bar".into(), Node::Leaf(Box::new("tests/data/bar".into()))),
+					])),
+					work_dir: "fake_path".into(),
+					statuses: Statuses::from([
+						("tests/none/foo".into(), Status::Ready),
+						("tests/data/bar".into(), Status::Ready),
+					]),
 					..Tree::default()
 				},
-				output: Tree {
-					root: Node::Branch(Edges::from([(
-						"LICENSE".into(),
-						Node::Leaf("LICENSE".into()),
-					)])),
-					work_dir: current_dir.into(),
-					problems: Problems::from([("LICENSE".into(), Status::Conflict)]),
+				output: Ok(Linked {
+					created: Vec::from(["tests/data/bar".into()]),
+					warnings: Vec::from([Warning::FailedOptional(
+						"tests/none/foo".into(),
+						IoErrorKind::NotFound,
+					)]),
+					..Linked::default()
+				}),
+				files_created: Vec::from(["tests/data/bar".into()]),
+				dirs_created: Vec::from([]),
+			},
+			Test {
+				description: "multiple links",
+				input: Tree {
+					root: Node::Branch(Edges::from([
+						("foo".into(), Node::Leaf(Box::new("tests/data/foo".into()))),
+						("bar".into(), Node::Leaf(Box::new("tests/data/bar".into()))),
+					])),
+					work_dir: "fake_path".into(),
+					statuses: Statuses::from([
+						("tests/data/foo".into(), Status::Ready),
+						("tests/data/bar".into(), Status::Ready),
+					]),
 					..Tree::default()
 				},
+				output: Ok(Linked {
+					created: Vec::from(["tests/data/foo".into(), "tests/data/bar".into()]),
+					..Linked::default()
+				}),
+				files_created: Vec::from(["tests/data/foo".into(), "tests/data/bar".into()]),
+				dirs_created: Vec::from([]),
 			},
 			Test {
-				description: "single target with wrong existing link (ok)",
+				description: "bad link with conflict",
 				input: Tree {
 					root: Node::Branch(Edges::from([(
 						"something".into(),
-						Node::Leaf("tests/data/something".into()),
-					)])),
-					work_dir: current_dir.into(),
-					link_opts: LinkOpts {
-						replace: true,
-						..LinkOpts::default()
-					},
-					..Tree::default()
-				},
-				output: Tree {
-					root: Node::Branch(Edges::from([(
-						"something".into(),
-						Node::Leaf("tests/data/something".into()),
+						Node::Leaf(Box::new("tests/data/something".into())),
 					)])),
-					work_dir: current_dir.into(),
-					link_opts: LinkOpts {
-						replace: true,
-						..LinkOpts::default()
-					},
-					statuses: Statuses::from([("tests/data/something".into(), Status::Mismatch)]),
+					work_dir: "fake_path".into(),
+					statuses: Statuses::from([("tests/data/something".into(), Status::Conflict)]),
 					..Tree::default()
 				},
+				output: Err(Error::InternalError("tests/data/something".into())),
+				files_created: Vec::from([]),
+				dirs_created: Vec::from([]),
 			},
 			Test {
-				description: "single target with correct existing link",
+				description: "bad link with obstruction",
 				input: Tree {
 					root: Node::Branch(Edges::from([(
 						"something".into(),
-						Node::Leaf("tests/data/something".into()),
-					)])),
-					work_dir: "test".into(),
-					..Tree::default()
-				},
-				output: Tree {
-					root: Node::Branch(Edges::from([(
-						"something".into(),
-						Node::Leaf("tests/data/something".into()),
+						Node::Leaf(Box::new("tests/data/something".into())),
 					)])),
-					work_dir: "test".into(),
-					statuses: Statuses::from([("tests/data/something".into(), Status::Done)]),
+					work_dir: "fake_path".into(),
+					statuses: Statuses::from([("tests/data/something".into(), Status::Obstructed)]),
 					..Tree::default()
 				},
+				output: Err(Error::InternalError("tests/data/something".into())),
+				files_created: Vec::from([]),
+				dirs_created: Vec::from([]),
 			},
 			Test {
-				description: "link with invalid parent directory",
 				input: Tree {
 					root: Node::Branch(Edges::from([(
 						"something".into(),
-						Node::Leaf("LICENSE/something".into()),
-					)])),
-					work_dir: "test".into(),
-					..Tree::default()
-				},
-				output: Tree {
-					root: Node::Branch(Edges::from([(
-						"something".into(),
-						Node::Leaf("LICENSE/something".into()),
+						Node::Leaf(Box::new("tests/data/something".into())),
 					)])),
-					work_dir: "test".into(),
-					problems: Problems::from([("LICENSE/something".into(), Status::Obstructed)]),
+					work_dir: "fake_path".into(),
+					statuses: Statuses::from([("tests/data/something".into(), Status::Mismatch)]),
 					..Tree::default()
 				},
+				description: "replace mismatch",
+				output: Ok(Linked {
+					created: Vec::from(["tests/data/something".into()]),
+					..Linked::default()
+				}),
+				files_created: Vec::from(["tests/data/something".into()]),
+				dirs_created: Vec::from([]),
 			},
 			Test {
-				description: "link with invalid parent path",
 				input: Tree {
 					root: Node::Branch(Edges::from([(
 						"something".into(),
-						Node::Leaf("LICENSE/foo/bar/something".into()),
+						Node::Leaf(Box::new("tests/data/something".into())),
 					)])),
-					work_dir: "test".into(),
+					work_dir: "fake_path".into(),
+					problems: Problems::from([("tests/data/something".into(), Status::Mismatch)]),
 					..Tree::default()
 				},
-				output: Tree {
-					root: Node::Branch(Edges::from([(
-						"something".into(),
-						Node::Leaf("LICENSE/foo/bar/something".into()),
+				description: "bad link with mismatch",
+				output: Err(Error::BadFiles(
+					Box::new(Problems::from([(
+						"tests/data/something".into(),
+						Status::Mismatch,
 					)])),
-					work_dir: "test".into(),
-					problems: Problems::from([(
-						"LICENSE/foo/bar/something".into(),
-						Status::Obstructed,
-					)]),
-					..Tree::default()
-				},
+					Box::default(),
+					Box::new(Origins::from([(
+						"tests/data/something".into(),
+						Origin {
+							target_path: "something".into(),
+							file: None,
+							line: None,
+						},
+					)])),
+					Box::default(),
+				)),
+				files_created: Vec::from([]),
+				dirs_created: Vec::from([]),
+			},
+		]);
+
+		for case in test_cases {
+			let got = case.input.link(|_| {});
+
+			let mut file_assertions = Vec::from([]);
+			let mut dir_assertions = Vec::from([]);
+			for file in &case.files_created {
+				let link_path = PathBuf::from("fake_path").join(file.file_name().unwrap());
+
+				file_assertions.push((file.read_link()?, link_path));
+
+				fs::remove_file(file)?;
+			}
+
+			for dir in &case.dirs_created {
+				dir_assertions.push((dir.is_dir(), dir));
+
+				fs::remove_dir_all(dir)?;
+			}
+
+			for (got, want) in file_assertions {
+				assert_eq!(
+					got, want,
+					"wrong target path for {:?} in {}",
+					want, case.description
+				);
+			}
+
+			for (is_dir, dir_path) in dir_assertions {
+				assert!(
+					is_dir,
+					"did not create dir at {:?} in {}",
+					dir_path, case.description
+				);
+			}
+
+			assert_eq!(got, case.output, "bad result for {:?}", case.description);
+		}
+
+		// Reset testing symlink.
+		unix_fs::symlink("test/something", "tests/data/something")
+	}
+
+	#[test]
+	fn link_with_actions() -> Result<(), IoError> {
+		let tree = Tree {
+			root: Node::Branch(Edges::from([
+				(
+					"foo".into(),
+					Node::Leaf(Box::new("tests/actions/foo".into())),
+				),
+				("bar".into(), Node::Leaf(Box::new("tests/data/bar".into()))),
+			])),
+			work_dir: "fake_path".into(),
+			statuses: Statuses::from([
+				("tests/actions/foo".into(), Status::Unparented),
+				("tests/data/bar".into(), Status::Done),
+			]),
+			..Tree::default()
+		};
+
+		let mut actions = Vec::new();
+		let got = tree.link(|action| actions.push(action));
+
+		fs::remove_dir_all("tests/actions")?;
+
+		assert_eq!(got.map(|linked| linked.created.len()), Ok(1));
+		assert_eq!(
+			actions.iter().map(Action::to_string).collect::<Vec<_>>(),
+			Vec::from([
+				"skip (done) tests/data/bar",
+				"mkdir tests/actions",
+				"ln -s fake_path/foo tests/actions/foo",
+			]),
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn link_with_shared_dirs() -> Result<(), IoError> {
+		let tree = Tree {
+			root: Node::Branch(Edges::from([
+				(
+					"foo".into(),
+					Node::Leaf(Box::new("tests/shared_dirs/a/b/foo".into())),
+				),
+				(
+					"bar".into(),
+					Node::Leaf(Box::new("tests/shared_dirs/a/b/bar".into())),
+				),
+				(
+					"baz".into(),
+					Node::Leaf(Box::new("tests/shared_dirs/a/baz".into())),
+				),
+			])),
+			work_dir: "fake_path".into(),
+			statuses: Statuses::from([
+				("tests/shared_dirs/a/b/foo".into(), Status::Unparented),
+				("tests/shared_dirs/a/b/bar".into(), Status::Unparented),
+				("tests/shared_dirs/a/baz".into(), Status::Unparented),
+			]),
+			..Tree::default()
+		};
+
+		assert_eq!(
+			tree.dirs_to_create(),
+			BTreeSet::from(["tests/shared_dirs/a/b".into()]),
+		);
+
+		let mut actions = Vec::new();
+		let got = tree.link(|action| actions.push(action));
+
+		fs::remove_dir_all("tests/shared_dirs")?;
+
+		assert_eq!(got.map(|linked| linked.created.len()), Ok(3));
+		assert_eq!(
+			actions.iter().map(Action::to_string).collect::<Vec<_>>(),
+			Vec::from([
+				"mkdir tests/shared_dirs/a/b",
+				"ln -s fake_path/foo tests/shared_dirs/a/b/foo",
+				"ln -s fake_path/bar tests/shared_dirs/a/b/bar",
+				"ln -s fake_path/baz tests/shared_dirs/a/baz",
+			]),
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn link_unfolded_dirs() -> Result<(), IoError> {
+		let current_dir = env::current_dir()?;
+		let link = |base_dir: &str| Link {
+			base_dir: Some(base_dir.into()),
+			..Link::default()
+		};
+		let config = Config {
+			work_dir: Some(current_dir.join("tests/unfold/a")),
+			unfold: Some(true),
+			targets: Some(TargetMap::from([
+				(
+					"nvim".into(),
+					Target {
+						link: Some(link("tests/unfold/home")),
+						..Target::default()
+					},
+				),
+				(
+					"extra.lua".into(),
+					Target {
+						link: Some(link("tests/unfold/home/nvim/lua")),
+						work_dir: Some(current_dir.join("tests/unfold/b")),
+						..Target::default()
+					},
+				),
+			])),
+			..Config::default()
+		};
+
+		let mut actions = Vec::new();
+		let got = (|| {
+			fs::create_dir_all("tests/unfold/a/nvim/lua")?;
+			fs::create_dir_all("tests/unfold/b")?;
+			fs::create_dir_all("tests/unfold/home")?;
+			fs::write("tests/unfold/a/nvim/init.lua", "")?;
+			fs::write("tests/unfold/a/nvim/lua/plugins.lua", "")?;
+			fs::write("tests/unfold/b/extra.lua", "")?;
+			unix_fs::symlink(
+				current_dir.join("tests/unfold/a/nvim"),
+				"tests/unfold/home/nvim",
+			)?;
+
+			let mut tree = Tree::parse(config, Default::default(), LinkOpts::default())
+				.map_err(|err| IoError::other(err.to_string()))?;
+			tree.analyze()?;
+
+			let mut statuses: Vec<(PathBuf, Status)> = tree.statuses.clone().into_iter().collect();
+			statuses.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+			tree.link(|action| actions.push(action.to_string()))
+				.map_err(|err| IoError::other(err.to_string()))?;
+
+			let destination = fs::read_link("tests/unfold/home/nvim/lua/extra.lua")?;
+
+			Ok::<_, IoError>((statuses, destination))
+		})();
+
+		fs::remove_dir_all("tests/unfold")?;
+
+		let (statuses, destination) = got?;
+		assert_eq!(
+			statuses,
+			Vec::from([
+				("tests/unfold/home/nvim/init.lua".into(), Status::Ready),
+				("tests/unfold/home/nvim/lua/extra.lua".into(), Status::Ready),
+				(
+					"tests/unfold/home/nvim/lua/plugins.lua".into(),
+					Status::Ready
+				),
+			]),
+		);
+		assert_eq!(destination, current_dir.join("tests/unfold/b/extra.lua"));
+		assert_eq!(
+			actions[..3],
+			[
+				"rm tests/unfold/home/nvim",
+				"mkdir tests/unfold/home/nvim",
+				"mkdir tests/unfold/home/nvim/lua",
+			],
+		);
+		assert_eq!(actions.len(), 6);
+
+		Ok(())
+	}
+
+	#[test]
+	fn parse_unfolded_dirs_past_files() -> Result<(), IoError> {
+		let current_dir = env::current_dir()?;
+		let link = |base_dir: &str| Link {
+			base_dir: Some(base_dir.into()),
+			..Link::default()
+		};
+		// The file is the outermost link, but the directory is still unfolded, so that only the
+		// file's link is reported.
+		let config = Config {
+			work_dir: Some(current_dir.join("tests/unfold_files")),
+			unfold: Some(true),
+			targets: Some(TargetMap::from([
+				(
+					"gitconfig".into(),
+					Target {
+						link: Some(link("/home")),
+						..Target::default()
+					},
+				),
+				(
+					"include".into(),
+					Target {
+						link: Some(link("/home/gitconfig")),
+						..Target::default()
+					},
+				),
+				(
+					"nvim".into(),
+					Target {
+						link: Some(link("/home/.config")),
+						..Target::default()
+					},
+				),
+				(
+					"plugins.lua".into(),
+					Target {
+						link: Some(link("/home/.config/nvim")),
+						..Target::default()
+					},
+				),
+			])),
+			..Config::default()
+		};
+
+		fs::create_dir_all("tests/unfold_files/nvim")?;
+		fs::write("tests/unfold_files/gitconfig", "")?;
+		fs::write("tests/unfold_files/nvim/init.lua", "")?;
+		let got = Tree::parse(config, Default::default(), LinkOpts::default());
+		fs::remove_dir_all("tests/unfold_files")?;
+
+		assert_eq!(
+			got,
+			Err(NodeError::OverlappingLinks(
+				"gitconfig".into(),
+				"include".into(),
+				"/home/gitconfig".into()
+			))
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn link_secret() -> Result<(), IoError> {
+		let tree = Tree {
+			root: Node::Branch(Edges::from([(
+				"netrc".into(),
+				Node::Leaf(Box::new(Leaf {
+					link_path: "tests/secret/netrc".into(),
+					seed: true,
+					secret: Some("printf hunter2".into()),
+					..Leaf::default()
+				})),
+			)])),
+			work_dir: "fake_path".into(),
+			statuses: Statuses::from([("tests/secret/netrc".into(), Status::Unparented)]),
+			..Tree::default()
+		};
+
+		let mut actions = Vec::new();
+		let got = (|| {
+			tree.link(|action| actions.push(action.to_string()))
+				.map_err(|err| IoError::other(err.to_string()))?;
+
+			let content = fs::read_to_string("tests/secret/netrc")?;
+			let mode = fs::metadata("tests/secret/netrc")?.permissions().mode() & 0o777;
+
+			Ok::<_, IoError>((content, mode))
+		})();
+
+		fs::remove_dir_all("tests/secret")?;
+
+		assert_eq!(got?, ("hunter2".into(), 0o600));
+		assert_eq!(
+			actions,
+			Vec::from(["mkdir tests/secret", "printf hunter2 > tests/secret/netrc",]),
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn link_secret_from_terminal() -> Result<(), IoError> {
+		let tree = Tree {
+			root: Node::Branch(Edges::from([(
+				"netrc".into(),
+				Node::Leaf(Box::new(Leaf {
+					link_path: "tests/prompted_secret/netrc".into(),
+					seed: true,
+					secret: Some(r#"read answer && printf %s "$answer""#.into()),
+					..Leaf::default()
+				})),
+			)])),
+			work_dir: "fake_path".into(),
+			statuses: Statuses::from([("tests/prompted_secret/netrc".into(), Status::Ready)]),
+			terminal: Some("tests/prompted_secret/tty".into()),
+			..Tree::default()
+		};
+
+		fs::create_dir_all("tests/prompted_secret")?;
+		let got = (|| {
+			fs::write("tests/prompted_secret/tty", "hunter2\n")?;
+			tree.link(|_| {})
+				.map_err(|err| IoError::other(err.to_string()))?;
+
+			let content = fs::read_to_string("tests/prompted_secret/netrc")?;
+			let mut names: Vec<_> = fs::read_dir("tests/prompted_secret")?
+				.map(|entry| Ok(entry?.file_name()))
+				.collect::<Result<_, IoError>>()?;
+			names.sort();
+
+			Ok::<_, IoError>((content, names))
+		})();
+
+		fs::remove_dir_all("tests/prompted_secret")?;
+
+		// Nothing is left of the temporary file the secret is written to first.
+		assert_eq!(
+			got?,
+			("hunter2".into(), Vec::from(["netrc".into(), "tty".into()]))
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn link_renamed() -> Result<(), IoError> {
+		let current_dir = env::current_dir()?;
+		let mut tree = Tree {
+			root: Node::Branch(Edges::from([(
+				"LICENSE".into(),
+				Node::Leaf(Box::new("tests/renamed".into())),
+			)])),
+			work_dir: current_dir.clone(),
+			renames: BTreeMap::from([("COPYING".into(), "LICENSE".into())]),
+			..Tree::default()
+		};
+
+		unix_fs::symlink(current_dir.join("COPYING"), "tests/renamed")?;
+		tree.analyze()?;
+
+		let status = tree.statuses.get(Path::new("tests/renamed")).cloned();
+		let mut actions = Vec::new();
+		let got = tree.link(|action| actions.push(action));
+		let link = Path::new("tests/renamed").read_link();
+		fs::remove_file("tests/renamed")?;
+
+		assert_eq!(status, Some(Status::Renamed));
+		assert_eq!(got.map(|linked| linked.created.len()), Ok(1));
+		assert_eq!(link?, current_dir.join("LICENSE"));
+		assert_eq!(
+			actions.iter().map(Action::to_string).collect::<Vec<_>>(),
+			Vec::from([format!(
+				"ln -sfn {} tests/renamed",
+				current_dir.join("LICENSE").display()
+			)]),
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn link_seed() -> Result<(), IoError> {
+		let tree = Tree {
+			root: Node::Branch(Edges::from([(
+				"LICENSE".into(),
+				Node::Leaf(Box::new(Leaf {
+					link_path: "tests/data/LICENSE".into(),
+					seed: true,
+					..Leaf::default()
+				})),
+			)])),
+			work_dir: env::current_dir()?,
+			statuses: Statuses::from([("tests/data/LICENSE".into(), Status::Ready)]),
+			..Tree::default()
+		};
+
+		let got = tree.link(|_| {});
+
+		let seed = fs::symlink_metadata("tests/data/LICENSE")?;
+		let content = fs::read("tests/data/LICENSE")?;
+		fs::remove_file("tests/data/LICENSE")?;
+
+		assert_eq!(got.map(|linked| linked.created.len()), Ok(1));
+		assert!(seed.file_type().is_file(), "seed should be a regular file");
+		assert_eq!(content, fs::read("LICENSE")?);
+
+		Ok(())
+	}
+
+	#[test]
+	fn link_clone_over_file() -> Result<(), IoError> {
+		let tree = Tree {
+			root: Node::Branch(Edges::from([(
+				"LICENSE".into(),
+				Node::Leaf(Box::new(Leaf {
+					link_path: "tests/data/LICENSE.cloned".into(),
+					reflink: true,
+					..Leaf::default()
+				})),
+			)])),
+			work_dir: env::current_dir()?,
+			statuses: Statuses::from([("tests/data/LICENSE.cloned".into(), Status::Mismatch)]),
+			..Tree::default()
+		};
+
+		fs::write("tests/data/LICENSE.cloned", "edited")?;
+		let got = tree.link(|_| {});
+
+		let content = fs::read("tests/data/LICENSE.cloned")?;
+		let aside_path = format!("tests/data/.LICENSE.cloned.park-{}", process::id());
+		let moved_aside = Path::new(&aside_path).exists();
+		fs::remove_file("tests/data/LICENSE.cloned")?;
+
+		// Cloning fails on file systems without copy-on-write, which must keep the file.
+		match got {
+			Ok(linked) => {
+				assert_eq!(linked.created.len(), 1);
+				assert_eq!(content, fs::read("LICENSE")?);
+			}
+			Err(_) => assert_eq!(content, b"edited"),
+		}
+		assert!(!moved_aside, "file moved aside should be gone");
+
+		Ok(())
+	}
+
+	#[test]
+	fn link_seed_with_copy_mode() -> Result<(), IoError> {
+		let tree = Tree {
+			root: Node::Branch(Edges::from([(
+				"LICENSE".into(),
+				Node::Leaf(Box::new(Leaf {
+					link_path: "tests/data/LICENSE.exec".into(),
+					seed: true,
+					copy_mode: Some(0o750),
+					..Leaf::default()
+				})),
+			)])),
+			work_dir: env::current_dir()?,
+			statuses: Statuses::from([("tests/data/LICENSE.exec".into(), Status::Ready)]),
+			..Tree::default()
+		};
+
+		let got = tree.link(|_| {});
+
+		let seed = fs::symlink_metadata("tests/data/LICENSE.exec")?;
+		fs::remove_file("tests/data/LICENSE.exec")?;
+
+		assert_eq!(got.map(|linked| linked.created.len()), Ok(1));
+		assert_eq!(seed.permissions().mode() & 0o7777, 0o750);
+
+		Ok(())
+	}
+
+	#[test]
+	fn retain_targets() {
+		let mut tree = Tree {
+			root: Node::Branch(Edges::from([
+				(
+					"nvim".into(),
+					Node::Branch(Edges::from([
+						("config".into(), Node::Leaf(Box::new("test/config".into()))),
+						(
+							"plugins".into(),
+							Node::Leaf(Box::new("test/plugins".into())),
+						),
+					])),
+				),
+				(
+					"zsh".into(),
+					Node::Branch(Edges::from([(
+						"zshrc".into(),
+						Node::Leaf(Box::new("test/zshrc".into())),
+					)])),
+				),
+			])),
+			statuses: Statuses::from([
+				("test/config".into(), Status::Ready),
+				("test/zshrc".into(), Status::Ready),
+			]),
+			problems: Problems::from([("test/plugins".into(), Status::Conflict)]),
+			..Tree::default()
+		};
+
+		tree.retain(|target_path| target_path == Path::new("nvim/config"));
+
+		assert_eq!(
+			tree.root,
+			Node::Branch(Edges::from([(
+				"nvim".into(),
+				Node::Branch(Edges::from([(
+					"config".into(),
+					Node::Leaf(Box::new("test/config".into()))
+				)])),
+			)]))
+		);
+		assert_eq!(
+			tree.statuses,
+			Statuses::from([("test/config".into(), Status::Ready)])
+		);
+		assert!(tree.problems.is_empty());
+		assert_eq!(
+			tree.skipped,
+			Vec::from([PathBuf::from("nvim/plugins"), PathBuf::from("zsh/zshrc")])
+		);
+	}
+
+	#[test]
+	fn link_with_owner() -> Result<(), IoError> {
+		use std::os::unix::fs::MetadataExt;
+
+		// Only the current user and group can be handed links over to without privileges.
+		// SAFETY: neither call can fail.
+		let owner = Owner {
+			uid: Some(unsafe { libc::getuid() }),
+			gid: Some(unsafe { libc::getgid() }),
+		};
+		let tree = Tree {
+			root: Node::Branch(Edges::from([(
+				"LICENSE".into(),
+				Node::Leaf(Box::new(Leaf {
+					link_path: "tests/data/owned/nested/LICENSE".into(),
+					owner: Some(owner),
+					..Leaf::default()
+				})),
+			)])),
+			work_dir: env::current_dir()?,
+			statuses: Statuses::from([(
+				"tests/data/owned/nested/LICENSE".into(),
+				Status::Unparented,
+			)]),
+			link_opts: LinkOpts {
+				create_dirs: true,
+				..LinkOpts::default()
 			},
-		]);
+			..Tree::default()
+		};
 
-		for mut case in test_cases {
-			case.input.analyze()?;
+		let mut actions = Vec::new();
+		let got = tree.link(|action| actions.push(action.to_string()));
+		let link = fs::symlink_metadata("tests/data/owned/nested/LICENSE");
+		fs::remove_dir_all("tests/data/owned")?;
 
-			assert_eq!(
-				case.input, case.output,
-				"bad result for {:?}",
-				case.description
-			);
+		assert_eq!(got.map(|linked| linked.created.len()), Ok(1));
+		assert_eq!(link?.uid(), owner.uid.unwrap());
+		assert_eq!(
+			actions[actions.len() - 3..],
+			[
+				"tests/data/owned",
+				"tests/data/owned/nested",
+				"tests/data/owned/nested/LICENSE"
+			]
+			.map(|path| format!("chown -h {} {}", owner, path))
+		);
+
+		Ok(())
+	}
+
+	/// Runs `f` with the file system permissions of an unprivileged user, since tests may run as root.
+	fn unprivileged<T>(f: impl FnOnce() -> T) -> T {
+		// SAFETY: the file system user is only changed for the current thread, and restored right after.
+		let uid = unsafe { libc::setfsuid(65534) };
+		let result = f();
+		unsafe { libc::setfsuid(uid as libc::uid_t) };
+		result
+	}
+
+	#[test]
+	fn link_with_denied_owner() -> Result<(), IoError> {
+		let dir = env::temp_dir().join(format!("park-unowned-{}", std::process::id()));
+		fs::create_dir(&dir)?;
+		fs::set_permissions(&dir, fs::Permissions::from_mode(0o777))?;
+		let link_path = dir.join("LICENSE");
+		let tree = Tree {
+			root: Node::Branch(Edges::from([(
+				"LICENSE".into(),
+				Node::Leaf(Box::new(Leaf {
+					link_path: link_path.clone(),
+					owner: Some(Owner {
+						uid: Some(1),
+						gid: None,
+					}),
+					..Leaf::default()
+				})),
+			)])),
+			work_dir: env::current_dir()?,
+			statuses: Statuses::from([(link_path.clone(), Status::Ready)]),
+			..Tree::default()
+		};
+
+		let got = unprivileged(|| tree.link(|_| {}));
+		let link = fs::symlink_metadata(&link_path);
+		fs::remove_dir_all(&dir)?;
+
+		assert!(link?.is_symlink());
+		assert_eq!(
+			got.map(|linked| (linked.created, linked.warnings)),
+			Ok((
+				Vec::from([link_path.clone()]),
+				Vec::from([Warning::UnownedLink(link_path)])
+			))
+		);
+
+		Ok(())
+	}
+
+	/// Puts a sudo in `bin_dir` and first in `PATH`, which runs commands as they are, since
+	/// tests run as root, unless they're about paths named "refused".
+	fn stub_sudo(bin_dir: &Path) -> Result<(), IoError> {
+		fs::create_dir(bin_dir)?;
+		let stub = bin_dir.join("sudo");
+		fs::write(
+			&stub,
+			"#!/bin/sh\ncase \"$*\" in *refused*) exit 1 ;; esac\nexec \"$@\"\n",
+		)?;
+		fs::set_permissions(&stub, fs::Permissions::from_mode(0o755))?;
+
+		let path = env::var_os("PATH").unwrap_or_default();
+		let paths = iter::once(bin_dir.to_path_buf()).chain(env::split_paths(&path));
+		env::set_var("PATH", env::join_paths(paths).map_err(IoError::other)?);
+
+		Ok(())
+	}
+
+	#[test]
+	fn link_with_escalation() -> Result<(), IoError> {
+		let dir = env::temp_dir().join(format!("park-escalated-{}", std::process::id()));
+		fs::create_dir(&dir)?;
+		fs::set_permissions(&dir, fs::Permissions::from_mode(0o555))?;
+		let link = |name: &str, escalate| {
+			let link_path = dir.join(name);
+			let tree = Tree {
+				root: Node::Branch(Edges::from([(
+					"LICENSE".into(),
+					Node::Leaf(Box::new(Leaf {
+						link_path: link_path.clone(),
+						..Leaf::default()
+					})),
+				)])),
+				work_dir: env::current_dir().unwrap(),
+				statuses: Statuses::from([(link_path, Status::Ready)]),
+				link_opts: LinkOpts {
+					escalate,
+					..LinkOpts::default()
+				},
+				..Tree::default()
+			};
+
+			unprivileged(|| tree.link(|_| {})).map(|linked| linked.created)
+		};
+
+		let bin_dir = env::temp_dir().join(format!("park-sudo-{}", std::process::id()));
+		stub_sudo(&bin_dir)?;
+		let denied = link("denied", false);
+		let escalated = link("escalated", true);
+		let refused = link("refused", true);
+		let links = ["denied", "escalated", "refused"].map(|name| dir.join(name).is_symlink());
+		fs::remove_dir_all(&dir)?;
+		fs::remove_dir_all(&bin_dir)?;
+
+		assert_eq!(
+			denied,
+			Err(Error::PermissionDenied(Vec::from([dir.join("denied")])))
+		);
+		assert_eq!(escalated, Ok(Vec::from([dir.join("escalated")])));
+		assert_eq!(refused, Err(Error::EscalationFailed(dir.join("refused"))));
+		assert_eq!(links, [false, true, false]);
+
+		Ok(())
+	}
+
+	#[test]
+	fn link_with_conflict_strategies() -> Result<(), IoError> {
+		let leaf = |target_path: &str, on_conflict| {
+			(
+				target_path.into(),
+				Node::Leaf(Box::new(Leaf {
+					link_path: format!("tests/data/{}.conflict", target_path).into(),
+					on_conflict: Some(on_conflict),
+					..Leaf::default()
+				})),
+			)
+		};
+		let mut tree = Tree {
+			root: Node::Branch(Edges::from([
+				leaf("COPYING", OnConflict::Skip),
+				leaf("LICENSE", OnConflict::Backup),
+				leaf("README.md", OnConflict::Replace),
+			])),
+			work_dir: env::current_dir()?,
+			..Tree::default()
+		};
+
+		for name in ["COPYING", "LICENSE", "README.md"] {
+			fs::write(format!("tests/data/{}.conflict", name), name)?;
+		}
+		tree.analyze()?;
+		let skipped_warnings: Vec<Warning> = tree
+			.validate()
+			.into_iter()
+			.filter(|warning| matches!(warning, Warning::SkippedConflict(..)))
+			.collect();
+		let got = tree.link(|_| {});
+
+		let skipped = fs::read_to_string("tests/data/COPYING.conflict")?;
+		let backup = fs::read_to_string("tests/data/LICENSE.conflict.park-backup")?;
+		let links = ["LICENSE", "README.md"]
+			.map(|name| fs::read_link(format!("tests/data/{}.conflict", name)).ok());
+		for path in [
+			"COPYING.conflict",
+			"LICENSE.conflict",
+			"LICENSE.conflict.park-backup",
+			"README.md.conflict",
+		] {
+			fs::remove_file(Path::new("tests/data").join(path))?;
 		}
 
+		assert_eq!(got.map(|linked| linked.created.len()), Ok(2));
+		assert_eq!(
+			skipped_warnings,
+			Vec::from([Warning::SkippedConflict(
+				"tests/data/COPYING.conflict".into(),
+				Status::Conflict
+			)])
+		);
+		assert_eq!(skipped, "COPYING");
+		assert_eq!(backup, "LICENSE");
+		assert_eq!(
+			links,
+			["LICENSE", "README.md"].map(|name| Some(env::current_dir().unwrap().join(name)))
+		);
+
 		Ok(())
 	}
 
 	#[test]
-	fn link() -> Result<(), IoError> {
+	fn analyze_replace_over_directory() -> Result<(), IoError> {
+		let mut tree = Tree {
+			root: Node::Branch(Edges::from([(
+				"README.md".into(),
+				Node::Leaf(Box::new(Leaf {
+					link_path: "tests/data/README.md.dir".into(),
+					on_conflict: Some(OnConflict::Replace),
+					..Leaf::default()
+				})),
+			)])),
+			work_dir: env::current_dir()?,
+			..Tree::default()
+		};
+
+		fs::create_dir("tests/data/README.md.dir")?;
+		let got = tree.analyze();
+		fs::remove_dir("tests/data/README.md.dir")?;
+
+		assert!(got.is_ok());
+		assert_eq!(
+			tree.problems,
+			Problems::from([("tests/data/README.md.dir".into(), Status::Conflict)])
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn link_identical() -> Result<(), IoError> {
+		let tree = |replace_identical| Tree {
+			root: Node::Branch(Edges::from([(
+				"LICENSE".into(),
+				Node::Leaf(Box::new("tests/data/LICENSE.identical".into())),
+			)])),
+			work_dir: env::current_dir().unwrap(),
+			link_opts: LinkOpts {
+				replace_identical,
+				..LinkOpts::default()
+			},
+			..Tree::default()
+		};
+
+		fs::copy("LICENSE", "tests/data/LICENSE.identical")?;
+		let mut conflicting = tree(false);
+		conflicting.analyze()?;
+		let mut identical = tree(true);
+		identical.analyze()?;
+		let statuses = [&conflicting, &identical].map(|tree| {
+			tree.get_status(Path::new("tests/data/LICENSE.identical"))
+				.cloned()
+		});
+		let got = identical.link(|_| {});
+
+		let link_target = fs::read_link("tests/data/LICENSE.identical");
+		fs::remove_file("tests/data/LICENSE.identical")?;
+
+		assert_eq!(statuses, [Some(Status::Conflict), Some(Status::Identical)]);
+		assert_eq!(got.map(|linked| linked.created.len()), Ok(1));
+		assert_eq!(link_target?, env::current_dir()?.join("LICENSE"));
+
+		Ok(())
+	}
+
+	#[test]
+	fn analyze_with_compare_limits() -> Result<(), IoError> {
 		struct Test<'a> {
 			description: &'a str,
-			input: Tree,
-			output: Result<(), Error>,
-			files_created: Vec<PathBuf>,
-			dirs_created: Vec<PathBuf>,
+			input: (Option<u64>, Option<u64>, Option<u64>),
+			output: Status,
 		}
 
-		let test_cases = Vec::from([
-			Test {
-				description: "nothing to be done",
-				input: Tree {
-					root: Node::Branch(Edges::from([(
-						"foo".into(),
-						Node::Leaf("tests/data/foo".into()),
-					)])),
-					work_dir: "fake_path".into(),
-					statuses: Statuses::from([("tests/data/foo".into(), Status::Done)]),
-					..Tree::default()
-				},
-				output: Ok(()),
-				files_created: Vec::from([]),
-				dirs_created: Vec::from([]),
-			},
-			Test {
-				description: "simple link",
-				input: Tree {
-					root: Node::Branch(Edges::from([(
-						"foo".into(),
-						Node::Leaf("tests/data/foo".into()),
-					)])),
-					work_dir: "fake_path".into(),
-					statuses: Statuses::from([("tests/data/foo".into(), Status::Ready)]),
-					..Tree::default()
-				},
-				output: Ok(()),
-				files_created: Vec::from(["tests/data/foo".into()]),
-				dirs_created: Vec::from([]),
-			},
-			Test {
-				description: "simple unparented link",
-				input: Tree {
-					root: Node::Branch(Edges::from([(
-						"foo".into(),
-						Node::Leaf("tests/xxx/foo".into()),
-					)])),
-					work_dir: "fake_path".into(),
-					statuses: Statuses::from([("tests/xxx/foo".into(), Status::Unparented)]),
-					..Tree::default()
-				},
-				output: Ok(()),
-				files_created: Vec::from(["tests/xxx/foo".into()]),
-				dirs_created: Vec::from(["tests/xxx".into()]),
-			},
+		let test_cases = [
 			Test {
-				description: "bad unparented link",
-				input: Tree {
-					root: Node::Branch(Edges::from([(
-						"foo".into(),
-						Node::Leaf("tests/xxx/foo".into()),
-					)])),
-					work_dir: "fake_path".into(),
-					problems: Problems::from([("tests/xxx/foo".into(), Status::Unparented)]),
-					..Tree::default()
-				},
-				output: Err(Error::BadFiles(Problems::from([(
-					"tests/xxx/foo".into(),
-					Status::Unparented,
-				)]))),
-				files_created: Vec::from([]),
-				dirs_created: Vec::from([]),
+				description: "no limits",
+				input: (None, None, None),
+				output: Status::Identical,
 			},
 			Test {
-				description: "multiple links",
-				input: Tree {
-					root: Node::Branch(Edges::from([
-						("foo".into(), Node::Leaf("tests/data/foo".into())),
-						("bar".into(), Node::Leaf("tests/data/bar".into())),
-					])),
-					work_dir: "fake_path".into(),
-					statuses: Statuses::from([
-						("tests/data/foo".into(), Status::Ready),
-						("tests/data/bar".into(), Status::Ready),
-					]),
-					..Tree::default()
-				},
-				output: Ok(()),
-				files_created: Vec::from(["tests/data/foo".into(), "tests/data/bar".into()]),
-				dirs_created: Vec::from([]),
+				description: "file larger than the global limit",
+				input: (Some(1), None, None),
+				output: Status::Conflict,
 			},
 			Test {
-				description: "bad link with conflict",
-				input: Tree {
-					root: Node::Branch(Edges::from([(
-						"something".into(),
-						Node::Leaf("tests/data/something".into()),
-					)])),
-					work_dir: "fake_path".into(),
-					statuses: Statuses::from([("tests/data/something".into(), Status::Conflict)]),
-					..Tree::default()
-				},
-				output: Err(Error::InternalError("tests/data/something".into())),
-				files_created: Vec::from([]),
-				dirs_created: Vec::from([]),
+				description: "file within the target's limit",
+				input: (Some(1), Some(u64::MAX), None),
+				output: Status::Identical,
 			},
 			Test {
-				description: "bad link with obstruction",
-				input: Tree {
-					root: Node::Branch(Edges::from([(
-						"something".into(),
-						Node::Leaf("tests/data/something".into()),
-					)])),
-					work_dir: "fake_path".into(),
-					statuses: Statuses::from([("tests/data/something".into(), Status::Obstructed)]),
-					..Tree::default()
-				},
-				output: Err(Error::InternalError("tests/data/something".into())),
-				files_created: Vec::from([]),
-				dirs_created: Vec::from([]),
+				description: "file larger than the target's limit",
+				input: (None, Some(1), None),
+				output: Status::Conflict,
 			},
 			Test {
-				input: Tree {
-					root: Node::Branch(Edges::from([(
-						"something".into(),
-						Node::Leaf("tests/data/something".into()),
-					)])),
-					work_dir: "fake_path".into(),
-					statuses: Statuses::from([("tests/data/something".into(), Status::Mismatch)]),
-					..Tree::default()
-				},
-				description: "replace mismatch",
-				output: Ok(()),
-				files_created: Vec::from(["tests/data/something".into()]),
-				dirs_created: Vec::from([]),
+				description: "budget spent",
+				input: (None, None, Some(1)),
+				output: Status::Conflict,
 			},
-			Test {
-				input: Tree {
+		];
+
+		fs::copy("LICENSE", "tests/data/LICENSE.limited")?;
+		let got: Vec<Option<Status>> = test_cases
+			.iter()
+			.map(|case| {
+				let (max_size, target_max_size, budget) = case.input;
+				let mut tree = Tree {
 					root: Node::Branch(Edges::from([(
-						"something".into(),
-						Node::Leaf("tests/data/something".into()),
+						"LICENSE".into(),
+						Node::Leaf(Box::new(Leaf {
+							link_path: "tests/data/LICENSE.limited".into(),
+							max_compare_size: target_max_size,
+							..Leaf::default()
+						})),
 					)])),
-					work_dir: "fake_path".into(),
-					problems: Problems::from([("tests/data/something".into(), Status::Mismatch)]),
+					work_dir: env::current_dir().unwrap(),
+					link_opts: LinkOpts {
+						replace_identical: true,
+						max_compare_size: max_size,
+						..LinkOpts::default()
+					},
+					compare_budget: CompareBudget::new(budget),
 					..Tree::default()
-				},
-				description: "bad link with mismatch",
-				output: Err(Error::BadFiles(Problems::from([(
-					"tests/data/something".into(),
-					Status::Mismatch,
-				)]))),
-				files_created: Vec::from([]),
-				dirs_created: Vec::from([]),
-			},
-		]);
+				};
+				tree.analyze().ok()?;
 
-		for case in test_cases {
-			let got = case.input.link();
+				tree.get_status(Path::new("tests/data/LICENSE.limited"))
+					.cloned()
+			})
+			.collect();
+		fs::remove_file("tests/data/LICENSE.limited")?;
 
-			let mut file_assertions = Vec::from([]);
-			let mut dir_assertions = Vec::from([]);
-			for file in &case.files_created {
-				let link_path = PathBuf::from("fake_path").join(file.file_name().unwrap());
+		for (case, got) in test_cases.iter().zip(got) {
+			assert_eq!(
+				got,
+				Some(case.output.clone()),
+				"bad status for {:?}",
+				case.description
+			);
+		}
 
-				file_assertions.push((file.read_link()?, link_path));
+		Ok(())
+	}
 
-				fs::remove_file(file)?;
-			}
+	#[test]
+	fn link_source() -> Result<(), IoError> {
+		let source = env::current_dir()?.join("LICENSE");
+		let tree = Tree {
+			root: Node::Branch(Edges::from([(
+				"license".into(),
+				Node::Leaf(Box::new(Leaf {
+					link_path: "tests/data/license".into(),
+					source: Some(source.clone()),
+					..Leaf::default()
+				})),
+			)])),
+			work_dir: "fake_path".into(),
+			statuses: Statuses::from([("tests/data/license".into(), Status::Ready)]),
+			..Tree::default()
+		};
 
-			for dir in &case.dirs_created {
-				dir_assertions.push((dir.is_dir(), dir));
+		let got = tree.link(|_| {});
 
-				fs::remove_dir_all(dir)?;
-			}
+		let link_target = fs::read_link("tests/data/license")?;
+		fs::remove_file("tests/data/license")?;
 
-			for (got, want) in file_assertions {
-				assert_eq!(
-					got, want,
-					"wrong target path for {:?} in {}",
-					want, case.description
-				);
-			}
+		assert_eq!(got.map(|linked| linked.created.len()), Ok(1));
+		assert_eq!(link_target, source);
 
-			for (is_dir, dir_path) in dir_assertions {
-				assert!(
-					is_dir,
-					"did not create dir at {:?} in {}",
-					dir_path, case.description
-				);
-			}
+		Ok(())
+	}
 
-			assert_eq!(got, case.output, "bad result for {:?}", case.description);
-		}
+	#[test]
+	fn link_unsupported() -> Result<(), IoError> {
+		let tree = Tree {
+			root: Node::Branch(Edges::from([(
+				"LICENSE".into(),
+				Node::Leaf(Box::new("tests/data/LICENSE.copy".into())),
+			)])),
+			work_dir: env::current_dir()?,
+			statuses: Statuses::from([("tests/data/LICENSE.copy".into(), Status::Unsupported)]),
+			link_opts: LinkOpts {
+				copy_fallback: true,
+				..LinkOpts::default()
+			},
+			..Tree::default()
+		};
 
-		// Reset testing symlink.
-		unix_fs::symlink("test/something", "tests/data/something")
+		let got = tree.link(|_| {});
+
+		let copy = fs::symlink_metadata("tests/data/LICENSE.copy")?;
+		fs::remove_file("tests/data/LICENSE.copy")?;
+
+		assert_eq!(got.map(|linked| linked.created.len()), Ok(1));
+		assert!(
+			copy.file_type().is_file(),
+			"fallback should be a regular file"
+		);
+
+		Ok(())
 	}
 }
@@ -0,0 +1,49 @@
+use std::{io::ErrorKind as IoErrorKind, path::PathBuf};
+
+use thiserror::Error;
+
+use super::node::Status;
+
+/// Issues that don't prevent a tree from being linked, but are likely mistakes.
+#[derive(Debug, Error, PartialEq)]
+pub enum Warning {
+	#[error("work directory {0:?} is relative and resolves to {1:?}")]
+	RelativeWorkDir(PathBuf, PathBuf),
+	#[error("base directory of link {0:?} is relative and resolves to {1:?}")]
+	RelativeBaseDir(PathBuf, PathBuf),
+	#[error("link {0:?} is in a private directory, but linking it creates a file or directory with mode {1:o}, which others can read")]
+	ExposedLink(PathBuf, u32),
+	#[error("optional link {0:?} is skipped due to its status, {1:?}")]
+	SkippedOptional(PathBuf, Status),
+	#[error("link {0:?} is skipped due to its status, {1:?}, since its target skips conflicts")]
+	SkippedConflict(PathBuf, Status),
+	#[error("optional link {0:?} could not be created: {1}")]
+	FailedOptional(PathBuf, IoErrorKind),
+	#[error("link {0:?} is orphaned, since its target is no longer configured, and --prune-orphans removes it")]
+	Orphaned(PathBuf),
+	#[error("link {0:?} was created, but handing it over to its owner needs --escalate")]
+	UnownedLink(PathBuf),
+	#[error("orphaned link {0:?} could not be pruned: {1}")]
+	FailedPrune(PathBuf, IoErrorKind),
+	#[error(
+		"link {0:?} has a mode, but symlinks have no permissions of their own on this platform"
+	)]
+	IgnoredLinkMode(PathBuf),
+	#[error("target {0:?} matches nothing{}", hint(.1))]
+	UnknownTarget(PathBuf, Option<String>),
+	#[error("tag {0:?} matches nothing{}", hint(.1))]
+	UnknownTag(String, Option<String>),
+	#[error("tag {0:?} is used by targets, but no profile activates it")]
+	UnusedTag(String),
+	#[error("target {0:?} is enabled by no profile")]
+	UnreachableTarget(PathBuf),
+	#[error("target {0:?} can never be enabled, since its tags require both linux and macos")]
+	ContradictoryTarget(PathBuf),
+}
+
+fn hint(suggestion: &Option<String>) -> String {
+	suggestion
+		.as_ref()
+		.map(|suggestion| format!(", did you mean {:?}?", suggestion))
+		.unwrap_or_default()
+}
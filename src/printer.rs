@@ -1,23 +1,79 @@
 use std::{
+	collections::{BTreeMap, BTreeSet},
 	ffi::{OsStr, OsString},
-	fmt::{Display, Error as FmtError, Formatter, Result as FmtResult},
-	io::Write,
+	fmt::{self, Display, Error as FmtError, Result as FmtResult},
+	io::{Error as IoError, Write},
+	path::{Path, PathBuf},
 	str,
 };
 
 use ansi_term::{Colour, Style};
+use serde::Serialize;
+use serde_json::{json, Value as JsonValue};
 use tabwriter::TabWriter;
 
 use crate::parser::{
 	iter::{Element as IterElement, NodeMetadata},
-	node::Status,
-	tree::Tree,
+	node::{Edges, Leaf, Node, Status},
+	tree::{Modes, Origin, Problem, Remediation, Tree},
 };
 
 pub struct Printer<'a> {
 	pub tree: &'a Tree,
 	pub colored: bool,
 	pub home: Option<OsString>,
+	/// Whether target descriptions are shown.
+	pub long: bool,
+	/// Icons shown in place of status labels, if any.
+	pub icons: Option<Icons>,
+	/// Width rows should fit in by shortening link paths, unless descriptions are shown.
+	pub width: Option<usize>,
+}
+
+/// Link paths are never shortened below this width, even if rows don't fit anyway.
+const MIN_LINK_WIDTH: usize = 8;
+
+/// Set of glyphs used to show statuses.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Icons {
+	Unicode,
+	/// Glyphs from Nerd Fonts, which need a patched font to be displayed.
+	NerdFont,
+}
+
+impl Icons {
+	fn glyph(self, status: &Status) -> &'static str {
+		match (self, status) {
+			(Icons::Unicode, Status::Unknown) => "?",
+			(Icons::Unicode, Status::Ready) => "→",
+			(Icons::Unicode, Status::Done) => "✓",
+			(Icons::Unicode, Status::Mismatch) => "≠",
+			(Icons::Unicode, Status::Foreign) => "⚑",
+			(Icons::Unicode, Status::Unparented) => "…",
+			(Icons::Unicode, Status::Conflict) => "✗",
+			(Icons::Unicode, Status::Obstructed) => "⊘",
+			(Icons::Unicode, Status::Unsupported) => "!",
+			(Icons::Unicode, Status::ReadOnly) => "⊠",
+			(Icons::Unicode, Status::Looping) => "↻",
+			(Icons::Unicode, Status::Renamed) => "↪",
+			(Icons::Unicode, Status::Identical) => "≡",
+			(Icons::Unicode, Status::Orphaned) => "∅",
+			(Icons::NerdFont, Status::Unknown) => "\u{f128}",
+			(Icons::NerdFont, Status::Ready) => "\u{f061}",
+			(Icons::NerdFont, Status::Done) => "\u{f00c}",
+			(Icons::NerdFont, Status::Mismatch) => "\u{f0c1}",
+			(Icons::NerdFont, Status::Foreign) => "\u{f21b}",
+			(Icons::NerdFont, Status::Unparented) => "\u{f07b}",
+			(Icons::NerdFont, Status::Conflict) => "\u{f00d}",
+			(Icons::NerdFont, Status::Obstructed) => "\u{f05e}",
+			(Icons::NerdFont, Status::Unsupported) => "\u{f071}",
+			(Icons::NerdFont, Status::ReadOnly) => "\u{f023}",
+			(Icons::NerdFont, Status::Looping) => "\u{f021}",
+			(Icons::NerdFont, Status::Renamed) => "\u{f0ec}",
+			(Icons::NerdFont, Status::Identical) => "\u{f0c5}",
+			(Icons::NerdFont, Status::Orphaned) => "\u{f127}",
+		}
+	}
 }
 
 impl<'a> Printer<'a> {
@@ -29,23 +85,85 @@ impl<'a> Printer<'a> {
 		}
 	}
 
-	fn replace_home<S>(&self, s: S) -> String
-	where
-		S: AsRef<str>,
-	{
-		if let Some(home) = self.home.as_ref().and_then(|s| s.to_str()) {
-			return s.as_ref().replacen(home, "~", 1);
+	fn replace_home(&self, path: &Path) -> String {
+		abbreviate_home(path, self.home.as_deref())
+			.to_string_lossy()
+			.into_owned()
+	}
+
+	/// Computes how many characters link paths may take for rows to fit in the width.
+	fn link_width(&self) -> Option<usize> {
+		let width = self.width.filter(|_| !self.long)?;
+		let mut name_width = 0;
+		let mut status_width = 0;
+
+		for IterElement {
+			metadata: NodeMetadata { level, .. },
+			target_path,
+			leaf,
+		} in &self.tree.root
+		{
+			if level == 0 {
+				continue;
+			}
+
+			let name = target_path.file_name().unwrap().to_string_lossy();
+			let icon_width = if leaf.is_some() && self.icons.is_some() {
+				2
+			} else {
+				0
+			};
+			name_width = name_width.max(4 * level + icon_width + name.chars().count());
+
+			if let (Some(leaf), None) = (leaf, self.icons) {
+				let status = self
+					.tree
+					.get_status(&leaf.link_path)
+					.unwrap_or(&Status::Unknown);
+				// Labels are wrapped in two characters and preceded by the link's padding.
+				status_width = status_width.max(format!("{:?}", status).len() + 3);
+			}
 		}
 
-		s.as_ref().into()
+		// Names are followed by a padding, and links are wrapped in two characters.
+		let link_width = width.saturating_sub(name_width + 1 + status_width + 2);
+
+		Some(link_width.max(MIN_LINK_WIDTH))
 	}
 }
 
-impl<'a> Display for Printer<'a> {
-	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-		let table = Vec::new();
-		let mut tab_writer = TabWriter::new(table).padding(1);
+/// Replaces the home directory at the beginning of a path with "~". Only whole components are
+/// matched, so that paths merely sharing a prefix with it, like "/home/park-backup", are kept.
+pub fn abbreviate_home(path: &Path, home: Option<&OsStr>) -> PathBuf {
+	match home.and_then(|home| path.strip_prefix(home).ok()) {
+		Some(rest) if rest.as_os_str().is_empty() => "~".into(),
+		Some(rest) => Path::new("~").join(rest),
+		None => path.into(),
+	}
+}
+
+/// Shortens a path to a number of characters by replacing its beginning with an ellipsis, which
+/// keeps the file name visible.
+fn ellipsize(path: String, width: usize) -> String {
+	let count = path.chars().count();
+
+	if count <= width {
+		return path;
+	}
+
+	let tail: String = path.chars().skip(count - width + 1).collect();
+
+	format!("…{}", tail)
+}
 
+impl<'a> Printer<'a> {
+	/// Writes the rows of a tree, leaving its root out.
+	fn write_rows(
+		&self,
+		root: &Node,
+		link_width: Option<usize>,
+		tab_writer: &mut TabWriter<Vec<u8>>,
+	) -> FmtResult {
 		let mut indent_blocks = Vec::<bool>::new();
 
 		for IterElement {
@@ -54,24 +172,10 @@ impl<'a> Display for Printer<'a> {
 				level,
 			},
 			target_path,
-			link_path,
-		} in &self.tree.root
+			leaf,
+		} in root
 		{
 			if level == 0 {
-				let cwd = self.resolve_style(Colour::White.italic()).paint({
-					let path = self.replace_home(self.tree.work_dir.to_string_lossy());
-
-					if self.colored {
-						path
-					} else {
-						format!("({})", path)
-					}
-				});
-
-				if writeln!(tab_writer, ". {}", cwd,).is_err() {
-					return Err(FmtError);
-				}
-
 				continue;
 			}
 
@@ -102,38 +206,84 @@ impl<'a> Display for Printer<'a> {
 				}
 			}
 
-			if let Some(link_path) = link_path {
+			if let Some(leaf) = leaf {
+				let Leaf {
+					link_path,
+					description,
+					gate,
+					..
+				} = &leaf;
 				let default_status = Status::Unknown;
-				let status = self
-					.tree
-					.problems
-					.get(&link_path)
-					.or_else(|| self.tree.statuses.get(&link_path))
-					.unwrap_or(&default_status);
-
-				let status_style = self.resolve_style(
-					match status {
-						Status::Unknown => Colour::White,
-						Status::Done => Colour::Blue,
-						Status::Ready => Colour::Green,
-						Status::Mismatch | Status::Unparented => Colour::Yellow,
-						Status::Conflict | Status::Obstructed => Colour::Red,
+				let status = self.tree.get_status(link_path).unwrap_or(&default_status);
+
+				let status_colour = match status {
+					Status::Unknown => Colour::White,
+					Status::Done => Colour::Blue,
+					Status::Ready | Status::Renamed | Status::Identical => Colour::Green,
+					Status::Mismatch
+					| Status::Unparented
+					| Status::Unsupported
+					| Status::Orphaned => Colour::Yellow,
+					Status::Conflict
+					| Status::Obstructed
+					| Status::Foreign
+					| Status::ReadOnly
+					| Status::Looping => Colour::Red,
+				};
+				let (icon, status) = match self.icons {
+					Some(icons) => (
+						format!(
+							"{} ",
+							self.resolve_style(status_colour.normal())
+								.paint(icons.glyph(status))
+						),
+						String::new(),
+					),
+					None => {
+						let label = if self.colored {
+							format!(" {:?} ", status)
+						} else {
+							format!("[{:?}]", status)
+						}
+						.to_uppercase();
+
+						(
+							String::new(),
+							format!(
+								"\t{}",
+								self.resolve_style(status_colour.reverse()).paint(label)
+							),
+						)
 					}
-					.reverse(),
-				);
-				let status = if self.colored {
-					format!(" {:?} ", status)
-				} else {
-					format!("[{:?}]", status)
-				}
-				.to_uppercase();
+				};
 
 				let target_segment: Vec<&OsStr> = target_path.iter().collect();
 				let is_leaf = level == target_segment.len();
+				let details = if self.long {
+					let Modes { dirs, file } = self.tree.modes_of(&target_path, &leaf);
+					let modes: Vec<String> = [("dirs", dirs), ("file", file)]
+						.into_iter()
+						.filter_map(|(kind, mode)| Some(format!("{} {:04o}", kind, mode?)))
+						.collect();
+					let dimmed = self.resolve_style(Style::new().dimmed());
+
+					let origin = Origin::of(&target_path, &self.tree.lines, &self.tree.files);
+
+					format!(
+						"\t{}\t{}\t{}\t{}",
+						dimmed.paint(modes.join(", ")),
+						dimmed.paint(gate.to_string()),
+						dimmed.paint(origin.location().unwrap_or_default()),
+						dimmed.paint(description.as_deref().unwrap_or_default())
+					)
+				} else {
+					String::new()
+				};
 				let target_path = target_path.file_name().unwrap().to_string_lossy();
+
 				if writeln!(
 					tab_writer,
-					"{target_path}\t{link_path}\t{status}",
+					"{icon}{target_path}\t{link_path}{status}{details}",
 					target_path = {
 						let mut style = Style::new();
 
@@ -144,7 +294,11 @@ impl<'a> Display for Printer<'a> {
 						self.resolve_style(style).paint(target_path)
 					},
 					link_path = self.resolve_style(Colour::Purple.italic()).paint({
-						let path = self.replace_home(link_path.to_string_lossy());
+						let mut path = self.replace_home(link_path);
+
+						if let Some(width) = link_width {
+							path = ellipsize(path, width);
+						}
 
 						if self.colored {
 							format!(" {} ", path)
@@ -152,7 +306,6 @@ impl<'a> Display for Printer<'a> {
 							format!("({})", path)
 						}
 					}),
-					status = status_style.paint(status),
 				)
 				.is_err()
 				{
@@ -160,12 +313,74 @@ impl<'a> Display for Printer<'a> {
 				};
 			} else {
 				let path = target_path.file_name().unwrap();
-				if writeln!(tab_writer, "{}\t\t", path.to_string_lossy()).is_err() {
+
+				if writeln!(tab_writer, "{}{}", path.to_string_lossy(), self.padding()).is_err() {
 					return Err(FmtError);
 				};
 			}
 		}
 
+		Ok(())
+	}
+
+	/// Tabs that keep rows without a link aligned with the others.
+	fn padding(&self) -> String {
+		let columns = 1 + usize::from(self.icons.is_none()) + if self.long { 4 } else { 0 };
+
+		"\t".repeat(columns)
+	}
+}
+
+impl<'a> Display for Printer<'a> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> FmtResult {
+		let table = Vec::new();
+		let mut tab_writer = TabWriter::new(table).padding(1);
+		let link_width = self.link_width();
+
+		let cwd = self.resolve_style(Colour::White.italic()).paint({
+			let path = self.replace_home(&self.tree.work_dir);
+
+			if self.colored {
+				path
+			} else {
+				format!("({})", path)
+			}
+		});
+
+		if writeln!(tab_writer, ". {}", cwd,).is_err() {
+			return Err(FmtError);
+		}
+
+		let groups: BTreeSet<String> = self
+			.tree
+			.root
+			.into_iter()
+			.filter_map(|IterElement { leaf, .. }| leaf?.group)
+			.collect();
+
+		if groups.is_empty() {
+			self.write_rows(&self.tree.root, link_width, &mut tab_writer)?;
+		} else {
+			// Targets without a group come first, so that they're not taken for the last group's.
+			if let Some(ungrouped) = grouped(&self.tree.root, None) {
+				self.write_rows(&ungrouped, link_width, &mut tab_writer)?;
+			}
+
+			for group in &groups {
+				let header = self
+					.resolve_style(Style::new().bold())
+					.paint(format!("{}:", group));
+
+				if writeln!(tab_writer, "{}{}", header, self.padding()).is_err() {
+					return Err(FmtError);
+				}
+
+				if let Some(root) = grouped(&self.tree.root, Some(group)) {
+					self.write_rows(&root, link_width, &mut tab_writer)?;
+				}
+			}
+		}
+
 		match tab_writer.into_inner() {
 			Err(_) => return Err(FmtError),
 			Ok(w) => {
@@ -173,10 +388,441 @@ impl<'a> Display for Printer<'a> {
 			}
 		}
 
+		for link_path in self.tree.orphans.keys() {
+			writeln!(
+				f,
+				"{} {}",
+				self.resolve_style(Colour::Yellow.bold()).paint("orphaned:"),
+				self.replace_home(link_path)
+			)?;
+		}
+
+		for dir in self.tree.dirs_to_create() {
+			writeln!(
+				f,
+				"{} {}",
+				self.resolve_style(Colour::Green.bold()).paint("mkdir:"),
+				self.replace_home(&dir)
+			)?;
+		}
+
+		for (_, leaf, _) in targets(self.tree) {
+			if let (true, Some(hint)) = (
+				self.tree.problems.contains_key(&leaf.link_path),
+				&leaf.problem_hint,
+			) {
+				writeln!(
+					f,
+					"{} {:?}: {}",
+					self.resolve_style(Colour::Yellow.bold()).paint("hint:"),
+					leaf.link_path,
+					hint
+				)?;
+			}
+		}
+
+		let mut remediations: BTreeMap<Remediation, usize> = BTreeMap::new();
+		for Problem { remediation, .. } in self.tree.problems() {
+			*remediations.entry(remediation).or_default() += 1;
+		}
+
+		for (remediation, count) in remediations {
+			writeln!(
+				f,
+				"{} {} ({} link(s))",
+				self.resolve_style(Colour::Yellow.bold()).paint("hint:"),
+				remediation,
+				count
+			)?;
+		}
+
 		Ok(())
 	}
 }
 
+/// Prints the tree as a JSON document, listing each target along with its link and status.
+pub struct JsonPrinter<'a> {
+	pub tree: &'a Tree,
+}
+
+impl<'a> JsonPrinter<'a> {
+	/// Builds the document, which the YAML format shares.
+	fn document(&self) -> JsonValue {
+		let targets: Vec<JsonValue> = targets(self.tree)
+			.map(|(target_path, leaf, status)| {
+				let Leaf {
+					link_path,
+					description: leaf_description,
+					group,
+					problem_hint,
+					..
+				} = leaf;
+				let problem = self.tree.problems.contains_key(&link_path);
+
+				json!({
+					"target": target_path,
+					"link": link_path,
+					"status": status,
+					"problem": problem,
+					"remediation": self
+						.tree
+						.problems
+						.get(&link_path)
+						.and_then(Remediation::of),
+					"hint": problem_hint.filter(|_| problem),
+					"description": leaf_description,
+					"group": group,
+				})
+			})
+			.chain(self.tree.orphans.keys().map(|link_path| {
+				json!({
+					"target": null,
+					"link": link_path,
+					"status": Status::Orphaned,
+					"problem": false,
+					"remediation": null,
+					"hint": null,
+					"description": null,
+					"group": null,
+				})
+			}))
+			.collect();
+
+		json!({
+			"work_dir": self.tree.work_dir,
+			"targets": targets,
+			"dirs_to_create": self.tree.dirs_to_create(),
+		})
+	}
+}
+
+impl<'a> Display for JsonPrinter<'a> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> FmtResult {
+		writeln!(f, "{:#}", self.document())
+	}
+}
+
+/// Output format of the preview. Formats render trees on their own, so that adding one doesn't
+/// touch the others.
+pub trait Formatter {
+	fn format(&self, tree: &Tree, w: &mut dyn Write) -> Result<(), IoError>;
+}
+
+/// Prints the preview tree, the default format meant for humans.
+pub struct TreeFormatter {
+	pub colored: bool,
+	pub home: Option<OsString>,
+	pub long: bool,
+	pub icons: Option<Icons>,
+	pub width: Option<usize>,
+}
+
+impl Formatter for TreeFormatter {
+	fn format(&self, tree: &Tree, w: &mut dyn Write) -> Result<(), IoError> {
+		write!(
+			w,
+			"{}",
+			Printer {
+				tree,
+				colored: self.colored,
+				home: self.home.clone(),
+				long: self.long,
+				icons: self.icons,
+				width: self.width,
+			}
+		)
+	}
+}
+
+/// Prints the preview document as JSON.
+pub struct JsonFormatter;
+
+impl Formatter for JsonFormatter {
+	fn format(&self, tree: &Tree, w: &mut dyn Write) -> Result<(), IoError> {
+		write!(w, "{}", JsonPrinter { tree })
+	}
+}
+
+/// Prints a line per target with its status, name and link path separated by tabs, which is
+/// stable across versions and easy to split in scripts.
+pub struct PorcelainFormatter;
+
+impl Formatter for PorcelainFormatter {
+	fn format(&self, tree: &Tree, w: &mut dyn Write) -> Result<(), IoError> {
+		for (target_path, leaf, status) in targets(tree) {
+			writeln!(
+				w,
+				"{}\t{}\t{}",
+				status.name(),
+				target_path.display(),
+				leaf.link_path.display()
+			)?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Prints the preview document as YAML, with the same fields as the JSON one.
+pub struct YamlFormatter;
+
+impl Formatter for YamlFormatter {
+	fn format(&self, tree: &Tree, w: &mut dyn Write) -> Result<(), IoError> {
+		let mut yaml = String::new();
+		write_yaml(&JsonPrinter { tree }.document(), 0, &mut yaml);
+
+		w.write_all(yaml.as_bytes())
+	}
+}
+
+/// Prints a Graphviz graph with an edge from each target to its link, labeled by its status.
+pub struct DotFormatter;
+
+impl Formatter for DotFormatter {
+	fn format(&self, tree: &Tree, w: &mut dyn Write) -> Result<(), IoError> {
+		let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+
+		writeln!(w, "digraph park {{")?;
+
+		for (target_path, leaf, status) in targets(tree) {
+			let colour = if tree.problems.contains_key(&leaf.link_path) {
+				", color=red"
+			} else {
+				""
+			};
+
+			writeln!(
+				w,
+				"\t{} -> {} [label={}{}];",
+				quote(&target_path.to_string_lossy()),
+				quote(&leaf.link_path.to_string_lossy()),
+				quote(status.name()),
+				colour
+			)?;
+		}
+
+		writeln!(w, "}}")
+	}
+}
+
+/// Prints a standalone HTML page with a table of targets, their links and statuses.
+pub struct HtmlFormatter;
+
+impl Formatter for HtmlFormatter {
+	fn format(&self, tree: &Tree, w: &mut dyn Write) -> Result<(), IoError> {
+		let escape = |s: &str| {
+			s.replace('&', "&amp;")
+				.replace('<', "&lt;")
+				.replace('>', "&gt;")
+				.replace('"', "&quot;")
+		};
+
+		writeln!(w, "<!DOCTYPE html>")?;
+		writeln!(w, "<html>")?;
+		writeln!(
+			w,
+			"<head><meta charset=\"utf-8\"><title>park</title></head>"
+		)?;
+		writeln!(w, "<body>")?;
+		writeln!(w, "<table>")?;
+		writeln!(w, "<tr><th>Target</th><th>Link</th><th>Status</th></tr>")?;
+
+		for (target_path, leaf, status) in targets(tree) {
+			writeln!(
+				w,
+				"<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td></tr>",
+				status.name(),
+				escape(&target_path.to_string_lossy()),
+				escape(&leaf.link_path.to_string_lossy()),
+				status.name()
+			)?;
+		}
+
+		writeln!(w, "</table>")?;
+		writeln!(w, "</body>")?;
+		writeln!(w, "</html>")
+	}
+}
+
+/// Copies the part of a tree whose targets are in a group, or in none, if there's any.
+fn grouped(node: &Node, group: Option<&str>) -> Option<Node> {
+	match node {
+		Node::Leaf(leaf) => (leaf.group.as_deref() == group).then(|| Node::Leaf(leaf.clone())),
+		Node::Branch(edges) => {
+			let edges: Edges = edges
+				.iter()
+				.filter_map(|(segment, child)| Some((segment.clone(), grouped(child, group)?)))
+				.collect();
+
+			(!edges.is_empty()).then_some(Node::Branch(edges))
+		}
+	}
+}
+
+/// Iterates over the targets of a tree along with their leaves and statuses.
+fn targets(tree: &Tree) -> impl Iterator<Item = (PathBuf, Leaf, &Status)> {
+	tree.root.into_iter().filter_map(
+		|IterElement {
+		     target_path, leaf, ..
+		 }| {
+			let leaf = leaf?;
+			let status = tree.get_status(&leaf.link_path).unwrap_or(&Status::Unknown);
+
+			Some((target_path, leaf, status))
+		},
+	)
+}
+
+/// Writes a JSON value as YAML. Strings are quoted the way JSON does it, which YAML reads the
+/// same way, so that nothing in them needs escaping otherwise.
+fn write_yaml(value: &JsonValue, indent: usize, yaml: &mut String) {
+	let padding = " ".repeat(indent);
+	let is_nested = |value: &JsonValue| match value {
+		JsonValue::Object(map) => !map.is_empty(),
+		JsonValue::Array(items) => !items.is_empty(),
+		_ => false,
+	};
+	let scalar = |value: &JsonValue| match value {
+		JsonValue::Object(_) => "{}".into(),
+		JsonValue::Array(_) => "[]".into(),
+		value => value.to_string(),
+	};
+
+	match value {
+		JsonValue::Object(map) if !map.is_empty() => {
+			for (key, value) in map {
+				if is_nested(value) {
+					yaml.push_str(&format!("{}{}:\n", padding, key));
+					write_yaml(value, indent + 2, yaml);
+				} else {
+					yaml.push_str(&format!("{}{}: {}\n", padding, key, scalar(value)));
+				}
+			}
+		}
+		JsonValue::Array(items) if !items.is_empty() => {
+			for item in items {
+				if is_nested(item) {
+					// The first line of the item takes the dash in place of its padding.
+					let mut nested = String::new();
+					write_yaml(item, indent + 2, &mut nested);
+					yaml.push_str(&format!("{}- {}", padding, &nested[indent + 2..]));
+				} else {
+					yaml.push_str(&format!("{}- {}\n", padding, scalar(item)));
+				}
+			}
+		}
+		value => yaml.push_str(&format!("{}{}\n", padding, scalar(value))),
+	}
+}
+
+/// How many targets are done, pending and with problems.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Summary {
+	pub done: usize,
+	pub pending: usize,
+	pub problems: usize,
+}
+
+impl Summary {
+	pub fn of(tree: &Tree) -> Self {
+		let done = tree
+			.statuses
+			.values()
+			.filter(|status| **status == Status::Done)
+			.count();
+
+		Self {
+			done,
+			pending: tree.statuses.len() - done,
+			problems: tree.problems.len(),
+		}
+	}
+}
+
+/// Prints a summary in a single line, which fits shell prompts.
+pub struct SummaryPrinter {
+	pub summary: Summary,
+	pub colored: bool,
+}
+
+impl Display for SummaryPrinter {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> FmtResult {
+		let Summary {
+			done,
+			pending,
+			problems,
+		} = self.summary;
+
+		let paint = |colour: Colour, s: String| {
+			if self.colored {
+				colour.paint(s).to_string()
+			} else {
+				s
+			}
+		};
+
+		writeln!(
+			f,
+			"park: {}, {}, {}",
+			paint(Colour::Blue, format!("{} done", done)),
+			paint(Colour::Green, format!("{} pending", pending)),
+			paint(
+				Colour::Red,
+				format!(
+					"{} problem{}",
+					problems,
+					if problems == 1 { "" } else { "s" }
+				)
+			),
+		)
+	}
+}
+
+/// Prints a flat list of targets along with their links and descriptions.
+pub struct TargetsPrinter<'a> {
+	pub tree: &'a Tree,
+	pub home: Option<OsString>,
+}
+
+impl<'a> Display for TargetsPrinter<'a> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> FmtResult {
+		let mut tab_writer = TabWriter::new(Vec::new()).padding(1);
+
+		for IterElement {
+			target_path, leaf, ..
+		} in &self.tree.root
+		{
+			let Some(Leaf {
+				link_path,
+				description,
+				..
+			}) = leaf
+			else {
+				continue; // filters branches
+			};
+
+			let link_path = abbreviate_home(&link_path, self.home.as_deref());
+
+			if writeln!(
+				tab_writer,
+				"{}\t{}\t{}",
+				target_path.to_string_lossy(),
+				link_path.to_string_lossy(),
+				description.unwrap_or_default(),
+			)
+			.is_err()
+			{
+				return Err(FmtError);
+			}
+		}
+
+		match tab_writer.into_inner() {
+			Err(_) => Err(FmtError),
+			Ok(w) => write!(f, "{}", str::from_utf8(&w).unwrap()),
+		}
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use std::io::Error as IoError;
@@ -185,42 +831,99 @@ mod tests {
 	use pretty_assertions::assert_eq;
 
 	use crate::parser::{
-		node::{Edges, Node},
-		tree::Statuses,
+		node::{Edges, Gate, Node},
+		tree::{Files, Lines, LinkOpts, Problems, Statuses},
 	};
 
 	use super::*;
 
+	#[test]
+	fn abbreviate_home_paths() {
+		struct Test<'a> {
+			description: &'a str,
+			input: (&'a str, Option<&'a str>),
+			output: &'a str,
+		}
+
+		let test_cases = Vec::from([
+			Test {
+				description: "path within home",
+				input: ("/home/park/.config/nvim", Some("/home/park")),
+				output: "~/.config/nvim",
+			},
+			Test {
+				description: "home itself",
+				input: ("/home/park", Some("/home/park/")),
+				output: "~",
+			},
+			Test {
+				description: "path sharing a prefix with home",
+				input: ("/home/park-backup/.bashrc", Some("/home/park")),
+				output: "/home/park-backup/.bashrc",
+			},
+			Test {
+				description: "path containing home",
+				input: ("/data/home/park/.bashrc", Some("/home/park")),
+				output: "/data/home/park/.bashrc",
+			},
+			Test {
+				description: "no home",
+				input: ("/home/park/.bashrc", None),
+				output: "/home/park/.bashrc",
+			},
+		]);
+
+		for case in test_cases {
+			let (path, home) = case.input;
+
+			assert_eq!(
+				abbreviate_home(Path::new(path), home.map(OsStr::new)),
+				PathBuf::from(case.output),
+				"bad abbreviation for {:?}",
+				case.description
+			);
+		}
+	}
+
 	#[test]
 	fn format_tree() -> Result<(), IoError> {
 		let tree = Tree {
 			root: Node::Branch(Edges::from([
 				(
 					"baz".into(),
-					Node::Branch(Edges::from([("qux".into(), Node::Leaf("test/qux".into()))])),
+					Node::Branch(Edges::from([(
+						"qux".into(),
+						Node::Leaf(Box::new("test/qux".into())),
+					)])),
 				),
 				(
 					"corge".into(),
 					Node::Branch(Edges::from([
-						("anything".into(), Node::Leaf("file/file".into())),
-						("gralt".into(), Node::Leaf("test/gralt".into())),
+						("anything".into(), Node::Leaf(Box::new("file/file".into()))),
+						("gralt".into(), Node::Leaf(Box::new("test/gralt".into()))),
 						(
 							"something".into(),
-							Node::Leaf("tests/data/something".into()),
+							Node::Leaf(Box::new("tests/data/something".into())),
 						),
 						(
 							"s0m37h1ng".into(),
-							Node::Leaf("tests/none/s0m37h1ng".into()),
+							Node::Leaf(Box::new("tests/none/s0m37h1ng".into())),
 						),
 					])),
 				),
 				(
 					"foo".into(),
-					Node::Branch(Edges::from([("bar".into(), Node::Leaf("bar".into()))])),
+					Node::Branch(Edges::from([(
+						"bar".into(),
+						Node::Leaf(Box::new("bar".into())),
+					)])),
 				),
 				(
 					"quux".into(),
-					Node::Branch(Edges::from([("quuz".into(), Node::Leaf("quuz".into()))])),
+					Node::Branch(Edges::from([(
+						"quuz".into(),
+						Node::Leaf(Box::new("quuz".into())),
+					)])),
 				),
 			])),
 			statuses: Statuses::from([
@@ -241,6 +944,9 @@ mod tests {
 				tree: &tree,
 				colored: true,
 				home: Some("file".into()),
+				long: false,
+				icons: None,
+				width: None,
 			};
 
 			println!("\n{}", printer);
@@ -265,6 +971,7 @@ mod tests {
 					{straight_bar}{l_bar}{tgt6}       {bar}                  {unknown}
 					{l_bar}quux                                 
 					{blank}{l_bar}{tgt7}      {quuz}                 {ready}
+					{mkdir} tests/none
 				"},
 					t_bar = symbols_color.paint("├── "),
 					l_bar = symbols_color.paint("└── "),
@@ -292,6 +999,7 @@ mod tests {
 					mismatch = Colour::Yellow.reverse().paint(" MISMATCH "),
 					conflict = Colour::Red.reverse().paint(" CONFLICT "),
 					obstructed = Colour::Red.reverse().paint(" OBSTRUCTED "),
+					mkdir = Colour::Green.bold().paint("mkdir:"),
 				),
 				"invalid colored output",
 			);
@@ -302,6 +1010,9 @@ mod tests {
 				tree: &tree,
 				colored: false,
 				home: Some("file".into()),
+				long: false,
+				icons: None,
+				width: None,
 			};
 
 			println!("\n{}", printer);
@@ -321,6 +1032,7 @@ mod tests {
 					│   └── bar       (bar)                  [UNKNOWN]
 					└── quux                                 
 					    └── quuz      (quuz)                 [READY]
+					mkdir: tests/none
 				"}),
 				"invalid non-colored output",
 			);
@@ -328,4 +1040,434 @@ mod tests {
 
 		Ok(())
 	}
+
+	#[test]
+	fn format_outputs() {
+		struct Test<'a> {
+			description: &'a str,
+			input: Box<dyn Formatter>,
+			output: &'a str,
+		}
+
+		let tree = Tree {
+			root: Node::Branch(Edges::from([(
+				"foo".into(),
+				Node::Branch(Edges::from([
+					("bar".into(), Node::Leaf(Box::new("test/bar".into()))),
+					("baz".into(), Node::Leaf(Box::new("test/<baz>".into()))),
+				])),
+			)])),
+			statuses: Statuses::from([("test/bar".into(), Status::Ready)]),
+			problems: Problems::from([("test/<baz>".into(), Status::ReadOnly)]),
+			work_dir: "test".into(),
+			..Tree::default()
+		};
+
+		let test_cases = Vec::from([
+			Test {
+				description: "porcelain",
+				input: Box::new(PorcelainFormatter),
+				output: indoc! {"
+					ready\tfoo/bar\ttest/bar
+					read-only\tfoo/baz\ttest/<baz>
+				"},
+			},
+			Test {
+				description: "YAML",
+				input: Box::new(YamlFormatter),
+				output: indoc! {r#"
+					dirs_to_create: []
+					targets:
+					  - description: null
+					    group: null
+					    hint: null
+					    link: "test/bar"
+					    problem: false
+					    remediation: null
+					    status: "ready"
+					    target: "foo/bar"
+					  - description: null
+					    group: null
+					    hint: null
+					    link: "test/<baz>"
+					    problem: true
+					    remediation: "manual-intervention"
+					    status: "read-only"
+					    target: "foo/baz"
+					work_dir: "test"
+				"#},
+			},
+			Test {
+				description: "DOT",
+				input: Box::new(DotFormatter),
+				output: indoc! {r#"
+					digraph park {
+						"foo/bar" -> "test/bar" [label="ready"];
+						"foo/baz" -> "test/<baz>" [label="read-only", color=red];
+					}
+				"#},
+			},
+			Test {
+				description: "HTML",
+				input: Box::new(HtmlFormatter),
+				output: indoc! {r#"
+					<!DOCTYPE html>
+					<html>
+					<head><meta charset="utf-8"><title>park</title></head>
+					<body>
+					<table>
+					<tr><th>Target</th><th>Link</th><th>Status</th></tr>
+					<tr class="ready"><td>foo/bar</td><td>test/bar</td><td>ready</td></tr>
+					<tr class="read-only"><td>foo/baz</td><td>test/&lt;baz&gt;</td><td>read-only</td></tr>
+					</table>
+					</body>
+					</html>
+				"#},
+			},
+		]);
+
+		for case in test_cases {
+			let mut got = Vec::new();
+			case.input.format(&tree, &mut got).unwrap();
+
+			assert_eq!(
+				String::from_utf8(got).unwrap(),
+				case.output,
+				"bad output for {:?}",
+				case.description
+			);
+		}
+	}
+
+	#[test]
+	fn format_json() {
+		let tree = Tree {
+			root: Node::Branch(Edges::from([(
+				"foo".into(),
+				Node::Branch(Edges::from([
+					("bar".into(), Node::Leaf(Box::new("test/bar".into()))),
+					(
+						"baz".into(),
+						Node::Leaf(Box::new(Leaf {
+							link_path: "test/baz".into(),
+							group: Some("shell".into()),
+							problem_hint: Some("move test/baz aside".into()),
+							..Leaf::default()
+						})),
+					),
+				])),
+			)])),
+			statuses: Statuses::from([("test/bar".into(), Status::Ready)]),
+			problems: Problems::from([("test/baz".into(), Status::Conflict)]),
+			work_dir: "test".into(),
+			..Tree::default()
+		};
+
+		let got: JsonValue =
+			serde_json::from_str(&JsonPrinter { tree: &tree }.to_string()).unwrap();
+
+		assert_eq!(
+			got,
+			json!({
+				"work_dir": "test",
+				"dirs_to_create": [],
+				"targets": [
+					{ "target": "foo/bar", "link": "test/bar", "status": "ready", "problem": false, "remediation": null, "hint": null, "description": null, "group": null },
+					{ "target": "foo/baz", "link": "test/baz", "status": "conflict", "problem": true, "remediation": "manual-intervention", "hint": "move test/baz aside", "description": null, "group": "shell" },
+				],
+			}),
+		);
+	}
+
+	#[test]
+	fn format_descriptions() {
+		let tree = Tree {
+			root: Node::Branch(Edges::from([
+				(
+					"foo".into(),
+					Node::Leaf(Box::new(Leaf {
+						link_path: "test/foo".into(),
+						description: Some("The foo".into()),
+						gate: Gate {
+							all_of: Vec::from(["desktop".into()]),
+							any_of: Vec::from(["sway".into()]),
+						},
+						..Leaf::default()
+					})),
+				),
+				(
+					"qux".into(),
+					Node::Branch(Edges::from([
+						("bar".into(), Node::Leaf(Box::new("test/bar".into()))),
+						("baz".into(), Node::Leaf(Box::new("test/qux/baz".into()))),
+					])),
+				),
+			])),
+			statuses: Statuses::from([
+				("test/foo".into(), Status::Ready),
+				("test/bar".into(), Status::Done),
+				("test/qux/baz".into(), Status::Unparented),
+			]),
+			work_dir: "test".into(),
+			link_opts: LinkOpts {
+				create_dirs: true,
+				..LinkOpts::default()
+			},
+			umask: 0o022,
+			lines: Lines::from([("foo".into(), 4), ("qux/bar".into(), 2)]),
+			files: Files::from([("qux/bar".into(), "park.home.toml".into())]),
+			..Tree::default()
+		};
+
+		let printer = Printer {
+			tree: &tree,
+			colored: false,
+			home: None,
+			long: true,
+			icons: None,
+			width: None,
+		};
+
+		assert_eq!(
+			printer.to_string(),
+			indoc! {"
+				. (test)
+				├── foo     (test/foo)     [READY]                all_of +desktop, any_of +sway line 4                 The foo
+				└── qux                                                                                                
+				    ├── bar (test/bar)     [DONE]                                               park.home.toml, line 2 
+				    └── baz (test/qux/baz) [UNPARENTED] dirs 0755                                                      
+				mkdir: test/qux
+			"},
+			"invalid long output",
+		);
+
+		let printer = TargetsPrinter {
+			tree: &tree,
+			home: Some("test".into()),
+		};
+
+		assert_eq!(
+			printer.to_string(),
+			indoc! {"
+				foo     ~/foo     The foo
+				qux/bar ~/bar     
+				qux/baz ~/qux/baz 
+			"},
+			"invalid target list",
+		);
+	}
+
+	#[test]
+	fn format_groups() {
+		let leaf = |link_path: &str, group: Option<&str>| {
+			Node::Leaf(Box::new(Leaf {
+				link_path: link_path.into(),
+				group: group.map(String::from),
+				..Leaf::default()
+			}))
+		};
+		let tree = Tree {
+			root: Node::Branch(Edges::from([
+				(
+					"bash".into(),
+					Node::Branch(Edges::from([
+						("bashrc".into(), leaf("test/bashrc", Some("shell"))),
+						("inputrc".into(), leaf("test/inputrc", None)),
+					])),
+				),
+				("gitconfig".into(), leaf("test/gitconfig", Some("git"))),
+				("zshrc".into(), leaf("test/zshrc", Some("shell"))),
+			])),
+			statuses: Statuses::from([
+				("test/bashrc".into(), Status::Ready),
+				("test/inputrc".into(), Status::Done),
+				("test/gitconfig".into(), Status::Ready),
+				("test/zshrc".into(), Status::Done),
+			]),
+			work_dir: "test".into(),
+			..Tree::default()
+		};
+
+		let printer = Printer {
+			tree: &tree,
+			colored: false,
+			home: None,
+			long: false,
+			icons: None,
+			width: None,
+		};
+
+		assert_eq!(
+			printer.to_string(),
+			indoc! {"
+				. (test)
+				└── bash                         
+				    └── inputrc (test/inputrc)   [DONE]
+				git:                             
+				└── gitconfig   (test/gitconfig) [READY]
+				shell:                           
+				├── bash                         
+				│   └── bashrc  (test/bashrc)    [READY]
+				└── zshrc       (test/zshrc)     [DONE]
+			"},
+			"invalid grouped output",
+		);
+	}
+
+	#[test]
+	fn format_icons() {
+		let tree = Tree {
+			root: Node::Branch(Edges::from([
+				("foo".into(), Node::Leaf(Box::new("test/foo".into()))),
+				(
+					"qux".into(),
+					Node::Branch(Edges::from([
+						("bar".into(), Node::Leaf(Box::new("test/bar".into()))),
+						("baz".into(), Node::Leaf(Box::new("test/baz".into()))),
+					])),
+				),
+			])),
+			statuses: Statuses::from([
+				("test/foo".into(), Status::Ready),
+				("test/bar".into(), Status::Done),
+				("test/baz".into(), Status::Conflict),
+			]),
+			work_dir: "test".into(),
+			..Tree::default()
+		};
+
+		let printer = Printer {
+			tree: &tree,
+			colored: false,
+			home: None,
+			long: false,
+			icons: Some(Icons::Unicode),
+			width: None,
+		};
+
+		assert_eq!(
+			printer.to_string(),
+			indoc! {"
+				. (test)
+				├── → foo     (test/foo)
+				└── qux       
+				    ├── ✓ bar (test/bar)
+				    └── ✗ baz (test/baz)
+			"},
+			"invalid output with icons",
+		);
+
+		let printer = Printer {
+			icons: Some(Icons::NerdFont),
+			..printer
+		};
+
+		assert_eq!(
+			printer.to_string().lines().nth(1),
+			Some("├── \u{f061} foo     (test/foo)"),
+			"invalid output with Nerd Font icons",
+		);
+	}
+
+	#[test]
+	fn format_narrow_tree() {
+		let tree = Tree {
+			root: Node::Branch(Edges::from([
+				(
+					"foo".into(),
+					Node::Leaf(Box::new("/home/park/.config/foo".into())),
+				),
+				(
+					"qux".into(),
+					Node::Branch(Edges::from([(
+						"bar".into(),
+						Node::Leaf(Box::new("/home/park/.local/share/qux/bar".into())),
+					)])),
+				),
+			])),
+			statuses: Statuses::from([
+				("/home/park/.config/foo".into(), Status::Ready),
+				("/home/park/.local/share/qux/bar".into(), Status::Done),
+			]),
+			work_dir: "test".into(),
+			..Tree::default()
+		};
+
+		let printer = Printer {
+			tree: &tree,
+			colored: false,
+			home: Some("/home/park".into()),
+			long: false,
+			icons: None,
+			width: Some(36),
+		};
+
+		assert_eq!(
+			printer.to_string(),
+			indoc! {"
+				. (test)
+				├── foo     (~/.config/foo)  [READY]
+				└── qux                      
+				    └── bar (…share/qux/bar) [DONE]
+			"},
+			"invalid narrow output",
+		);
+
+		let printer = Printer {
+			width: Some(80),
+			..printer
+		};
+
+		assert!(
+			printer.to_string().contains("(~/.local/share/qux/bar)"),
+			"link paths should be shortened only when needed",
+		);
+	}
+
+	#[test]
+	fn format_tree_hints() {
+		let tree = Tree {
+			root: Node::Branch(Edges::from([
+				(
+					"foo".into(),
+					Node::Leaf(Box::new(Leaf {
+						link_path: "test/foo".into(),
+						problem_hint: Some("run :PackerSync after linking".into()),
+						..Leaf::default()
+					})),
+				),
+				(
+					"bar".into(),
+					Node::Leaf(Box::new(Leaf {
+						link_path: "test/bar".into(),
+						problem_hint: Some("never shown".into()),
+						..Leaf::default()
+					})),
+				),
+			])),
+			statuses: Statuses::from([("test/bar".into(), Status::Ready)]),
+			problems: Problems::from([("test/foo".into(), Status::Conflict)]),
+			work_dir: "test".into(),
+			..Tree::default()
+		};
+
+		let printer = Printer {
+			tree: &tree,
+			colored: false,
+			home: None,
+			long: false,
+			icons: None,
+			width: None,
+		};
+		let got = printer.to_string();
+
+		assert!(
+			got.contains("hint: \"test/foo\": run :PackerSync after linking\n"),
+			"problems should be followed by their hints",
+		);
+		assert!(
+			!got.contains("never shown"),
+			"hints should be shown only for problems",
+		);
+	}
 }
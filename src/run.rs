@@ -1,295 +1,5296 @@
-use std::path::PathBuf;
-use std::{ffi::OsString, io::Write};
+use std::path::{Path, PathBuf};
+use std::{
+	collections::{BTreeMap, BTreeSet, HashSet},
+	env,
+	ffi::{OsStr, OsString},
+	fs::{self, File, OpenOptions},
+	io::{BufRead, BufReader, Error as IoError, Read, Write},
+	mem,
+	os::fd::AsRawFd,
+	process::{Command as ProcessCommand, ExitCode, Stdio},
+	thread,
+	time::{Duration, Instant, SystemTime},
+};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use clap::CommandFactory;
+use serde_json::{json, Value as JsonValue};
+use tabwriter::TabWriter;
+use toml::{de::Error as TomlError, value::Table as TomlTable, Value as TomlValue};
 
-use crate::cli::Park;
-use crate::parser::tree::LinkOpts;
-use crate::{config::Config, parser::tree::Tree, printer::Printer};
+use crate::cli::{
+	Check, ColorChoice, Command, Completions, Explain, Export, Filters, Matrix, Output, Park,
+	Pending, Query, QueryFormat, Status as StatusArgs, Subtree, Targets, Unlink, Verify,
+};
+use crate::parser::tree::{CompareBudget, LinkOpts, Retry};
+use crate::{
+	cache::Cache,
+	config::{self, Config, Policy, RemapEntry, TagSet},
+	manifest::{self, Manifest},
+	parser::{
+		error::Error as LinkError,
+		explain::{Explanation, Step},
+		iter::Element as IterElement,
+		node::{Error as NodeError, Gate, Leaf, Status},
+		tree::{self, Linked, Origin, Remediation, Resolver, Tree},
+		warning::Warning,
+	},
+	printer::{
+		self, DotFormatter, Formatter, HtmlFormatter, Icons, JsonFormatter, PorcelainFormatter,
+		Printer, Summary, SummaryPrinter, TargetsPrinter, TreeFormatter, YamlFormatter,
+	},
+	scenario::Scenario,
+	schema,
+	state::StateStore,
+	vars::{self, Vars},
+};
 
+#[derive(Clone)]
 pub struct Env {
 	pub colored: bool,
 	pub home: Option<OsString>,
+	/// Tags that are automatically activated, like the current operating system.
+	pub auto_tags: TagSet,
+	/// Where state kept between runs, like the record of created links and the analysis
+	/// cache, is stored.
+	pub state_dir: Option<PathBuf>,
+	/// Where variables local to the machine are read from.
+	pub vars_path: Option<PathBuf>,
+	/// Variables available to every configuration, like the XDG base directories.
+	pub builtin_vars: Vars,
+	/// File mode creation mask of the process.
+	pub umask: u32,
+	/// Width of the terminal the output is written to, if any.
+	pub width: Option<usize>,
+	/// Height of the terminal the output is written to, if any.
+	pub height: Option<usize>,
+	/// Command previews taller than the terminal are piped into, if any.
+	pub pager: Option<OsString>,
+	/// Name of the host, which picks the configuration overlay for the host.
+	pub hostname: Option<String>,
+	/// Terminal that prompts are read from and written to, if any, since standard input may
+	/// hold the configuration.
+	pub terminal: Option<PathBuf>,
+	/// Character tags are prepended with among the arguments, which overrides the one set by
+	/// the configuration.
+	pub tag_prefix: Option<char>,
+}
+
+/// Returns the file mode creation mask of the process, which can only be read by replacing it,
+/// so it's restored right afterwards.
+pub fn current_umask() -> u32 {
+	// SAFETY: umask can't fail, and the original mask is restored right away.
+	unsafe {
+		let mask = libc::umask(0);
+		libc::umask(mask);
+
+		mask as u32
+	}
+}
+
+/// Returns the controlling terminal of the process, if it can be opened for prompts.
+pub fn find_terminal() -> Option<PathBuf> {
+	let path = PathBuf::from("/dev/tty");
+
+	OpenOptions::new()
+		.read(true)
+		.write(true)
+		.open(&path)
+		.is_ok()
+		.then_some(path)
+}
+
+/// Opens the terminal prompts are read from and written to. Answers never come from standard
+/// input, which may hold the configuration, so that prompts work even when it's piped.
+pub fn open_terminal(terminal: Option<&Path>) -> Result<(BufReader<File>, File)> {
+	let Some(path) = terminal else {
+		bail!("no terminal is available to prompt on");
+	};
+
+	let terminal = OpenOptions::new()
+		.read(true)
+		.write(true)
+		.open(path)
+		.with_context(|| format!("could not open terminal {:?}", path))?;
+
+	Ok((BufReader::new(terminal.try_clone()?), terminal))
+}
+
+/// Reads a configuration file under a shared lock, which is held until the returned file is
+/// dropped.
+pub fn read_locked_config(path: &Path) -> Result<(String, File), IoError> {
+	let mut file = File::open(path)?;
+
+	// SAFETY: the descriptor stays open during the call.
+	if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_SH) } != 0 {
+		return Err(IoError::last_os_error());
+	}
+
+	let mut content = String::new();
+	file.read_to_string(&mut content)?;
+
+	Ok((content, file))
+}
+
+/// Returns the name of the host, without its domain, if it can be read.
+pub fn current_hostname() -> Option<String> {
+	let mut buffer = [0u8; 256];
+
+	// SAFETY: the buffer's length is passed along, so the name is truncated rather than
+	// overflowing it.
+	if unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) } != 0 {
+		return None;
+	}
+
+	let name = buffer.split(|byte| *byte == 0).next()?;
+	let name = String::from_utf8(name.to_vec()).ok()?;
+
+	name.split('.')
+		.next()
+		.filter(|name| !name.is_empty())
+		.map(String::from)
+}
+
+/// Reads a configuration as of a Git revision, given as "<rev>:<path>", from the repository
+/// holding its work directory. Where that is can only be told by the configuration, so it's
+/// first read from the repository holding `dir`, and read again from the one holding its work
+/// directory only if that's another repository.
+pub fn read_work_dir_config_ref(
+	dir: &Path,
+	config_ref: &str,
+	home: Option<&OsStr>,
+) -> Result<String> {
+	let content = read_config_ref(dir, config_ref)?;
+	let work_dir = toml::from_str::<Config>(&content)
+		.ok()
+		.and_then(|config| config.work_dir)
+		.map(|work_dir| dir.join(expand_home(work_dir, home)))
+		.and_then(|work_dir| fs::canonicalize(work_dir).ok());
+	let Some(work_dir) = work_dir else {
+		return Ok(content);
+	};
+
+	match repository_root(dir) {
+		Some(root) if !work_dir.starts_with(&root) => read_config_ref(&work_dir, config_ref),
+		_ => Ok(content),
+	}
+}
+
+/// Returns the root of the repository holding `dir`, if any.
+fn repository_root(dir: &Path) -> Option<PathBuf> {
+	let output = ProcessCommand::new("git")
+		.arg("-C")
+		.arg(dir)
+		.args(["rev-parse", "--show-toplevel"])
+		.stdin(Stdio::null())
+		.stderr(Stdio::null())
+		.output()
+		.ok()
+		.filter(|output| output.status.success())?;
+	let root = String::from_utf8(output.stdout).ok()?;
+
+	Some(root.trim_end_matches('\n').into())
+}
+
+/// Reads a file as of a Git revision, given as "<rev>:<path>", from the repository holding
+/// `dir`.
+pub fn read_config_ref(dir: &Path, config_ref: &str) -> Result<String> {
+	if !config_ref.contains(':') {
+		bail!("{:?} is not in the form <rev>:<path>", config_ref);
+	}
+
+	let output = ProcessCommand::new("git")
+		.arg("-C")
+		.arg(dir)
+		.args(["cat-file", "blob", config_ref])
+		.stdin(Stdio::null())
+		.output()
+		.with_context(|| "could not run git")?;
+
+	if !output.status.success() {
+		bail!(
+			"could not read {:?} from git: {}",
+			config_ref,
+			String::from_utf8_lossy(&output.stderr).trim()
+		);
+	}
+
+	String::from_utf8(output.stdout).with_context(|| format!("{:?} is not valid UTF-8", config_ref))
+}
+
+/// Detects the environment Park runs in, returning "wsl" under the Windows Subsystem for Linux,
+/// "container" inside containers, like Docker or Podman ones, and "ssh" within SSH sessions.
+/// Files are read through `read`, so that any of them can be missing, while markers that only
+/// need to exist are checked through `exists`, since they may be unreadable.
+pub fn environment_tags<F, R, X>(var: F, read: R, exists: X) -> TagSet
+where
+	F: Fn(&str) -> Option<OsString>,
+	R: Fn(&Path) -> Option<String>,
+	X: Fn(&Path) -> bool,
+{
+	let wsl = var("WSL_DISTRO_NAME").is_some()
+		|| read(Path::new("/proc/sys/kernel/osrelease"))
+			.is_some_and(|release| release.to_lowercase().contains("microsoft"));
+	let container = var("container").is_some()
+		|| var("KUBERNETES_SERVICE_HOST").is_some()
+		|| ["/.dockerenv", "/run/.containerenv"]
+			.into_iter()
+			.any(|path| exists(Path::new(path)));
+	let ssh = ["SSH_CONNECTION", "SSH_CLIENT", "SSH_TTY"]
+		.into_iter()
+		.any(|key| var(key).is_some());
+
+	[("wsl", wsl), ("container", container), ("ssh", ssh)]
+		.into_iter()
+		.filter(|(_, detected)| *detected)
+		.map(|(tag, _)| tag.into())
+		.collect()
+}
+
+/// Decides whether output should be colored. An explicit choice always wins, otherwise
+/// CLICOLOR_FORCE, NO_COLOR and CLICOLOR are checked, in that order, before falling back to
+/// whether the output is a terminal.
+pub fn use_colors<F>(choice: ColorChoice, is_terminal: bool, var: F) -> bool
+where
+	F: Fn(&str) -> Option<OsString>,
+{
+	match choice {
+		ColorChoice::Always => return true,
+		ColorChoice::Never => return false,
+		ColorChoice::Auto => {}
+	}
+
+	if var("CLICOLOR_FORCE").is_some_and(|value| !value.is_empty() && value != "0") {
+		return true;
+	}
+
+	if var("NO_COLOR").is_some() || var("CLICOLOR").is_some_and(|value| value == "0") {
+		return false;
+	}
+
+	is_terminal
+}
+
+/// Resolves the pager previews are piped into, which is PAGER or, if it's not set, less. Paging is
+/// disabled when PAGER is empty or when not writing to a terminal.
+pub fn find_pager<F>(is_terminal: bool, var: F) -> Option<OsString>
+where
+	F: Fn(&str) -> Option<OsString>,
+{
+	if !is_terminal {
+		return None;
+	}
+
+	match var("PAGER") {
+		Some(pager) if pager.is_empty() => None,
+		Some(pager) => Some(pager),
+		None => Some("less".into()),
+	}
+}
+
+/// Returns the character PARK_TAG_PREFIX sets for tags to be prepended with, unless it's
+/// anything other than a single character.
+pub fn tag_prefix<F>(var: F) -> Option<char>
+where
+	F: Fn(&str) -> Option<OsString>,
+{
+	let value = var("PARK_TAG_PREFIX")?;
+	let mut chars = value.to_str()?.chars();
+
+	match (chars.next(), chars.next()) {
+		(Some(prefix), None) => Some(prefix),
+		_ => None,
+	}
 }
 
 /// Runs the program, parsing STDIN for a config file.
-pub fn run<W>(env: Env, input: &str, mut stdout: W, cli: Park) -> Result<()>
+pub fn run<W, E>(env: Env, input: &str, stdout: W, mut stderr: E, cli: Park) -> Result<ExitCode>
+where
+	W: Write,
+	E: Write,
+{
+	if cli.watch {
+		return watch(env, input, stdout, stderr, cli);
+	}
+
+	let output = cli.output;
+
+	match execute(env, input, stdout, &mut stderr, cli) {
+		Err(err) if output == Output::Json => {
+			writeln!(stderr, "{:#}", error_document(&err))
+				.with_context(|| "could not print error")?;
+
+			Ok(ExitCode::FAILURE)
+		}
+		result => result,
+	}
+}
+
+/// Links targets over and over, each time files change where they're linked from, until the
+/// program is interrupted. Errors of a round are printed rather than returned, so that a
+/// problem, like a typo in the configuration file, can be fixed without watching again.
+fn watch<W, E>(env: Env, input: &str, mut stdout: W, mut stderr: E, cli: Park) -> Result<ExitCode>
+where
+	W: Write,
+	E: Write,
+{
+	if cli.command.is_some() {
+		bail!("could not watch: only the linking step can be watched");
+	}
+
+	let interval = Duration::from_millis(cli.watch_interval);
+
+	loop {
+		let paths = match watched_paths(input, cli.config.as_deref()) {
+			Ok(paths) => paths,
+			// Standard input is only read once, so its configuration can't be fixed.
+			Err(err) if cli.config.is_none() => return Err(err),
+			Err(_) => cli.config.iter().cloned().collect(),
+		};
+		let times = modification_times(&paths);
+
+		if let Err(err) = execute(env.clone(), input, &mut stdout, &mut stderr, cli.clone()) {
+			if cli.output == Output::Json {
+				writeln!(stderr, "{:#}", error_document(&err))
+			} else {
+				writeln!(stderr, "error: {:#}", err)
+			}
+			.with_context(|| "could not print error")?;
+		}
+
+		wait_for_changes(&paths, times, interval);
+	}
+}
+
+/// Returns the paths to watch for a configuration, which are the sources of its targets and the
+/// directories holding them, along with the configuration file, if it's read from one. Targets
+/// only become READY once their sources exist, so nothing else in work directories is watched,
+/// and the directories tell when sources are added or replaced, even by renaming.
+fn watched_paths(input: &str, config_path: Option<&Path>) -> Result<BTreeSet<PathBuf>> {
+	let content;
+	let input = match config_path {
+		Some(path) => {
+			content = fs::read_to_string(path)
+				.with_context(|| format!("could not read configuration from {:?}", path))?;
+
+			content.as_str()
+		}
+		None => input,
+	};
+	let config: Config = toml::from_str(input).with_context(|| "could not read configuration")?;
+
+	let mut paths = BTreeSet::new();
+
+	for source in tree::configured_sources(&config) {
+		match source.parent() {
+			Some(parent) if parent.as_os_str().is_empty() => paths.insert(".".into()),
+			Some(parent) => paths.insert(parent.to_path_buf()),
+			None => false,
+		};
+
+		paths.insert(source);
+	}
+
+	paths.extend(config_path.map(Path::to_path_buf));
+
+	Ok(paths)
+}
+
+/// Records when some paths were last modified, without looking into directories, so that
+/// polling costs the same however large they are. Missing paths are recorded too, so that
+/// creating them counts as a change. Symlinks aren't followed.
+fn modification_times(paths: &BTreeSet<PathBuf>) -> BTreeMap<PathBuf, Option<SystemTime>> {
+	paths
+		.iter()
+		.map(|path| {
+			let time = fs::symlink_metadata(path).and_then(|metadata| metadata.modified());
+
+			(path.clone(), time.ok())
+		})
+		.collect()
+}
+
+/// Polls paths until any of them changed since some modification times were recorded,
+/// then waits for them to stay unchanged for a whole interval, so that files still being
+/// written, like by a checkout, are only linked once they're done.
+fn wait_for_changes(
+	paths: &BTreeSet<PathBuf>,
+	mut times: BTreeMap<PathBuf, Option<SystemTime>>,
+	interval: Duration,
+) {
+	let mut changed = false;
+
+	loop {
+		thread::sleep(interval);
+		let current = modification_times(paths);
+
+		if current == times {
+			if changed {
+				return;
+			}
+
+			continue;
+		}
+
+		changed = true;
+		times = current;
+	}
+}
+
+/// Describes an error as a JSON document, so that automation can tell errors apart.
+fn error_document(err: &anyhow::Error) -> JsonValue {
+	let message = format!("{:#}", err);
+
+	if let Some(err) = err.downcast_ref::<TomlError>() {
+		let (line, column) = err
+			.line_col()
+			.map_or((None, None), |(line, col)| (Some(line + 1), Some(col + 1)));
+
+		// The snippet in the context is meant for humans, so it's left out here.
+		return json!({
+			"kind": "invalid-config",
+			"path": null,
+			"message": format!("could not read input configuration: {}", err),
+			"line": line,
+			"column": column,
+		});
+	}
+
+	if let Some(err) = err.downcast_ref::<NodeError>() {
+		let path = match err {
+			NodeError::NotABranch(_, link_path) | NodeError::LeafExists(_, link_path) => {
+				Some(link_path)
+			}
+			NodeError::UnsafeLinkName(_, name) => Some(name),
+			NodeError::UnknownVar(target_path, _) | NodeError::UnknownOwner(target_path, _) => {
+				Some(target_path)
+			}
+			NodeError::FailedScript(target_path, ..) => Some(target_path),
+			NodeError::OverlappingTargets(_, target_path) => Some(target_path),
+			NodeError::OverlappingLinks(_, _, link_path) => Some(link_path),
+			NodeError::EmptySegment => None,
+		};
+
+		return json!({ "kind": "invalid-target", "path": path, "message": message });
+	}
+
+	if let Some(err) = err.downcast_ref::<LinkError>() {
+		return match err {
+			LinkError::BadFiles(problems, _, origins, hints) => json!({
+				"kind": "problems",
+				"path": null,
+				"message": message,
+				"problems": problems
+					.iter()
+					.map(|(path, status)| {
+						let origin = origins.get(path);
+
+						json!({
+							"path": path,
+							"status": status,
+							"target": origin.map(|origin| &origin.target_path),
+							"line": origin.and_then(|origin| origin.line),
+							"remediation": Remediation::of(status),
+							"hint": hints.get(path),
+						})
+					})
+					.collect::<Vec<_>>(),
+			}),
+			LinkError::PermissionDenied(link_paths) => json!({
+				"kind": "permission-denied",
+				"path": null,
+				"message": message,
+				"paths": link_paths,
+			}),
+			LinkError::EscalationFailed(link_path) => {
+				json!({ "kind": "escalation-failed", "path": link_path, "message": message })
+			}
+			LinkError::InternalError(link_path) => {
+				json!({ "kind": "internal", "path": link_path, "message": message })
+			}
+			LinkError::IoError(_) => json!({ "kind": "io", "path": null, "message": message }),
+		};
+	}
+
+	if err.downcast_ref::<IoError>().is_some() {
+		return json!({ "kind": "io", "path": null, "message": message });
+	}
+
+	json!({ "kind": "other", "path": null, "message": message })
+}
+
+/// Replaces a leading '~' in a path with the home directory, if it's known.
+fn expand_home(path: PathBuf, home: Option<&OsStr>) -> PathBuf {
+	match (path.strip_prefix("~"), home) {
+		(Ok(path), Some(home)) => Path::new(home).join(path),
+		_ => path,
+	}
+}
+
+/// Describes where a configuration error happened, pointing at it in a snippet of the input.
+fn config_diagnostic(input: &str, source: &str, err: &TomlError) -> String {
+	let (line, col) = match err.line_col() {
+		Some(line_col) => line_col,
+		None => return "could not read input configuration".into(),
+	};
+
+	let source_line = input.split_terminator('\n').nth(line).unwrap_or_default();
+	let source_line = source_line.strip_suffix('\r').unwrap_or(source_line);
+	let caret_offset = source_line
+		.get(..col)
+		.map_or(col, |prefix| prefix.chars().count());
+
+	let line_number = (line + 1).to_string();
+	let gutter = " ".repeat(line_number.len());
+
+	format!(
+		"could not read input configuration at {source}:{}:{}\n{gutter} |\n{line_number} | {source_line}\n{gutter} | {caret:>width$}",
+		line + 1,
+		col + 1,
+		caret = "^",
+		width = caret_offset + 1,
+	)
+}
+
+fn execute<W, E>(
+	mut env: Env,
+	input: &str,
+	mut stdout: W,
+	mut stderr: E,
+	cli: Park,
+) -> Result<ExitCode>
 where
 	W: Write,
+	E: Write,
 {
-	let config: Config =
-		toml::from_str(input).with_context(|| "could not read input configuration")?;
+	if let Some(Command::Completions(Completions { shell, .. })) = cli.command {
+		clap_complete::generate(shell, &mut Park::command(), "park", &mut stdout);
+
+		return Ok(ExitCode::SUCCESS);
+	}
+
+	if let Some(Command::Schema(_)) = cli.command {
+		writeln!(stdout, "{:#}", schema::config()).with_context(|| "could not print schema")?;
+
+		return Ok(ExitCode::SUCCESS);
+	}
+
+	if cli.non_interactive {
+		env.colored = false;
+		env.width = None;
+		env.height = None;
+		env.pager = None;
+		env.terminal = None;
+	}
+
+	let other_input;
+	let config_path;
+	// Held until linking is done, so that tools honoring locks don't change the file meanwhile.
+	let mut config_file = None;
+	let (input, source) = match (&cli.config, &cli.config_ref) {
+		(Some(path), _) => {
+			let (content, file) = read_locked_config(path)
+				.with_context(|| format!("could not read configuration from {:?}", path))?;
+			other_input = content;
+			config_path = path.to_string_lossy();
+			config_file = Some((path.clone(), file));
+
+			(other_input.as_str(), config_path.as_ref())
+		}
+		(None, Some(config_ref)) => {
+			other_input =
+				read_work_dir_config_ref(&env::current_dir()?, config_ref, env.home.as_deref())?;
+
+			(other_input.as_str(), config_ref.as_str())
+		}
+		(None, None) => (input, "<stdin>"),
+	};
+
+	let mut timer = Timer::new();
+	let mut config: Config = toml::from_str(input).map_err(|err| {
+		let context = config_diagnostic(input, source, &err);
+
+		anyhow::Error::new(err).context(context)
+	})?;
+
+	// Overlays sit next to the configuration file, which only standard input and Git revisions
+	// lack, so those look for it in the work directory.
+	let overlay_dir = match &config_file {
+		Some((path, _)) => path.parent().map(Path::to_path_buf).unwrap_or_default(),
+		None => config.work_dir.clone().unwrap_or_default(),
+	};
+	let overlay_path = env
+		.hostname
+		.as_ref()
+		.map(|hostname| overlay_dir.join(format!("park.{}.toml", hostname)));
+
+	let mut files = tree::Files::new();
+	let mut overlay_lines = tree::Lines::new();
+
+	if let Some(overlay_path) = overlay_path.filter(|path| path.is_file()) {
+		let context = || format!("could not read host configuration {:?}", overlay_path);
+		let overlay_input = fs::read_to_string(&overlay_path).with_context(context)?;
+		let overlay: TomlValue = toml::from_str(&overlay_input)
+			.map_err(|err| anyhow::Error::new(err).context(context()))?;
+
+		// Targets declared in the overlay are told to come from it, since it has the last say.
+		overlay_lines = config::target_lines(&overlay_input);
+		files.extend(
+			overlay_lines
+				.keys()
+				.map(|target_path| (target_path.clone(), overlay_path.clone())),
+		);
+
+		// The input is known to be valid by now.
+		let mut document: TomlValue = toml::from_str(input)?;
+		config::merge(&mut document, overlay);
+		config = document.try_into().with_context(context)?;
+	}
+
+	if config.base_dir.is_none() {
+		config.base_dir = if config.xdg.unwrap_or_default() {
+			Some("{xdg_config}".into())
+		} else {
+			env.home.as_ref().map(PathBuf::from)
+		};
+	}
+
+	let mut vars = mem::take(&mut env.builtin_vars);
+	vars.extend(config.vars.take().unwrap_or_default());
+
+	if let Some(vars_path) = &env.vars_path {
+		let local_vars = vars::load(vars_path)
+			.with_context(|| format!("could not read variables {:?}", vars_path))?;
+
+		vars.extend(local_vars);
+	}
+
+	config.vars = Some(vars);
+
+	// Link paths have the home directory spelled out by now, so remapped prefixes need it too.
+	if let Some(remap) = &mut config.remap {
+		*remap = mem::take(remap)
+			.into_iter()
+			.map(|(key, entry)| match entry {
+				RemapEntry::Prefix(to) => (
+					expand_home(PathBuf::from(key), env.home.as_deref())
+						.to_string_lossy()
+						.into_owned(),
+					RemapEntry::Prefix(expand_home(to, env.home.as_deref())),
+				),
+				RemapEntry::Tagged(prefixes) => (
+					key,
+					RemapEntry::Tagged(
+						prefixes
+							.into_iter()
+							.map(|(from, to)| {
+								(
+									expand_home(from, env.home.as_deref()),
+									expand_home(to, env.home.as_deref()),
+								)
+							})
+							.collect(),
+					),
+				),
+			})
+			.collect();
+	}
+
 	let Park {
 		link,
 		filters,
+		tag,
 		replace,
 		create_dirs,
+		escalate,
+		copy_fallback,
+		deep_check,
+		strict,
+		cache,
+		changed,
+		notify,
+		verbose,
+		simulate,
+		timings,
+		output,
+		only,
+		show_tree,
+		pick,
+		summary_only,
+		prune_orphans,
+		watch,
+		long,
+		icons,
+		no_pager,
+		no_tilde,
+		track,
+		checksums,
+		jobs,
+		retries,
+		retry_delay,
+		command,
 		..
 	} = cli;
 
-	let (tags, targets): (Vec<String>, Vec<String>) =
-		filters.into_iter().partition(|s| s.starts_with('+'));
+	let display_home = env.home.clone().filter(|_| !no_tilde);
+
+	// Literal target names are never taken as tags, and neither are the targets of commands
+	// about a single one.
+	let Filters { filters, literal } = match &command {
+		Some(
+			Command::Check(Check { filters, .. })
+			| Command::Targets(Targets { filters, .. })
+			| Command::Unlink(Unlink { filters, .. })
+			| Command::Export(Export { filters, .. })
+			| Command::Verify(Verify { filters, .. })
+			| Command::Query(Query { filters, .. })
+			| Command::Pending(Pending { filters, .. }),
+		) => filters.clone(),
+		Some(Command::Status(StatusArgs { target, tags, .. })) => Filters {
+			filters: tags.clone(),
+			literal: Vec::from([target.clone()]),
+		},
+		// Other targets are parsed too, since they decide whether the target's link is unfolded.
+		Some(Command::Explain(Explain { tags, .. }) | Command::Tree(Subtree { tags, .. })) => {
+			Filters {
+				filters: tags.clone(),
+				literal: Vec::new(),
+			}
+		}
+		Some(Command::Completions(_) | Command::Schema(_) | Command::Matrix(_)) | None => filters,
+	};
+
+	let tag_prefix = env.tag_prefix.or(config.tag_prefix).unwrap_or('+');
+	let (tags, targets) = partition_filters(filters, tag_prefix);
+
+	let mut tags: TagSet = tags.into_iter().chain(tag).collect();
+	let targets = targets
+		.iter()
+		.chain(&literal)
+		.chain(&only)
+		.map(PathBuf::from)
+		.collect();
+
+	let filter_tags = tags.clone();
+
+	// Automatic tags are left out, since configurations needn't mention them.
+	let mut warnings = Tree::check_filters(&config, (&tags, &targets));
+	tags.extend(mem::take(&mut env.auto_tags));
+
+	if let Some(Command::Matrix(Matrix { profiles, .. })) = &command {
+		return matrix(&config, profiles, output, stdout);
+	}
+
+	let lints = match &command {
+		Some(Command::Check(Check { lint: true, .. })) => {
+			if config
+				.profiles
+				.as_ref()
+				.is_none_or(|profiles| profiles.is_empty())
+			{
+				bail!("could not lint configuration: no profiles are configured");
+			}
+
+			Tree::lint(&config)
+		}
+		_ => Vec::new(),
+	};
+
+	// The target is explained once the tree is parsed, so that it reuses the results of scripts.
+	let explaining = match &command {
+		Some(Command::Explain(Explain { target, .. })) => {
+			let target_path = PathBuf::from(target);
+			let Some(target) = config
+				.targets
+				.as_ref()
+				.and_then(|targets| targets.get(&target_path))
+			else {
+				let filters = (&TagSet::new(), &HashSet::from([target_path]));
+
+				match Tree::check_filters(&config, filters)
+					.into_iter()
+					.find(|warning| matches!(warning, Warning::UnknownTarget(..)))
+				{
+					Some(warning) => bail!("could not explain target: {}", warning),
+					None => bail!("could not explain target: {:?} does not exist", target),
+				}
+			};
+
+			Some((
+				target_path,
+				target.clone(),
+				Resolver::new(&config, tags.clone()),
+			))
+		}
+		_ => None,
+	};
+
+	let printer_config = config.printer.take().unwrap_or_default();
+	let policy = config.policy.take().unwrap_or_default();
+	let icons = (icons || printer_config.icons.unwrap_or_default()).then(|| {
+		if printer_config.nerd_font.unwrap_or_default() {
+			Icons::NerdFont
+		} else {
+			Icons::Unicode
+		}
+	});
+
+	let create_dirs_roots = policy.create_dirs_allowed_under.clone().map(|roots| {
+		roots
+			.into_iter()
+			.map(|root| expand_home(root, env.home.as_deref()))
+			.collect()
+	});
+
+	let mut lines = config::target_lines(input);
+	lines.extend(overlay_lines);
+	let sources = tree::configured_sources(&config);
+	let mut tree = Tree::parse(
+		config,
+		(tags, targets),
+		LinkOpts {
+			replace,
+			create_dirs,
+			create_dirs_roots,
+			escalate,
+			copy_fallback,
+			deep_check,
+			replace_identical: policy.replace_identical.unwrap_or_default(),
+			max_compare_size: policy.max_compare_size,
+			prune_orphans,
+			watch,
+		},
+	)
+	.map_err(|err| {
+		let context = match err.target_path() {
+			Some(target_path) => {
+				format!(
+					"could not parse {}",
+					Origin::of(target_path, &lines, &files)
+				)
+			}
+			None => "could not parse target".into(),
+		};
+
+		anyhow::Error::new(err).context(context)
+	})?;
+
+	timer.lap("parse");
+	tree.jobs = jobs;
+	tree.compare_budget = CompareBudget::new(policy.compare_budget);
+	tree.retry = Retry {
+		attempts: retries,
+		delay: Duration::from_millis(retry_delay),
+	};
+	tree.lines = lines;
+	tree.files = files;
+	tree.umask = env.umask;
+	tree.terminal = env.terminal.clone();
+
+	if let Some(Command::Tree(Subtree { prefix, .. })) = &command {
+		tree.retain(|target_path| target_path.starts_with(prefix));
+
+		if tree.root.into_iter().all(|element| element.leaf.is_none()) {
+			bail!("could not print tree: no targets under {:?}", prefix);
+		}
+	}
+
+	// Verifying relies on the manifest, so it reads it even without tracking.
+	let verifying = matches!(command, Some(Command::Verify(_)));
+	// Linking always requires a fresh analysis.
+	let caching = cache && !link;
+	let state = env
+		.state_dir
+		.take()
+		.filter(|_| track || verifying || caching)
+		.map(StateStore::new);
+	// Held until the manifest is stored, so that concurrent runs don't lose each other's links,
+	// or until the cache is, so that they don't read it while it's being replaced.
+	let unlinking = matches!(command, Some(Command::Unlink(_)));
+	let _lock = match state.as_ref().filter(|_| link || unlinking || caching) {
+		Some(state) => Some(
+			state
+				.lock()
+				.with_context(|| "could not lock state directory")?,
+		),
+		None => None,
+	};
+	let mut manifest = state
+		.as_ref()
+		.filter(|_| track || verifying)
+		.map(Manifest::load)
+		.transpose()
+		.with_context(|| "could not read manifest")?;
+	tree.managed = manifest
+		.as_ref()
+		.map(|manifest| manifest.links.keys().cloned().collect());
+
+	if let Some(manifest) = &manifest {
+		tree.find_orphans(&manifest.links, &sources);
+	}
+
+	if let Some(Command::Check(Check { quick, verbose, .. })) = command {
+		return check(tree, &policy, quick, verbose, &lints, stdout);
+	}
+
+	if let Some((target_path, target, mut resolver)) = explaining {
+		resolver.scripts = tree.scripts.clone();
+		let explanation = Explanation::new(resolver, &target_path, target).with_context(|| {
+			format!(
+				"could not explain {}",
+				Origin::of(&target_path, &tree.lines, &tree.files)
+			)
+		})?;
+
+		return explain(tree, &target_path, explanation, stdout);
+	}
+
+	if let Some(Command::Status(StatusArgs { target, .. })) = &command {
+		let target_path = Path::new(target);
+		let leaf = tree
+			.root
+			.into_iter()
+			.find_map(|element| element.leaf.filter(|_| element.target_path == target_path));
+
+		return match leaf {
+			Some(leaf) => status(&tree, target_path, &leaf, output, stdout),
+			None => match warnings
+				.iter()
+				.find(|warning| matches!(warning, Warning::UnknownTarget(..)))
+			{
+				Some(warning) => bail!("could not query target: {}", warning),
+				None => bail!("could not query target: {:?} is left out by tags", target),
+			},
+		};
+	}
+
+	if let Some(Command::Query(Query { format, .. })) = command {
+		return query(&tree, format, stdout);
+	}
+
+	if let Some(Command::Pending(Pending { names_only, .. })) = command {
+		return pending(tree, names_only, stdout);
+	}
+
+	if verifying {
+		let Some(manifest) = &manifest else {
+			bail!("could not verify targets: no state directory");
+		};
+
+		return verify(&tree, manifest, stdout);
+	}
+
+	if let Some(Command::Targets(_)) = command {
+		write!(
+			stdout,
+			"{}",
+			TargetsPrinter {
+				tree: &tree,
+				home: display_home,
+			}
+		)
+		.with_context(|| "could not print targets")?;
+
+		return Ok(ExitCode::SUCCESS);
+	}
+
+	if unlinking {
+		return unlink(
+			&tree,
+			&filter_tags,
+			state.as_ref().zip(manifest.as_mut()),
+			stdout,
+			stderr,
+		);
+	}
+
+	let cache_state = state.as_ref().filter(|_| caching);
+	let scenario = match simulate {
+		Some(path) => {
+			let context = || format!("could not read scenario {:?}", path);
+			let scenario: Scenario =
+				toml::from_str(&fs::read_to_string(&path).with_context(context)?)
+					.with_context(context)?;
+
+			Some(scenario)
+		}
+		None => None,
+	};
+	let cache_key = cache_state.map(|_| Cache::key(&tree));
+	// The previous analysis is whatever the cache holds, even if it's no longer valid.
+	let previous = cache_state.filter(|_| changed).and_then(Cache::read);
+	timer.restart();
+
+	match cache_state
+		.zip(cache_key.as_ref())
+		.and_then(|(state, key)| Cache::load(state, key))
+	{
+		Some(Cache {
+			statuses, problems, ..
+		}) => {
+			tree.statuses = statuses;
+			tree.problems = problems;
+		}
+		None => {
+			tree.analyze()
+				.with_context(|| "could not analyze targets")?;
+
+			if let Some((state, key)) = cache_state.zip(cache_key) {
+				let cache = Cache {
+					key,
+					statuses: tree.statuses,
+					problems: tree.problems,
+				};
+
+				if let Err(err) = cache.store(state) {
+					writeln!(stderr, "warning: could not store analysis cache: {}", err)
+						.with_context(|| "could not print warnings")?;
+				}
+
+				tree.statuses = cache.statuses;
+				tree.problems = cache.problems;
+			}
+		}
+	}
+
+	timer.lap("analyze");
+
+	if changed {
+		let changed_targets = changed_targets(&tree, previous.as_ref());
+		tree.retain(|target_path| changed_targets.contains(target_path));
+	}
+
+	if let Some(scenario) = scenario {
+		scenario.apply(&mut tree, env.home.as_deref().map(Path::new));
+	}
+
+	warnings.extend(tree.validate());
+
+	for warning in &warnings {
+		writeln!(stderr, "warning: {}", warning).with_context(|| "could not print warnings")?;
+	}
+
+	if strict && !warnings.is_empty() {
+		bail!(
+			"aborting due to {} warning(s) in strict mode",
+			warnings.len()
+		);
+	}
+
+	if let Some(Command::Export(_)) = command {
+		return export(&tree, stdout);
+	}
+
+	// Previews only fail when there's a policy, since they're not meant to change anything.
+	let failed = policy.failures.as_ref().is_some_and(|failures| {
+		tree.problems
+			.values()
+			.chain(tree.statuses.values())
+			.chain(tree.orphans.keys().map(|_| &Status::Orphaned))
+			.any(|status| failures.contains(status))
+	});
+
+	if timings && !link {
+		timer.print(output, &mut stderr)?;
+	}
+
+	if link {
+		if pick {
+			let pending: Vec<(PathBuf, Status)> = tree
+				.root
+				.into_iter()
+				.filter_map(
+					|IterElement {
+					     target_path, leaf, ..
+					 }| {
+						let status = tree.get_status(&leaf?.link_path)?.clone();
+
+						(status != Status::Done).then_some((target_path, status))
+					},
+				)
+				.collect();
+			let (answer, mut terminal) =
+				open_terminal(env.terminal.as_deref()).with_context(|| "could not pick targets")?;
+			let picked = pick_targets(&pending, answer, &mut terminal)?;
+
+			// Targets that are done are kept, so that they still count as linked.
+			tree.retain(|target_path| {
+				picked.contains(target_path)
+					|| !pending
+						.iter()
+						.any(|(pending_path, _)| pending_path == target_path)
+			});
+		}
+
+		if show_tree {
+			print_preview(&tree, &env, &display_home, output, long, icons, &mut stdout)?;
+		}
+
+		let skipped = mem::take(&mut tree.skipped);
+		let mut links: Vec<(PathBuf, PathBuf)> = tree
+			.root
+			.into_iter()
+			.filter_map(
+				|IterElement {
+				     target_path, leaf, ..
+				 }| {
+					leaf.map(|leaf| {
+						let source_path = tree.source_path(&target_path, &leaf);
+
+						(leaf.link_path, source_path)
+					})
+				},
+			)
+			.collect();
+		let hints: tree::Hints = tree
+			.root
+			.into_iter()
+			.filter_map(|IterElement { leaf, .. }| {
+				let leaf = leaf?;
+
+				Some((leaf.link_path, leaf.problem_hint?))
+			})
+			.collect();
+		// Editors rarely honor locks, so the file is compared as well, rather than linking
+		// targets of a configuration that is no longer there.
+		if let Some((path, _)) = &config_file {
+			let changed = fs::read_to_string(path).map_or(true, |content| content != input);
+
+			if changed {
+				bail!(
+					"could not link targets: configuration {:?} changed since it was read, run park again",
+					path
+				);
+			}
+		}
+
+		let mut log_result = Ok(());
+		let mut linked = Linked::default();
+		timer.restart();
+		let result = tree.link_with(&mut linked, |action| {
+			if !verbose || log_result.is_err() {
+				return;
+			}
+
+			let action =
+				action.map_paths(&|path| printer::abbreviate_home(path, display_home.as_deref()));
+
+			log_result = writeln!(stderr, "{}", action);
+		});
+		log_result.with_context(|| "could not print actions")?;
+		timer.lap("link");
+
+		// Links that were already in place aren't recorded, since Park may not own them, unlike
+		// the ones created before linking failed.
+		let created: HashSet<&PathBuf> = linked.created.iter().collect();
+		links.retain(|(link_path, _)| created.contains(link_path));
+		let pruned = linked.pruned.clone();
+		let result = result.map(|()| linked);
+
+		if timings {
+			timer.print(output, &mut stderr)?;
+		}
+
+		if let Ok(Linked { warnings, .. }) = &result {
+			for warning in warnings {
+				writeln!(stderr, "warning: {}", warning)
+					.with_context(|| "could not print warnings")?;
+			}
+		}
+
+		// Problems carry their hints already, unlike links that failed while being created.
+		let failed_paths = match &result {
+			Ok(Linked { warnings, .. }) => warnings
+				.iter()
+				.filter_map(|warning| match warning {
+					Warning::FailedOptional(link_path, _) => Some(link_path),
+					_ => None,
+				})
+				.collect(),
+			Err(LinkError::PermissionDenied(link_paths)) => link_paths.iter().collect(),
+			Err(LinkError::EscalationFailed(link_path)) => Vec::from([link_path]),
+			Err(_) => Vec::new(),
+		};
+
+		for link_path in failed_paths {
+			if let Some(hint) = hints.get(link_path) {
+				writeln!(stderr, "hint: {:?}: {}", link_path, hint)
+					.with_context(|| "could not print hints")?;
+			}
+		}
+
+		if let Some((state, manifest)) = state.as_ref().zip(manifest.as_mut()) {
+			// Only files are checksummed, since directories are never copied.
+			for (link_path, source_path) in links.iter().filter(|_| checksums) {
+				let is_copy =
+					fs::symlink_metadata(link_path).is_ok_and(|metadata| metadata.is_file());
+
+				for (path, checksums) in [
+					(
+						source_path.is_file().then_some(source_path),
+						&mut manifest.checksums,
+					),
+					(is_copy.then_some(link_path), &mut manifest.copies),
+				] {
+					let Some(path) = path else {
+						continue;
+					};
+
+					match manifest::checksum(path) {
+						Ok(checksum) => {
+							checksums.insert(link_path.clone(), checksum);
+						}
+						Err(err) => writeln!(
+							stderr,
+							"warning: could not compute checksum of {:?}: {}",
+							path, err
+						)
+						.with_context(|| "could not print warnings")?,
+					}
+				}
+			}
+
+			for link_path in &pruned {
+				manifest.links.remove(link_path);
+				manifest.checksums.remove(link_path);
+				manifest.copies.remove(link_path);
+			}
+
+			manifest.links.extend(links);
+
+			if let Err(err) = manifest.store(state) {
+				writeln!(stderr, "warning: could not store manifest: {}", err)
+					.with_context(|| "could not print warnings")?;
+			}
+		}
+
+		if !skipped.is_empty() {
+			writeln!(
+				stderr,
+				"skipped {} target(s) left out by filters:",
+				skipped.len()
+			)
+			.with_context(|| "could not print summary")?;
+
+			for target_path in skipped {
+				writeln!(stderr, "\t- {:?}", target_path)
+					.with_context(|| "could not print summary")?;
+			}
+		}
+
+		if notify {
+			if let Err(err) = send_notification(&result) {
+				writeln!(stderr, "warning: could not send notification: {}", err)
+					.with_context(|| "could not print warnings")?;
+			}
+		}
+
+		if let (
+			Ok(Linked {
+				created, warnings, ..
+			}),
+			true,
+		) = (&result, show_tree)
+		{
+			writeln!(
+				stdout,
+				"linked {} target(s), {} optional target(s) failed",
+				created.len(),
+				warnings.len()
+			)
+			.with_context(|| "could not print summary")?;
+		}
+
+		result.with_context(|| "could not link targets")?;
+	} else if summary_only {
+		let summary = Summary::of(&tree);
+
+		if output == Output::Json {
+			writeln!(stdout, "{}", json!(summary))
+		} else {
+			write!(
+				stdout,
+				"{}",
+				SummaryPrinter {
+					summary,
+					colored: env.colored,
+				}
+			)
+		}
+		.with_context(|| "could not print summary")?;
+	} else if output != Output::Tree {
+		print_preview(&tree, &env, &display_home, output, long, icons, &mut stdout)?;
+	} else {
+		let preview = Printer {
+			tree: &tree,
+			colored: env.colored,
+			home: display_home,
+			long,
+			icons,
+			width: env.width,
+		}
+		.to_string();
+
+		let paged = match (env.pager.filter(|_| !no_pager), env.height) {
+			(Some(pager), Some(height)) if preview.lines().count() > height => {
+				page(&pager, &preview).is_ok()
+			}
+			_ => false,
+		};
+
+		if !paged {
+			write!(stdout, "{}", preview).with_context(|| "could not print preview tree")?;
+		}
+	}
+
+	if failed && !link {
+		return Ok(ExitCode::FAILURE);
+	}
+
+	Ok(ExitCode::SUCCESS)
+}
+
+/// Durations of the phases of a run, along with how many file system calls each one made.
+struct Timer {
+	phases: Vec<(&'static str, Duration, usize)>,
+	started: (Instant, usize),
+}
+
+impl Timer {
+	fn new() -> Self {
+		Self {
+			phases: Vec::new(),
+			started: (Instant::now(), tree::fs_calls()),
+		}
+	}
+
+	/// Starts timing the next phase.
+	fn restart(&mut self) {
+		self.started = (Instant::now(), tree::fs_calls());
+	}
+
+	/// Finishes timing a phase and starts timing the next one.
+	fn lap(&mut self, name: &'static str) {
+		let (instant, fs_calls) = self.started;
+		self.phases
+			.push((name, instant.elapsed(), tree::fs_calls() - fs_calls));
+		self.restart();
+	}
+
+	fn print<W>(&self, output: Output, mut w: W) -> Result<()>
+	where
+		W: Write,
+	{
+		let context = || "could not print timings";
+
+		if output == Output::Json {
+			let phases: Vec<JsonValue> = self
+				.phases
+				.iter()
+				.map(|(name, elapsed, fs_calls)| {
+					json!({
+						"phase": name,
+						"seconds": elapsed.as_secs_f64(),
+						"fs_calls": fs_calls,
+					})
+				})
+				.collect();
+
+			return writeln!(w, "{}", json!({ "timings": phases })).with_context(context);
+		}
+
+		let mut tab_writer = TabWriter::new(Vec::new()).padding(1);
+		writeln!(tab_writer, "timings:").with_context(context)?;
+
+		for (name, elapsed, fs_calls) in &self.phases {
+			writeln!(
+				tab_writer,
+				"\t{}:\t{:.3?}\t({} file system call(s))",
+				name, elapsed, fs_calls
+			)
+			.with_context(context)?;
+		}
+
+		let buffer = tab_writer.into_inner().with_context(context)?;
+		w.write_all(&buffer).with_context(context)
+	}
+}
+
+/// Lists pending targets with numbers, then reads which ones are picked. Answers are made of
+/// numbers, ranges like "2-4" and parts of target names, which pick every target whose name
+/// has the part's characters in order, like "nvcfg" for "nvim/config".
+fn pick_targets<R, W>(
+	pending: &[(PathBuf, Status)],
+	mut answer: R,
+	mut w: W,
+) -> Result<HashSet<PathBuf>>
+where
+	R: BufRead,
+	W: Write,
+{
+	let context = || "could not list targets to pick";
+	let width = pending.len().to_string().len();
+
+	for (idx, (target_path, status)) in pending.iter().enumerate() {
+		writeln!(
+			w,
+			"{:>width$}) {} [{}]",
+			idx + 1,
+			target_path.display(),
+			status.name()
+		)
+		.with_context(context)?;
+	}
+
+	write!(w, "pick targets (numbers, ranges or names): ").with_context(context)?;
+	w.flush().with_context(context)?;
+
+	let mut line = String::new();
+	answer
+		.read_line(&mut line)
+		.with_context(|| "could not read picked targets")?;
+
+	let index = |number: &str| match number.parse::<usize>() {
+		Ok(number) if (1..=pending.len()).contains(&number) => Ok(Some(number - 1)),
+		Ok(number) => bail!("could not pick targets: {} is out of range", number),
+		Err(_) => Ok(None),
+	};
+	let matches = |part: &str, target_path: &Path| {
+		let mut name = target_path.to_string_lossy().to_lowercase();
+
+		part.to_lowercase().chars().all(|c| match name.find(c) {
+			Some(idx) => {
+				name.drain(..idx + c.len_utf8());
+
+				true
+			}
+			None => false,
+		})
+	};
+
+	let mut picked = HashSet::new();
+	for part in line
+		.split(|c: char| c.is_whitespace() || c == ',')
+		.filter(|part| !part.is_empty())
+	{
+		let range = match part.split_once('-') {
+			Some((start, end)) => index(start)?.zip(index(end)?),
+			None => index(part)?.map(|idx| (idx, idx)),
+		};
+
+		match range {
+			Some((start, end)) => picked.extend(
+				pending[start.min(end)..=start.max(end)]
+					.iter()
+					.map(|(target_path, _)| target_path.clone()),
+			),
+			None => {
+				let matched: Vec<PathBuf> = pending
+					.iter()
+					.filter(|(target_path, _)| matches(part, target_path))
+					.map(|(target_path, _)| target_path.clone())
+					.collect();
+
+				if matched.is_empty() {
+					bail!(
+						"could not pick targets: {:?} matches no pending target",
+						part
+					);
+				}
+
+				picked.extend(matched);
+			}
+		}
+	}
+
+	Ok(picked)
+}
+
+/// Prints the preview in the output format, without a pager.
+fn print_preview<W>(
+	tree: &Tree,
+	env: &Env,
+	home: &Option<OsString>,
+	output: Output,
+	long: bool,
+	icons: Option<Icons>,
+	mut w: W,
+) -> Result<()>
+where
+	W: Write,
+{
+	let formatter: Box<dyn Formatter> = match output {
+		Output::Tree => Box::new(TreeFormatter {
+			colored: env.colored,
+			home: home.clone(),
+			long,
+			icons,
+			width: env.width,
+		}),
+		Output::Json => Box::new(JsonFormatter),
+		Output::Porcelain => Box::new(PorcelainFormatter),
+		Output::Yaml => Box::new(YamlFormatter),
+		Output::Dot => Box::new(DotFormatter),
+		Output::Html => Box::new(HtmlFormatter),
+	};
+
+	formatter
+		.format(tree, &mut w)
+		.with_context(|| "could not print preview")
+}
+
+/// Pipes content into a pager, which is run by the shell, the same way Git does it. Errors are
+/// only returned when the pager couldn't be run, since it may have shown part of the content
+/// otherwise, like when it's exited before reading everything.
+fn page(pager: &OsStr, content: &str) -> Result<(), IoError> {
+	let mut command = ProcessCommand::new("sh");
+	command.arg("-c").arg(pager).stdin(Stdio::piped());
+
+	// Unless told otherwise, less keeps colors and exits right away if everything fits.
+	if env::var_os("LESS").is_none() {
+		command.env("LESS", "FRX");
+	}
+
+	let mut child = command.spawn()?;
+	if let Some(mut stdin) = child.stdin.take() {
+		// Whatever the pager read is shown already.
+		let _ = stdin.write_all(content.as_bytes());
+	}
+
+	let status = child.wait()?;
+
+	// The shell exits with these when the pager is missing or can't be executed.
+	if matches!(status.code(), Some(126 | 127)) {
+		return Err(IoError::other(format!("could not run pager: {}", status)));
+	}
+
+	Ok(())
+}
+
+/// Sends a desktop notification summarizing the result of the linking step.
+fn send_notification(result: &Result<Linked, LinkError>) -> Result<()> {
+	let (summary, body) = match result {
+		Ok(Linked {
+			created, warnings, ..
+		}) => (
+			"Dotfiles linked",
+			format!("Applied {}, failed {}.", created.len(), warnings.len()),
+		),
+		Err(LinkError::BadFiles(problems, ..)) => (
+			"Dotfiles not linked",
+			format!("Applied 0, failed {}.", problems.len()),
+		),
+		Err(LinkError::PermissionDenied(link_paths)) => (
+			"Dotfiles partially linked",
+			format!("Failed {} due to missing permissions.", link_paths.len()),
+		),
+		Err(err) => ("Dotfiles not linked", err.to_string()),
+	};
+
+	notify(summary, &body)
+}
+
+#[cfg(feature = "notify")]
+fn notify(summary: &str, body: &str) -> Result<()> {
+	notify_rust::Notification::new()
+		.appname("park")
+		.summary(summary)
+		.body(body)
+		.show()?;
+
+	Ok(())
+}
+
+#[cfg(not(feature = "notify"))]
+fn notify(_summary: &str, _body: &str) -> Result<()> {
+	bail!("park was built without notification support")
+}
+
+/// Checks whether all targets are done and the configuration has no lints, exiting with failure
+/// otherwise.
+fn check<W>(
+	mut tree: Tree,
+	policy: &Policy,
+	quick: bool,
+	verbose: bool,
+	lints: &[Warning],
+	mut stdout: W,
+) -> Result<ExitCode>
+where
+	W: Write,
+{
+	// Lints are printed regardless of verbosity, since they're asked for.
+	for lint in lints {
+		writeln!(stdout, "lint: {}", lint).with_context(|| "could not print lints")?;
+	}
+
+	// Without a policy, anything that is not done is a failure.
+	let is_pending = |status: &Status| match &policy.failures {
+		Some(failures) => failures.contains(status),
+		None => *status != Status::Done,
+	};
+	let orphans: Vec<(PathBuf, Status)> = tree
+		.orphans
+		.keys()
+		.map(|link_path| (link_path.clone(), Status::Orphaned))
+		.filter(|(_, status)| is_pending(status))
+		.collect();
+
+	let mut pending = if quick {
+		Vec::from_iter(tree.find_pending(is_pending))
+	} else {
+		tree.analyze()
+			.with_context(|| "could not analyze targets")?;
+
+		let mut pending: Vec<_> = tree
+			.problems
+			.into_iter()
+			.chain(tree.statuses)
+			.filter(|(_, status)| is_pending(status))
+			.collect();
+		pending.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+		pending
+	};
+	pending.extend(orphans);
+
+	if verbose {
+		for (link_path, status) in &pending {
+			writeln!(stdout, "{} at {:?}", status.name(), link_path)
+				.with_context(|| "could not print pending targets")?;
+		}
+	}
+
+	if pending.is_empty() && lints.is_empty() {
+		Ok(ExitCode::SUCCESS)
+	} else {
+		Ok(ExitCode::FAILURE)
+	}
+}
+
+/// Removes links of targets gated on any of the given tags, or of all targets if there are no
+/// tags, then prints them. Removed links are dropped from the manifest as well.
+fn unlink<W, E>(
+	tree: &Tree,
+	tags: &TagSet,
+	manifest: Option<(&StateStore, &mut Manifest)>,
+	mut stdout: W,
+	mut stderr: E,
+) -> Result<ExitCode>
+where
+	W: Write,
+	E: Write,
+{
+	let unlinked = tree
+		.unlink(|leaf| {
+			let Gate { all_of, any_of } = &leaf.gate;
+
+			tags.is_empty() || all_of.iter().chain(any_of).any(|tag| tags.contains(tag))
+		})
+		.with_context(|| "could not unlink targets")?;
+
+	for link_path in &unlinked {
+		writeln!(stdout, "rm {}", link_path.display()).with_context(|| "could not print links")?;
+	}
+
+	if let Some((state, manifest)) = manifest {
+		for link_path in &unlinked {
+			manifest.links.remove(link_path);
+			manifest.checksums.remove(link_path);
+			manifest.copies.remove(link_path);
+		}
+
+		if let Err(err) = manifest.store(state) {
+			writeln!(stderr, "warning: could not store manifest: {}", err)
+				.with_context(|| "could not print warnings")?;
+		}
+	}
+
+	Ok(ExitCode::SUCCESS)
+}
+
+/// Prints targets whose files changed since they were linked, as well as copies modified in
+/// place and links replaced by files, according to the checksums in the manifest. It fails if
+/// any of them is printed.
+fn verify<W>(tree: &Tree, manifest: &Manifest, mut stdout: W) -> Result<ExitCode>
+where
+	W: Write,
+{
+	let mut changed = 0;
+
+	for IterElement {
+		target_path, leaf, ..
+	} in &tree.root
+	{
+		let Some(leaf) = leaf else {
+			continue; // filters branches
+		};
+
+		let Some(checksum) = manifest.checksums.get(&leaf.link_path) else {
+			continue;
+		};
+
+		let differs =
+			|path: &Path, checksum| manifest::checksum(path).ok().as_ref() != Some(checksum);
+		let source_path = tree.source_path(&target_path, &leaf);
+
+		if differs(&source_path, checksum) {
+			changed += 1;
+			writeln!(
+				stdout,
+				"target {:?} changed since it was linked",
+				target_path
+			)
+			.with_context(|| "could not print changed targets")?;
+		}
+
+		let is_file =
+			fs::symlink_metadata(&leaf.link_path).is_ok_and(|metadata| metadata.is_file());
+		let modified = match manifest.copies.get(&leaf.link_path) {
+			Some(checksum) => differs(&leaf.link_path, checksum)
+				.then(|| format!("copy at {:?} was modified locally", leaf.link_path)),
+			None => is_file.then(|| format!("link at {:?} was replaced by a file", leaf.link_path)),
+		};
+
+		if let Some(modified) = modified {
+			changed += 1;
+			writeln!(stdout, "{}", modified).with_context(|| "could not print changed targets")?;
+		}
+	}
+
+	if changed == 0 {
+		Ok(ExitCode::SUCCESS)
+	} else {
+		Ok(ExitCode::FAILURE)
+	}
+}
+
+/// Prints a configuration of the targets that are done, with their link paths spelled out.
+fn export<W>(tree: &Tree, mut stdout: W) -> Result<ExitCode>
+where
+	W: Write,
+{
+	let mut targets = TomlTable::new();
+
+	for IterElement {
+		target_path, leaf, ..
+	} in &tree.root
+	{
+		let Some(leaf) = leaf else {
+			continue; // filters branches
+		};
+
+		if tree.get_status(&leaf.link_path) != Some(&Status::Done) {
+			continue;
+		}
+
+		let Leaf {
+			link_path,
+			seed,
+			source,
+			work_dir,
+			description,
+			optional,
+			link_mode,
+			reflink,
+			copy_mode,
+			on_conflict,
+			problem_hint,
+			// The configured owner is exported instead, since IDs differ between machines.
+			owner: _,
+			owner_name,
+			group,
+			secret,
+			max_compare_size,
+			// Tags already let the target in, so they're left out along with the gate.
+			gate: _,
+		} = leaf;
+
+		// Braces in link paths would be taken for variables when the export is read.
+		let escaped = |path: &Path| {
+			let path = path.to_string_lossy();
+
+			TomlValue::String(path.replace('{', "{{").replace('}', "}}"))
+		};
+
+		let mut link = TomlTable::new();
+		if let Some(base_dir) = link_path.parent() {
+			link.insert("base_dir".into(), escaped(base_dir));
+		}
+		if let Some(name) = link_path.file_name() {
+			link.insert("name".into(), escaped(Path::new(name)));
+		}
+		if let Some(mode) = link_mode {
+			link.insert("mode".into(), TomlValue::String(format!("{:o}", mode)));
+		}
+		if reflink {
+			link.insert("reflink".into(), TomlValue::Boolean(true));
+		}
+		if let Some(mode) = copy_mode {
+			link.insert("copy_mode".into(), TomlValue::String(format!("{:o}", mode)));
+		}
+
+		let mut target = TomlTable::from_iter([("link".into(), TomlValue::Table(link))]);
+		if seed {
+			target.insert("seed".into(), TomlValue::Boolean(true));
+		}
+		if let Some(source) = source {
+			target.insert("source".into(), path_value(&source));
+		}
+		if let Some(work_dir) = work_dir {
+			target.insert("work_dir".into(), path_value(&work_dir));
+		}
+		if let Some(description) = description {
+			target.insert("description".into(), TomlValue::String(description));
+		}
+		if optional {
+			target.insert("optional".into(), TomlValue::Boolean(true));
+		}
+		if let Some(on_conflict) = on_conflict {
+			target.insert(
+				"on_conflict".into(),
+				TomlValue::try_from(on_conflict).with_context(|| "could not export targets")?,
+			);
+		}
+		if let Some(problem_hint) = problem_hint {
+			target.insert("problem_hint".into(), TomlValue::String(problem_hint));
+		}
+		if let Some(owner) = owner_name {
+			target.insert("owner".into(), TomlValue::String(owner));
+		}
+		if let Some(group) = group {
+			target.insert("group".into(), TomlValue::String(group));
+		}
+		if let Some(command) = secret {
+			let secret = TomlTable::from_iter([("command".into(), TomlValue::String(command))]);
+			target.insert("secret".into(), TomlValue::Table(secret));
+		}
+		if let Some(max_size) = max_compare_size {
+			target.insert(
+				"max_compare_size".into(),
+				TomlValue::Integer(max_size.try_into().unwrap_or(i64::MAX)),
+			);
+		}
+
+		targets.insert(
+			target_path.to_string_lossy().into_owned(),
+			TomlValue::Table(target),
+		);
+	}
+
+	let document = TomlValue::Table(TomlTable::from_iter([
+		("work_dir".into(), path_value(&tree.work_dir)),
+		("targets".into(), TomlValue::Table(targets)),
+	]));
+
+	write!(
+		stdout,
+		"{}",
+		toml::to_string(&document).with_context(|| "could not export targets")?
+	)
+	.with_context(|| "could not print exported targets")?;
+
+	Ok(ExitCode::SUCCESS)
+}
+
+fn path_value(path: &Path) -> TomlValue {
+	TomlValue::String(path.to_string_lossy().into_owned())
+}
+
+/// Completes the explanation of a target with its analysis, then prints it.
+fn explain<W>(
+	tree: Tree,
+	target_path: &Path,
+	mut explanation: Explanation,
+	mut stdout: W,
+) -> Result<ExitCode>
+where
+	W: Write,
+{
+	let origin = Origin::of(target_path, &tree.lines, &tree.files);
+	explanation.steps.insert(
+		0,
+		Step::new(
+			"declared at",
+			origin.location().unwrap_or_else(|| "unknown line".into()),
+		),
+	);
+
+	let describe = |target_path: &Path, leaf: &Leaf| {
+		let (status, reason) = tree.diagnose(target_path, leaf);
+		let problem = if tree.is_problem(leaf, &status) {
+			" (problem)"
+		} else {
+			""
+		};
+
+		format!("{}{}, {}", status.name(), problem, reason)
+	};
+
+	match &explanation.leaf {
+		Some(leaf) if tree.unfolded.contains_key(&leaf.link_path) => {
+			explanation.steps.push(Step::new(
+				"unfold",
+				"linked entry by entry, since other links are within it",
+			));
+
+			for element in tree.root.into_iter() {
+				if let Some(leaf) = element
+					.leaf
+					.filter(|_| element.target_path.starts_with(target_path))
+				{
+					explanation.steps.push(Step::new(
+						"status",
+						format!(
+							"{:?} {}",
+							element.target_path,
+							describe(&element.target_path, &leaf)
+						),
+					));
+				}
+			}
+		}
+		Some(leaf) => {
+			let source_path = tree.source_path(target_path, leaf);
+
+			explanation.steps.extend([
+				Step::new("source", format!("{:?}", source_path)),
+				Step::new("status", describe(target_path, leaf)),
+			]);
+		}
+		None => explanation
+			.steps
+			.push(Step::new("status", "none, left out by its tags")),
+	}
+
+	write!(stdout, "{}", explanation).with_context(|| "could not print explanation")?;
+
+	Ok(ExitCode::SUCCESS)
+}
+
+/// Analyzes a single target, then prints its status along with its link.
+fn status<W>(
+	tree: &Tree,
+	target_path: &Path,
+	leaf: &Leaf,
+	output: Output,
+	mut stdout: W,
+) -> Result<ExitCode>
+where
+	W: Write,
+{
+	let (status, _) = tree.diagnose(target_path, leaf);
+	let destination = leaf.link_path.read_link().ok();
+
+	if output == Output::Json {
+		let document = json!({
+			"target": target_path,
+			"link": leaf.link_path,
+			"status": status,
+			"problem": tree.is_problem(leaf, &status),
+			"destination": destination,
+		});
+
+		writeln!(stdout, "{:#}", document).with_context(|| "could not print status document")?;
+
+		return Ok(ExitCode::SUCCESS);
+	}
+
+	let mut tab_writer = TabWriter::new(Vec::new()).padding(1);
+
+	writeln!(tab_writer, "status:\t{}", status.name())?;
+	writeln!(tab_writer, "link:\t{}", leaf.link_path.display())?;
+
+	if let Some(destination) = destination {
+		writeln!(tab_writer, "destination:\t{}", destination.display())?;
+	}
+
+	stdout
+		.write_all(&tab_writer.into_inner()?)
+		.with_context(|| "could not print status")?;
+
+	Ok(ExitCode::SUCCESS)
+}
+
+/// Returns the targets whose status differs from the one in a previous analysis, including the
+/// ones it doesn't know about.
+fn changed_targets(tree: &Tree, previous: Option<&Cache>) -> HashSet<PathBuf> {
+	tree.root
+		.into_iter()
+		.filter_map(
+			|IterElement {
+			     target_path, leaf, ..
+			 }| {
+				let link_path = leaf?.link_path;
+				let before = previous.and_then(|previous| {
+					previous
+						.problems
+						.get(&link_path)
+						.or_else(|| previous.statuses.get(&link_path))
+				});
+
+				(before != tree.get_status(&link_path)).then_some(target_path)
+			},
+		)
+		.collect()
+}
+
+/// Prints which targets are active for each profile, that is, which ones its tags alone enable,
+/// along with the configuration's tags.
+fn matrix<W>(config: &Config, names: &[String], output: Output, mut stdout: W) -> Result<ExitCode>
+where
+	W: Write,
+{
+	let profiles = config.profiles.clone().unwrap_or_default();
+
+	if profiles.is_empty() {
+		bail!("could not print matrix: no profiles are configured");
+	}
+
+	let profiles: Vec<(&String, &TagSet)> = if names.is_empty() {
+		profiles.iter().collect()
+	} else {
+		names
+			.iter()
+			.map(|name| match profiles.get_key_value(name) {
+				Some(profile) => Ok(profile),
+				None => bail!("could not print matrix: unknown profile {:?}", name),
+			})
+			.collect::<Result<_>>()?
+	};
+
+	// Only tags are taken into account, like when linting, so that no script gets run.
+	let profiles: Vec<(&String, TagSet)> = profiles
+		.into_iter()
+		.map(|(name, tags)| {
+			let tags = tags
+				.iter()
+				.chain(config.tags.iter().flatten())
+				.cloned()
+				.collect();
+
+			(name, tags)
+		})
+		.collect();
+
+	// Targets are kept sorted by their paths.
+	let rows: Vec<(&PathBuf, Vec<bool>)> = config
+		.targets
+		.iter()
+		.flatten()
+		.map(|(target_path, target)| {
+			let active = profiles
+				.iter()
+				.map(|(_, tags)| Tree::enables(target, tags))
+				.collect();
+
+			(target_path, active)
+		})
+		.collect();
+
+	if output == Output::Json {
+		let document: serde_json::Map<String, JsonValue> = rows
+			.iter()
+			.map(|(target_path, active)| {
+				let active_profiles: Vec<&String> = profiles
+					.iter()
+					.zip(active)
+					.filter_map(|((name, _), active)| active.then_some(*name))
+					.collect();
+
+				(target_path.to_string_lossy().into(), json!(active_profiles))
+			})
+			.collect();
+
+		writeln!(stdout, "{:#}", JsonValue::Object(document))
+			.with_context(|| "could not print matrix document")?;
+
+		return Ok(ExitCode::SUCCESS);
+	}
+
+	let mut tab_writer = TabWriter::new(Vec::new()).padding(1);
+
+	write!(tab_writer, "TARGET")?;
+	for (name, _) in &profiles {
+		write!(tab_writer, "\t{}", name)?;
+	}
+	writeln!(tab_writer)?;
+
+	for (target_path, active) in rows {
+		write!(tab_writer, "{}", target_path.display())?;
+		for active in active {
+			write!(tab_writer, "\t{}", if active { "x" } else { "-" })?;
+		}
+		writeln!(tab_writer)?;
+	}
+
+	stdout
+		.write_all(&tab_writer.into_inner()?)
+		.with_context(|| "could not print matrix")?;
+
+	Ok(ExitCode::SUCCESS)
+}
+
+/// Prints the status, link path and source path of every target left by filters.
+fn query<W>(tree: &Tree, format: QueryFormat, mut stdout: W) -> Result<ExitCode>
+where
+	W: Write,
+{
+	let targets = tree.root.into_iter().filter_map(
+		|IterElement {
+		     target_path, leaf, ..
+		 }| {
+			let leaf = leaf?;
+			let (status, _) = tree.diagnose(&target_path, &leaf);
+			let source_path = tree.source_path(&target_path, &leaf);
+
+			Some((target_path, status, leaf.link_path, source_path))
+		},
+	);
+
+	if format == QueryFormat::Json {
+		let document: Vec<JsonValue> = targets
+			.map(|(target_path, status, link_path, source_path)| {
+				json!({
+					"target": target_path,
+					"status": status,
+					"link": link_path,
+					"source": source_path,
+				})
+			})
+			.collect();
+
+		writeln!(stdout, "{:#}", JsonValue::Array(document))
+			.with_context(|| "could not print query document")?;
+
+		return Ok(ExitCode::SUCCESS);
+	}
+
+	let mut tab_writer = TabWriter::new(Vec::new()).padding(1);
+
+	for (target_path, status, link_path, source_path) in targets {
+		writeln!(
+			tab_writer,
+			"{}\t{}\t{}\t{}",
+			target_path.display(),
+			status.name(),
+			link_path.display(),
+			source_path.display()
+		)?;
+	}
+
+	stdout
+		.write_all(&tab_writer.into_inner()?)
+		.with_context(|| "could not print queried targets")?;
+
+	Ok(ExitCode::SUCCESS)
+}
+
+/// Splits filters into tags, which begin with the tag prefix, and target names. A backslash
+/// before the prefix makes it part of a target name instead, like in '\\+weird'.
+fn partition_filters(filters: Vec<String>, prefix: char) -> (Vec<String>, Vec<String>) {
+	let mut tags = Vec::new();
+	let mut targets = Vec::new();
+
+	for filter in filters {
+		if let Some(tag) = filter.strip_prefix(prefix) {
+			tags.push(tag.into());
+		} else if let Some(name) = filter
+			.strip_prefix('\\')
+			.filter(|name| name.starts_with(prefix))
+		{
+			targets.push(name.into());
+		} else {
+			targets.push(filter);
+		}
+	}
+
+	(tags, targets)
+}
+
+/// Prints targets that are not done, either along with their statuses or alone.
+fn pending<W>(mut tree: Tree, names_only: bool, mut stdout: W) -> Result<ExitCode>
+where
+	W: Write,
+{
+	tree.analyze()
+		.with_context(|| "could not analyze targets")?;
+
+	let pending = tree.root.into_iter().filter_map(
+		|IterElement {
+		     target_path, leaf, ..
+		 }| {
+			let status = tree.get_status(&leaf?.link_path)?;
+
+			(*status != Status::Done).then_some((target_path, status))
+		},
+	);
+
+	// Names are printed as they are, since there's nothing to align them with.
+	if names_only {
+		for (target_path, _) in pending {
+			writeln!(stdout, "{}", target_path.display())
+				.with_context(|| "could not print pending targets")?;
+		}
+
+		return Ok(ExitCode::SUCCESS);
+	}
+
+	let mut tab_writer = TabWriter::new(Vec::new()).padding(1);
+
+	for (target_path, status) in pending {
+		writeln!(tab_writer, "{}\t{}", target_path.display(), status.name())?;
+	}
+
+	stdout
+		.write_all(&tab_writer.into_inner()?)
+		.with_context(|| "could not print pending targets")?;
+
+	Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		env, fs,
+		os::unix::fs::{self as unix_fs, PermissionsExt},
+		path::{Path, PathBuf},
+		str,
+	};
+
+	use ansi_term::Colour;
+	use clap_complete::Shell;
+	use indoc::indoc;
+	use pretty_assertions::assert_eq;
+
+	use crate::parser::{node::Status, tree::Statuses};
+
+	use super::*;
+
+	/// Environment tests run in, which has nothing set but the usual umask.
+	fn test_env() -> Env {
+		Env {
+			colored: false,
+			home: None,
+			auto_tags: TagSet::new(),
+			state_dir: None,
+			vars_path: None,
+			builtin_vars: Vars::new(),
+			umask: 0o022,
+			width: None,
+			height: None,
+			pager: None,
+			hostname: None,
+			terminal: None,
+			tag_prefix: None,
+		}
+	}
+
+	#[test]
+	fn test_using_colors() {
+		struct Test<'a> {
+			description: &'a str,
+			input: (ColorChoice, bool, Vec<(&'a str, &'a str)>),
+			output: bool,
+		}
+
+		let test_cases = Vec::from([
+			Test {
+				description: "terminal without variables",
+				input: (ColorChoice::Auto, true, Vec::new()),
+				output: true,
+			},
+			Test {
+				description: "no terminal without variables",
+				input: (ColorChoice::Auto, false, Vec::new()),
+				output: false,
+			},
+			Test {
+				description: "terminal with NO_COLOR",
+				input: (ColorChoice::Auto, true, Vec::from([("NO_COLOR", "")])),
+				output: false,
+			},
+			Test {
+				description: "terminal with CLICOLOR disabled",
+				input: (ColorChoice::Auto, true, Vec::from([("CLICOLOR", "0")])),
+				output: false,
+			},
+			Test {
+				description: "terminal with CLICOLOR enabled",
+				input: (ColorChoice::Auto, true, Vec::from([("CLICOLOR", "1")])),
+				output: true,
+			},
+			Test {
+				description: "no terminal with CLICOLOR_FORCE",
+				input: (
+					ColorChoice::Auto,
+					false,
+					Vec::from([("CLICOLOR_FORCE", "1"), ("NO_COLOR", "1")]),
+				),
+				output: true,
+			},
+			Test {
+				description: "no terminal with CLICOLOR_FORCE disabled",
+				input: (
+					ColorChoice::Auto,
+					false,
+					Vec::from([("CLICOLOR_FORCE", "0")]),
+				),
+				output: false,
+			},
+			Test {
+				description: "always despite variables",
+				input: (ColorChoice::Always, false, Vec::from([("NO_COLOR", "1")])),
+				output: true,
+			},
+			Test {
+				description: "never despite variables",
+				input: (
+					ColorChoice::Never,
+					true,
+					Vec::from([("CLICOLOR_FORCE", "1")]),
+				),
+				output: false,
+			},
+		]);
+
+		for case in test_cases {
+			let (choice, is_terminal, vars) = case.input;
+			let got = use_colors(choice, is_terminal, |key| {
+				vars.iter()
+					.find(|(name, _)| *name == key)
+					.map(|(_, value)| value.into())
+			});
+
+			assert_eq!(got, case.output, "bad result for {:?}", case.description);
+		}
+	}
+
+	#[test]
+	fn test_detecting_environment_tags() {
+		type Entries<'a> = Vec<(&'a str, &'a str)>;
+		// Files without content are unreadable.
+		type Files<'a> = Vec<(&'a str, Option<&'a str>)>;
+
+		struct Test<'a> {
+			description: &'a str,
+			input: (Entries<'a>, Files<'a>),
+			output: Vec<&'a str>,
+		}
+
+		let test_cases = Vec::from([
+			Test {
+				description: "plain machine",
+				input: (
+					Vec::new(),
+					Vec::from([("/proc/sys/kernel/osrelease", Some("6.1.0-13-amd64"))]),
+				),
+				output: Vec::new(),
+			},
+			Test {
+				description: "WSL detected by its kernel",
+				input: (
+					Vec::new(),
+					Vec::from([(
+						"/proc/sys/kernel/osrelease",
+						Some("5.15.90.1-microsoft-standard-WSL2"),
+					)]),
+				),
+				output: Vec::from(["wsl"]),
+			},
+			Test {
+				description: "Docker container",
+				input: (Vec::new(), Vec::from([("/.dockerenv", Some(""))])),
+				output: Vec::from(["container"]),
+			},
+			Test {
+				description: "container with an unreadable marker",
+				input: (Vec::new(), Vec::from([("/run/.containerenv", None)])),
+				output: Vec::from(["container"]),
+			},
+			Test {
+				description: "SSH session in WSL",
+				input: (
+					Vec::from([
+						("SSH_CONNECTION", "10.0.0.1 22 10.0.0.2 22"),
+						("WSL_DISTRO_NAME", "Ubuntu"),
+					]),
+					Vec::new(),
+				),
+				output: Vec::from(["ssh", "wsl"]),
+			},
+			Test {
+				description: "Podman container",
+				input: (Vec::from([("container", "podman")]), Vec::new()),
+				output: Vec::from(["container"]),
+			},
+		]);
+
+		for case in test_cases {
+			let (vars, files) = case.input;
+			let got = environment_tags(
+				|key| {
+					vars.iter()
+						.find(|(name, _)| *name == key)
+						.map(|(_, value)| value.into())
+				},
+				|path| {
+					files
+						.iter()
+						.find(|(name, _)| Path::new(name) == path)
+						.and_then(|(_, content)| content.map(String::from))
+				},
+				|path| files.iter().any(|(name, _)| Path::new(name) == path),
+			);
+
+			assert_eq!(
+				got,
+				case.output.into_iter().map(String::from).collect(),
+				"bad tags for {:?}",
+				case.description
+			);
+		}
+	}
+
+	#[test]
+	fn test_reading_config_ref() -> Result<()> {
+		let git = |dir: &str, args: &[&str]| -> Result<()> {
+			let status = ProcessCommand::new("git")
+				.args(["-C", dir])
+				.args(["-c", "user.name=park", "-c", "user.email=park@localhost"])
+				.args(["-c", "commit.gpgsign=false"])
+				.args(args)
+				.stdout(Stdio::null())
+				.status()?;
+
+			if !status.success() {
+				bail!("git {:?} failed", args);
+			}
+
+			Ok(())
+		};
+
+		let commit = |dir: &str, content: &str| -> Result<()> {
+			fs::create_dir_all(dir)?;
+			git(dir, &["init", "-q"])?;
+			fs::write(Path::new(dir).join("park.toml"), content)?;
+			git(dir, &["add", "park.toml"])?;
+			git(dir, &["commit", "-q", "-m", "Add configuration"])
+		};
+
+		let work_dir = env::current_dir()?.join("tests/config_ref_work");
+		let moved = format!("work_dir = {:?}\n", work_dir);
+		let got = (|| {
+			commit("tests/config_ref", "base_dir = \"/old\"\n")?;
+			fs::write("tests/config_ref/park.toml", "base_dir = \"/new\"\n")?;
+			commit("tests/config_ref/local", "work_dir = \".\"\n")?;
+			commit("tests/config_ref/moved", &moved)?;
+			commit("tests/config_ref_work", "base_dir = \"/work\"\n")?;
+
+			let dir = Path::new("tests/config_ref");
+			let read = |dir: &str| {
+				read_work_dir_config_ref(Path::new(dir), "HEAD:park.toml", None)
+					.map_err(|err| err.to_string())
+			};
+
+			Ok::<_, anyhow::Error>([
+				read_config_ref(dir, "HEAD:park.toml").map_err(|err| err.to_string()),
+				read_config_ref(dir, "HEAD:missing.toml").map_err(|_| "failed".to_string()),
+				read_config_ref(dir, "HEAD").map_err(|err| err.to_string()),
+				read("tests/config_ref/local"),
+				read("tests/config_ref/moved"),
+			])
+		})();
+		fs::remove_dir_all("tests/config_ref")?;
+		fs::remove_dir_all("tests/config_ref_work")?;
+
+		assert_eq!(
+			got?,
+			[
+				Ok("base_dir = \"/old\"\n".into()),
+				Err("failed".into()),
+				Err(r#""HEAD" is not in the form <rev>:<path>"#.into()),
+				Ok("work_dir = \".\"\n".into()),
+				Ok("base_dir = \"/work\"\n".into()),
+			]
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_opening_terminal() -> Result<()> {
+		let path = env::temp_dir().join(format!("park-test-terminal-{}", std::process::id()));
+
+		fs::write(&path, "1-2\n")?;
+		let got = (|| {
+			let (mut answer, mut terminal) = open_terminal(Some(&path))?;
+			let mut line = String::new();
+
+			answer.read_line(&mut line)?;
+			write!(terminal, "prompt: ")?;
+
+			Ok::<_, anyhow::Error>(line)
+		})();
+		fs::remove_file(&path)?;
+
+		assert_eq!(got?, "1-2\n");
+		assert_eq!(
+			open_terminal(None)
+				.map(|_| ())
+				.map_err(|err| err.to_string()),
+			Err("no terminal is available to prompt on".into())
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_picking_targets() {
+		struct Test<'a> {
+			description: &'a str,
+			input: &'a str,
+			output: Result<Vec<&'a str>, &'a str>,
+		}
+
+		let pending = [
+			("nvim/config", Status::Ready),
+			("nvim/plugins", Status::Unparented),
+			("zsh/zshrc", Status::Mismatch),
+			("zsh/zshenv", Status::Ready),
+		]
+		.map(|(target_path, status)| (PathBuf::from(target_path), status));
+
+		let test_cases = Vec::from([
+			Test {
+				description: "numbers and ranges",
+				input: "1, 3-4\n",
+				output: Ok(Vec::from(["nvim/config", "zsh/zshenv", "zsh/zshrc"])),
+			},
+			Test {
+				description: "parts of names",
+				input: "nvcfg ZSHENV\n",
+				output: Ok(Vec::from(["nvim/config", "zsh/zshenv"])),
+			},
+			Test {
+				description: "nothing",
+				input: "\n",
+				output: Ok(Vec::new()),
+			},
+			Test {
+				description: "out of range",
+				input: "2-5\n",
+				output: Err("could not pick targets: 5 is out of range"),
+			},
+			Test {
+				description: "no match",
+				input: "emacs\n",
+				output: Err("could not pick targets: \"emacs\" matches no pending target"),
+			},
+		]);
+
+		for case in test_cases {
+			let mut listing = Vec::new();
+			let got = pick_targets(&pending, case.input.as_bytes(), &mut listing).map(|picked| {
+				let mut picked: Vec<String> = picked
+					.into_iter()
+					.map(|target_path| target_path.to_string_lossy().into_owned())
+					.collect();
+				picked.sort();
+
+				picked
+			});
+
+			assert_eq!(
+				got.map_err(|err| err.to_string()),
+				case.output
+					.map(|picked| picked.into_iter().map(String::from).collect())
+					.map_err(String::from),
+				"bad picked targets for {:?}",
+				case.description
+			);
+			assert_eq!(
+				String::from_utf8(listing).unwrap(),
+				indoc! {"
+					1) nvim/config [ready]
+					2) nvim/plugins [unparented]
+					3) zsh/zshrc [mismatch]
+					4) zsh/zshenv [ready]
+					pick targets (numbers, ranges or names): "}
+			);
+		}
+	}
+
+	#[test]
+	fn test_finding_pager() {
+		struct Test<'a> {
+			description: &'a str,
+			input: (bool, Option<&'a str>),
+			output: Option<&'a str>,
+		}
+
+		let test_cases = Vec::from([
+			Test {
+				description: "terminal without PAGER",
+				input: (true, None),
+				output: Some("less"),
+			},
+			Test {
+				description: "terminal with PAGER",
+				input: (true, Some("more")),
+				output: Some("more"),
+			},
+			Test {
+				description: "terminal with empty PAGER",
+				input: (true, Some("")),
+				output: None,
+			},
+			Test {
+				description: "no terminal with PAGER",
+				input: (false, Some("more")),
+				output: None,
+			},
+		]);
+
+		for case in test_cases {
+			let (is_terminal, pager) = case.input;
+			let got = find_pager(is_terminal, |key| {
+				pager.filter(|_| key == "PAGER").map(OsString::from)
+			});
+
+			assert_eq!(
+				got,
+				case.output.map(OsString::from),
+				"bad result for {:?}",
+				case.description
+			);
+		}
+	}
+
+	#[test]
+	fn test_finding_tag_prefix() {
+		struct Test<'a> {
+			description: &'a str,
+			input: Option<&'a str>,
+			output: Option<char>,
+		}
+
+		let test_cases = Vec::from([
+			Test {
+				description: "without PARK_TAG_PREFIX",
+				input: None,
+				output: None,
+			},
+			Test {
+				description: "single character",
+				input: Some("%"),
+				output: Some('%'),
+			},
+			Test {
+				description: "empty",
+				input: Some(""),
+				output: None,
+			},
+			Test {
+				description: "many characters",
+				input: Some("%%"),
+				output: None,
+			},
+		]);
+
+		for case in test_cases {
+			let got = tag_prefix(|key| {
+				case.input
+					.filter(|_| key == "PARK_TAG_PREFIX")
+					.map(OsString::from)
+			});
+
+			assert_eq!(got, case.output, "bad result for {:?}", case.description);
+		}
+	}
+
+	#[test]
+	fn test_running_with_pager() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "tests"
+
+			[targets.0xDEADBEEF]
+		"#};
+		let paged_path = env::temp_dir().join("park-test-pager");
+		let env = |height| Env {
+			height: Some(height),
+			pager: Some(format!("cat > {:?}", paged_path).into()),
+			..test_env()
+		};
+
+		let mut stdout = Vec::new();
+		run(env(1), input, &mut stdout, Vec::new(), Park::default())?;
+
+		let paged = fs::read_to_string(&paged_path)?;
+		fs::remove_file(&paged_path)?;
+
+		assert_eq!(String::from_utf8(stdout)?, "");
+		assert!(paged.contains("0xDEADBEEF"), "preview should be paged");
+
+		let mut stdout = Vec::new();
+		run(
+			env(1),
+			input,
+			&mut stdout,
+			Vec::new(),
+			Park {
+				no_pager: true,
+				..Park::default()
+			},
+		)?;
+
+		assert!(!paged_path.exists(), "pager should not run with --no-pager");
+		assert!(String::from_utf8(stdout)?.contains("0xDEADBEEF"));
+
+		let mut stdout = Vec::new();
+		run(env(2), input, &mut stdout, Vec::new(), Park::default())?;
+
+		assert!(
+			!paged_path.exists(),
+			"pager should not run for short previews"
+		);
+		assert!(String::from_utf8(stdout)?.contains("0xDEADBEEF"));
+
+		let pager = |pager: &str| Env {
+			pager: Some(pager.into()),
+			..env(1)
+		};
+
+		let mut stdout = Vec::new();
+		run(
+			pager("head -c 1 > /dev/null; exit 1"),
+			input,
+			&mut stdout,
+			Vec::new(),
+			Park::default(),
+		)?;
+
+		assert_eq!(
+			String::from_utf8(stdout)?,
+			"",
+			"preview shouldn't be printed again after the pager fails"
+		);
+
+		let mut stdout = Vec::new();
+		run(
+			pager("park-missing-pager"),
+			input,
+			&mut stdout,
+			Vec::new(),
+			Park::default(),
+		)?;
+
+		assert!(
+			String::from_utf8(stdout)?.contains("0xDEADBEEF"),
+			"preview should be printed when the pager can't run"
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_running_non_interactively() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "tests/some/long/directory"
+			work_dir = "test"
+
+			[targets.0xDEADBEEF]
+		"#};
+		let paged_path = env::temp_dir().join("park-test-non-interactive");
+
+		let mut stdout = Vec::new();
+		run(
+			Env {
+				colored: true,
+				width: Some(20),
+				height: Some(1),
+				pager: Some(format!("cat > {:?}", paged_path).into()),
+				..test_env()
+			},
+			input,
+			&mut stdout,
+			Vec::new(),
+			Park {
+				non_interactive: true,
+				..Park::default()
+			},
+		)?;
+
+		assert!(!paged_path.exists(), "pager should not run");
+		assert_eq!(
+			String::from_utf8(stdout)?,
+			indoc! {"
+				. (test)
+				└── 0xDEADBEEF (tests/some/long/directory/0xDEADBEEF) [UNPARENTED]
+				hint: use --create-dirs to create missing parent directories (1 link(s))
+			"},
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_running_without_args() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "tests"
+
+			[targets.0xDEADBABE]
+			tags.all_of = ["0xDEADBABE"]
+
+			[targets.0xDEADBEEF]
+		"#};
+
+		{
+			let mut stdout = Vec::new();
+
+			run(
+				Env {
+					colored: true,
+					..test_env()
+				},
+				input,
+				&mut stdout,
+				Vec::new(),
+				Park::default(),
+			)?;
+
+			let target_color = Colour::Cyan.bold();
+			let link_color = Colour::Purple.italic();
+			let symbols_color = Colour::White.normal();
+			let current_dir = env::current_dir().unwrap_or_default();
+
+			assert_eq!(
+				String::from_utf8(stdout).unwrap(),
+				format!(
+					indoc! {"
+						. {current_dir}
+						{l_bar}{tgt} {beef} {ready}
+					"},
+					tgt = target_color.paint("0xDEADBEEF"),
+					l_bar = symbols_color.paint("└── "),
+					current_dir = Colour::White.italic().paint(current_dir.to_string_lossy()),
+					beef = link_color.paint(" tests/0xDEADBEEF "),
+					ready = Colour::Green.reverse().paint(" READY "),
+				),
+				"with color",
+			);
+		}
+
+		{
+			let mut stdout = Vec::new();
+
+			run(
+				test_env(),
+				input,
+				&mut stdout,
+				Vec::new(),
+				Park {
+					filters: Filters {
+						filters: vec!["+0xDEADBABE".into()],
+						..Filters::default()
+					},
+					..Park::default()
+				},
+			)?;
+
+			let current_dir = env::current_dir().unwrap_or_default();
+
+			assert_eq!(
+				String::from_utf8(stdout).unwrap(),
+				format!(
+					indoc! {"
+						. ({current_dir})
+						├── 0xDEADBABE (tests/0xDEADBABE) [READY]
+						└── 0xDEADBEEF (tests/0xDEADBEEF) [READY]
+					"},
+					current_dir = current_dir.to_string_lossy(),
+				),
+				"without color",
+			);
+		}
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_running_with_tags_as_args() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "tests"
+
+			[targets.foo]
+			tags.all_of = ["foo"]
+
+			[targets.bar]
+		"#};
+
+		let mut stdout = Vec::new();
+
+		run(
+			Env {
+				colored: true,
+				..test_env()
+			},
+			input,
+			&mut stdout,
+			Vec::new(),
+			Park {
+				filters: Filters {
+					filters: vec!["+foo".into()],
+					..Filters::default()
+				},
+				..Park::default()
+			},
+		)?;
+
+		let target_color = Colour::Cyan.bold();
+		let link_color = Colour::Purple.italic();
+		let symbols_color = Colour::White.normal();
+		let current_dir = env::current_dir().unwrap_or_default();
+
+		assert_eq!(
+			String::from_utf8(stdout).unwrap(),
+			format!(
+				indoc! {"
+						. {current_dir}
+						{t_bar}{tgt1} {bar} {ready}
+						{l_bar}{tgt2} {foo} {ready}
+					"},
+				tgt1 = target_color.paint("bar"),
+				tgt2 = target_color.paint("foo"),
+				t_bar = symbols_color.paint("├── "),
+				l_bar = symbols_color.paint("└── "),
+				current_dir = Colour::White.italic().paint(current_dir.to_string_lossy()),
+				foo = link_color.paint(" tests/foo "),
+				bar = link_color.paint(" tests/bar "),
+				ready = Colour::Green.reverse().paint(" READY "),
+			),
+			"invalid colored output",
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_printing_matrix() -> Result<()> {
+		let input = indoc! {r#"
+			tags = ["shell"]
+
+			[profiles]
+			desktop = ["linux", "wayland"]
+			laptop = ["macos"]
+			server = ["linux", "headless"]
+
+			[targets.sway]
+			tags.all_of = ["linux", "wayland"]
+
+			[targets.zshrc]
+			tags.any_of = ["shell"]
+
+			[targets.tmux]
+			tags.any_of = ["headless", "macos"]
+
+			# Scripts depend on the machine, so they're never run.
+			[targets.gitconfig]
+			when.script = "tests/when/fail.sh"
+		"#};
+		let matrix = |profiles: &[&str], output| -> Result<String> {
+			let mut stdout = Vec::new();
+
+			run(
+				Env {
+					auto_tags: TagSet::from(["linux".into(), "wayland".into()]),
+					..test_env()
+				},
+				input,
+				&mut stdout,
+				Vec::new(),
+				Park {
+					command: Some(Command::Matrix(Matrix {
+						help: None,
+						profiles: profiles.iter().copied().map(String::from).collect(),
+					})),
+					output,
+					..Park::default()
+				},
+			)?;
+
+			Ok(String::from_utf8(stdout)?)
+		};
+
+		assert_eq!(
+			matrix(&[], Output::Tree)?,
+			indoc! {"
+				TARGET    desktop laptop server
+				gitconfig x       x      x
+				sway      x       -      -
+				tmux      -       x      x
+				zshrc     x       x      x
+			"}
+		);
+		assert_eq!(
+			serde_json::from_str::<JsonValue>(&matrix(&["server", "desktop"], Output::Json)?)?,
+			json!({
+				"gitconfig": ["server", "desktop"],
+				"sway": ["desktop"],
+				"tmux": ["server"],
+				"zshrc": ["server", "desktop"],
+			})
+		);
+		assert_eq!(
+			matrix(&["phone"], Output::Tree).map_err(|err| err.to_string()),
+			Err(r#"could not print matrix: unknown profile "phone""#.into())
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_printing_subtree() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "tests"
+
+			[targets."nvim/init.lua"]
+
+			[targets."nvim/lua"]
+
+			[targets.zshrc]
+		"#};
+		let subtree = |prefix: &str| -> Result<String> {
+			let mut stdout = Vec::new();
+
+			run(
+				test_env(),
+				input,
+				&mut stdout,
+				Vec::new(),
+				Park {
+					command: Some(Command::Tree(Subtree {
+						help: None,
+						prefix: prefix.into(),
+						tags: Vec::new(),
+					})),
+					..Park::default()
+				},
+			)?;
+
+			Ok(String::from_utf8(stdout)?)
+		};
+
+		let current_dir = env::current_dir().unwrap_or_default();
+
+		assert_eq!(
+			subtree("nvim")?,
+			[
+				format!(". ({})", current_dir.display()),
+				format!("└── nvim{}", " ".repeat(26)),
+				"    ├── init.lua (tests/init.lua) [READY]".into(),
+				"    └── lua      (tests/lua)      [READY]".into(),
+				"".into(),
+			]
+			.join("\n")
+		);
+		assert_eq!(
+			subtree("nv").map_err(|err| err.to_string()),
+			Err(r#"could not print tree: no targets under "nv""#.into())
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_querying_targets() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "tests"
+
+			[targets."nvim/init.lua"]
+
+			[targets."nvim/lua"]
+
+			[targets.zshrc]
+		"#};
+		let query = |format| -> Result<String> {
+			let mut stdout = Vec::new();
+
+			run(
+				test_env(),
+				input,
+				&mut stdout,
+				Vec::new(),
+				Park {
+					command: Some(Command::Query(Query {
+						help: None,
+						format,
+						filters: Filters {
+							filters: vec!["nvim/*".into()],
+							..Filters::default()
+						},
+					})),
+					..Park::default()
+				},
+			)?;
+
+			Ok(String::from_utf8(stdout)?)
+		};
+		let current_dir = env::current_dir().unwrap_or_default();
+
+		assert_eq!(
+			query(QueryFormat::Text)?,
+			format!(
+				indoc! {"
+					nvim/init.lua ready tests/init.lua {dir}/nvim/init.lua
+					nvim/lua      ready tests/lua      {dir}/nvim/lua
+				"},
+				dir = current_dir.display()
+			)
+		);
+
+		let document: JsonValue = serde_json::from_str(&query(QueryFormat::Json)?)?;
+
+		assert_eq!(
+			document,
+			json!([
+				{
+					"target": "nvim/init.lua",
+					"status": "ready",
+					"link": "tests/init.lua",
+					"source": current_dir.join("nvim/init.lua"),
+				},
+				{
+					"target": "nvim/lua",
+					"status": "ready",
+					"link": "tests/lua",
+					"source": current_dir.join("nvim/lua"),
+				},
+			])
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_partitioning_filters() {
+		struct Test<'a> {
+			description: &'a str,
+			input: (Vec<&'a str>, char),
+			output: (Vec<&'a str>, Vec<&'a str>),
+		}
+
+		let test_cases = [
+			Test {
+				description: "tags and targets",
+				input: (Vec::from(["+desktop", "nvim", "+wayland"]), '+'),
+				output: (Vec::from(["desktop", "wayland"]), Vec::from(["nvim"])),
+			},
+			Test {
+				description: "escaped plus sign",
+				input: (Vec::from(["\\+weird", "\\nvim"]), '+'),
+				output: (Vec::new(), Vec::from(["+weird", "\\nvim"])),
+			},
+			Test {
+				description: "another prefix",
+				input: (Vec::from(["%desktop", "+weird", "\\%odd"]), '%'),
+				output: (Vec::from(["desktop"]), Vec::from(["+weird", "%odd"])),
+			},
+		];
+
+		for case in test_cases {
+			let (filters, prefix) = case.input;
+			let (tags, targets) =
+				partition_filters(filters.into_iter().map(String::from).collect(), prefix);
+
+			assert_eq!(
+				(tags, targets),
+				(
+					case.output.0.into_iter().map(String::from).collect(),
+					case.output.1.into_iter().map(String::from).collect(),
+				),
+				"bad filters for {:?}",
+				case.description
+			);
+		}
+	}
+
+	#[test]
+	fn test_listing_pending_targets() -> Result<()> {
+		let input = indoc! {r#"
+			work_dir = "test"
+
+			[targets.something]
+			link.base_dir = "tests/data"
+
+			[targets.LICENSE]
+			link.base_dir = "."
+
+			[targets.zshrc]
+			link.base_dir = "tests"
+		"#};
+		let pending = |names_only| -> Result<String> {
+			let mut stdout = Vec::new();
+
+			run(
+				test_env(),
+				input,
+				&mut stdout,
+				Vec::new(),
+				Park {
+					command: Some(Command::Pending(Pending {
+						names_only,
+						..Pending::default()
+					})),
+					..Park::default()
+				},
+			)?;
+
+			Ok(String::from_utf8(stdout)?)
+		};
+
+		assert_eq!(
+			pending(false)?,
+			indoc! {"
+				LICENSE conflict
+				zshrc   ready
+			"}
+		);
+		assert_eq!(
+			pending(true)?,
+			indoc! {"
+				LICENSE
+				zshrc
+			"}
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_running_with_target_filters_as_args() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "tests"
+
+			[targets.foo]
+
+			[targets.bar]
+		"#};
+
+		let mut stdout = Vec::new();
+
+		env::remove_var("NO_COLOR");
+		run(
+			Env {
+				colored: true,
+				..test_env()
+			},
+			input,
+			&mut stdout,
+			Vec::new(),
+			Park {
+				filters: Filters {
+					filters: vec!["foo".into()],
+					..Filters::default()
+				},
+				..Park::default()
+			},
+		)?;
+
+		let link_color = Colour::Purple.italic();
+		let target_color = Colour::Cyan.bold();
+		let symbols_color = Colour::White.normal();
+		let current_dir = env::current_dir().unwrap_or_default();
+
+		assert_eq!(
+			String::from_utf8(stdout).unwrap(),
+			format!(
+				indoc! {"
+						. {current_dir}
+						{l_bar}{tgt} {foo} {ready}
+					"},
+				l_bar = symbols_color.paint("└── "),
+				current_dir = Colour::White.italic().paint(current_dir.to_string_lossy()),
+				tgt = target_color.paint("foo"),
+				foo = link_color.paint(" tests/foo "),
+				ready = Colour::Green.reverse().paint(" READY "),
+			),
+			"invalid colored output",
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_running_with_home_as_default_base_dir() -> Result<()> {
+		let input = indoc! {r#"
+			[targets.foo]
+
+			[targets.bar]
+			link.base_dir = "elsewhere"
+		"#};
+
+		let mut stdout = Vec::new();
+
+		run(
+			Env {
+				home: Some("tests".into()),
+				..test_env()
+			},
+			input,
+			&mut stdout,
+			Vec::new(),
+			Park::default(),
+		)?;
+
+		let current_dir = env::current_dir().unwrap_or_default();
+
+		assert_eq!(
+			String::from_utf8(stdout).unwrap(),
+			format!(
+				indoc! {"
+					. ({current_dir})
+					├── bar (elsewhere/bar) [UNPARENTED]
+					└── foo (~/foo)         [READY]
+					hint: use --create-dirs to create missing parent directories (1 link(s))
+				"},
+				current_dir = current_dir.to_string_lossy(),
+			),
+			"invalid non-colored output",
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_running_in_strict_mode() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "tests"
+
+			[targets.foo]
+		"#};
+
+		let mut stderr = Vec::new();
+
+		let result = run(
+			test_env(),
+			input,
+			Vec::new(),
+			&mut stderr,
+			Park {
+				strict: true,
+				..Park::default()
+			},
+		);
+
+		let current_dir = env::current_dir().unwrap_or_default();
+
+		assert_eq!(
+			result.map_err(|err| err.to_string()),
+			Err("aborting due to 1 warning(s) in strict mode".into()),
+		);
+		assert_eq!(
+			String::from_utf8(stderr).unwrap(),
+			format!(
+				"warning: base directory of link \"tests/foo\" is relative and resolves to {:?}\n",
+				current_dir.join("tests/foo"),
+			),
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_previewing_changed_targets() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "tests/changed/links"
+
+			[targets.foo]
+			[targets.bar]
+		"#};
+
+		let state_dir = PathBuf::from("tests/changed/park");
+		let preview = || {
+			let mut stdout = Vec::new();
+
+			run(
+				Env {
+					state_dir: Some(state_dir.clone()),
+					..test_env()
+				},
+				input,
+				&mut stdout,
+				Vec::new(),
+				Park {
+					cache: true,
+					changed: true,
+					output: Output::Porcelain,
+					..Park::default()
+				},
+			)?;
+
+			Ok::<_, anyhow::Error>(String::from_utf8(stdout)?)
+		};
+
+		let got = (|| {
+			fs::create_dir_all("tests/changed/links")?;
+
+			// Without a previous analysis, every target is new.
+			let first = preview()?;
+
+			fs::write("tests/changed/links/bar", "")?;
+			let second = preview()?;
+
+			// A valid cache means nothing changed.
+			let third = preview()?;
+
+			Ok::<_, anyhow::Error>([first, second, third])
+		})();
+		fs::remove_dir_all("tests/changed")?;
+
+		assert_eq!(
+			got?,
+			[
+				"ready\tbar\ttests/changed/links/bar\nready\tfoo\ttests/changed/links/foo\n".into(),
+				"conflict\tbar\ttests/changed/links/bar\n".into(),
+				String::new(),
+			]
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_running_with_cache() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "tests/cache/links"
+
+			[targets.foo]
+		"#};
+
+		let state_dir = PathBuf::from("tests/cache/park");
+		fs::create_dir_all("tests/cache/links")?;
+
+		let preview = |stdout: &mut Vec<u8>| {
+			run(
+				Env {
+					state_dir: Some(state_dir.clone()),
+					..test_env()
+				},
+				input,
+				stdout,
+				Vec::new(),
+				Park {
+					cache: true,
+					..Park::default()
+				},
+			)
+		};
+
+		let mut stdout = Vec::new();
+		preview(&mut stdout)?;
+
+		let state = StateStore::new(&state_dir);
+		let cache = Cache::read(&state).unwrap_or_default();
+
+		// A tampered cache that is still valid must be used as is.
+		let tampered_cache = Cache {
+			statuses: Statuses::from([("tests/cache/links/foo".into(), Status::Done)]),
+			..cache
+		};
+		tampered_cache.store(&state)?;
+
+		let mut cached_stdout = Vec::new();
+		preview(&mut cached_stdout)?;
+
+		// Changing a directory invalidates the cache.
+		fs::write("tests/cache/links/foo", "")?;
+		fs::remove_file("tests/cache/links/foo")?;
+
+		let mut fresh_stdout = Vec::new();
+		let result = preview(&mut fresh_stdout);
+
+		fs::remove_dir_all("tests/cache")?;
+		result?;
+
+		let current_dir = env::current_dir().unwrap_or_default();
+
+		assert_eq!(
+			String::from_utf8(stdout).unwrap(),
+			format!(
+				indoc! {"
+					. ({current_dir})
+					└── foo (tests/cache/links/foo) [READY]
+				"},
+				current_dir = current_dir.to_string_lossy(),
+			),
+		);
+		assert_eq!(
+			String::from_utf8(cached_stdout).unwrap(),
+			format!(
+				indoc! {"
+					. ({current_dir})
+					└── foo (tests/cache/links/foo) [DONE]
+				"},
+				current_dir = current_dir.to_string_lossy(),
+			),
+		);
+		assert_eq!(
+			String::from_utf8(fresh_stdout).unwrap(),
+			format!(
+				indoc! {"
+					. ({current_dir})
+					└── foo (tests/cache/links/foo) [READY]
+				"},
+				current_dir = current_dir.to_string_lossy(),
+			),
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_cache_key_of_edited_files() -> Result<()> {
+		let work_dir = PathBuf::from("tests/cache_key");
+		fs::create_dir_all(work_dir.join("links"))?;
+		fs::write(work_dir.join("foo"), "foo")?;
+		fs::write(work_dir.join("links/foo"), "foo")?;
+
+		let key = || -> Result<String> {
+			let config = Config {
+				base_dir: Some(work_dir.join("links")),
+				work_dir: Some(work_dir.clone()),
+				targets: Some(config::TargetMap::from([(
+					"foo".into(),
+					Default::default(),
+				)])),
+				..Config::default()
+			};
+			let tree = Tree::parse(config, Default::default(), LinkOpts::default())?;
+
+			Ok(Cache::key(&tree))
+		};
+
+		let got = (|| {
+			let original = key()?;
+			fs::write(work_dir.join("foo"), "edited")?;
+			let edited_source = key()?;
+			fs::write(work_dir.join("links/foo"), "edited in place")?;
+			let edited_link = key()?;
+
+			Ok::<_, anyhow::Error>([original, edited_source, edited_link])
+		})();
+		fs::remove_dir_all(&work_dir)?;
+		let [original, edited_source, edited_link] = got?;
+
+		assert_ne!(
+			original, edited_source,
+			"editing the source should change the key"
+		);
+		assert_ne!(
+			edited_source, edited_link,
+			"editing the link in place should change the key"
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_checking() -> Result<()> {
+		let input = indoc! {r#"
+			work_dir = "test"
+
+			[targets.something]
+			link.base_dir = "tests/data"
+
+			[targets.LICENSE]
+			tags.all_of = ["conflict"]
+			link.base_dir = "."
+		"#};
+
+		struct Test<'a> {
+			description: &'a str,
+			input: Check,
+			output: (ExitCode, &'a str),
+		}
+
+		let test_cases = Vec::from([
+			Test {
+				description: "all done",
+				input: Check {
+					verbose: true,
+					..Check::default()
+				},
+				output: (ExitCode::SUCCESS, ""),
+			},
+			Test {
+				description: "pending target",
+				input: Check {
+					verbose: true,
+					filters: Filters {
+						filters: Vec::from(["+conflict".into()]),
+						..Filters::default()
+					},
+					..Check::default()
+				},
+				output: (ExitCode::FAILURE, "conflict at \"./LICENSE\"\n"),
+			},
+			Test {
+				description: "quick check",
+				input: Check {
+					quick: true,
+					verbose: true,
+					filters: Filters {
+						filters: Vec::from(["+conflict".into()]),
+						..Filters::default()
+					},
+					..Check::default()
+				},
+				output: (ExitCode::FAILURE, "conflict at \"./LICENSE\"\n"),
+			},
+			Test {
+				description: "quiet check",
+				input: Check {
+					quick: true,
+					filters: Filters {
+						filters: Vec::from(["+conflict".into()]),
+						..Filters::default()
+					},
+					..Check::default()
+				},
+				output: (ExitCode::FAILURE, ""),
+			},
+		]);
+
+		for case in test_cases {
+			let mut stdout = Vec::new();
+
+			let got = run(
+				test_env(),
+				input,
+				&mut stdout,
+				Vec::new(),
+				Park {
+					command: Some(Command::Check(case.input)),
+					..Park::default()
+				},
+			)?;
+
+			assert_eq!(
+				(got, String::from_utf8(stdout).unwrap().as_str()),
+				case.output,
+				"bad result for {:?}",
+				case.description
+			);
+		}
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_linting() -> Result<()> {
+		let input = indoc! {r#"
+			work_dir = "test"
+			tags = ["common"]
+			profiles.laptop = ["wayland"]
+			profiles.server = ["linux"]
+
+			[targets.something]
+			link.base_dir = "tests/data"
+			tags.any_of = ["common"]
+
+			[targets.sway]
+			link.base_dir = "tests/data"
+			tags.all_of = ["wayland"]
+			macos.tags.all_of = ["aqua"]
+
+			[targets.foo]
+			link.base_dir = "tests/data"
+			tags.all_of = ["wayland", "linux"]
+
+			[targets.bar]
+			link.base_dir = "tests/data"
+			tags.all_of = ["linux"]
+			linux.tags.all_of = ["linux", "macos"]
+		"#};
+
+		let lint = |input| {
+			let mut stdout = Vec::new();
+
+			let got = run(
+				test_env(),
+				input,
+				&mut stdout,
+				Vec::new(),
+				Park {
+					command: Some(Command::Check(Check {
+						lint: true,
+						filters: Filters {
+							filters: Vec::from(["something".into()]),
+							..Filters::default()
+						},
+						..Check::default()
+					})),
+					..Park::default()
+				},
+			);
+
+			got.map(|got| (got, String::from_utf8(stdout).unwrap()))
+				.map_err(|err| err.to_string())
+		};
+
+		assert_eq!(
+			lint(input),
+			Ok((
+				ExitCode::FAILURE,
+				indoc! {r#"
+					lint: tag "aqua" is used by targets, but no profile activates it
+					lint: tag "macos" is used by targets, but no profile activates it
+					lint: target "bar" can never be enabled, since its tags require both linux and macos
+					lint: target "foo" is enabled by no profile
+				"#}
+				.into()
+			)),
+		);
+		assert_eq!(
+			lint("[targets.foo]"),
+			Err("could not lint configuration: no profiles are configured".into()),
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_running_with_scenario() -> Result<()> {
+		let input = indoc! {r#"
+			work_dir = "/tmp"
+
+			[targets.zshrc]
+			link.name = ".zshrc"
+
+			[targets.bashrc]
+			link.name = ".bashrc"
+
+			[targets.nvim]
+			link.base_dir = "/home/park/.config"
+		"#};
+		let mut stdout = Vec::new();
+
+		run(
+			Env {
+				home: Some("/home/park".into()),
+				..test_env()
+			},
+			input,
+			&mut stdout,
+			Vec::new(),
+			Park {
+				simulate: Some("tests/scenario/machine.toml".into()),
+				..Park::default()
+			},
+		)?;
+
+		assert_eq!(
+			str::from_utf8(&stdout).unwrap(),
+			indoc! {r#"
+				. (/tmp)
+				├── bashrc (~/.bashrc)      [UNPARENTED]
+				├── nvim   (~/.config/nvim) [READONLY]
+				└── zshrc  (~/.zshrc)       [CONFLICT]
+				hint: use --create-dirs to create missing parent directories (1 link(s))
+				hint: move, remove or make writable what's in the way manually (2 link(s))
+			"#},
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_running_with_vars() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "/tmp"
+			work_dir = "test"
+
+			[vars]
+			user = "shared"
+			host = "desktop"
+
+			[targets.foo]
+			link.name = "{user}@{host}.conf"
+
+			[targets.bar]
+			link.name = "{{bar}}"
+		"#};
+		let mut stdout = Vec::new();
+
+		run(
+			Env {
+				vars_path: Some("tests/vars/vars.toml".into()),
+				..test_env()
+			},
+			input,
+			&mut stdout,
+			Vec::new(),
+			Park::default(),
+		)?;
+
+		assert_eq!(
+			str::from_utf8(&stdout).unwrap(),
+			indoc! {r#"
+				. (test)
+				├── bar (/tmp/{bar})              [READY]
+				└── foo (/tmp/local@desktop.conf) [READY]
+			"#},
+		);
+
+		let got = run(
+			test_env(),
+			"[targets.foo]\nlink.name = \"{email}\"\n",
+			Vec::new(),
+			Vec::new(),
+			Park::default(),
+		);
+
+		assert_eq!(
+			got.map_err(|err| format!("{:#}", err)),
+			Err(
+				"could not parse target `foo` (line 1): link of target \"foo\" uses the unknown variable \"email\""
+					.into()
+			),
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_running_with_xdg_dirs() -> Result<()> {
+		let input = indoc! {r#"
+			work_dir = "test"
+			xdg = true
+
+			[targets.foo]
+
+			[targets.bar]
+			link.base_dir = "{xdg_data}/bar"
+		"#};
+		let mut stdout = Vec::new();
+
+		run(
+			Env {
+				home: Some("/tmp".into()),
+				builtin_vars: vars::builtin(|key| match key {
+					"HOME" => Some("/tmp".into()),
+					"XDG_CONFIG_HOME" => Some("/tmp/config".into()),
+					"XDG_DATA_HOME" => Some("/tmp/data".into()),
+					_ => None,
+				}),
+				..test_env()
+			},
+			input,
+			&mut stdout,
+			Vec::new(),
+			Park::default(),
+		)?;
+
+		assert_eq!(
+			str::from_utf8(&stdout).unwrap(),
+			indoc! {r#"
+				. (test)
+				├── bar (~/data/bar/bar) [UNPARENTED]
+				└── foo (~/config/foo)   [UNPARENTED]
+				hint: use --create-dirs to create missing parent directories (2 link(s))
+			"#},
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_running_with_policy() -> Result<()> {
+		let input = indoc! {r#"
+			work_dir = "test"
+
+			[policy]
+			failures = ["Conflict"]
+
+			[targets.something]
+			link.base_dir = "tests/none"
+
+			[targets.LICENSE]
+			tags.all_of = ["conflict"]
+			link.base_dir = "."
+		"#};
+
+		struct Test<'a> {
+			description: &'a str,
+			input: Park,
+			output: ExitCode,
+		}
+
+		let test_cases = Vec::from([
+			Test {
+				description: "preview with acceptable statuses",
+				input: Park::default(),
+				output: ExitCode::SUCCESS,
+			},
+			Test {
+				description: "preview with failures",
+				input: Park {
+					filters: Filters {
+						filters: Vec::from(["+conflict".into()]),
+						..Filters::default()
+					},
+					..Park::default()
+				},
+				output: ExitCode::FAILURE,
+			},
+			Test {
+				description: "check with acceptable statuses",
+				input: Park {
+					command: Some(Command::Check(Check::default())),
+					..Park::default()
+				},
+				output: ExitCode::SUCCESS,
+			},
+			Test {
+				description: "quick check with failures",
+				input: Park {
+					command: Some(Command::Check(Check {
+						quick: true,
+						filters: Filters {
+							filters: Vec::from(["+conflict".into()]),
+							..Filters::default()
+						},
+						..Check::default()
+					})),
+					..Park::default()
+				},
+				output: ExitCode::FAILURE,
+			},
+		]);
+
+		for case in test_cases {
+			let got = run(test_env(), input, Vec::new(), Vec::new(), case.input)?;
+
+			assert_eq!(got, case.output, "bad result for {:?}", case.description);
+		}
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_config_diagnostic() {
+		struct Test<'a> {
+			description: &'a str,
+			input: &'a str,
+			output: &'a str,
+		}
+
+		let test_cases = Vec::from([
+			Test {
+				description: "bad type",
+				input: indoc! {r#"
+					tags = ["foo"]
+
+					[targets.bar]
+					link.name = 3
+				"#},
+				output: indoc! {"
+					could not read input configuration at <stdin>:4:13
+					  |
+					4 | link.name = 3
+					  |             ^"
+				},
+			},
+			Test {
+				description: "multibyte characters and carriage return",
+				input: "x = \"ação\" y\r\n",
+				output: indoc! {r#"
+					could not read input configuration at <stdin>:1:14
+					  |
+					1 | x = "ação" y
+					  |            ^"#
+				},
+			},
+		]);
+
+		for case in test_cases {
+			let err = toml::from_str::<Config>(case.input).unwrap_err();
+
+			assert_eq!(
+				config_diagnostic(case.input, "<stdin>", &err),
+				case.output,
+				"bad diagnostic for {:?}",
+				case.description
+			);
+		}
+	}
+
+	#[test]
+	fn test_running_with_json_errors() -> Result<()> {
+		struct Test<'a> {
+			description: &'a str,
+			input: (&'a str, Park),
+			output: JsonValue,
+		}
+
+		let license = Path::new(env!("CARGO_MANIFEST_DIR")).join("LICENSE");
+		let link_config = format!(
+			"[targets.LICENSE]\nlink.base_dir = {:?}\n",
+			env!("CARGO_MANIFEST_DIR"),
+		);
+
+		let test_cases = Vec::from([
+			Test {
+				description: "invalid configuration",
+				input: ("targets = 1", Park::default()),
+				output: json!({
+					"kind": "invalid-config",
+					"path": null,
+					"message": "could not read input configuration: invalid type: integer `1`, expected a map for key `targets` at line 1 column 11",
+					"line": 1,
+					"column": 11,
+				}),
+			},
+			Test {
+				description: "overlapping targets",
+				input: (
+					"[targets.nvim]\n\n[targets.\"nvim/init.lua\"]\n",
+					Park::default(),
+				),
+				output: json!({
+					"kind": "invalid-target",
+					"path": "nvim/init.lua",
+					"message": "could not parse target `nvim/init.lua` (line 3): target \"nvim/init.lua\" cannot be added because it overlaps with target \"nvim\"",
+				}),
+			},
+			Test {
+				description: "problems when linking",
+				input: (
+					&link_config,
+					Park {
+						link: true,
+						..Park::default()
+					},
+				),
+				output: json!({
+					"kind": "problems",
+					"path": null,
+					"message": format!(
+						"could not link targets: found 1 problematic target(s):\n\t- target `LICENSE` (line 1): Conflict at {:?}\nhint: move, remove or make writable what's in the way manually",
+						license,
+					),
+					"problems": [{
+						"path": license,
+						"status": "conflict",
+						"target": "LICENSE",
+						"line": 1,
+						"remediation": "manual-intervention",
+						"hint": null,
+					}],
+				}),
+			},
+		]);
+
+		for case in test_cases {
+			let (input, cli) = case.input;
+			let mut stderr = Vec::new();
+
+			let got = run(
+				test_env(),
+				input,
+				Vec::new(),
+				&mut stderr,
+				Park {
+					output: Output::Json,
+					..cli
+				},
+			)?;
+
+			assert_eq!(
+				got,
+				ExitCode::FAILURE,
+				"bad exit code for {:?}",
+				case.description
+			);
+			assert_eq!(
+				serde_json::from_slice::<JsonValue>(&stderr)?,
+				case.output,
+				"bad error for {:?}",
+				case.description
+			);
+		}
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_linking() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "tests"
+
+			[targets.skip_me]
+			tags.all_of = ["dont_skip"]
+
+			[targets.my_symlink]
+		"#};
+		let mut stdout = Vec::new();
+
+		run(
+			Env {
+				colored: true,
+				..test_env()
+			},
+			input,
+			&mut stdout,
+			Vec::new(),
+			Park {
+				link: true,
+				..Park::default()
+			},
+		)?;
+
+		let link_path = "tests/my_symlink";
+		let link = PathBuf::from(link_path).read_link();
+		fs::remove_file(link_path)?;
+
+		assert!(link.is_ok());
+		assert_eq!(str::from_utf8(&stdout).unwrap(), "");
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_watching_for_changes() -> Result<()> {
+		let input = indoc! {r#"
+			work_dir = "tests/watched"
+
+			[targets.foo]
+			[targets."nested/bar"]
+		"#};
+		let work_dir = PathBuf::from("tests/watched");
+		fs::create_dir_all(&work_dir)?;
+		fs::write(work_dir.join("foo"), "")?;
+		fs::write(work_dir.join("baz"), "")?;
+
+		let paths = watched_paths(input, None)?;
+		let times = modification_times(&paths);
+		let writer = thread::spawn({
+			let work_dir = work_dir.clone();
+
+			move || {
+				thread::sleep(Duration::from_millis(50));
+				fs::create_dir(work_dir.join("nested"))?;
+				fs::write(work_dir.join("nested/bar"), "")
+			}
+		});
+
+		wait_for_changes(&paths, times.clone(), Duration::from_millis(10));
+		writer.join().unwrap()?;
+		let changed_times = modification_times(&paths);
+		fs::remove_dir_all(&work_dir)?;
+
+		assert_eq!(
+			paths,
+			BTreeSet::from([
+				work_dir.clone(),
+				work_dir.join("foo"),
+				work_dir.join("nested"),
+				work_dir.join("nested/bar"),
+			]),
+			"only sources and the directories holding them should be watched",
+		);
+		assert_eq!(times[&work_dir.join("nested/bar")], None);
+		assert!(changed_times[&work_dir.join("nested/bar")].is_some());
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_watching_targets_replacing_conflicts() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "tests/watch_replace/links"
+			work_dir = "tests/watch_replace"
+
+			[targets.foo]
+			on_conflict = "replace"
+		"#};
+		fs::create_dir_all("tests/watch_replace/links")?;
+		fs::write("tests/watch_replace/foo", "foo")?;
+		fs::write("tests/watch_replace/links/foo", "kept")?;
+
+		let got = execute(
+			test_env(),
+			input,
+			Vec::new(),
+			Vec::new(),
+			Park {
+				link: true,
+				watch: true,
+				..Park::default()
+			},
+		);
+		let content = fs::read_to_string("tests/watch_replace/links/foo");
+		fs::remove_dir_all("tests/watch_replace")?;
+
+		assert!(
+			matches!(
+				got.unwrap_err().downcast_ref::<LinkError>(),
+				Some(LinkError::BadFiles(problems, ..))
+					if problems.get(Path::new("tests/watch_replace/links/foo"))
+						== Some(&Status::Conflict)
+			),
+			"a conflict should be a problem while watching",
+		);
+		assert_eq!(content?, "kept");
+
+		Ok(())
+	}
+
+	#[cfg(not(feature = "notify"))]
+	#[test]
+	fn test_notifying_without_support() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "tests"
+
+			[targets.notified_symlink]
+		"#};
+		let mut stderr = Vec::new();
+
+		let got = run(
+			test_env(),
+			input,
+			Vec::new(),
+			&mut stderr,
+			Park {
+				link: true,
+				notify: true,
+				..Park::default()
+			},
+		);
+
+		let link_path = "tests/notified_symlink";
+		let link = PathBuf::from(link_path).read_link();
+		fs::remove_file(link_path)?;
+
+		assert_eq!(got?, ExitCode::SUCCESS);
+		assert!(link.is_ok(), "linking shouldn't depend on notifications");
+		assert!(String::from_utf8(stderr)?.ends_with(
+			"warning: could not send notification: park was built without notification support\n"
+		));
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_linking_verbosely() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "tests"
+
+			[targets.verbose_symlink]
+		"#};
+		let mut stderr = Vec::new();
+
+		run(
+			Env {
+				home: Some(env::current_dir()?.into()),
+				..test_env()
+			},
+			input,
+			Vec::new(),
+			&mut stderr,
+			Park {
+				link: true,
+				verbose: true,
+				..Park::default()
+			},
+		)?;
+
+		let link_path = "tests/verbose_symlink";
+		let link = PathBuf::from(link_path).read_link();
+		fs::remove_file(link_path)?;
+
+		assert!(link.is_ok());
+		assert_eq!(
+			str::from_utf8(&stderr).unwrap(),
+			indoc! {r#"
+				warning: base directory of link "tests/verbose_symlink" is relative and resolves to "CWD/tests/verbose_symlink"
+				ln -s ~/verbose_symlink tests/verbose_symlink
+			"#}
+			.replace("CWD", &env::current_dir()?.to_string_lossy()),
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_running_with_timings() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "tests"
+
+			[targets.timed_symlink]
+		"#};
+
+		for (link, output, phases) in [
+			(false, Output::Tree, ["parse", "analyze"].as_slice()),
+			(false, Output::Json, ["parse", "analyze"].as_slice()),
+			(true, Output::Tree, ["parse", "analyze", "link"].as_slice()),
+		] {
+			let mut stderr = Vec::new();
+
+			run(
+				test_env(),
+				input,
+				Vec::new(),
+				&mut stderr,
+				Park {
+					link,
+					timings: true,
+					output,
+					..Park::default()
+				},
+			)?;
+
+			if link {
+				fs::remove_file("tests/timed_symlink")?;
+			}
+
+			let stderr = String::from_utf8(stderr)?;
+			let timings = stderr
+				.lines()
+				.skip_while(|line| line.starts_with("warning:"))
+				.collect::<Vec<_>>()
+				.join("\n");
+			let got: Vec<String> = match output {
+				Output::Json => {
+					let document: JsonValue = serde_json::from_str(&timings)?;
+
+					document["timings"]
+						.as_array()
+						.into_iter()
+						.flatten()
+						.filter_map(|phase| phase["phase"].as_str().map(String::from))
+						.collect()
+				}
+				_ => timings
+					.lines()
+					.skip(1)
+					.filter_map(|line| line.trim().split(':').next().map(String::from))
+					.collect(),
+			};
+
+			assert_eq!(got, phases, "bad timings for {:?}", (link, output));
+		}
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_linking_with_tree() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "tests/show_tree"
+
+			[targets.LICENSE]
+		"#};
+		let mut stdout = Vec::new();
+		fs::create_dir_all("tests/show_tree")?;
+
+		let got = run(
+			Env {
+				home: Some(env::current_dir()?.into()),
+				..test_env()
+			},
+			input,
+			&mut stdout,
+			Vec::new(),
+			Park {
+				link: true,
+				show_tree: true,
+				..Park::default()
+			},
+		)?;
+		let link = fs::read_link("tests/show_tree/LICENSE");
+		fs::remove_dir_all("tests/show_tree")?;
+
+		assert_eq!(got, ExitCode::SUCCESS);
+		assert!(link.is_ok());
+		assert_eq!(
+			str::from_utf8(&stdout)?,
+			indoc! {"
+				. (~)
+				└── LICENSE (tests/show_tree/LICENSE) [READY]
+				linked 1 target(s), 0 optional target(s) failed
+			"},
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_printing_summary() -> Result<()> {
+		let input = format!(
+			indoc! {r#"
+				base_dir = "tests/summary"
+				work_dir = {:?}
+
+				[targets.COPYING]
+				[targets.LICENSE]
+				[targets."README.md"]
+			"#},
+			env::current_dir()?,
+		);
+		let summary = |output| -> Result<String> {
+			let mut stdout = Vec::new();
+
+			run(
+				test_env(),
+				&input,
+				&mut stdout,
+				Vec::new(),
+				Park {
+					summary_only: true,
+					output,
+					..Park::default()
+				},
+			)?;
+
+			Ok(String::from_utf8(stdout)?)
+		};
+
+		fs::create_dir_all("tests/summary")?;
+		fs::write("tests/summary/COPYING", "")?;
+		unix_fs::symlink(env::current_dir()?.join("LICENSE"), "tests/summary/LICENSE")?;
+		let got = [summary(Output::Tree), summary(Output::Json)];
+		fs::remove_dir_all("tests/summary")?;
+
+		let [tree, json] = got;
+		assert_eq!(tree?, "park: 1 done, 1 pending, 1 problem\n");
+		assert_eq!(
+			serde_json::from_str::<JsonValue>(&json?)?,
+			json!({ "done": 1, "pending": 1, "problems": 1 }),
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_unlinking_by_tag() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "tests/unlink"
+
+			[targets.LICENSE]
+			tags.all_of = ["work"]
+
+			[targets."README.md"]
+		"#};
+		let new_env = || test_env();
+		let mut stdout = Vec::new();
+		fs::create_dir_all("tests/unlink")?;
+
+		run(
+			new_env(),
+			input,
+			Vec::new(),
+			Vec::new(),
+			Park {
+				link: true,
+				filters: Filters {
+					filters: Vec::from(["+work".into()]),
+					..Filters::default()
+				},
+				..Park::default()
+			},
+		)?;
+		let got = run(
+			new_env(),
+			input,
+			&mut stdout,
+			Vec::new(),
+			Park {
+				command: Some(Command::Unlink(Unlink {
+					filters: Filters {
+						filters: Vec::from(["+work".into()]),
+						..Filters::default()
+					},
+					..Unlink::default()
+				})),
+				..Park::default()
+			},
+		)?;
+		let links = ["LICENSE", "README.md"].map(|name| {
+			Path::new("tests/unlink")
+				.join(name)
+				.symlink_metadata()
+				.is_ok()
+		});
+		fs::remove_dir_all("tests/unlink")?;
+
+		assert_eq!(got, ExitCode::SUCCESS);
+		assert_eq!(links, [false, true]);
+		assert_eq!(str::from_utf8(&stdout)?, "rm tests/unlink/LICENSE\n");
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_exporting_done_targets() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "tests/export"
+			work_dir = "."
+
+			[targets.LICENSE]
+			description = "The license"
+			tags.all_of = ["work"]
+
+			[targets."README.md"]
+			link.name = "{{README}}.md"
+		"#};
+		let mut stdout = Vec::new();
+		fs::create_dir_all("tests/export")?;
+		unix_fs::symlink("./LICENSE", "tests/export/LICENSE")?;
+		unix_fs::symlink("./README.md", "tests/export/{README}.md")?;
+
+		let got = run(
+			test_env(),
+			input,
+			&mut stdout,
+			Vec::new(),
+			Park {
+				command: Some(Command::Export(Export {
+					filters: Filters {
+						filters: Vec::from(["+work".into()]),
+						..Filters::default()
+					},
+					..Export::default()
+				})),
+				..Park::default()
+			},
+		)?;
+		fs::remove_dir_all("tests/export")?;
+
+		assert_eq!(got, ExitCode::SUCCESS);
+		assert_eq!(
+			str::from_utf8(&stdout)?,
+			indoc! {r#"
+				work_dir = "."
+				[targets.LICENSE]
+				description = "The license"
+
+				[targets.LICENSE.link]
+				base_dir = "tests/export"
+				name = "LICENSE"
+				[targets."README.md".link]
+				base_dir = "tests/export"
+				name = "{{README}}.md"
+			"#},
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_linking_only_some_targets() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "tests"
+
+			[targets.only_me]
+
+			[targets.only_you]
+
+			[targets.not_me]
+		"#};
+		let mut stderr = Vec::new();
+
+		run(
+			test_env(),
+			input,
+			Vec::new(),
+			&mut stderr,
+			Park {
+				link: true,
+				only: Vec::from(["only_*".into()]),
+				..Park::default()
+			},
+		)?;
+
+		let links = ["tests/only_me", "tests/only_you"].map(|link_path| {
+			let link = PathBuf::from(link_path).read_link();
+			fs::remove_file(link_path).ok();
+
+			link
+		});
+
+		assert!(links.iter().all(|link| link.is_ok()));
+		assert!(PathBuf::from("tests/not_me").symlink_metadata().is_err());
+		assert_eq!(
+			str::from_utf8(&stderr).unwrap(),
+			indoc! {r#"
+				warning: base directory of link "tests/only_me" is relative and resolves to "CWD/tests/only_me"
+				warning: base directory of link "tests/only_you" is relative and resolves to "CWD/tests/only_you"
+				skipped 1 target(s) left out by filters:
+					- "not_me"
+			"#}
+			.replace("CWD", &env::current_dir()?.to_string_lossy()),
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_explaining() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "/tmp"
+
+			[targets.LICENSE]
+		"#};
+		let env = || test_env();
+		let explain = |target: &str| Park {
+			command: Some(Command::Explain(Explain {
+				target: target.into(),
+				..Explain::default()
+			})),
+			..Park::default()
+		};
+
+		let mut stdout = Vec::new();
+		let got = run(env(), input, &mut stdout, Vec::new(), explain("LICENSE"))?;
+
+		assert_eq!(got, ExitCode::SUCCESS);
+		assert_eq!(
+			str::from_utf8(&stdout).unwrap(),
+			format!(
+				indoc! {r#"
+					declared at: line 3
+					active tags: []
+					base_dir:    "/tmp", set by the configuration
+					link name:   "LICENSE", taken from the target
+					link path:   "/tmp/LICENSE"
+					source:      {:?}
+					status:      ready, nothing exists in the link path and its parent directory exists
+				"#},
+				env::current_dir()?.join("LICENSE"),
+			),
+		);
+
+		let got = run(env(), input, Vec::new(), Vec::new(), explain("LICENCE"));
+
+		assert_eq!(
+			got.map_err(|err| err.to_string()),
+			Err(r#"could not explain target: target "LICENCE" matches nothing, did you mean "LICENSE"?"#.into()),
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_querying_status() -> Result<()> {
+		let input = indoc! {r#"
+			work_dir = "test"
+
+			[targets.something]
+			link.base_dir = "tests/data"
+
+			[targets.LICENSE]
+			tags.all_of = ["conflict"]
+			link.base_dir = "."
+		"#};
+		let env = || test_env();
+		let status = |target: &str, output| Park {
+			output,
+			command: Some(Command::Status(StatusArgs {
+				target: target.into(),
+				..StatusArgs::default()
+			})),
+			..Park::default()
+		};
+
+		let mut stdout = Vec::new();
+		let got = run(
+			env(),
+			input,
+			&mut stdout,
+			Vec::new(),
+			status("something", Output::Tree),
+		)?;
+
+		assert_eq!(got, ExitCode::SUCCESS);
+		assert_eq!(
+			str::from_utf8(&stdout).unwrap(),
+			indoc! {"
+				status:      done
+				link:        tests/data/something
+				destination: test/something
+			"},
+		);
+
+		let mut stdout = Vec::new();
+		run(
+			env(),
+			input,
+			&mut stdout,
+			Vec::new(),
+			status("something", Output::Json),
+		)?;
+
+		assert_eq!(
+			serde_json::from_slice::<JsonValue>(&stdout)?,
+			json!({
+				"target": "something",
+				"link": "tests/data/something",
+				"status": "done",
+				"problem": false,
+				"destination": "test/something",
+			}),
+		);
+
+		let got = run(
+			env(),
+			input,
+			Vec::new(),
+			Vec::new(),
+			status("LICENSE", Output::Tree),
+		);
+
+		assert_eq!(
+			got.map_err(|err| err.to_string()),
+			Err(r#"could not query target: "LICENSE" is left out by tags"#.into()),
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_running_with_host_overlay() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "/tmp"
+			work_dir = "tests/overlay"
+
+			[targets.foo]
+			description = "Foo"
+		"#};
+		let run_on = |hostname: &str, command| -> Result<String> {
+			let mut stdout = Vec::new();
+
+			run(
+				Env {
+					hostname: Some(hostname.into()),
+					..test_env()
+				},
+				input,
+				&mut stdout,
+				Vec::new(),
+				Park {
+					command: Some(command),
+					..Park::default()
+				},
+			)?;
+
+			Ok(String::from_utf8(stdout)?)
+		};
+		let targets = |hostname| run_on(hostname, Command::Targets(Targets::default()));
+
+		assert_eq!(
+			targets("testhost")?,
+			indoc! {"
+				bar /tmp/overlay/bar 
+				foo /tmp/overlay/foo Foo, but on this host
+			"},
+		);
+		assert_eq!(targets("otherhost")?, "foo /tmp/foo Foo\n");
+
+		let explanation = run_on(
+			"testhost",
+			Command::Explain(Explain {
+				target: "bar".into(),
+				..Explain::default()
+			}),
+		)?;
+
+		assert_eq!(
+			explanation.lines().next(),
+			Some("declared at: tests/overlay/park.testhost.toml, line 3"),
+		);
+
+		// Configuration files have their overlays next to them, wherever the work directory is.
+		let config_path = PathBuf::from("tests/overlay/park.toml");
+		fs::write(&config_path, "base_dir = \"/tmp\"\n\n[targets.foo]\n")?;
+		let mut stdout = Vec::new();
+		let got = run(
+			Env {
+				hostname: Some("testhost".into()),
+				..test_env()
+			},
+			"",
+			&mut stdout,
+			Vec::new(),
+			Park {
+				config: Some(config_path.clone()),
+				command: Some(Command::Targets(Targets::default())),
+				..Park::default()
+			},
+		);
+		fs::remove_file(&config_path)?;
+
+		assert_eq!(got?, ExitCode::SUCCESS);
+		assert_eq!(
+			String::from_utf8(stdout)?,
+			indoc! {"
+				bar /tmp/overlay/bar 
+				foo /tmp/overlay/foo Foo, but on this host
+			"},
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_linking_with_tracking() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "tests/track"
 
-	let tags = tags.iter().map(|s| &s[1..]).map(|s| s.into()).collect();
-	let targets = targets.iter().map(PathBuf::from).collect();
+			[targets.LICENSE]
+		"#};
+		let state_dir = PathBuf::from("tests/track/park");
+		fs::create_dir_all("tests/track")?;
 
-	let mut tree = Tree::parse(
-		config,
-		(tags, targets),
-		LinkOpts {
-			replace,
-			create_dirs,
-		},
-	)
-	.with_context(|| "could not parse target")?;
+		let got = run(
+			Env {
+				state_dir: Some(state_dir.clone()),
+				..test_env()
+			},
+			input,
+			Vec::new(),
+			Vec::new(),
+			Park {
+				link: true,
+				track: true,
+				..Park::default()
+			},
+		)?;
+		let manifest = Manifest::load(&StateStore::new(&state_dir))?;
+		let link_target = fs::read_link("tests/track/LICENSE");
+		fs::remove_dir_all("tests/track")?;
 
-	tree.analyze()
-		.with_context(|| "could not analyze targets")?;
+		assert_eq!(got, ExitCode::SUCCESS);
+		assert_eq!(
+			manifest,
+			Manifest {
+				links: [(
+					"tests/track/LICENSE".into(),
+					env::current_dir()?.join("LICENSE")
+				)]
+				.into(),
+				checksums: Default::default(),
+				copies: Default::default(),
+			},
+		);
+		assert!(link_target.is_ok());
 
-	if link {
-		tree.link().with_context(|| "could not link targets")?;
-	} else {
-		write!(
-			stdout,
-			"{}",
-			Printer {
-				tree: &tree,
-				colored: env.colored,
-				home: env.home,
-			}
-		)
-		.with_context(|| "could not print preview tree")?;
+		Ok(())
 	}
 
-	Ok(())
-}
+	#[test]
+	fn test_tracking_links_of_failed_linking() -> Result<()> {
+		let input = indoc! {r#"
+			base_dir = "tests/track_failed"
 
-#[cfg(test)]
-mod tests {
-	use std::{env, fs, path::PathBuf, str};
+			[targets.LICENSE]
 
-	use ansi_term::Colour;
-	use indoc::indoc;
-	use pretty_assertions::assert_eq;
+			[targets.secret]
+			secret.command = "exit 1"
+		"#};
+		let state_dir = PathBuf::from("tests/track_failed/park");
+		fs::create_dir_all("tests/track_failed")?;
 
-	use super::*;
+		let got = run(
+			Env {
+				state_dir: Some(state_dir.clone()),
+				..test_env()
+			},
+			input,
+			Vec::new(),
+			Vec::new(),
+			Park {
+				link: true,
+				track: true,
+				..Park::default()
+			},
+		);
+		let manifest = Manifest::load(&StateStore::new(&state_dir))?;
+		fs::remove_dir_all("tests/track_failed")?;
+
+		assert!(got.is_err(), "linking the secret should fail");
+		assert_eq!(
+			manifest.links,
+			[(
+				"tests/track_failed/LICENSE".into(),
+				env::current_dir()?.join("LICENSE")
+			)]
+			.into(),
+			"links created before linking failed should be tracked",
+		);
+
+		Ok(())
+	}
 
 	#[test]
-	fn test_running_without_args() -> Result<()> {
-		let input = indoc! {r#"
-			base_dir = "tests"
+	fn test_linking_changed_config() -> Result<()> {
+		let dir = env::current_dir()?.join("tests/config_lock");
+		let config_path = dir.join("park.toml");
+		let input = format!(
+			indoc! {r#"
+				work_dir = {:?}
+				base_dir = "tests/config_lock/home"
 
-			[targets.0xDEADBABE]
-			tags.all_of = ["0xDEADBABE"]
+				[targets.bashrc]
+				when.script = "edit.sh"
+			"#},
+			dir
+		);
 
-			[targets.0xDEADBEEF]
+		fs::create_dir_all(dir.join("home"))?;
+		let got = (|| {
+			fs::write(&config_path, &input)?;
+			fs::write(dir.join("bashrc"), "")?;
+			fs::write(
+				dir.join("edit.sh"),
+				"#!/bin/sh\necho '# edited' >> park.toml\n",
+			)?;
+			fs::set_permissions(dir.join("edit.sh"), fs::Permissions::from_mode(0o755))?;
+
+			let result = run(
+				test_env(),
+				"",
+				Vec::new(),
+				Vec::new(),
+				Park {
+					config: Some(config_path.clone()),
+					link: true,
+					..Park::default()
+				},
+			);
+
+			Ok::<_, anyhow::Error>(result.map_err(|err| err.to_string()))
+		})();
+		let linked = Path::new("tests/config_lock/home/bashrc")
+			.symlink_metadata()
+			.is_ok();
+		fs::remove_dir_all(&dir)?;
+
+		assert_eq!(
+			got?,
+			Err(format!(
+				"could not link targets: configuration {:?} changed since it was read, run park again",
+				config_path
+			))
+		);
+		assert!(!linked, "targets should not be linked");
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_pruning_orphans() -> Result<()> {
+		let input = indoc! {r#"
+			work_dir = "tests/prune/dotfiles"
+			base_dir = "tests/prune/home"
+
+			[targets.bashrc]
+			[targets.vimrc]
 		"#};
+		let retired_input = indoc! {r#"
+			work_dir = "tests/prune/dotfiles"
+			base_dir = "tests/prune/home"
 
-		{
+			[targets.bashrc]
+		"#};
+		let state_dir = PathBuf::from("tests/prune/park");
+		let run_with = |input: &str, park: Park| -> Result<String> {
 			let mut stdout = Vec::new();
-
+			let mut stderr = Vec::new();
 			run(
 				Env {
-					colored: true,
-					home: None,
+					state_dir: Some(state_dir.clone()),
+					..test_env()
 				},
 				input,
 				&mut stdout,
-				Park::default(),
+				&mut stderr,
+				park,
 			)?;
+			stdout.extend(stderr);
 
-			let target_color = Colour::Cyan.bold();
-			let link_color = Colour::Purple.italic();
-			let symbols_color = Colour::White.normal();
-			let current_dir = env::current_dir().unwrap_or_default();
+			Ok(String::from_utf8(stdout)?)
+		};
 
-			assert_eq!(
-				String::from_utf8(stdout).unwrap(),
-				format!(
-					indoc! {"
-						. {current_dir}
-						{l_bar}{tgt} {beef} {ready}
-					"},
-					tgt = target_color.paint("0xDEADBEEF"),
-					l_bar = symbols_color.paint("└── "),
-					current_dir = Colour::White.italic().paint(current_dir.to_string_lossy()),
-					beef = link_color.paint(" tests/0xDEADBEEF "),
-					ready = Colour::Green.reverse().paint(" READY "),
-				),
-				"with color",
-			);
-		}
+		fs::create_dir_all("tests/prune/dotfiles")?;
+		fs::create_dir_all("tests/prune/home")?;
+		let got = (|| {
+			fs::write("tests/prune/dotfiles/bashrc", "")?;
+			fs::write("tests/prune/dotfiles/vimrc", "")?;
 
-		{
-			let mut stdout = Vec::new();
+			let linking = Park {
+				link: true,
+				track: true,
+				..Park::default()
+			};
+			run_with(input, linking)?;
 
-			run(
-				Env {
-					colored: false,
-					home: None,
+			let preview = run_with(
+				retired_input,
+				Park {
+					track: true,
+					..Park::default()
 				},
-				&input,
-				&mut stdout,
+			)?;
+			let pruning = run_with(
+				retired_input,
 				Park {
-					filters: vec!["+0xDEADBABE".into()],
+					link: true,
+					track: true,
+					prune_orphans: true,
+					verbose: true,
 					..Park::default()
 				},
 			)?;
+			let manifest = Manifest::load(&StateStore::new(&state_dir))?;
 
-			let current_dir = env::current_dir().unwrap_or_default();
+			Ok::<_, anyhow::Error>((
+				preview,
+				pruning,
+				manifest.links.into_keys().collect::<Vec<_>>(),
+				Path::new("tests/prune/home/vimrc")
+					.symlink_metadata()
+					.is_ok(),
+			))
+		})();
+		fs::remove_dir_all("tests/prune")?;
 
-			assert_eq!(
-				String::from_utf8(stdout).unwrap(),
-				format!(
-					indoc! {"
-						. ({current_dir})
-						├── 0xDEADBABE (tests/0xDEADBABE) [READY]
-						└── 0xDEADBEEF (tests/0xDEADBEEF) [READY]
-					"},
-					current_dir = current_dir.to_string_lossy(),
-				),
-				"without color",
-			);
-		}
+		let (preview, pruning, links, vimrc_exists) = got?;
+
+		assert!(
+			preview.contains(
+				r#"warning: link "tests/prune/home/vimrc" is orphaned, since its target is no longer configured"#
+			),
+			"orphan should be reported, got {:?}",
+			preview
+		);
+		assert!(
+			preview.contains("orphaned: tests/prune/home/vimrc\n"),
+			"orphan should be previewed, got {:?}",
+			preview
+		);
+		assert!(
+			pruning.contains("rm tests/prune/home/vimrc"),
+			"orphan should be pruned, got {:?}",
+			pruning
+		);
+		assert!(!pruning.contains("is orphaned"));
+		assert_eq!(links, [PathBuf::from("tests/prune/home/bashrc")]);
+		assert!(!vimrc_exists);
 
 		Ok(())
 	}
 
 	#[test]
-	fn test_running_with_tags_as_args() -> Result<()> {
+	fn test_verifying_checksums() -> Result<()> {
 		let input = indoc! {r#"
-			base_dir = "tests"
-
-			[targets.foo]
-			tags.all_of = ["foo"]
+			work_dir = "tests/verify/dotfiles"
+			base_dir = "tests/verify/home"
 
-			[targets.bar]
+			[targets.bashrc]
+			[targets.gitconfig]
+			seed = true
+			[targets.vimrc]
+			seed = true
+			[targets.zshrc]
 		"#};
+		let state_dir = PathBuf::from("tests/verify/park");
+		let run_with = |park: Park| -> Result<(ExitCode, String)> {
+			let mut stdout = Vec::new();
+			let exit_code = run(
+				Env {
+					state_dir: Some(state_dir.clone()),
+					..test_env()
+				},
+				input,
+				&mut stdout,
+				Vec::new(),
+				park,
+			)?;
 
-		let mut stdout = Vec::new();
+			Ok((exit_code, String::from_utf8(stdout)?))
+		};
 
-		run(
-			Env {
-				colored: true,
-				home: None,
-			},
-			&input,
-			&mut stdout,
-			Park {
-				filters: vec!["+foo".into()],
+		fs::create_dir_all("tests/verify/dotfiles")?;
+		fs::create_dir_all("tests/verify/home")?;
+		let got = (|| {
+			for name in ["bashrc", "gitconfig", "vimrc", "zshrc"] {
+				fs::write(Path::new("tests/verify/dotfiles").join(name), name)?;
+			}
+
+			run_with(Park {
+				link: true,
+				track: true,
+				checksums: true,
 				..Park::default()
-			},
-		)?;
+			})?;
 
-		let target_color = Colour::Cyan.bold();
-		let link_color = Colour::Purple.italic();
-		let symbols_color = Colour::White.normal();
-		let current_dir = env::current_dir().unwrap_or_default();
+			// A link replaced by a file, a copy that was edited in place, and targets that were
+			// edited after linking, one of which is copied.
+			fs::remove_file("tests/verify/home/bashrc")?;
+			fs::write("tests/verify/home/bashrc", "bashrc")?;
+			fs::write("tests/verify/home/vimrc", "edited")?;
+			fs::write("tests/verify/dotfiles/gitconfig", "edited")?;
+			fs::write("tests/verify/dotfiles/zshrc", "edited")?;
+
+			let manifest = Manifest::load(&StateStore::new(&state_dir))?;
+			let verified = run_with(Park {
+				command: Some(Command::Verify(Verify::default())),
+				..Park::default()
+			})?;
+
+			Ok::<_, anyhow::Error>((manifest.checksums.into_keys().collect::<Vec<_>>(), verified))
+		})();
+		fs::remove_dir_all("tests/verify")?;
 
+		let (checksummed, verified) = got?;
 		assert_eq!(
-			String::from_utf8(stdout).unwrap(),
-			format!(
-				indoc! {"
-						. {current_dir}
-						{t_bar}{tgt1} {bar} {ready}
-						{l_bar}{tgt2} {foo} {ready}
-					"},
-				tgt1 = target_color.paint("bar"),
-				tgt2 = target_color.paint("foo"),
-				t_bar = symbols_color.paint("├── "),
-				l_bar = symbols_color.paint("└── "),
-				current_dir = Colour::White.italic().paint(current_dir.to_string_lossy()),
-				foo = link_color.paint(" tests/foo "),
-				bar = link_color.paint(" tests/bar "),
-				ready = Colour::Green.reverse().paint(" READY "),
-			),
-			"invalid colored output",
+			checksummed,
+			Vec::from([
+				PathBuf::from("tests/verify/home/bashrc"),
+				PathBuf::from("tests/verify/home/gitconfig"),
+				PathBuf::from("tests/verify/home/vimrc"),
+				PathBuf::from("tests/verify/home/zshrc"),
+			])
+		);
+		assert_eq!(
+			verified,
+			(
+				ExitCode::FAILURE,
+				indoc! {r#"
+					link at "tests/verify/home/bashrc" was replaced by a file
+					target "gitconfig" changed since it was linked
+					copy at "tests/verify/home/vimrc" was modified locally
+					target "zshrc" changed since it was linked
+				"#}
+				.into()
+			)
 		);
 
 		Ok(())
 	}
 
 	#[test]
-	fn test_running_with_target_filters_as_args() -> Result<()> {
+	fn test_tracking_with_newer_manifest() -> Result<()> {
 		let input = indoc! {r#"
-			base_dir = "tests"
-
-			[targets.foo]
+			base_dir = "tests/newer_track"
 
-			[targets.bar]
+			[targets.LICENSE]
 		"#};
+		let state_dir = PathBuf::from("tests/newer_track/park");
+		fs::create_dir_all(&state_dir)?;
+		fs::write(state_dir.join("manifest.toml"), "version = 999\n[links]\n")?;
 
-		let mut stdout = Vec::new();
-
-		env::remove_var("NO_COLOR");
-		run(
+		let got = run(
 			Env {
-				colored: true,
-				home: None,
+				state_dir: Some(state_dir.clone()),
+				..test_env()
 			},
-			&input,
-			&mut stdout,
+			input,
+			Vec::new(),
+			Vec::new(),
 			Park {
-				filters: vec!["foo".into()],
+				link: true,
+				track: true,
 				..Park::default()
 			},
-		)?;
-
-		let link_color = Colour::Purple.italic();
-		let target_color = Colour::Cyan.bold();
-		let symbols_color = Colour::White.normal();
-		let current_dir = env::current_dir().unwrap_or_default();
+		);
+		let link_exists = Path::new("tests/newer_track/LICENSE")
+			.symlink_metadata()
+			.is_ok();
+		fs::remove_dir_all("tests/newer_track")?;
 
 		assert_eq!(
-			String::from_utf8(stdout).unwrap(),
-			format!(
-				indoc! {"
-						. {current_dir}
-						{l_bar}{tgt} {foo} {ready}
-					"},
-				l_bar = symbols_color.paint("└── "),
-				current_dir = Colour::White.italic().paint(current_dir.to_string_lossy()),
-				tgt = target_color.paint("foo"),
-				foo = link_color.paint(" tests/foo "),
-				ready = Colour::Green.reverse().paint(" READY "),
-			),
-			"invalid colored output",
+			got.map_err(|err| format!("{:#}", err)),
+			Err(format!(
+				"could not read manifest: {:?} has version 999, which is newer than the supported version 1",
+				state_dir.join("manifest.toml")
+			)),
 		);
+		assert!(!link_exists);
 
 		Ok(())
 	}
 
 	#[test]
-	fn test_linking() -> Result<()> {
+	fn test_tracking_with_invalid_manifest() -> Result<()> {
 		let input = indoc! {r#"
-			base_dir = "tests"
+			base_dir = "tests/invalid_track"
 
-			[targets.skip_me]
-			tags.all_of = ["dont_skip"]
-
-			[targets.my_symlink]
+			[targets.LICENSE]
 		"#};
-		let mut stdout = Vec::new();
+		let state_dir = PathBuf::from("tests/invalid_track/park");
+		fs::create_dir_all(&state_dir)?;
+		fs::write(state_dir.join("manifest.toml"), "[links\n")?;
 
-		run(
+		let got = run(
 			Env {
-				colored: true,
-				home: None,
+				state_dir: Some(state_dir.clone()),
+				..test_env()
 			},
 			input,
-			&mut stdout,
+			Vec::new(),
+			Vec::new(),
 			Park {
 				link: true,
+				track: true,
 				..Park::default()
 			},
-		)?;
+		);
+		let link_exists = Path::new("tests/invalid_track/LICENSE")
+			.symlink_metadata()
+			.is_ok();
+		fs::remove_dir_all("tests/invalid_track")?;
 
-		let link_path = "tests/my_symlink";
-		let link = PathBuf::from(link_path).read_link();
-		fs::remove_file(link_path)?;
+		assert_eq!(
+			got.map_err(|err| format!("{:#}", err)),
+			Err(format!(
+				"could not read manifest: {:?} could not be parsed: expected a right bracket, found a newline at line 1 column 7",
+				state_dir.join("manifest.toml")
+			)),
+		);
+		assert!(!link_exists);
 
-		assert!(link.is_ok());
-		assert_eq!(str::from_utf8(&stdout).unwrap(), "");
+		Ok(())
+	}
+
+	#[test]
+	fn test_printing_completions() -> Result<()> {
+		let mut stdout = Vec::new();
+
+		for shell in [Shell::Bash, Shell::Fish, Shell::Zsh] {
+			stdout.clear();
+
+			// No configuration is needed, so an invalid one is ignored.
+			let got = run(
+				test_env(),
+				"not toml",
+				&mut stdout,
+				Vec::new(),
+				Park {
+					command: Some(Command::Completions(Completions { help: None, shell })),
+					..Park::default()
+				},
+			)?;
+
+			assert_eq!(got, ExitCode::SUCCESS);
+			assert!(
+				str::from_utf8(&stdout)?.contains("park"),
+				"bad completions for {:?}",
+				shell
+			);
+		}
 
 		Ok(())
 	}
@@ -0,0 +1,54 @@
+use std::{
+	collections::BTreeMap,
+	path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::parser::{iter::Element as IterElement, node::Status, tree::Tree};
+
+/// Statuses forced onto links, so that the analysis looks like it was done in another machine.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct Scenario {
+	/// Statuses of link paths. Links within a directory listed here take its status, unless
+	/// a path closer to them is listed as well.
+	pub paths: BTreeMap<PathBuf, Status>,
+}
+
+impl Scenario {
+	/// Overrides the statuses of links in an analyzed tree. A leading '~' in paths is
+	/// replaced by the home directory.
+	pub fn apply(self, tree: &mut Tree, home: Option<&Path>) {
+		let paths: BTreeMap<PathBuf, Status> = self
+			.paths
+			.into_iter()
+			.map(|(path, status)| match (path.strip_prefix("~"), home) {
+				(Ok(path), Some(home)) => (home.join(path), status),
+				_ => (path, status),
+			})
+			.collect();
+
+		for IterElement { leaf, .. } in &tree.root {
+			let Some(leaf) = leaf else {
+				continue; // filters branches
+			};
+
+			let status = leaf
+				.link_path
+				.ancestors()
+				.find_map(|path| paths.get(path))
+				.cloned();
+
+			if let Some(status) = status {
+				tree.statuses.remove(&leaf.link_path);
+				tree.problems.remove(&leaf.link_path);
+
+				if tree.is_problem(&leaf, &status) {
+					tree.problems.insert(leaf.link_path, status);
+				} else {
+					tree.statuses.insert(leaf.link_path, status);
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,416 @@
+use serde_json::{json, Value as JsonValue};
+
+use crate::parser::node::Status;
+
+/// Returns a JSON Schema of the configuration, which editors can use to validate and complete
+/// it. Unknown fields are rejected by the schema, even though Park ignores them, since they're
+/// most likely typos.
+pub fn config() -> JsonValue {
+	let statuses: Vec<&str> = Status::ALL.iter().map(Status::name).collect();
+
+	json!({
+		"$schema": "https://json-schema.org/draft/2020-12/schema",
+		"title": "park",
+		"description": "Configuration of which dotfiles Park links and how.",
+		"type": "object",
+		"additionalProperties": false,
+		"properties": {
+			"tags": {
+				"description": "Tags that complement the ones passed as arguments.",
+				"$ref": "#/$defs/tag_set",
+			},
+			"base_dir": {
+				"description": "Directory links are created in, which defaults to the home directory.",
+				"type": "string",
+			},
+			"work_dir": {
+				"description": "Directory targets are read from, which defaults to the current one.",
+				"type": "string",
+			},
+			"allow_path_names": {
+				"description": "Whether link names may be paths rather than plain file names.",
+				"type": "boolean",
+			},
+			"printer": {
+				"description": "Options for how previews are printed.",
+				"type": "object",
+				"additionalProperties": false,
+				"properties": {
+					"icons": {
+						"description": "Whether statuses are shown as icons before target names.",
+						"type": "boolean",
+					},
+					"nerd_font": {
+						"description": "Whether icons are Nerd Font glyphs rather than plain Unicode symbols.",
+						"type": "boolean",
+					},
+				},
+			},
+			"policy": {
+				"description": "Options for which statuses make Park exit with failure.",
+				"type": "object",
+				"additionalProperties": false,
+				"properties": {
+					"failures": {
+						"description": "Statuses that are considered failures.",
+						"type": "array",
+						"items": { "enum": statuses },
+					},
+					"create_dirs_allowed_under": {
+						"description": "Directories which --create-dirs may create missing directories under.",
+						"type": "array",
+						"items": { "type": "string" },
+					},
+					"replace_identical": {
+						"description": "Whether regular files with the same content as their targets are replaced by links.",
+						"type": "boolean",
+					},
+					"max_compare_size": {
+						"description": "Size, in bytes, of the largest files compared with targets that don't set one.",
+						"type": "integer",
+						"minimum": 0,
+					},
+					"compare_budget": {
+						"description": "Bytes of content that may be compared in a single analysis.",
+						"type": "integer",
+						"minimum": 0,
+					},
+				},
+			},
+			"vars": {
+				"description": "Variables for placeholders in link names and base directories.",
+				"type": "object",
+				"additionalProperties": { "type": "string" },
+			},
+			"xdg": {
+				"description": "Whether the base directory defaults to the XDG config directory.",
+				"type": "boolean",
+			},
+			"renames": {
+				"description": "Previous paths of renamed targets, mapped to their current ones.",
+				"type": "object",
+				"additionalProperties": { "type": "string" },
+			},
+			"owner": {
+				"description": "Owner of links, as \"user:group\", for targets that don't set one.",
+				"type": "string",
+			},
+			"profiles": {
+				"description": "Tags of each machine the configuration is meant for, keyed by the machine's name.",
+				"type": "object",
+				"additionalProperties": { "$ref": "#/$defs/tag_set" },
+			},
+			"remap": {
+				"description": "Prefixes of link paths replaced by others, along with the ones replaced while a tag is active, keyed by the tag.",
+				"type": "object",
+				"additionalProperties": {
+					"anyOf": [
+						{ "type": "string" },
+						{
+							"type": "object",
+							"additionalProperties": { "type": "string" },
+						},
+					],
+				},
+			},
+			"unfold": {
+				"description": "Whether directory links other links are within are split into links of their entries.",
+				"type": "boolean",
+			},
+			"tag_prefix": {
+				"description": "Character tags are prepended with among the arguments, instead of a plus sign.",
+				"type": "string",
+				"minLength": 1,
+				"maxLength": 1,
+			},
+			"targets": {
+				"description": "Targets keyed by their paths, which may also be written as nested tables.",
+				"type": "object",
+				"additionalProperties": { "$ref": "#/$defs/entry" },
+			},
+		},
+		"$defs": {
+			"tag_set": {
+				"type": "array",
+				"items": { "type": "string" },
+				"uniqueItems": true,
+			},
+			"mode": {
+				"description": "Permissions, as octal digits like \"644\" or an integer like 0o644.",
+				"anyOf": [
+					{ "type": "string", "pattern": "^[0-7]{1,4}$" },
+					{ "type": "integer", "minimum": 0, "maximum": 4095 },
+				],
+			},
+			"entry": {
+				"anyOf": [
+					{ "$ref": "#/$defs/target" },
+					{
+						"description": "Table of nested targets.",
+						"type": "object",
+						"additionalProperties": { "$ref": "#/$defs/entry" },
+					},
+				],
+			},
+			"target": {
+				"type": "object",
+				"additionalProperties": false,
+				"properties": {
+					"link": { "$ref": "#/$defs/link" },
+					"tags": { "$ref": "#/$defs/tags" },
+					"linux": {
+						"description": "Overrides used when the \"linux\" tag is active.",
+						"$ref": "#/$defs/target_override",
+					},
+					"macos": {
+						"description": "Overrides used when the \"macos\" tag is active.",
+						"$ref": "#/$defs/target_override",
+					},
+					"seed": {
+						"description": "Whether the target is copied only when nothing exists in its link path yet.",
+						"type": "boolean",
+					},
+					"source": {
+						"description": "Path of the target, in case it doesn't live in the work directory under its name.",
+						"type": "string",
+					},
+					"work_dir": {
+						"description": "Work directory of the target, in case it's not the top-level one.",
+						"type": "string",
+					},
+					"description": {
+						"description": "Human-readable description of the target.",
+						"type": "string",
+					},
+					"optional": {
+						"description": "Whether problems with the target are only reported, instead of blocking the others.",
+						"type": "boolean",
+					},
+					"when": {
+						"description": "Conditions under which the target is managed, besides its tags.",
+						"type": "object",
+						"additionalProperties": false,
+						"properties": {
+							"script": {
+								"description": "Executable, relative to the work directory, whose success makes the target managed.",
+								"type": "string",
+							},
+						},
+					},
+					"on_conflict": {
+						"description": "How files in the way of the target's link are dealt with when linking.",
+						"enum": ["backup", "replace", "skip", "fail"],
+					},
+					"problem_hint": {
+						"description": "Note shown along with problems of the target.",
+						"type": "string",
+					},
+					"owner": {
+						"description": "Owner of the target's link, as \"user:group\".",
+						"type": "string",
+					},
+					"group": {
+						"description": "Section the target is listed under in previews, regardless of its path.",
+						"type": "string",
+					},
+					"secret": {
+						"description": "Content of the target that is kept out of the work directory, like credentials.",
+						"type": "object",
+						"additionalProperties": false,
+						"properties": {
+							"command": {
+								"description": "Shell command whose output is written to the link path once.",
+								"type": "string",
+							},
+						},
+					},
+					"max_compare_size": {
+						"description": "Size, in bytes, of the largest files compared with the target, past which they're assumed to differ.",
+						"type": "integer",
+						"minimum": 0,
+					},
+				},
+			},
+			"target_override": {
+				"type": "object",
+				"additionalProperties": false,
+				"properties": {
+					"link": { "$ref": "#/$defs/link" },
+					"tags": { "$ref": "#/$defs/tags" },
+				},
+			},
+			"tags": {
+				"description": "Tags under which the target is managed.",
+				"type": "object",
+				"additionalProperties": false,
+				"properties": {
+					"all_of": {
+						"description": "Tags that must all be active.",
+						"$ref": "#/$defs/tag_set",
+					},
+					"any_of": {
+						"description": "Tags of which at least one must be active.",
+						"$ref": "#/$defs/tag_set",
+					},
+				},
+			},
+			"link": {
+				"description": "Options of the target's link.",
+				"type": "object",
+				"additionalProperties": false,
+				"properties": {
+					"base_dir": {
+						"description": "Directory the link is created in.",
+						"type": "string",
+					},
+					"name": {
+						"description": "File name of the link.",
+						"type": "string",
+					},
+					"mode": {
+						"description": "Permissions of the symlink itself, on platforms where symlinks have their own.",
+						"$ref": "#/$defs/mode",
+					},
+					"reflink": {
+						"description": "Whether the target is cloned to the link path instead of symlinked.",
+						"type": "boolean",
+					},
+					"copy_mode": {
+						"description": "Permissions of copies and clones of the target.",
+						"$ref": "#/$defs/mode",
+					},
+				},
+			},
+		},
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use pretty_assertions::assert_eq;
+	use serde::{
+		de::{value::Error as DeError, Error as _, Visitor},
+		forward_to_deserialize_any, Deserialize, Deserializer,
+	};
+
+	use crate::config::{
+		Config, Link, Policy, PrinterConfig, Secret, Tags, Target, TargetOverride, When,
+		TARGET_FIELDS,
+	};
+
+	use super::*;
+
+	/// Deserializer that fails right away, after taking note of the fields of the struct
+	/// being deserialized, so that they can be read from the type itself.
+	#[derive(Default)]
+	struct FieldNames(&'static [&'static str]);
+
+	impl<'de> Deserializer<'de> for &mut FieldNames {
+		type Error = DeError;
+
+		fn deserialize_any<V>(self, _: V) -> Result<V::Value, Self::Error>
+		where
+			V: Visitor<'de>,
+		{
+			Err(DeError::custom("not a struct"))
+		}
+
+		fn deserialize_struct<V>(
+			self,
+			_: &'static str,
+			fields: &'static [&'static str],
+			_: V,
+		) -> Result<V::Value, Self::Error>
+		where
+			V: Visitor<'de>,
+		{
+			self.0 = fields;
+
+			Err(DeError::custom("fields are all that's needed"))
+		}
+
+		forward_to_deserialize_any! {
+			bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+			byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct map enum
+			identifier ignored_any
+		}
+	}
+
+	/// Returns the sorted fields of a struct, as they're deserialized.
+	fn fields_of<'de, T>() -> Vec<String>
+	where
+		T: Deserialize<'de>,
+	{
+		let mut field_names = FieldNames::default();
+		let _ = T::deserialize(&mut field_names);
+		let mut fields: Vec<String> = field_names
+			.0
+			.iter()
+			.map(|field| field.to_string())
+			.collect();
+		fields.sort();
+
+		fields
+	}
+
+	#[test]
+	fn describe_config() {
+		let schema = config();
+		let fields = |pointer: &str| -> Vec<String> {
+			let mut fields: Vec<String> = schema
+				.pointer(pointer)
+				.and_then(JsonValue::as_object)
+				.map(|properties| properties.keys().cloned().collect())
+				.unwrap_or_default();
+			fields.sort();
+
+			fields
+		};
+
+		let mut target_fields: Vec<String> = TARGET_FIELDS.map(String::from).into();
+		target_fields.sort();
+
+		assert_eq!(
+			fields("/$defs/target/properties"),
+			target_fields,
+			"bad target fields"
+		);
+
+		for (pointer, struct_fields) in [
+			("/properties", fields_of::<Config>()),
+			(
+				"/properties/printer/properties",
+				fields_of::<PrinterConfig>(),
+			),
+			("/properties/policy/properties", fields_of::<Policy>()),
+			("/$defs/target/properties", fields_of::<Target>()),
+			(
+				"/$defs/target/properties/when/properties",
+				fields_of::<When>(),
+			),
+			(
+				"/$defs/target/properties/secret/properties",
+				fields_of::<Secret>(),
+			),
+			(
+				"/$defs/target_override/properties",
+				fields_of::<TargetOverride>(),
+			),
+			("/$defs/tags/properties", fields_of::<Tags>()),
+			("/$defs/link/properties", fields_of::<Link>()),
+		] {
+			assert!(!struct_fields.is_empty(), "no fields for {:?}", pointer);
+			assert_eq!(
+				fields(pointer),
+				struct_fields,
+				"bad fields for {:?}",
+				pointer
+			);
+		}
+		assert_eq!(
+			schema.pointer("/properties/policy/properties/failures/items/enum"),
+			Some(&json!(Status::ALL.map(|status| status.name()))),
+			"bad statuses"
+		);
+	}
+}
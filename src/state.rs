@@ -0,0 +1,134 @@
+use std::{
+	ffi::OsString,
+	fs::{self, File, OpenOptions},
+	io::{Error as IoError, ErrorKind as IoErrorKind},
+	os::fd::AsRawFd,
+	path::PathBuf,
+	process,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use toml::Value as TomlValue;
+
+use crate::vars;
+
+/// Version of the format of state files. Files without a version are from before versioning
+/// and are read as the first one.
+const VERSION: u32 = 1;
+
+/// Directory holding the state Park keeps between runs, like the manifest, so that every
+/// file in it is versioned, written atomically and guarded by the same lock.
+#[derive(Debug, PartialEq)]
+pub struct StateStore {
+	dir: PathBuf,
+}
+
+/// Exclusive lock on a state directory, which is released when dropped.
+#[derive(Debug)]
+pub struct StateLock {
+	_file: File,
+}
+
+#[derive(Deserialize)]
+struct Header {
+	version: Option<u32>,
+}
+
+impl StateStore {
+	pub fn new<P>(dir: P) -> Self
+	where
+		P: Into<PathBuf>,
+	{
+		Self { dir: dir.into() }
+	}
+
+	/// Returns the path of a file in the state directory.
+	pub fn path(&self, name: &str) -> PathBuf {
+		self.dir.join(name)
+	}
+
+	/// Waits until no other process holds the state directory, then holds it until the lock
+	/// is dropped. Readers and writers of the same files are expected to hold it throughout.
+	pub fn lock(&self) -> Result<StateLock, IoError> {
+		fs::create_dir_all(&self.dir)?;
+
+		let file = OpenOptions::new()
+			.create(true)
+			.truncate(false)
+			.write(true)
+			.open(self.path("lock"))?;
+
+		// SAFETY: the descriptor stays open during the call.
+		if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+			return Err(IoError::last_os_error());
+		}
+
+		Ok(StateLock { _file: file })
+	}
+
+	/// Reads a state file. A missing file results in nothing, while one that doesn't parse or
+	/// is written in a newer format is an error, so that it doesn't get overwritten by mistake.
+	pub fn load<T>(&self, name: &str) -> Result<Option<T>, IoError>
+	where
+		T: DeserializeOwned,
+	{
+		let path = self.path(name);
+		let content = match fs::read_to_string(&path) {
+			Ok(content) => content,
+			Err(err) if err.kind() == IoErrorKind::NotFound => return Ok(None),
+			Err(err) => return Err(err),
+		};
+
+		let invalid = |err: toml::de::Error| {
+			IoError::new(
+				IoErrorKind::InvalidData,
+				format!("{:?} could not be parsed: {}", path, err),
+			)
+		};
+		let Header { version } = toml::from_str(&content).map_err(invalid)?;
+
+		if let Some(version) = version.filter(|version| *version > VERSION) {
+			return Err(IoError::other(format!(
+				"{:?} has version {}, which is newer than the supported version {}",
+				path, version, VERSION
+			)));
+		}
+
+		toml::from_str(&content).map(Some).map_err(invalid)
+	}
+
+	/// Writes a state file along with its version. The content goes to a temporary file
+	/// first, which then replaces the old file, so that readers never see a partial one.
+	pub fn store<T>(&self, name: &str, value: &T) -> Result<(), IoError>
+	where
+		T: Serialize,
+	{
+		let mut document = TomlValue::try_from(value).map_err(IoError::other)?;
+
+		if let TomlValue::Table(table) = &mut document {
+			table.insert("version".into(), TomlValue::Integer(VERSION.into()));
+		}
+
+		let content = toml::to_string(&document).map_err(IoError::other)?;
+
+		fs::create_dir_all(&self.dir)?;
+
+		let path = self.path(name);
+		let temp_path = self.path(&format!(".{}.{}", name, process::id()));
+
+		fs::write(&temp_path, content)?;
+		fs::rename(&temp_path, &path).inspect_err(|_| {
+			let _ = fs::remove_file(&temp_path);
+		})
+	}
+}
+
+/// Default location of the state directory, according to the XDG base directories.
+pub fn default_dir<F>(var: F) -> Option<PathBuf>
+where
+	F: Fn(&str) -> Option<OsString>,
+{
+	let state_dir = vars::xdg_dir(var, "XDG_STATE_HOME", &[".local", "state"])?;
+
+	Some(state_dir.join("park"))
+}
@@ -0,0 +1,123 @@
+use std::{
+	collections::BTreeMap,
+	ffi::OsString,
+	fs,
+	io::{Error as IoError, ErrorKind as IoErrorKind},
+	path::{Path, PathBuf},
+};
+
+/// Variables whose values replace placeholders, like `{email}`, in link names and base
+/// directories.
+pub type Vars = BTreeMap<String, String>;
+
+/// Reads variables local to the machine. A missing file results in no variables.
+pub fn load(path: &Path) -> Result<Vars, IoError> {
+	match fs::read_to_string(path) {
+		Ok(content) => toml::from_str(&content).map_err(IoError::other),
+		Err(err) if err.kind() == IoErrorKind::NotFound => Ok(Vars::new()),
+		Err(err) => Err(err),
+	}
+}
+
+/// Returns an XDG base directory, which is the one its variable is set to, unless it's unset or
+/// empty, in which case it's `fallback` within the home directory.
+pub fn xdg_dir<F>(var: F, key: &str, fallback: &[&str]) -> Option<PathBuf>
+where
+	F: Fn(&str) -> Option<OsString>,
+{
+	var(key)
+		.filter(|dir| !dir.is_empty())
+		.map(PathBuf::from)
+		.or_else(|| Some(PathBuf::from(var("HOME")?).join(fallback.iter().collect::<PathBuf>())))
+}
+
+/// Default location of the variables file, according to the XDG base directories.
+pub fn default_path<F>(var: F) -> Option<PathBuf>
+where
+	F: Fn(&str) -> Option<OsString>,
+{
+	let config_dir = xdg_dir(var, "XDG_CONFIG_HOME", &[".config"])?;
+
+	Some(config_dir.join("park").join("vars.toml"))
+}
+
+/// Variables that are always available, namely `xdg_config`, `xdg_data` and `xdg_state`, which
+/// point at the XDG base directories. Unless the XDG variables are set, they default to
+/// `~/Library/Application Support` on macOS, where apps keep such files there.
+pub fn builtin<F>(var: F) -> Vars
+where
+	F: Fn(&str) -> Option<OsString>,
+{
+	let defaults: [(&str, &str, &[&str]); 3] = if cfg!(target_os = "macos") {
+		[
+			(
+				"xdg_config",
+				"XDG_CONFIG_HOME",
+				&["Library", "Application Support"],
+			),
+			(
+				"xdg_data",
+				"XDG_DATA_HOME",
+				&["Library", "Application Support"],
+			),
+			(
+				"xdg_state",
+				"XDG_STATE_HOME",
+				&["Library", "Application Support"],
+			),
+		]
+	} else {
+		[
+			("xdg_config", "XDG_CONFIG_HOME", &[".config"]),
+			("xdg_data", "XDG_DATA_HOME", &[".local", "share"]),
+			("xdg_state", "XDG_STATE_HOME", &[".local", "state"]),
+		]
+	};
+
+	defaults
+		.into_iter()
+		.filter_map(|(name, key, fallback)| {
+			let dir = xdg_dir(&var, key, fallback)?;
+
+			Some((name.into(), dir.to_string_lossy().into_owned()))
+		})
+		.collect()
+}
+
+/// Replaces placeholders in a string with the values of their variables, while `{{` and `}}`
+/// stand for literal braces. The name of the first unknown variable is returned as an error.
+pub fn expand(s: &str, vars: &Vars) -> Result<String, String> {
+	let mut expanded = String::with_capacity(s.len());
+	let mut chars = s.chars();
+
+	while let Some(c) = chars.next() {
+		match c {
+			'{' if chars.as_str().starts_with('{') => {
+				chars.next();
+				expanded.push('{');
+			}
+			'}' if chars.as_str().starts_with('}') => {
+				chars.next();
+				expanded.push('}');
+			}
+			'{' => {
+				let rest = chars.as_str();
+				let Some(end) = rest.find('}') else {
+					expanded.push(c);
+					continue;
+				};
+
+				let name = &rest[..end];
+				match vars.get(name) {
+					Some(value) => expanded.push_str(value),
+					None => return Err(name.into()),
+				}
+
+				chars = rest[end + 1..].chars();
+			}
+			c => expanded.push(c),
+		}
+	}
+
+	Ok(expanded)
+}